@@ -0,0 +1,27 @@
+use std::{env, fs, path::Path};
+
+// Embed every locales/<lang>.toml so new translations only need a new file
+fn main() {
+    println!("cargo:rerun-if-changed=locales");
+
+    let mut locales: Vec<(String, String)> = fs::read_dir("locales")
+        .expect("locales directory is missing")
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .filter_map(|path| {
+            let lang = path.file_stem()?.to_str()?.to_string();
+            let full_path = fs::canonicalize(&path).ok()?.to_str()?.to_string();
+            Some((lang, full_path))
+        })
+        .collect();
+    locales.sort();
+
+    let mut generated = String::from("pub const BUILTIN_LOCALES: &[(&str, &str)] = &[\n");
+    for (lang, path) in locales {
+        generated.push_str(&format!("    ({lang:?}, include_str!({path:?})),\n"));
+    }
+    generated.push_str("];\n");
+
+    let out_path = Path::new(&env::var("OUT_DIR").unwrap()).join("locales.rs");
+    fs::write(out_path, generated).unwrap();
+}
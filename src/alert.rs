@@ -0,0 +1,33 @@
+use std::io::{self, Write};
+
+use serde::Deserialize;
+
+/// How an event gets noticed from another pane or window.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Alert {
+    #[default]
+    Off,
+    // The terminal bell, which tmux and most terminals turn into a marker on the window or an urgency hint
+    Bell,
+    // The status bar shown inverted for a moment
+    Flash,
+    Both,
+}
+
+impl Alert {
+    pub fn rings(self) -> bool {
+        matches!(self, Alert::Bell | Alert::Both)
+    }
+
+    pub fn flashes(self) -> bool {
+        matches!(self, Alert::Flash | Alert::Both)
+    }
+}
+
+/// Ring the terminal bell; errors are ignored, like the terminal title.
+pub fn ring() {
+    let mut stdout = io::stdout();
+    let _ = stdout.write_all(b"\x07");
+    let _ = stdout.flush();
+}
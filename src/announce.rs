@@ -0,0 +1,87 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    sync::mpsc::{self, Sender},
+    thread,
+};
+
+use crate::{i18n::t_args, paths::expand_home};
+
+/// Where announcements go.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    Stderr,
+    // Lines are appended, so `tail -f` or a braille display driver can follow it
+    File(PathBuf),
+}
+
+impl Target {
+    /// `stderr`, or a file path; nothing for an empty setting.
+    pub fn parse(text: &str) -> Option<Target> {
+        match text.trim() {
+            "" => None,
+            "stderr" => Some(Target::Stderr),
+            path => Some(Target::File(expand_home(path))),
+        }
+    }
+}
+
+/// "Now playing: Title by Artist", or without the artist when there is none.
+pub fn line(title: &str, artist: Option<&str>) -> String {
+    match artist.filter(|artist| !artist.is_empty()) {
+        Some(artist) => t_args("announce.now_playing_by", &[("title", &title), ("artist", &artist)]),
+        None => t_args("announce.now_playing", &[("title", &title)]),
+    }
+}
+
+/// Writes one plain line per track change for screen readers and braille displays, outside the TUI grid.
+pub struct Announcer {
+    // A pipe into a speech synthesizer can fill up, so lines are written from a background thread
+    lines: Sender<String>,
+}
+
+impl Announcer {
+    pub fn open(target: &Target) -> io::Result<Self> {
+        let mut out: Box<dyn Write + Send> = match target {
+            Target::Stderr => Box::new(io::stderr()),
+            Target::File(path) => {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                Box::new(OpenOptions::new().create(true).append(true).open(path)?)
+            }
+        };
+        let (lines, received) = mpsc::channel::<String>();
+        thread::spawn(move || {
+            for line in received {
+                // A reader that went away ends the announcements rather than the player
+                if writeln!(out, "{line}").and_then(|()| out.flush()).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Announcer { lines })
+    }
+
+    pub fn write(&self, line: &str) {
+        let _ = self.lines.send(line.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_targets_and_lines() {
+        assert_eq!(Target::parse(" stderr "), Some(Target::Stderr));
+        assert_eq!(Target::parse(""), None);
+        assert_eq!(
+            Target::parse("/tmp/musix-announce.txt"),
+            Some(Target::File(PathBuf::from("/tmp/musix-announce.txt")))
+        );
+        assert_eq!(line("Voyager", Some("Daft Punk")), "Now playing: Voyager by Daft Punk");
+        assert_eq!(line("Voyager", Some("")), "Now playing: Voyager");
+    }
+}
@@ -0,0 +1,48 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::paths::expand_home;
+
+/// A `[[auto_skip]]` entry: tracks under `folder`, such as the episodes of one podcast, skip their first `intro`
+/// and last `outro` seconds. Per-track skips set with `I`/`O` take precedence.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct AutoSkipRule {
+    // `~` is expanded
+    pub folder: String,
+    #[serde(default)]
+    pub intro: f64,
+    #[serde(default)]
+    pub outro: f64,
+}
+
+/// The rule for the deepest folder containing `path`, so a rule for one show can refine one for all podcasts.
+pub fn rule_for<'a>(rules: &'a [AutoSkipRule], path: &Path) -> Option<&'a AutoSkipRule> {
+    rules
+        .iter()
+        .map(|rule| (rule, expand_home(&rule.folder)))
+        .filter(|(_, folder)| path.starts_with(folder))
+        .max_by_key(|(_, folder)| folder.components().count())
+        .map(|(rule, _)| rule)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deepest_folder_wins() {
+        let rule = |folder: &str, intro, outro| AutoSkipRule {
+            folder: folder.to_string(),
+            intro,
+            outro,
+        };
+        let rules = [rule("/podcasts", 10.0, 0.0), rule("/podcasts/daily", 45.0, 30.0)];
+
+        assert_eq!(rule_for(&rules, Path::new("/podcasts/daily/ep1.mp3")), Some(&rules[1]));
+        assert_eq!(rule_for(&rules, Path::new("/podcasts/weekly/ep1.mp3")), Some(&rules[0]));
+        // Whole path components only
+        assert_eq!(rule_for(&rules, Path::new("/podcasts-old/ep1.mp3")), None);
+        assert_eq!(rule_for(&rules, Path::new("/music/a.mp3")), None);
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    collections::HashSet,
+    ops::RangeInclusive,
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use rodio::Source;
+
+use crate::create_audio_source;
+
+// Onset envelope resolution; 5ms hops keep one lag step near 1 BPM at typical tempos
+const ENVELOPE_RATE: f32 = 200.0;
+// Only the first part of a track is needed to lock onto its tempo
+const ANALYSIS_SECONDS: u32 = 90;
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 200.0;
+// Results are folded by octaves into this range, where most music is notated
+const FOLD_MIN_BPM: f32 = 75.0;
+const FOLD_MAX_BPM: f32 = 180.0;
+
+/// Estimate the tempo of a source from the autocorrelation of its onset envelope.
+pub fn detect_bpm<S: Source<Item = i16>>(source: S) -> Option<f32> {
+    let channels = source.channels() as usize;
+    let sample_rate = source.sample_rate();
+    let hop = ((sample_rate as f32 / ENVELOPE_RATE) as usize * channels).max(1);
+    let limit = (sample_rate * ANALYSIS_SECONDS) as usize * channels;
+
+    // Log energy per hop, then its half-wave rectified rise as the onset strength
+    let mut energies = Vec::new();
+    let mut energy = 0.0f32;
+    for (index, sample) in source.take(limit).enumerate() {
+        let value = f32::from(sample) / 32768.0;
+        energy += value * value;
+        if (index + 1) % hop == 0 {
+            energies.push((1.0 + 1000.0 * energy / hop as f32).ln());
+            energy = 0.0;
+        }
+    }
+    let mut onsets: Vec<f32> = energies.windows(2).map(|pair| (pair[1] - pair[0]).max(0.0)).collect();
+    let mean = onsets.iter().sum::<f32>() / onsets.len().max(1) as f32;
+    onsets.iter_mut().for_each(|onset| *onset -= mean);
+
+    let min_lag = (60.0 * ENVELOPE_RATE / MAX_BPM) as usize;
+    let max_lag = (60.0 * ENVELOPE_RATE / MIN_BPM) as usize;
+    if onsets.len() < max_lag * 4 {
+        return None;
+    }
+
+    let autocorrelation = |lag: usize| -> f32 {
+        let pairs = onsets.len() - lag;
+        onsets.iter().zip(&onsets[lag..]).map(|(a, b)| a * b).sum::<f32>() / pairs as f32
+    };
+    let correlations: Vec<f32> = (0..=max_lag * 2).map(|lag| if lag < min_lag { 0.0 } else { autocorrelation(lag) }).collect();
+
+    // A true beat period also lines up at twice its lag, which breaks ties with off-beat periods
+    let score = |lag: usize| correlations[lag] + 0.5 * correlations[lag * 2];
+    let best = (min_lag..=max_lag).max_by(|&a, &b| score(a).total_cmp(&score(b)))?;
+    if correlations[best] <= 0.0 {
+        return None;
+    }
+
+    // Parabolic interpolation around the peak for sub-lag precision
+    let (left, center, right) = (correlations[best - 1], correlations[best], correlations[best + 1]);
+    let denominator = left - 2.0 * center + right;
+    let offset = if denominator.abs() > f32::EPSILON {
+        (0.5 * (left - right) / denominator).clamp(-0.5, 0.5)
+    } else {
+        0.0
+    };
+
+    let mut bpm = 60.0 * ENVELOPE_RATE / (best as f32 + offset);
+    while bpm < FOLD_MIN_BPM {
+        bpm *= 2.0;
+    }
+    while bpm > FOLD_MAX_BPM {
+        bpm /= 2.0;
+    }
+    Some((bpm * 10.0).round() / 10.0)
+}
+
+/// Parse a search token like `bpm:128`, `bpm:120-130`, `bpm:>140` or `bpm:<90` into a range of whole BPM.
+pub fn parse_filter(token: &str) -> Option<RangeInclusive<u32>> {
+    let spec = token.get(..4).filter(|prefix| prefix.eq_ignore_ascii_case("bpm:")).map(|_| &token[4..])?;
+    if let Some(min) = spec.strip_prefix('>') {
+        Some(min.parse::<u32>().ok()? + 1..=u32::MAX)
+    } else if let Some(max) = spec.strip_prefix('<') {
+        Some(0..=max.parse::<u32>().ok()?.checked_sub(1)?)
+    } else if let Some((min, max)) = spec.split_once('-') {
+        Some(min.parse().ok()?..=max.parse().ok()?)
+    } else {
+        let bpm = spec.parse().ok()?;
+        Some(bpm..=bpm)
+    }
+}
+
+/// Runs BPM detection for queued tracks on a background thread.
+pub struct BpmAnalyzer {
+    sender: Option<Sender<PathBuf>>,
+    results: Option<Receiver<(PathBuf, Option<f32>)>>,
+    pending: HashSet<PathBuf>,
+}
+
+impl BpmAnalyzer {
+    pub fn new() -> Self {
+        BpmAnalyzer {
+            sender: None,
+            results: None,
+            pending: HashSet::new(),
+        }
+    }
+
+    // The worker thread is only started once something is queued
+    pub fn request(&mut self, path: PathBuf) {
+        if !self.pending.insert(path.clone()) {
+            return;
+        }
+
+        let sender = self.sender.get_or_insert_with(|| {
+            let (path_sender, paths) = mpsc::channel::<PathBuf>();
+            let (result_sender, results) = mpsc::channel();
+            thread::spawn(move || {
+                for path in paths {
+                    let bpm = create_audio_source(&path).ok().and_then(detect_bpm);
+                    if result_sender.send((path, bpm)).is_err() {
+                        break;
+                    }
+                }
+            });
+            self.results = Some(results);
+            path_sender
+        });
+        let _ = sender.send(path);
+    }
+
+    pub fn is_pending(&self, path: &PathBuf) -> bool {
+        self.pending.contains(path)
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    // Collect finished analyses; called once per UI tick
+    pub fn poll(&mut self) -> Vec<(PathBuf, Option<f32>)> {
+        let Some(results) = &self.results else {
+            return Vec::new();
+        };
+
+        let finished: Vec<_> = results.try_iter().collect();
+        for (path, _) in &finished {
+            self.pending.remove(path);
+        }
+        finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    // Mono click track: a short decaying burst on every beat
+    fn click_track(bpm: f32, seconds: f32) -> SamplesBuffer<i16> {
+        let sample_rate = 22050;
+        let beat = (sample_rate as f32 * 60.0 / bpm) as usize;
+        let samples = (0..(sample_rate as f32 * seconds) as usize)
+            .map(|i| {
+                let t = i % beat;
+                if t < 600 {
+                    ((t as f32 * 0.7).sin() * 20000.0 * (1.0 - t as f32 / 600.0)) as i16
+                } else {
+                    0
+                }
+            })
+            .collect::<Vec<_>>();
+        SamplesBuffer::new(1, sample_rate, samples)
+    }
+
+    #[test]
+    fn test_detect_bpm_of_click_tracks() {
+        for expected in [90.0, 120.0, 128.0, 174.0] {
+            let bpm = detect_bpm(click_track(expected, 30.0)).unwrap();
+            assert!((bpm - expected).abs() < 1.5, "expected {expected}, got {bpm}");
+        }
+    }
+
+    #[test]
+    fn test_detect_bpm_needs_enough_audio() {
+        assert_eq!(detect_bpm(click_track(120.0, 1.0)), None);
+        assert_eq!(detect_bpm(SamplesBuffer::new(1, 22050, vec![0i16; 22050 * 20])), None);
+    }
+
+    #[test]
+    fn test_parse_filter() {
+        assert_eq!(parse_filter("bpm:128"), Some(128..=128));
+        assert_eq!(parse_filter("BPM:120-130"), Some(120..=130));
+        assert_eq!(parse_filter("bpm:>140"), Some(141..=u32::MAX));
+        assert_eq!(parse_filter("bpm:<90"), Some(0..=89));
+        assert_eq!(parse_filter("bpm:fast"), None);
+        assert_eq!(parse_filter("beatles"), None);
+    }
+}
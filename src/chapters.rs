@@ -0,0 +1,120 @@
+use std::{fs::File, path::Path, time::Duration};
+
+use lofty::{
+    config::ParseOptions,
+    file::AudioFile,
+    flac::FlacFile,
+    id3::v2::{Frame, FrameId},
+    mpeg::MpegFile,
+    ogg::{OpusFile, VorbisFile, tag::VorbisComments},
+};
+
+/// A chapter of a long file such as an audiobook or a podcast episode, from its tags.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chapter {
+    pub start: Duration,
+    pub title: Option<String>,
+}
+
+/// Chapters from ID3v2 `CHAP` frames (MP3) or `CHAPTERnnn` comments (FLAC, Ogg, Opus), in order; none when the
+/// file has none or can't be read.
+pub fn read(path: &Path) -> Vec<Chapter> {
+    let extension = path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase);
+    let Ok(mut file) = File::open(path) else {
+        return Vec::new();
+    };
+    let options = ParseOptions::new();
+    let mut chapters = match extension.as_deref() {
+        Some("mp3") => MpegFile::read_from(&mut file, options)
+            .ok()
+            .and_then(|mp3| mp3.id3v2().map(id3_chapters))
+            .unwrap_or_default(),
+        Some("flac") => FlacFile::read_from(&mut file, options)
+            .ok()
+            .and_then(|flac| flac.vorbis_comments().map(vorbis_chapters))
+            .unwrap_or_default(),
+        Some("ogg") => VorbisFile::read_from(&mut file, options)
+            .map(|ogg| vorbis_chapters(ogg.vorbis_comments()))
+            .unwrap_or_default(),
+        Some("opus") => OpusFile::read_from(&mut file, options)
+            .map(|opus| vorbis_chapters(opus.vorbis_comments()))
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    };
+    chapters.sort_by_key(|chapter| chapter.start);
+    chapters
+}
+
+fn id3_chapters(tag: &lofty::id3::v2::Id3v2Tag) -> Vec<Chapter> {
+    let title_id = FrameId::Valid("TIT2".into());
+    tag.into_iter()
+        .filter_map(|frame| match frame {
+            Frame::Chapter(chapter) => Some(Chapter {
+                start: Duration::from_millis(chapter.times.start.into()),
+                title: chapter.children.get_text(&title_id).map(str::to_string),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+// The Vorbis comment convention: `CHAPTER001=00:04:30.000` and `CHAPTER001NAME=Title`
+fn vorbis_chapters(comments: &VorbisComments) -> Vec<Chapter> {
+    comments
+        .items()
+        .filter_map(|(key, value)| {
+            let number = key.to_ascii_uppercase().strip_prefix("CHAPTER")?.to_string();
+            if number.is_empty() || !number.bytes().all(|byte| byte.is_ascii_digit()) {
+                return None;
+            }
+            let title = comments.get(&format!("{key}NAME")).map(str::to_string);
+            Some(Chapter {
+                start: parse_timestamp(value)?,
+                title,
+            })
+        })
+        .collect()
+}
+
+/// `HH:MM:SS.mmm`, with the hours, minutes and the fraction optional, so plain seconds parse too.
+pub fn parse_timestamp(text: &str) -> Option<Duration> {
+    let mut seconds = 0.0;
+    for part in text.trim().split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok().filter(|part| part.is_finite() && *part >= 0.0)?;
+    }
+    Some(Duration::from_secs_f64(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lofty::tag::Accessor;
+
+    #[test]
+    fn test_vorbis_chapter_comments() {
+        let mut comments = VorbisComments::default();
+        comments.set_title("Book".to_string());
+        comments.push("CHAPTER002".to_string(), "01:02:03.500".to_string());
+        comments.push("CHAPTER002NAME".to_string(), "The Middle".to_string());
+        comments.push("CHAPTER001".to_string(), "00:00:00.000".to_string());
+        comments.push("CHAPTERBAD".to_string(), "00:01:00".to_string());
+        comments.push("CHAPTER003".to_string(), "soon".to_string());
+
+        let chapters = vorbis_chapters(&comments);
+        assert_eq!(
+            chapters,
+            vec![
+                Chapter {
+                    start: Duration::from_secs_f64(3723.5),
+                    title: Some("The Middle".to_string()),
+                },
+                Chapter {
+                    start: Duration::ZERO,
+                    title: None,
+                },
+            ]
+        );
+        assert_eq!(parse_timestamp("4:30"), Some(Duration::from_secs(270)));
+        assert!(read(Path::new("/nonexistent/book.mp3")).is_empty());
+    }
+}
@@ -0,0 +1,181 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::Config,
+    create_audio_source,
+    error::MusixError,
+    i18n::{t, t_args},
+    library::Library,
+    scan_folders,
+};
+
+/// Something `musix check` found wrong with the library.
+#[derive(Debug, PartialEq)]
+enum Problem {
+    // Has ratings, plays or cues in library.json but is gone from disk
+    Missing(PathBuf),
+    Empty(PathBuf),
+    // The file is there but its headers can't be decoded
+    Corrupt(PathBuf, String),
+    // A line of a playlist naming a file that isn't there
+    Dangling { playlist: PathBuf, entry: PathBuf },
+}
+
+impl Problem {
+    // Tab separated and untranslated, for scripts
+    fn line(&self) -> String {
+        match self {
+            Problem::Missing(path) => format!("missing\t{}", path.display()),
+            Problem::Empty(path) => format!("empty\t{}", path.display()),
+            Problem::Corrupt(path, error) => format!("corrupt\t{}\t{error}", path.display()),
+            Problem::Dangling { playlist, entry } => format!("playlist\t{}\t{}", playlist.display(), entry.display()),
+        }
+    }
+}
+
+fn check_track(path: &Path) -> Option<Problem> {
+    match fs::metadata(path) {
+        Err(_) => return Some(Problem::Missing(path.to_path_buf())),
+        Ok(metadata) if metadata.len() == 0 => return Some(Problem::Empty(path.to_path_buf())),
+        Ok(_) => {}
+    }
+    create_audio_source(&path.to_path_buf())
+        .err()
+        .map(|error| Problem::Corrupt(path.to_path_buf(), error.to_string()))
+}
+
+// `.m3u` and `.m3u8` files anywhere in the library folders
+pub fn playlist_files(dir: &Path, playlists: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.is_dir() {
+            playlist_files(&path, playlists);
+        } else if path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("m3u") || ext.eq_ignore_ascii_case("m3u8"))
+        {
+            playlists.push(path);
+        }
+    }
+}
+
+// Local files a playlist names that don't exist; relative lines are taken from the playlist's folder, and stream
+// URLs and comments are passed over
+fn dangling_entries(playlist: &Path, text: &str) -> Vec<PathBuf> {
+    let dir = playlist.parent().unwrap_or(Path::new(""));
+    text.lines()
+        .map(|line| line.trim().trim_start_matches('\u{feff}'))
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.contains("://"))
+        .map(|line| dir.join(line))
+        .filter(|entry| !entry.exists())
+        .collect()
+}
+
+/// Entry point for `musix check`: every track in the library folders is opened and its headers decoded, every
+/// library.json entry looked for on disk and every playlist line followed. Problems are printed one per line on
+/// stdout, and the exit status is 1 when there are any.
+pub fn run() -> Result<(), MusixError> {
+    let folders = Config::load().unwrap_or_default().library.paths();
+    let tracks: Vec<PathBuf> = scan_folders(&folders)?
+        .into_iter()
+        .filter(|song| song.stream_url.is_none())
+        .map(|song| song.path)
+        .collect();
+    let mut problems = Vec::new();
+    let mut stdout = io::stdout();
+    let mut report = |problem: Problem, problems: &mut Vec<Problem>| {
+        eprint!("\r\x1b[2K");
+        let _ = writeln!(stdout, "{}", problem.line());
+        problems.push(problem);
+    };
+
+    for (number, path) in tracks.iter().enumerate() {
+        eprint!("\r\x1b[2K[{}/{}] {}", number + 1, tracks.len(), path.display());
+        let _ = io::stderr().flush();
+        if let Some(problem) = check_track(path) {
+            report(problem, &mut problems);
+        }
+    }
+
+    let library = Library::load();
+    let entries: Vec<&str> = library.paths().collect();
+    for entry in &entries {
+        let path = Path::new(entry);
+        if !path.exists() {
+            report(Problem::Missing(path.to_path_buf()), &mut problems);
+        }
+    }
+
+    let mut playlists = Vec::new();
+    for folder in &folders {
+        playlist_files(folder, &mut playlists);
+    }
+    for playlist in &playlists {
+        let Ok(text) = fs::read_to_string(playlist) else {
+            continue;
+        };
+        for entry in dangling_entries(playlist, &text) {
+            let playlist = playlist.clone();
+            report(Problem::Dangling { playlist, entry }, &mut problems);
+        }
+    }
+
+    eprint!("\r\x1b[2K");
+    eprintln!(
+        "{}",
+        t_args(
+            "check.checked",
+            &[("tracks", &tracks.len()), ("entries", &entries.len()), ("playlists", &playlists.len())]
+        )
+    );
+    if problems.is_empty() {
+        eprintln!("{}", t("check.clean"));
+        return Ok(());
+    }
+    Err(MusixError::ProblemsFound(problems.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::WavWriter;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_track_and_playlist_problems() {
+        let dir = TestDir::new("check");
+        fs::create_dir_all(dir.join("album")).unwrap();
+        let good = dir.join("album/good.wav");
+        let mut writer = WavWriter::new(fs::File::create(&good).unwrap(), 1, 8000).unwrap();
+        writer.write_samples(&[0; 800]).unwrap();
+        writer.finish().unwrap();
+        fs::write(dir.join("empty.mp3"), b"").unwrap();
+        fs::write(dir.join("corrupt.flac"), b"not a flac file at all").unwrap();
+
+        assert_eq!(check_track(&good), None);
+        assert_eq!(check_track(&dir.join("empty.mp3")), Some(Problem::Empty(dir.join("empty.mp3"))));
+        assert!(matches!(check_track(&dir.join("corrupt.flac")), Some(Problem::Corrupt(..))));
+        assert_eq!(check_track(&dir.join("gone.mp3")), Some(Problem::Missing(dir.join("gone.mp3"))));
+
+        let playlist = dir.join("mix.m3u8");
+        let text = "\u{feff}#EXTM3U\n#EXTINF:1,Good\nalbum/good.wav\n\nalbum/gone.wav\nhttp://radio.invalid/live\n";
+        fs::write(&playlist, text).unwrap();
+        assert_eq!(dangling_entries(&playlist, text), vec![dir.join("album/gone.wav")]);
+        let mut playlists = Vec::new();
+        playlist_files(&dir, &mut playlists);
+        assert_eq!(playlists, vec![playlist.clone()]);
+
+        let problem = Problem::Dangling {
+            playlist,
+            entry: dir.join("album/gone.wav"),
+        };
+        assert!(problem.line().starts_with("playlist\t"));
+    }
+}
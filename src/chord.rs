@@ -0,0 +1,151 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+// Counts beyond this are typos rather than intent
+const MAX_COUNT: u32 = 9999;
+
+/// A vim-style key sequence in progress: an optional count (`5j`), then a prefix key waiting for the key that
+/// completes the chord (`gg`, `ma`, `'a`, `d3`).
+#[derive(Default, Debug, PartialEq)]
+pub struct Chords {
+    count: Option<u32>,
+    prefix: Option<char>,
+}
+
+/// What one key press amounts to.
+#[derive(Debug, PartialEq)]
+pub enum Step {
+    // Part of a longer sequence; nothing to do yet
+    Pending,
+    // `prefix` followed by `key`; unknown combinations are for the caller to ignore
+    Chord { count: Option<u32>, prefix: char, key: char },
+    // An ordinary key, with the count typed before it
+    Key { count: Option<u32>, key: KeyEvent },
+    // A chord broken off by Esc or another key that can't complete it
+    Cancelled,
+}
+
+impl Chords {
+    /// Feed a key. `prefixes` are the keys that start a chord in the current context; digits are only
+    /// collected as counts when `counts` is set, so contexts that bind them (hot cues) keep them.
+    pub fn feed(&mut self, key: KeyEvent, prefixes: &[char], counts: bool) -> Step {
+        // Ctrl and Alt combinations always act at once, dropping whatever was typed before
+        if key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) {
+            self.reset();
+            return Step::Key { count: None, key };
+        }
+        let (count, prefix) = (self.count.take(), self.prefix.take());
+        let KeyCode::Char(c) = key.code else {
+            return if prefix.is_some() || (count.is_some() && key.code == KeyCode::Esc) {
+                Step::Cancelled
+            } else {
+                Step::Key { count, key }
+            };
+        };
+
+        if let Some(prefix) = prefix {
+            return Step::Chord { count, prefix, key: c };
+        }
+        if counts && c.is_ascii_digit() && (c != '0' || count.is_some()) {
+            let digit = c.to_digit(10).unwrap_or_default();
+            self.count = Some((count.unwrap_or(0) * 10 + digit).min(MAX_COUNT));
+            return Step::Pending;
+        }
+        if prefixes.contains(&c) {
+            self.count = count;
+            self.prefix = Some(c);
+            return Step::Pending;
+        }
+        Step::Key { count, key }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.count.is_some() || self.prefix.is_some()
+    }
+
+    /// The keys typed so far, e.g. `5g`, for the status bar.
+    pub fn pending(&self) -> Option<String> {
+        self.is_pending().then(|| {
+            let count = self.count.map(|count| count.to_string()).unwrap_or_default();
+            format!("{count}{}", self.prefix.map(String::from).unwrap_or_default())
+        })
+    }
+
+    pub fn prefix(&self) -> Option<char> {
+        self.prefix
+    }
+
+    pub fn reset(&mut self) {
+        *self = Chords::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_counts_and_chords() {
+        let mut chords = Chords::default();
+        let prefixes = ['g', 'm'];
+
+        assert_eq!(chords.feed(press('g'), &prefixes, true), Step::Pending);
+        assert_eq!(chords.pending().as_deref(), Some("g"));
+        assert_eq!(
+            chords.feed(press('g'), &prefixes, true),
+            Step::Chord {
+                count: None,
+                prefix: 'g',
+                key: 'g'
+            }
+        );
+        assert!(!chords.is_pending());
+
+        assert_eq!(chords.feed(press('1'), &prefixes, true), Step::Pending);
+        assert_eq!(chords.feed(press('0'), &prefixes, true), Step::Pending);
+        assert_eq!(chords.pending().as_deref(), Some("10"));
+        assert_eq!(
+            chords.feed(press('j'), &prefixes, true),
+            Step::Key {
+                count: Some(10),
+                key: press('j')
+            }
+        );
+
+        // A lone 0 is a key, not a count, and digits stay keys where counts are off
+        assert_eq!(chords.feed(press('0'), &prefixes, true), Step::Key { count: None, key: press('0') });
+        assert_eq!(chords.feed(press('3'), &['d'], false), Step::Key { count: None, key: press('3') });
+        chords.feed(press('d'), &['d'], false);
+        assert_eq!(
+            chords.feed(press('3'), &['d'], false),
+            Step::Chord {
+                count: None,
+                prefix: 'd',
+                key: '3'
+            }
+        );
+    }
+
+    #[test]
+    fn test_other_keys_break_sequences() {
+        let mut chords = Chords::default();
+        chords.feed(press('m'), &['m'], true);
+        assert_eq!(chords.feed(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE), &['m'], true), Step::Cancelled);
+        assert!(!chords.is_pending());
+
+        // Ctrl+c still quits in the middle of a chord
+        chords.feed(press('m'), &['m'], true);
+        let quit = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(chords.feed(quit, &['m'], true), Step::Key { count: None, key: quit });
+        assert!(!chords.is_pending());
+
+        // Arrow keys take the count along
+        chords.feed(press('4'), &[], true);
+        let down = KeyEvent::new(KeyCode::Down, KeyModifiers::NONE);
+        assert_eq!(chords.feed(down, &[], true), Step::Key { count: Some(4), key: down });
+        assert_eq!(chords.feed(down, &[], true), Step::Key { count: None, key: down });
+    }
+}
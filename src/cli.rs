@@ -0,0 +1,156 @@
+use std::{io, path::PathBuf};
+
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+
+use crate::{convert::ConvertOptions, events::LogCommand, i18n::t, podcast::PodcastCommand, rgscan::RgscanOptions};
+
+// Help texts come from the locale files, so they are set as attributes rather than doc comments
+#[derive(Parser, Debug, PartialEq)]
+#[command(name = "musix", version, about = t("cli.about"), args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[arg(help = t("cli.file"))]
+    pub file: Option<PathBuf>,
+    #[arg(long, help = t("cli.kiosk"))]
+    pub kiosk: bool,
+    #[arg(long, help = t("cli.ascii"))]
+    pub ascii: bool,
+    #[arg(long, global = true, help = t("cli.portable"))]
+    pub portable: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+pub enum Command {
+    #[command(about = t("cli.convert"))]
+    Convert(ConvertOptions),
+    #[command(about = t("cli.rgscan"))]
+    Rgscan(RgscanOptions),
+    #[command(about = t("cli.check"))]
+    Check,
+    #[command(about = t("cli.relocate"))]
+    Relocate {
+        #[arg(help = t("cli.relocate_old"))]
+        old: PathBuf,
+        #[arg(help = t("cli.relocate_new"))]
+        new: PathBuf,
+    },
+    #[command(about = t("cli.now_playing"))]
+    NowPlaying {
+        #[arg(long, help = t("cli.now_playing_format"))]
+        format: Option<String>,
+    },
+    #[command(about = t("cli.podcast"), subcommand)]
+    Podcast(PodcastCommand),
+    #[command(about = t("cli.log"), subcommand)]
+    Log(LogCommand),
+    #[command(about = t("cli.completions"))]
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+}
+
+/// Print the completion script for `shell`, e.g. `musix completions zsh > ~/.zfunc/_musix`.
+pub fn print_completions(shell: Shell) {
+    clap_complete::generate(shell, &mut Cli::command(), "musix", &mut io::stdout());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+
+        let player = Cli::try_parse_from(["musix", "--kiosk"]).unwrap();
+        assert!(player.kiosk && player.command.is_none());
+
+        let rgscan = Cli::try_parse_from(["musix", "rgscan", "--force", "jazz"]).unwrap();
+        assert_eq!(
+            rgscan.command,
+            Some(Command::Rgscan(RgscanOptions {
+                force: true,
+                selection: vec!["jazz".to_string()],
+            }))
+        );
+        assert!(matches!(
+            Cli::try_parse_from(["musix", "podcast", "export"]).unwrap().command,
+            Some(Command::Podcast(PodcastCommand::Export { file: None }))
+        ));
+        assert!(Cli::try_parse_from(["musix", "podcast", "import"]).is_err());
+        assert!(Cli::try_parse_from(["musix", "completions", "tcsh"]).is_err());
+
+        let mut script = Vec::new();
+        clap_complete::generate(Shell::Zsh, &mut Cli::command(), "musix", &mut script);
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("rgscan") && script.contains("--bitrate"));
+    }
+
+    #[test]
+    fn test_portable_flag() {
+        assert!(Cli::try_parse_from(["musix", "podcast", "list", "--portable"]).unwrap().portable);
+    }
+
+    #[test]
+    fn test_ascii_flag() {
+        assert!(Cli::try_parse_from(["musix", "--ascii"]).unwrap().ascii);
+        assert!(!Cli::try_parse_from(["musix", "--kiosk"]).unwrap().ascii);
+    }
+
+    #[test]
+    fn test_file_argument() {
+        assert_eq!(Cli::try_parse_from(["musix", "song.mp3"]).unwrap().file, Some(PathBuf::from("song.mp3")));
+        assert!(Cli::try_parse_from(["musix", "song.mp3", "convert"]).is_err());
+    }
+
+    #[test]
+    fn test_log_tail_command() {
+        assert_eq!(
+            Cli::try_parse_from(["musix", "log", "tail", "-n", "50"]).unwrap().command,
+            Some(Command::Log(LogCommand::Tail { lines: 50 }))
+        );
+    }
+
+    #[test]
+    fn test_check_command() {
+        assert_eq!(Cli::try_parse_from(["musix", "check"]).unwrap().command, Some(Command::Check));
+    }
+
+    #[test]
+    fn test_relocate_command() {
+        assert_eq!(
+            Cli::try_parse_from(["musix", "relocate", "/home/me/Music", "/mnt/nas/music"]).unwrap().command,
+            Some(Command::Relocate {
+                old: PathBuf::from("/home/me/Music"),
+                new: PathBuf::from("/mnt/nas/music"),
+            })
+        );
+        assert!(Cli::try_parse_from(["musix", "relocate", "/home/me/Music"]).is_err());
+    }
+
+    #[test]
+    fn test_log_setlist_command() {
+        assert_eq!(
+            Cli::try_parse_from(["musix", "log", "setlist", "party.m3u"]).unwrap().command,
+            Some(Command::Log(LogCommand::Setlist {
+                file: PathBuf::from("party.m3u"),
+                hours: 12,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_now_playing_command() {
+        assert_eq!(
+            Cli::try_parse_from(["musix", "now-playing", "--format", "#{artist} - #{title}"])
+                .unwrap()
+                .command,
+            Some(Command::NowPlaying {
+                format: Some("#{artist} - #{title}".to_string()),
+            })
+        );
+    }
+}
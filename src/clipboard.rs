@@ -0,0 +1,55 @@
+use std::{
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+// Clipboard tools tried in turn; the first that runs gets the text on its stdin
+#[cfg(target_os = "macos")]
+const COMMANDS: &[&[&str]] = &[&["pbcopy"]];
+#[cfg(windows)]
+const COMMANDS: &[&[&str]] = &[&["clip.exe"]];
+#[cfg(not(any(target_os = "macos", windows)))]
+const COMMANDS: &[&[&str]] = &[&["wl-copy"], &["xclip", "-selection", "clipboard"], &["xsel", "--clipboard", "--input"]];
+
+/// Put `text` on the system clipboard with the platform's own tool, or when there is none (or no display, as over
+/// SSH) ask the terminal to with OSC 52.
+pub fn copy(text: &str) -> io::Result<()> {
+    if COMMANDS.iter().any(|command| copy_with(command, text)) {
+        return Ok(());
+    }
+    let mut out = io::stdout();
+    out.write_all(osc52(text).as_bytes())?;
+    out.flush()
+}
+
+fn copy_with(command: &[&str], text: &str) -> bool {
+    let Ok(mut child) = Command::new(command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    else {
+        return false;
+    };
+    let written = child.stdin.take().is_some_and(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+    child.wait().is_ok_and(|status| status.success()) && written
+}
+
+// Terminals that support it (and tmux with `set-clipboard on`) copy the base64 payload to the clipboard
+fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", STANDARD.encode(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_osc52_carries_the_text_as_base64() {
+        assert_eq!(osc52("Daft Punk – Voyager"), "\x1b]52;c;RGFmdCBQdW5rIOKAkyBWb3lhZ2Vy\x07");
+        assert!(!copy_with(&["musix-no-such-clipboard-tool"], "text"));
+    }
+}
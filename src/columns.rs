@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::t;
+
+/// A field the song list can show as a column, and sort by.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Column {
+    // Position in the list
+    Number,
+    // Track number from the tags
+    Track,
+    // Title tag, or the file name for untagged files
+    Title,
+    Artist,
+    Album,
+    Duration,
+    Rating,
+}
+
+impl Column {
+    pub fn header(self) -> &'static str {
+        match self {
+            Column::Number => t("column.number"),
+            Column::Track => t("column.track"),
+            Column::Title => t("column.title"),
+            Column::Artist => t("column.artist"),
+            Column::Album => t("column.album"),
+            Column::Duration => t("column.duration"),
+            Column::Rating => t("column.rating"),
+        }
+    }
+
+    // Numbers line up on the right
+    pub fn right_aligned(self) -> bool {
+        matches!(self, Column::Number | Column::Track | Column::Duration)
+    }
+
+    // The list position changes with every sort, so there is nothing to sort by
+    pub fn sortable(self) -> bool {
+        self != Column::Number
+    }
+
+    // Cells for columns of short values; text columns share what is left of the row
+    fn fixed_width(self) -> Option<u16> {
+        match self {
+            Column::Number => Some(5),
+            Column::Track => Some(3),
+            Column::Duration => Some(5),
+            Column::Rating => Some(5),
+            Column::Title | Column::Artist | Column::Album => None,
+        }
+    }
+
+    // Share of the leftover width; titles tend to be the longest
+    fn weight(self) -> u16 {
+        if self == Column::Title { 2 } else { 1 }
+    }
+}
+
+/// An entry of `[ui] columns`: a bare name (`"artist"`) or a table with a fixed width (`{ column = "artist", width = 20 }`).
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(from = "ColumnSpec")]
+pub struct ColumnConfig {
+    pub column: Column,
+    pub width: Option<u16>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColumnSpec {
+    Name(Column),
+    Sized { column: Column, width: Option<u16> },
+}
+
+impl From<ColumnSpec> for ColumnConfig {
+    fn from(spec: ColumnSpec) -> Self {
+        match spec {
+            ColumnSpec::Name(column) => ColumnConfig { column, width: None },
+            ColumnSpec::Sized { column, width } => ColumnConfig { column, width },
+        }
+    }
+}
+
+/// The width of each column in a row `total` cells wide, leaving one space between columns.
+/// Columns without a fixed width split the remainder by weight.
+pub fn widths(columns: &[ColumnConfig], total: u16) -> Vec<u16> {
+    let gaps = columns.len().saturating_sub(1) as u16;
+    let fixed = |config: &ColumnConfig| config.width.or(config.column.fixed_width());
+    let used: u16 = columns.iter().filter_map(fixed).sum();
+    let weights: u16 = columns
+        .iter()
+        .filter(|config| fixed(config).is_none())
+        .map(|config| config.column.weight())
+        .sum();
+    let mut rest = total.saturating_sub(used + gaps);
+    let mut weights_left = weights;
+    columns
+        .iter()
+        .map(|config| {
+            fixed(config).unwrap_or_else(|| {
+                // The last flexible column takes what rounding left over
+                let share = rest * config.column.weight() / weights_left.max(1);
+                rest -= share;
+                weights_left -= config.column.weight();
+                share
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Ui {
+        columns: Vec<ColumnConfig>,
+    }
+
+    #[test]
+    fn test_column_widths() {
+        let ui: Ui = toml::from_str(r#"columns = ["number", "title", { column = "artist", width = 12 }, "album", "duration"]"#).unwrap();
+        assert_eq!(
+            ui.columns[2],
+            ColumnConfig {
+                column: Column::Artist,
+                width: Some(12)
+            }
+        );
+
+        // 80 - 5 - 12 - 5 - 4 gaps = 54, two thirds for the title
+        let widths = widths(&ui.columns, 80);
+        assert_eq!(widths, vec![5, 36, 12, 18, 5]);
+        assert_eq!(widths.iter().sum::<u16>() + 4, 80);
+
+        // Too narrow: flexible columns shrink to nothing rather than overflow
+        assert_eq!(super::widths(&ui.columns, 20), vec![5, 0, 12, 0, 5]);
+        assert!(toml::from_str::<Ui>(r#"columns = ["composer"]"#).is_err());
+    }
+}
@@ -0,0 +1,653 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use ratatui::{layout::Alignment, widgets::BorderType};
+use serde::Deserialize;
+
+use crate::{
+    alert::Alert,
+    announce,
+    auto_skip::AutoSkipRule,
+    columns::ColumnConfig,
+    error::MusixError,
+    icons::IconStyle,
+    nowplaying,
+    paths::{self, PORTABLE_CONFIG_FILE, config_dir, expand_home, file_in, portable_dir},
+    replaygain,
+    row_colors::RowColorRule,
+    share,
+    term_title::TitleMode,
+};
+
+const CONFIG_FILE: &str = "config.toml";
+
+/// User settings from `config.toml` in the config folder (`~/.config/musix`); every field has a default so the file is optional.
+#[derive(Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    pub shuffle: ShuffleConfig,
+    pub scrobble: ScrobbleConfig,
+    pub sync: SyncConfig,
+    pub kiosk: KioskConfig,
+    pub now_playing: NowPlayingConfig,
+    pub session: SessionConfig,
+    pub ui: UiConfig,
+    pub search: SearchConfig,
+    pub row_colors: Vec<RowColorRule>,
+    pub auto_skip: Vec<AutoSkipRule>,
+    pub preview: PreviewConfig,
+    pub inbox: InboxConfig,
+    pub library: LibraryConfig,
+    pub playback: PlaybackConfig,
+    pub share: ShareConfig,
+    pub announce: AnnounceConfig,
+    pub equalizer: EqualizerConfig,
+    pub alerts: AlertsConfig,
+}
+
+/// Weights for the weighted shuffle mode.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ShuffleConfig {
+    // How strongly star ratings tilt the odds (0 ignores ratings)
+    pub rating_weight: f64,
+    // How strongly recently played tracks are held back (0 ignores play history)
+    pub recency_weight: f64,
+    // Tracks last played longer ago than this count as completely fresh
+    pub recency_days: f64,
+    // Shuffle skips artists heard within this many plays (0 allows back-to-back)
+    pub artist_spread: usize,
+    // A skip before this much of a track (in percent) has played counts as an early skip
+    pub early_skip_percent: f64,
+    // Tracks skipped early this many times are left out of shuffle until re-enabled with `D` (0 never leaves any out)
+    pub demote_after: u32,
+}
+
+impl Default for ShuffleConfig {
+    fn default() -> Self {
+        ShuffleConfig {
+            rating_weight: 1.5,
+            recency_weight: 1.0,
+            recency_days: 7.0,
+            artist_spread: 1,
+            early_skip_percent: 25.0,
+            demote_after: 0,
+        }
+    }
+}
+
+/// ListenBrainz scrobbling; enabled by setting a user token.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ScrobbleConfig {
+    pub listenbrainz_token: Option<String>,
+    // Another ListenBrainz-compatible server can be used instead
+    pub api_url: String,
+}
+
+impl Default for ScrobbleConfig {
+    fn default() -> Self {
+        ScrobbleConfig {
+            listenbrainz_token: None,
+            api_url: "https://api.listenbrainz.org".to_string(),
+        }
+    }
+}
+
+/// Cross-device resume; enabled by pointing `file` into a synced folder.
+#[derive(Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct SyncConfig {
+    pub file: Option<String>,
+}
+
+impl SyncConfig {
+    pub fn path(&self) -> Option<PathBuf> {
+        self.file.as_deref().filter(|file| !file.trim().is_empty()).map(expand_home)
+    }
+}
+
+/// Locked mode for a shared player.
+#[derive(Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct KioskConfig {
+    // Needed to unlock; without one `Ctrl+l` can't lock and `--kiosk` stays locked for the session
+    pub pin: Option<String>,
+}
+
+/// Now-playing output for desktop status bars; enabled by setting `file`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct NowPlayingConfig {
+    // A regular file is replaced on every change; a named pipe gets one line per change
+    pub file: Option<String>,
+    pub format: nowplaying::Format,
+    // Used by the plain format and for waybar's text
+    pub template: String,
+}
+
+impl Default for NowPlayingConfig {
+    fn default() -> Self {
+        NowPlayingConfig {
+            file: None,
+            format: nowplaying::Format::Plain,
+            template: "{icon} {title}".to_string(),
+        }
+    }
+}
+
+impl NowPlayingConfig {
+    pub fn path(&self) -> Option<PathBuf> {
+        self.file.as_deref().filter(|file| !file.trim().is_empty()).map(expand_home)
+    }
+}
+
+/// The preview channel, for listening to a track before it goes out.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct PreviewConfig {
+    // 0.0 to 1.0, relative to the main output
+    pub volume: f32,
+    // Part of an output device name, e.g. headphones on a second sound card; empty plays beside the main track
+    pub device: Option<String>,
+    // Preview on select: a snippet of the track the selection rests on for a second, from `snippet_at` percent in
+    pub on_select: bool,
+    pub snippet_at: f64,
+    // Seconds
+    pub snippet_length: f64,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        PreviewConfig {
+            volume: 0.3,
+            device: None,
+            on_select: false,
+            snippet_at: 30.0,
+            snippet_length: 10.0,
+        }
+    }
+}
+
+/// A folder watched for new tracks, which are queued to play next; enabled by setting `folder`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct InboxConfig {
+    pub folder: Option<String>,
+    // Seconds between looks at the folder
+    pub interval: u64,
+}
+
+impl Default for InboxConfig {
+    fn default() -> Self {
+        InboxConfig { folder: None, interval: 5 }
+    }
+}
+
+impl InboxConfig {
+    pub fn path(&self) -> Option<PathBuf> {
+        self.folder.as_deref().filter(|folder| !folder.trim().is_empty()).map(expand_home)
+    }
+}
+
+/// Where the music is.
+#[derive(Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct LibraryConfig {
+    // Folders scanned at startup; empty scans the platform Music folder and `./data`
+    pub folders: Vec<String>,
+}
+
+impl LibraryConfig {
+    /// The folders to scan, in order of preference.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        if self.folders.is_empty() {
+            return vec![paths::music_dir(), PathBuf::from("./data")];
+        }
+        self.folders.iter().map(|folder| expand_home(folder)).collect()
+    }
+}
+
+/// How one track hands over to the next.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct PlaybackConfig {
+    // Seconds the end of a track overlaps the start of the next; 0 leaves a short gap between them
+    pub crossfade: f64,
+    // The next track on the same album follows without a gap or a crossfade, for live albums and DJ mixes
+    pub gapless_albums: bool,
+    // Rate the output runs at, every track converted to it; leave out for the device's own rate
+    pub sample_rate: Option<u32>,
+    // Next, previous and shuffle never pick the track that just ended, and a quick second Enter is not taken as
+    // another press on the track the first one started
+    pub avoid_repeats: bool,
+    // Seconds of audio decoded ahead on a thread of its own, so slow reads and busy disks don't cause dropouts;
+    // 0 decodes in the output callback as it plays
+    pub decode_ahead: f64,
+    // Level tracks by their ReplayGain tags: "track", "album" or "off"
+    pub replaygain: replaygain::Mode,
+    // dB added to the ReplayGain level, for tracks scanned against a quieter or louder reference
+    pub replaygain_preamp: f64,
+}
+
+impl Default for PlaybackConfig {
+    fn default() -> Self {
+        PlaybackConfig {
+            crossfade: 0.0,
+            gapless_albums: true,
+            sample_rate: None,
+            avoid_repeats: true,
+            decode_ahead: 2.0,
+            replaygain: replaygain::Mode::Track,
+            replaygain_preamp: 0.0,
+        }
+    }
+}
+
+/// Where `:share` and `:browse` look a track up.
+#[derive(Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ShareConfig {
+    // Used when the command names no service
+    pub service: share::Service,
+}
+
+/// Plain announcements of each new track for screen readers and braille displays; enabled by setting `to`, and
+/// spoken with `speak` in builds with the `tts` feature.
+#[derive(Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct AnnounceConfig {
+    // "stderr", or a file the lines are appended to
+    pub to: Option<String>,
+    pub speak: bool,
+    // A synthesizer that reads the text from stdin, e.g. ["espeak-ng", "--stdin", "-v", "en-gb"]; empty for the
+    // platform's own
+    pub speech_command: Vec<String>,
+}
+
+impl AnnounceConfig {
+    pub fn target(&self) -> Option<announce::Target> {
+        self.to.as_deref().and_then(announce::Target::parse)
+    }
+}
+
+/// The graphic equalizer, as last left in its panel.
+#[derive(Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct EqualizerConfig {
+    pub enabled: bool,
+    // dB for each band from 31 Hz to 16 kHz; missing bands are flat
+    pub bands: Vec<f32>,
+}
+
+impl EqualizerConfig {
+    pub fn gains(&self) -> [f32; 10] {
+        std::array::from_fn(|band| self.bands.get(band).copied().filter(|gain| gain.is_finite()).unwrap_or(0.0))
+    }
+}
+
+/// A bell or a flash of the status bar on events worth hearing about with the player in a background pane; each
+/// event is `off` (the default), `bell`, `flash` or `both`.
+#[derive(Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct AlertsConfig {
+    // The next track started on its own, after one ended
+    pub track_change: Alert,
+    // A track ended and nothing plays after it
+    pub queue_empty: Alert,
+    // The station playing stopped sending
+    pub stream_dropped: Alert,
+}
+
+/// Crash recovery snapshots of the playing session.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct SessionConfig {
+    // Seconds between snapshots; 0 turns them off
+    pub snapshot_interval: u64,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig { snapshot_interval: 10 }
+    }
+}
+
+/// Starting points for the UI timing; `tick_ms` and `redraw_ms` override single values.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UiPreset {
+    #[default]
+    Default,
+    // Fewer wakeups on laptops: input is still handled at once, but the progress only moves every second
+    Battery,
+    // About 60 frames per second for fast terminals
+    Smooth,
+}
+
+/// Frame drawn around the panels.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BorderStyle {
+    #[default]
+    Plain,
+    Rounded,
+    Thick,
+    Double,
+    // No frames on the main screen; popups still get a plain one
+    None,
+}
+
+impl BorderStyle {
+    pub fn border_type(self) -> BorderType {
+        match self {
+            BorderStyle::Plain | BorderStyle::None => BorderType::Plain,
+            BorderStyle::Rounded => BorderType::Rounded,
+            BorderStyle::Thick => BorderType::Thick,
+            BorderStyle::Double => BorderType::Double,
+        }
+    }
+}
+
+/// Where panel titles sit on the top border.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TitleAlignment {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+impl TitleAlignment {
+    pub fn alignment(self) -> Alignment {
+        match self {
+            TitleAlignment::Left => Alignment::Left,
+            TitleAlignment::Center => Alignment::Center,
+            TitleAlignment::Right => Alignment::Right,
+        }
+    }
+}
+
+/// What it takes to quit with `q` or Esc while something is playing; Ctrl+C always quits at once.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum QuitConfirm {
+    Off,
+    // Press the key a second time within a second
+    #[default]
+    Double,
+    // Answer a yes/no prompt
+    Prompt,
+}
+
+/// Look of the interface, and how often the event loop wakes up and redraws while nothing happens.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct UiConfig {
+    pub preset: UiPreset,
+    pub tick_ms: Option<u64>,
+    pub redraw_ms: Option<u64>,
+    pub icons: IconStyle,
+    // Album art in the details panel, drawn with colored half blocks
+    pub cover_art: bool,
+    pub border: BorderStyle,
+    pub title_alignment: TitleAlignment,
+    // The "MUSIX" banner at the top and the status bar at the bottom
+    pub show_title: bool,
+    pub show_status: bool,
+    // List columns in order; empty keeps the plain "1. name" rows
+    pub columns: Vec<ColumnConfig>,
+    // Track lengths at the right end of plain rows
+    pub durations: bool,
+    pub terminal_title: TitleMode,
+    pub quit_confirm: QuitConfirm,
+    // Minutes without a key press before the idle screen; 0 never idles
+    pub idle_minutes: u64,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        UiConfig {
+            preset: UiPreset::default(),
+            tick_ms: None,
+            redraw_ms: None,
+            icons: IconStyle::default(),
+            cover_art: true,
+            border: BorderStyle::default(),
+            title_alignment: TitleAlignment::default(),
+            show_title: true,
+            show_status: true,
+            columns: Vec::new(),
+            durations: true,
+            terminal_title: TitleMode::default(),
+            quit_confirm: QuitConfirm::default(),
+            idle_minutes: 0,
+        }
+    }
+}
+
+impl UiConfig {
+    // (tick, redraw) in milliseconds
+    fn preset_rates(&self) -> (u64, u64) {
+        match self.preset {
+            UiPreset::Default => (100, 100),
+            UiPreset::Battery => (500, 1000),
+            UiPreset::Smooth => (16, 16),
+        }
+    }
+
+    /// How long to wait for input before the loop runs its periodic work.
+    pub fn tick_rate(&self) -> Duration {
+        Duration::from_millis(self.tick_ms.unwrap_or(self.preset_rates().0).max(1))
+    }
+
+    /// Longest time between frames when no input arrives; input always redraws right away.
+    pub fn redraw_interval(&self) -> Duration {
+        Duration::from_millis(self.redraw_ms.unwrap_or(self.preset_rates().1))
+    }
+}
+
+/// What typing a search does to the list.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    // Only the matches are listed, best first
+    #[default]
+    Filter,
+    // The full list stays, and the cursor jumps to the next match in list order, like `/` in less or vim
+    Jump,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct SearchConfig {
+    pub mode: SearchMode,
+    // Esc puts the cursor back where it was before the search; Enter still plays the match
+    pub restore_on_esc: bool,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            mode: SearchMode::default(),
+            restore_on_esc: true,
+        }
+    }
+}
+
+pub fn config_path() -> PathBuf {
+    match portable_dir() {
+        Some(dir) => dir.join(PORTABLE_CONFIG_FILE),
+        None => file_in(config_dir(), CONFIG_FILE),
+    }
+}
+
+/// Write the library folders into the config file at `path`, leaving the rest of it, comments included, as it was.
+pub fn save_library_folders(path: &Path, folders: &[String]) -> Result<(), MusixError> {
+    edit(path, |document| {
+        document["library"]["folders"] = toml_edit::value(folders.iter().collect::<toml_edit::Array>());
+    })
+}
+
+/// Write `[playback] crossfade` into the config file at `path`, the same way.
+pub fn save_crossfade(path: &Path, seconds: f64) -> Result<(), MusixError> {
+    edit(path, |document| document["playback"]["crossfade"] = toml_edit::value(seconds))
+}
+
+/// Write `[equalizer]` into the config file at `path`, the same way.
+pub fn save_equalizer(path: &Path, enabled: bool, gains: &[f32; 10]) -> Result<(), MusixError> {
+    edit(path, |document| {
+        document["equalizer"]["enabled"] = toml_edit::value(enabled);
+        document["equalizer"]["bands"] = toml_edit::value(gains.iter().map(|&gain| gain as f64).collect::<toml_edit::Array>());
+    })
+}
+
+fn edit(path: &Path, change: impl FnOnce(&mut toml_edit::DocumentMut)) -> Result<(), MusixError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut document: toml_edit::DocumentMut = text.parse().map_err(|e| MusixError::Config(format!("{}: {e}", path.display())))?;
+    change(&mut document);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, document.to_string())?;
+    Ok(())
+}
+
+impl Config {
+    /// Load the config file, falling back to defaults when it is missing.
+    pub fn load() -> Result<Self, MusixError> {
+        Self::load_from(&config_path())
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self, MusixError> {
+        match fs::read_to_string(path) {
+            Ok(text) => toml::from_str(&text).map_err(|e| MusixError::Config(format!("{}: {e}", path.display()))),
+            Err(_) => Ok(Config::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_partial_config_uses_defaults() {
+        let config: Config = toml::from_str("[shuffle]\nrating_weight = 3.0\n").unwrap();
+        assert_eq!(config.shuffle.rating_weight, 3.0);
+        assert_eq!(config.shuffle.recency_days, ShuffleConfig::default().recency_days);
+
+        let empty: Config = toml::from_str("").unwrap();
+        assert_eq!(empty, Config::default());
+        assert!(toml::from_str::<Config>("[shuffle]\nrating_weight = \"high\"\n").is_err());
+    }
+
+    #[test]
+    fn test_sync_is_off_by_default() {
+        assert_eq!(Config::default().sync.path(), None);
+    }
+
+    #[test]
+    fn test_now_playing_format_keeps_the_default_template() {
+        let waybar: Config = toml::from_str("[now_playing]\nfile = \"/tmp/np\"\nformat = \"waybar\"\n").unwrap();
+        assert_eq!(waybar.now_playing.format, nowplaying::Format::Waybar);
+        assert_eq!(waybar.now_playing.template, NowPlayingConfig::default().template);
+    }
+
+    #[test]
+    fn test_ui_tick_rate_and_presets() {
+        assert_eq!(Config::default().ui.tick_rate(), Duration::from_millis(100));
+        let battery: Config = toml::from_str("[ui]\npreset = \"battery\"\ntick_ms = 250\n").unwrap();
+        assert_eq!(battery.ui.tick_rate(), Duration::from_millis(250));
+        assert_eq!(battery.ui.redraw_interval(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_share_service() {
+        let share: Config = toml::from_str("[share]\nservice = \"youtube\"\n").unwrap();
+        assert_eq!(share.share.service, share::Service::YouTube);
+        assert_eq!(Config::default().share.service, share::Service::MusicBrainz);
+    }
+
+    #[test]
+    fn test_replaygain_mode() {
+        let album: Config = toml::from_str("[playback]\nreplaygain = \"album\"\n").unwrap();
+        assert_eq!(album.playback.replaygain, replaygain::Mode::Album);
+        assert_eq!(Config::default().playback.replaygain, replaygain::Mode::Track);
+    }
+
+    #[test]
+    fn test_alerts_are_off_unless_set() {
+        let alerts: Config = toml::from_str("[alerts]\nqueue_empty = \"bell\"\nstream_dropped = \"both\"\n").unwrap();
+        assert_eq!(alerts.alerts.queue_empty, Alert::Bell);
+        assert!(alerts.alerts.stream_dropped.rings() && alerts.alerts.stream_dropped.flashes());
+        assert_eq!(alerts.alerts.track_change, Alert::Off);
+    }
+
+    #[test]
+    fn test_library_folders_are_written_into_the_config() {
+        let dir = TestDir::new("config");
+        let path = dir.join("config.toml");
+        fs::write(&path, "# my settings\n[ui]\nborder = \"rounded\"  # the nice one\n").unwrap();
+        let folders = vec!["~/Music".to_string(), "/mnt/nas/music".to_string()];
+        save_library_folders(&path, &folders).unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("# my settings") && text.contains("# the nice one"));
+        let config: Config = toml::from_str(&text).unwrap();
+        assert_eq!(config.library.folders, folders);
+        assert_eq!(config.ui.border, BorderStyle::Rounded);
+        assert_eq!(config.library.paths()[1], PathBuf::from("/mnt/nas/music"));
+
+        fs::remove_file(&path).unwrap();
+        save_library_folders(&path, &folders[..1]).unwrap();
+        let config: Config = toml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(config.library.folders, folders[..1]);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(LibraryConfig::default().paths().len(), 2);
+    }
+
+    #[test]
+    fn test_crossfade_is_written_into_the_config() {
+        let dir = TestDir::new("config-crossfade");
+        let path = dir.join("config-crossfade.toml");
+        fs::write(
+            &path,
+            "[playback]
+gapless_albums = false  # DJ mixes
+",
+        )
+        .unwrap();
+        save_crossfade(&path, 6.0).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("# DJ mixes"));
+        let config: Config = toml::from_str(&text).unwrap();
+        assert_eq!(config.playback.crossfade, 6.0);
+        assert!(!config.playback.gapless_albums);
+    }
+
+    #[test]
+    fn test_equalizer_is_written_into_the_config() {
+        let dir = TestDir::new("config-equalizer");
+        let path = dir.join("config-equalizer.toml");
+        fs::write(&path, "[playback]\ncrossfade = 6.0  # between tracks\n").unwrap();
+        let mut gains = [0.0; 10];
+        gains[0] = 6.0;
+        save_equalizer(&path, true, &gains).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("# between tracks"));
+        let config: Config = toml::from_str(&text).unwrap();
+        assert!(config.equalizer.enabled);
+        assert_eq!(config.equalizer.gains(), gains);
+        assert_eq!(config.playback.crossfade, 6.0);
+    }
+}
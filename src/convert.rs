@@ -0,0 +1,371 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::{Args, ValueEnum};
+use ogg::{PacketWriteEndInfo, PacketWriter};
+use rodio::{Source, source::UniformSourceIterator};
+
+use crate::{
+    create_audio_source,
+    error::MusixError,
+    get_audio_duration,
+    i18n::{t, t_args},
+    recording, resolve_selection,
+};
+
+const DEFAULT_BITRATE_KBPS: u32 = 128;
+const DEFAULT_OUT_DIR: &str = "musix-export";
+
+// Opus always runs at 48kHz; 20ms frames are the usual choice for music
+const OPUS_SAMPLE_RATE: u32 = 48000;
+const OPUS_FRAME_SAMPLES: usize = 960;
+const OPUS_MAX_PACKET: usize = 4000;
+const OGG_SERIAL: u32 = 0x6d75_7378;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Format {
+    Opus,
+    Wav,
+}
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Opus => "opus",
+            Format::Wav => "wav",
+        }
+    }
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct ConvertOptions {
+    #[arg(long = "to", value_enum, ignore_case = true, default_value_t = Format::Opus, help = t("cli.convert_to"))]
+    pub format: Format,
+    #[arg(
+        long = "bitrate",
+        value_name = "KBPS",
+        default_value_t = DEFAULT_BITRATE_KBPS,
+        value_parser = clap::value_parser!(u32).range(6..=510),
+        help = t("cli.convert_bitrate")
+    )]
+    pub bitrate_kbps: u32,
+    #[arg(long = "out", value_name = "DIR", default_value = DEFAULT_OUT_DIR, help = t("cli.convert_out"))]
+    pub out_dir: PathBuf,
+    // Files, directories, or search queries matched against the library
+    #[arg(required = true, value_name = "SELECTION", help = t("cli.selection"))]
+    pub selection: Vec<String>,
+}
+
+/// Entry point for `musix convert [--to opus|wav] [--bitrate KBPS] [--out DIR] <selection>...`.
+pub fn run(options: ConvertOptions) -> Result<(), MusixError> {
+    let songs = resolve_selection(&options.selection)?;
+    if songs.is_empty() {
+        return Err(MusixError::Config(t("convert.no_tracks").to_string()));
+    }
+
+    fs::create_dir_all(&options.out_dir)?;
+
+    let total = songs.len();
+    let mut failed = 0;
+    for (number, song) in songs.iter().enumerate() {
+        let file_name = format!("{}.{}", recording::sanitize_file_name(&song.name), options.format.extension());
+        let output = options.out_dir.join(file_name);
+        let label = format!("[{}/{total}]", number + 1);
+
+        let result = convert_file(&song.path, &output, &options, |percent| {
+            eprint!("\r\x1b[2K{label} {percent:3}% {}", song.name);
+            let _ = io::stderr().flush();
+        });
+
+        match result {
+            Ok(()) => eprintln!("\r\x1b[2K{label} ✓ {}", song.name),
+            Err(e) => {
+                failed += 1;
+                let _ = fs::remove_file(&output);
+                eprintln!("\r\x1b[2K{label} ✗ {}", t_args("convert.failed", &[("name", &song.name), ("error", &e)]));
+            }
+        }
+    }
+
+    eprintln!(
+        "{}",
+        t_args(
+            "convert.done",
+            &[("count", &(total - failed)), ("total", &total), ("dir", &options.out_dir.display())]
+        )
+    );
+    Ok(())
+}
+
+fn convert_file(input: &PathBuf, output: &Path, options: &ConvertOptions, mut on_progress: impl FnMut(u32)) -> Result<(), MusixError> {
+    let source = create_audio_source(input)?;
+    let total = get_audio_duration(input).or_else(|| source.total_duration());
+    let file = BufWriter::new(File::create(output)?);
+
+    let (channels, sample_rate, samples): (u16, u32, Box<dyn Iterator<Item = i16>>) = match options.format {
+        Format::Wav => (source.channels(), source.sample_rate(), Box::new(source)),
+        Format::Opus => {
+            let channels = source.channels().clamp(1, 2);
+            (
+                channels,
+                OPUS_SAMPLE_RATE,
+                Box::new(UniformSourceIterator::new(source, channels, OPUS_SAMPLE_RATE)),
+            )
+        }
+    };
+
+    let samples_per_percent = total.map(|total| (total.as_secs_f64() * f64::from(sample_rate) * f64::from(channels) / 100.0).max(1.0));
+    let mut last_percent = None;
+    let mut report = |written: usize| {
+        let percent = samples_per_percent.map_or(0, |per| ((written as f64 / per) as u32).min(100));
+        if last_percent != Some(percent) {
+            last_percent = Some(percent);
+            on_progress(percent);
+        }
+    };
+
+    let frame_len = OPUS_FRAME_SAMPLES * channels as usize;
+    let mut chunk = Vec::with_capacity(frame_len);
+    let mut written = 0;
+    match options.format {
+        Format::Wav => {
+            let mut writer = recording::WavWriter::new(file, channels, sample_rate)?;
+            for sample in samples {
+                chunk.push(sample);
+                if chunk.len() == frame_len {
+                    writer.write_samples(&chunk)?;
+                    written += chunk.len();
+                    chunk.clear();
+                    report(written);
+                }
+            }
+            writer.write_samples(&chunk)?;
+            writer.finish()?;
+        }
+        Format::Opus => {
+            let mut writer = OggOpusWriter::new(file, channels, options.bitrate_kbps * 1000)?;
+            for sample in samples {
+                chunk.push(sample);
+                if chunk.len() == frame_len {
+                    writer.write_frame(&chunk)?;
+                    written += chunk.len();
+                    chunk.clear();
+                    report(written);
+                }
+            }
+            writer.finish(&chunk)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Owned libopus encoder state.
+struct OpusEncoder {
+    raw: *mut unsafe_libopus::OpusEncoder,
+}
+
+impl OpusEncoder {
+    fn new(channels: u16, bitrate: u32) -> io::Result<Self> {
+        let mut error = 0;
+        // SAFETY: plain constructor call; the result is checked before use
+        let raw =
+            unsafe { unsafe_libopus::opus_encoder_create(OPUS_SAMPLE_RATE as i32, i32::from(channels), unsafe_libopus::OPUS_APPLICATION_AUDIO, &mut error) };
+        if raw.is_null() || error != unsafe_libopus::OPUS_OK {
+            return Err(io::Error::other(format!("opus encoder error {error}")));
+        }
+
+        let encoder = OpusEncoder { raw };
+        // SAFETY: `raw` is a live encoder owned by `encoder`
+        let result = unsafe { unsafe_libopus::opus_encoder_ctl!(encoder.raw, unsafe_libopus::OPUS_SET_BITRATE_REQUEST, bitrate as i32) };
+        if result != unsafe_libopus::OPUS_OK {
+            return Err(io::Error::other(format!("opus bitrate error {result}")));
+        }
+        Ok(encoder)
+    }
+
+    // Samples the decoder has to drop at the start (the OpusHead pre-skip)
+    fn lookahead(&self) -> u16 {
+        let mut lookahead = 0i32;
+        // SAFETY: `raw` is a live encoder and the request writes a single i32
+        unsafe { unsafe_libopus::opus_encoder_ctl!(self.raw, unsafe_libopus::OPUS_GET_LOOKAHEAD_REQUEST, &mut lookahead) };
+        lookahead as u16
+    }
+
+    fn encode(&mut self, frame: &[i16], channels: u16) -> io::Result<Vec<u8>> {
+        let mut packet = vec![0u8; OPUS_MAX_PACKET];
+        // SAFETY: `frame` holds exactly OPUS_FRAME_SAMPLES samples per channel and `packet` is OPUS_MAX_PACKET long
+        let len = unsafe {
+            unsafe_libopus::opus_encode(
+                self.raw,
+                frame.as_ptr(),
+                (frame.len() / channels as usize) as i32,
+                packet.as_mut_ptr(),
+                OPUS_MAX_PACKET as i32,
+            )
+        };
+        if len < 0 {
+            return Err(io::Error::other(format!("opus encode error {len}")));
+        }
+        packet.truncate(len as usize);
+        Ok(packet)
+    }
+}
+
+impl Drop for OpusEncoder {
+    fn drop(&mut self) {
+        // SAFETY: `raw` came from opus_encoder_create and is not used afterwards
+        unsafe { unsafe_libopus::opus_encoder_destroy(self.raw) };
+    }
+}
+
+/// Writes interleaved 48kHz frames as an Ogg Opus file (RFC 7845).
+struct OggOpusWriter<W: Write> {
+    packets: PacketWriter<'static, W>,
+    encoder: OpusEncoder,
+    channels: u16,
+    pre_skip: u64,
+    // Per-channel samples encoded so far
+    position: u64,
+    // Held back one frame so the final packet can be flagged as end of stream
+    pending: Option<(Vec<u8>, u64)>,
+}
+
+impl<W: Write> OggOpusWriter<W> {
+    fn new(inner: W, channels: u16, bitrate: u32) -> io::Result<Self> {
+        let encoder = OpusEncoder::new(channels, bitrate)?;
+        let pre_skip = encoder.lookahead();
+        let mut packets = PacketWriter::new(inner);
+
+        let mut head = Vec::with_capacity(19);
+        head.extend_from_slice(b"OpusHead");
+        head.push(1);
+        head.push(channels as u8);
+        head.extend_from_slice(&pre_skip.to_le_bytes());
+        head.extend_from_slice(&OPUS_SAMPLE_RATE.to_le_bytes());
+        head.extend_from_slice(&0i16.to_le_bytes());
+        head.push(0);
+        packets.write_packet(head, OGG_SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+
+        let vendor = concat!("musix ", env!("CARGO_PKG_VERSION"));
+        let mut tags = Vec::new();
+        tags.extend_from_slice(b"OpusTags");
+        tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        tags.extend_from_slice(vendor.as_bytes());
+        tags.extend_from_slice(&0u32.to_le_bytes());
+        packets.write_packet(tags, OGG_SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+
+        Ok(OggOpusWriter {
+            packets,
+            encoder,
+            channels,
+            pre_skip: u64::from(pre_skip),
+            position: 0,
+            pending: None,
+        })
+    }
+
+    fn write_frame(&mut self, frame: &[i16]) -> io::Result<()> {
+        self.encode_frame(frame, u64::MAX)
+    }
+
+    // Granule positions count decoded samples including the pre-skip, capped at the real end of the track
+    fn encode_frame(&mut self, frame: &[i16], end: u64) -> io::Result<()> {
+        let packet = self.encoder.encode(frame, self.channels)?;
+        self.position += (frame.len() / self.channels as usize) as u64;
+        if let Some((previous, granule)) = self.pending.replace((packet, self.position.min(end))) {
+            self.packets.write_packet(previous, OGG_SERIAL, PacketWriteEndInfo::NormalPacket, granule)?;
+        }
+        Ok(())
+    }
+
+    // Pads the last partial frame with silence; the granule position trims it back off on playback
+    fn finish(mut self, rest: &[i16]) -> io::Result<W> {
+        let frame_len = OPUS_FRAME_SAMPLES * self.channels as usize;
+        // Flush the encoder's lookahead so the tail of the track is not cut short
+        let mut tail = rest.to_vec();
+        tail.resize(tail.len() + self.pre_skip as usize * self.channels as usize, 0);
+        let end = self.pre_skip + self.position + (rest.len() / self.channels as usize) as u64;
+
+        for frame in tail.chunks(frame_len) {
+            let mut frame = frame.to_vec();
+            frame.resize(frame_len, 0);
+            self.encode_frame(&frame, end)?;
+        }
+
+        if let Some((last, _)) = self.pending.take() {
+            self.packets.write_packet(last, OGG_SERIAL, PacketWriteEndInfo::EndStream, end)?;
+        }
+        let mut inner = self.packets.into_inner();
+        inner.flush()?;
+        Ok(inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::io::Cursor;
+
+    #[derive(Parser)]
+    struct Command {
+        #[command(flatten)]
+        options: ConvertOptions,
+    }
+
+    fn parse_args(list: &[&str]) -> Result<ConvertOptions, clap::Error> {
+        Command::try_parse_from(std::iter::once("convert").chain(list.iter().copied())).map(|command| command.options)
+    }
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse_args(&["--to", "OPUS", "--bitrate", "96", "--out", "phone", "jazz", "a.flac"]).unwrap();
+        assert_eq!(options.format, Format::Opus);
+        assert_eq!(options.bitrate_kbps, 96);
+        assert_eq!(options.out_dir, PathBuf::from("phone"));
+        assert_eq!(options.selection, args(&["jazz", "a.flac"]));
+
+        let defaults = parse_args(&["jazz"]).unwrap();
+        assert_eq!((defaults.format, defaults.bitrate_kbps), (Format::Opus, DEFAULT_BITRATE_KBPS));
+
+        assert!(parse_args(&["--to", "mp3", "jazz"]).is_err());
+        assert!(parse_args(&["--bitrate", "loud", "jazz"]).is_err());
+        assert!(parse_args(&["--bitrate", "1000", "jazz"]).is_err());
+        assert!(parse_args(&["--to"]).is_err());
+        assert!(parse_args(&[]).is_err());
+    }
+
+    #[test]
+    fn test_ogg_opus_writer() {
+        let mut writer = OggOpusWriter::new(Cursor::new(Vec::new()), 2, 128_000).unwrap();
+        let frame: Vec<i16> = (0..OPUS_FRAME_SAMPLES * 2).map(|i| ((i as f32 * 0.05).sin() * 8000.0) as i16).collect();
+        for _ in 0..10 {
+            writer.write_frame(&frame).unwrap();
+        }
+        let pre_skip = writer.pre_skip;
+        let bytes = writer.finish(&frame[..200]).unwrap().into_inner();
+
+        let mut reader = ogg::PacketReader::new(Cursor::new(bytes));
+        let head = reader.read_packet_expected().unwrap();
+        assert_eq!(&head.data[..8], b"OpusHead");
+        assert_eq!(head.data[9], 2);
+        assert_eq!(u16::from_le_bytes([head.data[10], head.data[11]]) as u64, pre_skip);
+        assert_eq!(&reader.read_packet_expected().unwrap().data[..8], b"OpusTags");
+
+        let mut last = None;
+        while let Some(packet) = reader.read_packet().unwrap() {
+            last = Some(packet);
+        }
+        let last = last.unwrap();
+        assert!(last.last_in_stream());
+        assert_eq!(last.absgp_page(), pre_skip + 10 * OPUS_FRAME_SAMPLES as u64 + 100);
+    }
+}
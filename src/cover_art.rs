@@ -0,0 +1,220 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use image::{RgbImage, imageops::FilterType};
+use lofty::{
+    config::WriteOptions,
+    picture::{Picture, PictureType},
+    prelude::*,
+    tag::Tag,
+};
+use ratatui::{
+    style::{Color, Style},
+    text::{Line, Span},
+};
+
+use crate::{
+    error::MusixError,
+    i18n::{t, t_args},
+};
+
+// Covers are kept this small; a terminal panel never has more pixels than that
+const MAX_SIZE: u32 = 160;
+// Image files next to the tracks, used when the tags carry no picture
+const FOLDER_IMAGES: [&str; 6] = ["cover.jpg", "cover.png", "folder.jpg", "folder.png", "front.jpg", "front.png"];
+
+/// The front cover embedded in the tags, or an image file in the track's folder.
+pub fn load(path: &Path) -> Option<RgbImage> {
+    let tagged = lofty::read_from_path(path).ok();
+    let pictures = tagged
+        .as_ref()
+        .and_then(|file| file.primary_tag().or_else(|| file.first_tag()))
+        .map(|tag| tag.pictures())
+        .unwrap_or_default();
+    let embedded = pictures
+        .iter()
+        .find(|picture| picture.pic_type() == PictureType::CoverFront)
+        .or_else(|| pictures.first())
+        .map(|picture| picture.data().to_vec());
+    let data = embedded.or_else(|| {
+        let dir = path.parent()?;
+        FOLDER_IMAGES.iter().find_map(|name| fs::read(dir.join(name)).ok())
+    })?;
+    let image = image::load_from_memory(&data).ok()?;
+    Some(image.thumbnail(MAX_SIZE, MAX_SIZE).to_rgb8())
+}
+
+/// An image file as a front cover picture, ready to embed; JPEG and PNG are what players show most reliably.
+pub fn read_picture(image: &Path) -> Result<Picture, MusixError> {
+    let mut picture =
+        Picture::from_reader(&mut fs::File::open(image)?).map_err(|_| MusixError::Decode(t_args("message.art_not_image", &[("file", &image.display())])))?;
+    picture.set_pic_type(PictureType::CoverFront);
+    Ok(picture)
+}
+
+/// Embed `picture` as the front cover of `track`, replacing the one it had: an ID3v2 `APIC` frame in MP3 and WAV
+/// files, a picture block in FLAC and the tag's own picture field elsewhere.
+pub fn embed(track: &Path, picture: &Picture) -> Result<(), MusixError> {
+    let mut file = lofty::read_from_path(track)?;
+    if file.primary_tag().is_none() {
+        file.insert_tag(Tag::new(file.primary_tag_type()));
+    }
+    let tag = file.primary_tag_mut().ok_or_else(|| MusixError::Decode(t("rgscan.no_tag").to_string()))?;
+    tag.remove_picture_type(PictureType::CoverFront);
+    tag.push_picture(picture.clone());
+    file.save_to_path(track, WriteOptions::default())?;
+    Ok(())
+}
+
+/// Draw `image` with `▀` half blocks, two pixels per cell, as large as fits in `width` x `height` cells.
+/// Works in any terminal with colors, unlike the sixel and kitty graphics protocols.
+pub fn half_blocks(image: &RgbImage, width: u16, height: u16) -> Vec<Line<'static>> {
+    if width == 0 || height == 0 || image.width() == 0 || image.height() == 0 {
+        return Vec::new();
+    }
+    // Keep the aspect ratio: a cell is one pixel wide and two pixels tall
+    let scale = (f64::from(width) / f64::from(image.width())).min(f64::from(height) * 2.0 / f64::from(image.height()));
+    let columns = ((f64::from(image.width()) * scale) as u32).clamp(1, u32::from(width));
+    let rows = ((f64::from(image.height()) * scale / 2.0) as u32).clamp(1, u32::from(height));
+    let scaled = image::imageops::resize(image, columns, rows * 2, FilterType::Triangle);
+
+    let color = |x: u32, y: u32| {
+        let [r, g, b] = scaled.get_pixel(x, y).0;
+        Color::Rgb(r, g, b)
+    };
+    (0..rows)
+        .map(|row| {
+            let cells: Vec<Span> = (0..columns)
+                .map(|x| Span::styled("▀", Style::default().fg(color(x, row * 2)).bg(color(x, row * 2 + 1))))
+                .collect();
+            Line::from(cells)
+        })
+        .collect()
+}
+
+/// Loads covers on a background thread so moving through the list never waits on image decoding.
+pub struct CoverLoader {
+    sender: Option<Sender<PathBuf>>,
+    results: Option<Receiver<(PathBuf, Option<RgbImage>)>>,
+    // The track whose cover is wanted, and the cover once it arrived
+    pub path: Option<PathBuf>,
+    pub image: Option<RgbImage>,
+}
+
+impl CoverLoader {
+    pub fn new() -> Self {
+        CoverLoader {
+            sender: None,
+            results: None,
+            path: None,
+            image: None,
+        }
+    }
+
+    /// Switch to the cover of `path`; the old one is dropped right away so it never shows beside the wrong track.
+    pub fn request(&mut self, path: &Path) {
+        if self.path.as_deref() == Some(path) {
+            return;
+        }
+        self.path = Some(path.to_path_buf());
+        self.image = None;
+
+        let sender = self.sender.get_or_insert_with(|| {
+            let (path_sender, paths) = mpsc::channel::<PathBuf>();
+            let (result_sender, results) = mpsc::channel();
+            thread::spawn(move || {
+                while let Ok(mut path) = paths.recv() {
+                    // Only the newest request matters when the selection moved on quickly
+                    while let Ok(newer) = paths.try_recv() {
+                        path = newer;
+                    }
+                    let image = load(&path);
+                    if result_sender.send((path, image)).is_err() {
+                        break;
+                    }
+                }
+            });
+            self.results = Some(results);
+            path_sender
+        });
+        let _ = sender.send(path.to_path_buf());
+    }
+
+    // Pick up finished covers; returns whether the wanted one arrived
+    pub fn poll(&mut self) -> bool {
+        let Some(results) = &self.results else {
+            return false;
+        };
+        let mut arrived = false;
+        for (path, image) in results.try_iter() {
+            if self.path.as_ref() == Some(&path) {
+                self.image = image;
+                arrived = true;
+            }
+        }
+        arrived
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::WavWriter;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_half_blocks_keep_aspect_ratio() {
+        // Red top half, blue bottom half
+        let image = RgbImage::from_fn(40, 40, |_, y| if y < 20 { image::Rgb([255, 0, 0]) } else { image::Rgb([0, 0, 255]) });
+
+        let lines = half_blocks(&image, 30, 10);
+        assert_eq!(lines.len(), 10);
+        assert!(lines.iter().all(|line| line.spans.len() == 20));
+        let top = lines[0].spans[0].style;
+        assert_eq!((top.fg, top.bg), (Some(Color::Rgb(255, 0, 0)), Some(Color::Rgb(255, 0, 0))));
+        let bottom = lines[9].spans[0].style;
+        assert_eq!((bottom.fg, bottom.bg), (Some(Color::Rgb(0, 0, 255)), Some(Color::Rgb(0, 0, 255))));
+
+        // A wide panel is limited by its height and vice versa
+        assert_eq!(half_blocks(&image, 100, 5)[0].spans.len(), 10);
+        assert_eq!(half_blocks(&image, 8, 40).len(), 4);
+        assert!(half_blocks(&image, 0, 5).is_empty());
+    }
+
+    #[test]
+    fn test_cover_from_folder_image() {
+        let dir = TestDir::new("cover");
+        let track = dir.join("a.mp3");
+        fs::write(&track, b"not audio").unwrap();
+        assert!(load(&track).is_none());
+
+        RgbImage::from_pixel(400, 200, image::Rgb([10, 20, 30])).save(dir.join("cover.png")).unwrap();
+        let cover = load(&track).unwrap();
+        assert_eq!(cover.dimensions(), (MAX_SIZE, MAX_SIZE / 2));
+    }
+
+    #[test]
+    fn test_embedded_cover_replaces_the_old_one() {
+        let dir = TestDir::new("embed");
+        let track = dir.join("a.wav");
+        let mut writer = WavWriter::new(fs::File::create(&track).unwrap(), 1, 8000).unwrap();
+        writer.write_samples(&[0; 800]).unwrap();
+        writer.finish().unwrap();
+
+        let not_image = dir.join("notes.txt");
+        fs::write(&not_image, "not an image at all").unwrap();
+        assert!(matches!(read_picture(&not_image), Err(MusixError::Decode(_))));
+
+        for (name, width) in [("old.png", 40), ("new.png", 80)] {
+            RgbImage::from_pixel(width, 40, image::Rgb([200, 0, 0])).save(dir.join(name)).unwrap();
+            embed(&track, &read_picture(&dir.join(name)).unwrap()).unwrap();
+        }
+        assert_eq!(load(&track).unwrap().dimensions(), (MAX_SIZE, MAX_SIZE / 2));
+        let tagged = lofty::read_from_path(&track).unwrap();
+        assert_eq!(tagged.primary_tag().unwrap().pictures().len(), 1);
+    }
+}
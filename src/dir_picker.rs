@@ -0,0 +1,157 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ratatui::widgets::ListState;
+
+use crate::natural;
+
+/// A popup for choosing a folder without leaving the TUI. Enter opens the highlighted folder, Backspace with
+/// nothing typed goes up one, typing narrows the list and Tab picks the folder being shown.
+pub struct DirPicker {
+    pub dir: PathBuf,
+    pub filter: String,
+    // Subfolders of `dir` in natural order, without hidden ones
+    folders: Vec<String>,
+    pub list_state: ListState,
+    pub error: Option<String>,
+}
+
+impl DirPicker {
+    pub fn open(dir: PathBuf) -> Self {
+        let mut picker = DirPicker {
+            dir,
+            filter: String::new(),
+            folders: Vec::new(),
+            list_state: ListState::default(),
+            error: None,
+        };
+        picker.load();
+        picker
+    }
+
+    fn load(&mut self) {
+        self.filter.clear();
+        self.error = None;
+        self.folders = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries
+                .flatten()
+                .filter(|entry| entry.path().is_dir())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .filter(|name| !name.starts_with('.'))
+                .collect(),
+            Err(e) => {
+                self.error = Some(e.to_string());
+                Vec::new()
+            }
+        };
+        self.folders.sort_by(|a, b| natural::compare(a, b));
+        self.select_first();
+    }
+
+    fn select_first(&mut self) {
+        self.list_state.select((!self.visible().is_empty()).then_some(0));
+    }
+
+    /// The subfolders whose names contain the filter, ignoring case.
+    pub fn visible(&self) -> Vec<&str> {
+        let filter = self.filter.to_lowercase();
+        self.folders
+            .iter()
+            .map(String::as_str)
+            .filter(|name| name.to_lowercase().contains(&filter))
+            .collect()
+    }
+
+    pub fn move_selection(&mut self, direction: i32) {
+        let len = self.visible().len();
+        if len == 0 {
+            return;
+        }
+        let current = self.list_state.selected().unwrap_or(0);
+        let new = if direction > 0 {
+            (current + 1) % len
+        } else if current == 0 {
+            len - 1
+        } else {
+            current - 1
+        };
+        self.list_state.select(Some(new));
+    }
+
+    pub fn push_filter(&mut self, c: char) {
+        self.filter.push(c);
+        self.select_first();
+    }
+
+    /// Backspace: drop a typed character, or with none left go up to the parent folder.
+    pub fn backspace(&mut self) {
+        if self.filter.pop().is_some() {
+            self.select_first();
+        } else {
+            self.up();
+        }
+    }
+
+    /// Open the highlighted subfolder.
+    pub fn enter(&mut self) {
+        let Some(name) = self
+            .list_state
+            .selected()
+            .and_then(|index| self.visible().get(index).map(|name| name.to_string()))
+        else {
+            return;
+        };
+        self.dir.push(name);
+        self.load();
+    }
+
+    /// Go to the parent folder, with the one just left highlighted.
+    pub fn up(&mut self) {
+        let Some(parent) = self.dir.parent().map(Path::to_path_buf) else {
+            return;
+        };
+        let left = self.dir.file_name().map(|name| name.to_string_lossy().into_owned());
+        self.dir = parent;
+        self.load();
+        if let Some(position) = left.and_then(|left| self.folders.iter().position(|name| *name == left)) {
+            self.list_state.select(Some(position));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_browse_filter_and_go_up() {
+        let root = TestDir::new("picker");
+        for folder in ["Album 10", "Album 2", "Singles", ".cache", "Singles/2024"] {
+            fs::create_dir_all(root.join(folder)).unwrap();
+        }
+        fs::write(root.join("song.mp3"), b"").unwrap();
+
+        let mut picker = DirPicker::open(root.to_path_buf());
+        assert_eq!(picker.visible(), vec!["Album 2", "Album 10", "Singles"]);
+
+        picker.push_filter('S');
+        picker.push_filter('i');
+        assert_eq!(picker.visible(), vec!["Singles"]);
+        picker.enter();
+        assert_eq!((picker.dir.clone(), picker.filter.as_str()), (root.join("Singles"), ""));
+        assert_eq!(picker.visible(), vec!["2024"]);
+
+        // Backspace with nothing typed goes back up, to where we came from
+        picker.backspace();
+        assert_eq!(picker.dir, *root);
+        assert_eq!(picker.list_state.selected(), Some(2));
+        picker.move_selection(1);
+        assert_eq!(picker.list_state.selected(), Some(0));
+
+        fs::remove_dir_all(&root).unwrap();
+        assert!(DirPicker::open(root.to_path_buf()).error.is_some());
+    }
+}
@@ -0,0 +1,48 @@
+use std::{env, fs, io, process::Command};
+
+#[cfg(windows)]
+const DEFAULT_EDITOR: &str = "notepad";
+#[cfg(not(windows))]
+const DEFAULT_EDITOR: &str = "vi";
+
+// `$VISUAL`, then `$EDITOR`, split into the program and its arguments (e.g. `code --wait`)
+fn command() -> Vec<String> {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .map(|value| value.split_whitespace().map(str::to_string).collect::<Vec<_>>())
+        .find(|words| !words.is_empty())
+        .unwrap_or_else(|| vec![DEFAULT_EDITOR.to_string()])
+}
+
+/// Let the user edit `text` in their editor, blocking until it exits. `None` when the editor quit with an error,
+/// as `:cq` in vim does, to throw the edit away.
+pub fn edit(text: &str) -> io::Result<Option<String>> {
+    let path = env::temp_dir().join(format!("musix-list-{}.txt", std::process::id()));
+    fs::write(&path, text)?;
+    let command = command();
+    let status = Command::new(&command[0]).args(&command[1..]).arg(&path).status();
+    let edited = match status {
+        Ok(status) if status.success() => fs::read_to_string(&path).map(Some),
+        Ok(_) => Ok(None),
+        Err(e) => Err(e),
+    };
+    let _ = fs::remove_file(&path);
+    edited
+}
+
+/// The entries of an edited list in order: one path or URL a line, blank lines and `#` comments left out.
+pub fn entries(text: &str) -> Vec<&str> {
+    text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries_skip_comments_and_blank_lines() {
+        let text = "# Reorder or delete lines\n\n/music/b.mp3\n  /music/a.mp3  \r\n# /music/c.mp3\nhttps://radio.example/stream\n";
+        assert_eq!(entries(text), vec!["/music/b.mp3", "/music/a.mp3", "https://radio.example/stream"]);
+    }
+}
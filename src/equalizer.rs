@@ -0,0 +1,282 @@
+use std::{
+    f32::consts::PI,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use rodio::{Source, source::SeekError};
+
+/// Centre frequencies of the bands, an octave apart, in Hz.
+pub const BANDS: [f32; 10] = [31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0];
+// Furthest a band goes either way, in dB
+pub const MAX_GAIN: f32 = 12.0;
+// Octave-wide bands overlap just enough for a flat response between neighbours
+const Q: f32 = 1.41;
+
+/// Band settings to start from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    Flat,
+    Rock,
+    Bass,
+    Treble,
+    Vocal,
+}
+
+pub const PRESETS: [Preset; 5] = [Preset::Flat, Preset::Rock, Preset::Bass, Preset::Treble, Preset::Vocal];
+
+impl Preset {
+    pub fn gains(self) -> [f32; 10] {
+        match self {
+            Preset::Flat => [0.0; 10],
+            Preset::Rock => [5.0, 4.0, 3.0, 1.0, -1.0, -1.0, 1.0, 3.0, 4.0, 5.0],
+            Preset::Bass => [6.0, 5.0, 4.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            Preset::Treble => [0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 3.0, 4.0, 5.0, 6.0],
+            Preset::Vocal => [-2.0, -2.0, -1.0, 1.0, 3.0, 3.0, 2.0, 1.0, 0.0, -1.0],
+        }
+    }
+
+    /// The preset with exactly these gains, if any.
+    pub fn matching(gains: &[f32; 10]) -> Option<Preset> {
+        PRESETS.into_iter().find(|preset| preset.gains() == *gains)
+    }
+}
+
+struct Shared {
+    enabled: AtomicBool,
+    // f32 bits, dB
+    gains: [AtomicU32; 10],
+    // Bumped on every change, so playing sources know to recompute their filters
+    version: AtomicU64,
+}
+
+/// The equalizer settings, shared with every source playing through it so a change is heard right away.
+#[derive(Clone)]
+pub struct Controls {
+    shared: Arc<Shared>,
+}
+
+impl Controls {
+    pub fn new(enabled: bool, gains: [f32; 10]) -> Self {
+        Controls {
+            shared: Arc::new(Shared {
+                enabled: AtomicBool::new(enabled),
+                gains: gains.map(|gain| AtomicU32::new(gain.clamp(-MAX_GAIN, MAX_GAIN).to_bits())),
+                version: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.shared.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.shared.enabled.store(enabled, Ordering::Relaxed);
+        self.shared.version.fetch_add(1, Ordering::Release);
+    }
+
+    pub fn gains(&self) -> [f32; 10] {
+        std::array::from_fn(|band| f32::from_bits(self.shared.gains[band].load(Ordering::Relaxed)))
+    }
+
+    pub fn set_gains(&self, gains: [f32; 10]) {
+        for (band, gain) in gains.into_iter().enumerate() {
+            self.shared.gains[band].store(gain.clamp(-MAX_GAIN, MAX_GAIN).to_bits(), Ordering::Relaxed);
+        }
+        self.shared.version.fetch_add(1, Ordering::Release);
+    }
+
+    fn version(&self) -> u64 {
+        self.shared.version.load(Ordering::Acquire)
+    }
+
+    pub fn wrap<S: Source<Item = i16>>(&self, inner: S) -> Equalize<S> {
+        Equalize {
+            inner,
+            controls: self.clone(),
+            version: None,
+            channels: 0,
+            sample_rate: 0,
+            filters: Vec::new(),
+            states: Vec::new(),
+            headroom: 1.0,
+            channel: 0,
+        }
+    }
+}
+
+// A peaking filter from the Audio EQ Cookbook, normalized so a0 is 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Biquad {
+    fn peaking(frequency: f32, gain: f32, sample_rate: u32) -> Self {
+        let a = 10f32.powf(gain / 40.0);
+        let w0 = 2.0 * PI * frequency / sample_rate as f32;
+        let alpha = w0.sin() / (2.0 * Q);
+        let a0 = 1.0 + alpha / a;
+        Biquad {
+            b0: (1.0 + alpha * a) / a0,
+            b1: -2.0 * w0.cos() / a0,
+            b2: (1.0 - alpha * a) / a0,
+            a1: -2.0 * w0.cos() / a0,
+            a2: (1.0 - alpha / a) / a0,
+        }
+    }
+
+    // Transposed direct form II, which keeps little state and stays stable in f32
+    fn process(&self, state: &mut [f32; 2], x: f32) -> f32 {
+        let y = self.b0 * x + state[0];
+        state[0] = self.b1 * x - self.a1 * y + state[1];
+        state[1] = self.b2 * x - self.a2 * y;
+        y
+    }
+}
+
+/// Runs a source through the equalizer's bands; with it off or flat, samples pass through untouched.
+pub struct Equalize<S> {
+    inner: S,
+    controls: Controls,
+    // The settings version the filters were made for; None before the first sample
+    version: Option<u64>,
+    channels: u16,
+    sample_rate: u32,
+    // Only the bands set away from 0 dB, and below the Nyquist frequency
+    filters: Vec<Biquad>,
+    // Per channel, per filter
+    states: Vec<Vec<[f32; 2]>>,
+    // Scales the signal down by the largest boost so boosted bands don't clip
+    headroom: f32,
+    // Channel of the next sample
+    channel: usize,
+}
+
+impl<S: Source<Item = i16>> Equalize<S> {
+    // Only looked at on frame boundaries, so a change never lands between the channels of one frame
+    fn refresh(&mut self) {
+        let (channels, sample_rate) = (self.inner.channels().max(1), self.inner.sample_rate());
+        let version = self.controls.version();
+        let format_changed = channels != self.channels || sample_rate != self.sample_rate;
+        if !format_changed && self.version == Some(version) {
+            return;
+        }
+        let gains = if self.controls.is_enabled() { self.controls.gains() } else { [0.0; 10] };
+        let filters: Vec<Biquad> = BANDS
+            .iter()
+            .zip(gains)
+            .filter(|&(&frequency, gain)| gain != 0.0 && frequency < sample_rate as f32 * 0.45)
+            .map(|(&frequency, gain)| Biquad::peaking(frequency, gain, sample_rate))
+            .collect();
+        // The filter state carries over a change of gains, which would otherwise click
+        if format_changed || filters.len() != self.filters.len() {
+            self.states = vec![vec![[0.0; 2]; filters.len()]; channels as usize];
+        }
+        let boost = gains.iter().copied().fold(0.0, f32::max);
+        self.headroom = 10f32.powf(-boost / 20.0);
+        self.filters = filters;
+        self.channels = channels;
+        self.sample_rate = sample_rate;
+        self.version = Some(version);
+    }
+}
+
+impl<S: Source<Item = i16>> Iterator for Equalize<S> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.channel == 0 {
+            self.refresh();
+        }
+        let sample = self.inner.next()?;
+        let channel = self.channel;
+        self.channel = (self.channel + 1) % self.channels as usize;
+        if self.filters.is_empty() {
+            return Some(sample);
+        }
+        let states = &mut self.states[channel];
+        let mut x = sample as f32 * self.headroom;
+        for (filter, state) in self.filters.iter().zip(states.iter_mut()) {
+            x = filter.process(state, x);
+        }
+        Some(x.clamp(i16::MIN as f32, i16::MAX as f32) as i16)
+    }
+}
+
+impl<S: Source<Item = i16>> Source for Equalize<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        // What is left in the filters belongs to the old position
+        for state in self.states.iter_mut().flatten() {
+            *state = [0.0; 2];
+        }
+        self.channel = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn sine(frequency: f32) -> SamplesBuffer<i16> {
+        let samples = (0..48000)
+            .map(|i| ((2.0 * PI * frequency * i as f32 / 48000.0).sin() * 10000.0) as i16)
+            .collect::<Vec<_>>();
+        SamplesBuffer::new(1, 48000, samples)
+    }
+
+    // Past the first tenth of a second, once the filters have settled
+    fn peak(samples: impl Iterator<Item = i16>) -> f32 {
+        samples.skip(4800).map(|sample| (sample as f32).abs()).fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn test_bands_boost_and_cut() {
+        let flat = Controls::new(true, Preset::Flat.gains());
+        assert!(flat.wrap(sine(1000.0)).eq(sine(1000.0)));
+
+        let mut gains = [0.0; 10];
+        gains[5] = 6.0;
+        let controls = Controls::new(true, gains);
+        // The boosted band keeps its level and everything else is cut by the headroom
+        assert!((peak(controls.wrap(sine(1000.0))) / 10000.0 - 1.0).abs() < 0.05);
+        assert!((peak(controls.wrap(sine(62.0))) / 10000.0 - 0.5).abs() < 0.05);
+
+        // Changes reach a source already playing
+        let mut playing = controls.wrap(sine(1000.0));
+        controls.set_enabled(false);
+        assert!(playing.by_ref().take(100).eq(sine(1000.0).take(100)));
+        controls.set_gains([99.0; 10]);
+        assert_eq!(controls.gains(), [MAX_GAIN; 10]);
+
+        assert_eq!(Preset::matching(&Preset::Rock.gains()), Some(Preset::Rock));
+        assert_eq!(Preset::matching(&gains), None);
+    }
+}
@@ -0,0 +1,200 @@
+use std::{io, thread, time::Duration};
+
+use thiserror::Error;
+
+use crate::i18n::{t, t_args};
+
+/// What went wrong, by where it went wrong, so the UI can tell a missing file from one it can't play.
+#[derive(Debug, Error)]
+pub enum MusixError {
+    // Reading or writing files, the terminal and local sockets
+    #[error("{}", describe_io(.0))]
+    Io(#[from] io::Error),
+    // A file that opened but whose audio, tags or contents couldn't be understood
+    #[error("{0}")]
+    Decode(String),
+    // The audio output: no device, or one that refused the stream
+    #[error("{0}")]
+    Device(String),
+    // Settings or command line arguments that can't be used
+    #[error("{0}")]
+    Config(String),
+    // Internet radio, podcasts, scrobbling and station search
+    #[error("{0}")]
+    Net(String),
+    // `musix check` finished and found this many broken tracks or playlist entries
+    #[error("{}", t_args("check.found", &[("count", .0)]))]
+    ProblemsFound(usize),
+    // A command that rewrites the player's files, refused while a player could write them back
+    #[error("{}", t("relocate.running"))]
+    PlayerRunning,
+}
+
+impl MusixError {
+    /// Whether trying again shortly might work, as with a network mount that was slow to answer. A missing file
+    /// or a format musix can't play stays that way.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, MusixError::Io(error) if !matches!(error.kind(), io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied))
+    }
+}
+
+/// Run `attempt`, trying again after each of `delays` while it fails in a way that may pass.
+pub fn retry_transient<T>(delays: &[Duration], mut attempt: impl FnMut() -> Result<T, MusixError>) -> Result<T, MusixError> {
+    let mut delays = delays.iter();
+    loop {
+        match attempt() {
+            Err(error) if error.is_transient() => match delays.next() {
+                Some(&delay) => thread::sleep(delay),
+                None => return Err(error),
+            },
+            result => return result,
+        }
+    }
+}
+
+// The common cases in words a listener recognizes; the rest as the system puts it
+fn describe_io(error: &io::Error) -> String {
+    match error.kind() {
+        io::ErrorKind::NotFound => t("error.not_found").to_string(),
+        io::ErrorKind::PermissionDenied => t("error.permission_denied").to_string(),
+        _ => error.to_string(),
+    }
+}
+
+impl From<rodio::decoder::DecoderError> for MusixError {
+    fn from(error: rodio::decoder::DecoderError) -> Self {
+        match error {
+            rodio::decoder::DecoderError::UnrecognizedFormat => MusixError::Decode(t("error.unsupported_format").to_string()),
+            // Reading failed, not the audio in it
+            rodio::decoder::DecoderError::IoError(error) => MusixError::Io(io::Error::other(error)),
+            error => MusixError::Decode(t_args("error.decode", &[("error", &error)])),
+        }
+    }
+}
+
+impl From<symphonia::core::errors::Error> for MusixError {
+    fn from(error: symphonia::core::errors::Error) -> Self {
+        match error {
+            symphonia::core::errors::Error::IoError(error) => MusixError::Io(error),
+            symphonia::core::errors::Error::Unsupported(_) => MusixError::Decode(t("error.unsupported_format").to_string()),
+            error => MusixError::Decode(t_args("error.decode", &[("error", &error)])),
+        }
+    }
+}
+
+impl From<lofty::error::FileParseError> for MusixError {
+    fn from(error: lofty::error::FileParseError) -> Self {
+        let unknown = std::error::Error::source(&error).is_some_and(|source| source.is::<lofty::error::UnknownFormatError>());
+        if unknown {
+            MusixError::Decode(t("error.unsupported_format").to_string())
+        } else {
+            MusixError::Decode(t_args("error.decode", &[("error", &error)]))
+        }
+    }
+}
+
+impl From<lofty::error::FileEncodingError> for MusixError {
+    fn from(error: lofty::error::FileEncodingError) -> Self {
+        MusixError::Decode(t_args("error.decode", &[("error", &error)]))
+    }
+}
+
+impl From<ebur128::Error> for MusixError {
+    fn from(error: ebur128::Error) -> Self {
+        MusixError::Decode(t_args("error.decode", &[("error", &error)]))
+    }
+}
+
+impl From<serde_json::Error> for MusixError {
+    fn from(error: serde_json::Error) -> Self {
+        MusixError::Decode(t_args("error.decode", &[("error", &error)]))
+    }
+}
+
+impl From<quick_xml::Error> for MusixError {
+    fn from(error: quick_xml::Error) -> Self {
+        MusixError::Decode(t_args("error.decode", &[("error", &error)]))
+    }
+}
+
+impl From<quick_xml::events::attributes::AttrError> for MusixError {
+    fn from(error: quick_xml::events::attributes::AttrError) -> Self {
+        MusixError::Decode(t_args("error.decode", &[("error", &error)]))
+    }
+}
+
+impl From<rodio::StreamError> for MusixError {
+    fn from(error: rodio::StreamError) -> Self {
+        MusixError::Device(error.to_string())
+    }
+}
+
+impl From<rodio::PlayError> for MusixError {
+    fn from(error: rodio::PlayError) -> Self {
+        MusixError::Device(error.to_string())
+    }
+}
+
+impl From<rodio::DevicesError> for MusixError {
+    fn from(error: rodio::DevicesError) -> Self {
+        MusixError::Device(error.to_string())
+    }
+}
+
+impl From<ureq::Error> for MusixError {
+    fn from(error: ureq::Error) -> Self {
+        MusixError::Net(error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_name_the_problem() {
+        let missing = MusixError::from(io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(missing.to_string(), t("error.not_found"));
+        let unsupported = MusixError::from(rodio::decoder::DecoderError::UnrecognizedFormat);
+        assert!(matches!(unsupported, MusixError::Decode(_)));
+        assert_eq!(unsupported.to_string(), t("error.unsupported_format"));
+        let other = MusixError::from(io::Error::other("disk on fire"));
+        assert_eq!(other.to_string(), "disk on fire");
+        assert_eq!(MusixError::ProblemsFound(3).to_string(), t_args("check.found", &[("count", &3)]));
+    }
+
+    #[test]
+    fn test_only_transient_failures_are_retried() {
+        let mut attempts = 0;
+        let result = retry_transient(&[Duration::ZERO; 2], || {
+            attempts += 1;
+            if attempts < 3 {
+                Err(io::Error::from(io::ErrorKind::TimedOut).into())
+            } else {
+                Ok(attempts)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        // Out of retries
+        attempts = 0;
+        let result: Result<(), _> = retry_transient(&[Duration::ZERO], || {
+            attempts += 1;
+            Err(io::Error::from(io::ErrorKind::TimedOut).into())
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 2);
+
+        // A missing file or an unplayable one fails at once
+        for error in [io::Error::from(io::ErrorKind::NotFound).into(), MusixError::Decode("bad".to_string())] {
+            let mut error = Some(error);
+            attempts = 0;
+            let result: Result<(), _> = retry_transient(&[Duration::ZERO; 2], || {
+                attempts += 1;
+                Err(error.take().unwrap())
+            });
+            assert!(result.is_err());
+            assert_eq!(attempts, 1);
+        }
+    }
+}
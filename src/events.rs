@@ -0,0 +1,212 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::MusixError, i18n::t, paths, setlist};
+
+const EVENTS_FILE: &str = "events.jsonl";
+// How often `musix log tail` looks for new lines
+const TAIL_POLL: Duration = Duration::from_millis(500);
+
+/// What happened to the track playing.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum Event {
+    Started,
+    // Left for another track before the end; `percent` is how far in, when the length is known
+    Skipped {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        percent: Option<f64>,
+    },
+    Finished,
+    // musix quit while it played
+    Stopped {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        percent: Option<f64>,
+    },
+}
+
+/// How a track stopped playing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum End {
+    Finished,
+    Skipped,
+    Quit,
+}
+
+impl Event {
+    pub fn ended(end: End, position: Duration, duration: Option<Duration>) -> Self {
+        let percent = duration
+            .filter(|duration| !duration.is_zero())
+            .map(|duration| (position.as_secs_f64() / duration.as_secs_f64() * 100.0).min(100.0));
+        match end {
+            End::Finished => Event::Finished,
+            End::Skipped => Event::Skipped { percent },
+            End::Quit => Event::Stopped { percent },
+        }
+    }
+}
+
+/// One line of the event log.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LogEntry {
+    // Unix timestamp
+    pub time: i64,
+    #[serde(flatten)]
+    pub event: Event,
+    pub path: String,
+    pub title: String,
+    // Seconds into the track
+    pub position: f64,
+}
+
+impl LogEntry {
+    pub fn new(event: Event, path: &Path, title: &str, position: Duration) -> Self {
+        LogEntry {
+            time: chrono::Utc::now().timestamp(),
+            event,
+            path: path.to_string_lossy().into_owned(),
+            title: title.to_string(),
+            position: position.as_secs_f64(),
+        }
+    }
+}
+
+pub fn events_path() -> PathBuf {
+    paths::file_in(paths::data_dir(), EVENTS_FILE)
+}
+
+/// Append an entry as one JSON line; like the listening log, the event log is never rewritten.
+pub fn append(file: &Path, entry: &LogEntry) -> io::Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let line = serde_json::to_string(entry).map_err(io::Error::other)?;
+    let mut log = OpenOptions::new().create(true).append(true).open(file)?;
+    writeln!(log, "{line}")
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+pub enum LogCommand {
+    #[command(about = t("cli.log_tail"))]
+    Tail {
+        #[arg(short = 'n', long, default_value_t = 10, help = t("cli.log_lines"))]
+        lines: usize,
+    },
+    #[command(about = t("cli.log_setlist"))]
+    Setlist {
+        #[arg(help = t("cli.setlist_file"))]
+        file: PathBuf,
+        #[arg(long, default_value_t = 12, help = t("cli.setlist_hours"))]
+        hours: u32,
+    },
+}
+
+/// Entry point for `musix log tail`: print the last lines of the event log, then each new one as it is written,
+/// until interrupted.
+pub fn run(command: LogCommand) -> Result<(), MusixError> {
+    let lines = match command {
+        LogCommand::Tail { lines } => lines,
+        LogCommand::Setlist { file, hours } => return setlist::run(&file, hours),
+    };
+    let file = events_path();
+    let mut out = io::stdout();
+    let mut offset = match fs::read_to_string(&file) {
+        Ok(text) => {
+            out.write_all(last_lines(&text, lines).as_bytes())?;
+            text.len() as u64
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(e.into()),
+    };
+    out.flush()?;
+    loop {
+        thread::sleep(TAIL_POLL);
+        let (text, next) = read_new(&file, offset)?;
+        out.write_all(text.as_bytes())?;
+        out.flush()?;
+        offset = next;
+    }
+}
+
+// The last `count` complete lines of `text`
+fn last_lines(text: &str, count: usize) -> &str {
+    if count == 0 {
+        return "";
+    }
+    let mut start = text.len();
+    for _ in 0..count {
+        match text[..start].trim_end_matches('\n').rfind('\n') {
+            Some(newline) => start = newline + 1,
+            None => return text,
+        }
+    }
+    &text[start..]
+}
+
+// Whole lines written after `offset`, and the offset after them. A line still being written waits for the
+// next call, and a log that got shorter (deleted or replaced) is read again from the start
+fn read_new(file: &Path, offset: u64) -> Result<(String, u64), MusixError> {
+    let mut log = match File::open(file) {
+        Ok(log) => log,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((String::new(), 0)),
+        Err(e) => return Err(e.into()),
+    };
+    let offset = if log.metadata()?.len() < offset { 0 } else { offset };
+    log.seek(SeekFrom::Start(offset))?;
+    let mut bytes = Vec::new();
+    log.read_to_end(&mut bytes)?;
+    let complete = bytes.iter().rposition(|&byte| byte == b'\n').map_or(0, |newline| newline + 1);
+    bytes.truncate(complete);
+    Ok((String::from_utf8_lossy(&bytes).into_owned(), offset + complete as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_entries_are_json_lines() {
+        let skipped = Event::ended(End::Skipped, Duration::from_secs(30), Some(Duration::from_secs(120)));
+        assert_eq!(skipped, Event::Skipped { percent: Some(25.0) });
+        assert_eq!(Event::ended(End::Quit, Duration::from_secs(30), None), Event::Stopped { percent: None });
+
+        let entry = LogEntry {
+            time: 1000,
+            ..LogEntry::new(skipped, Path::new("/music/a.mp3"), "a", Duration::from_secs(30))
+        };
+        let line = serde_json::to_string(&entry).unwrap();
+        assert_eq!(
+            line,
+            r#"{"time":1000,"event":"skipped","percent":25.0,"path":"/music/a.mp3","title":"a","position":30.0}"#
+        );
+        assert_eq!(serde_json::from_str::<LogEntry>(&line).unwrap(), entry);
+    }
+
+    #[test]
+    fn test_tail_follows_whole_lines() {
+        assert_eq!(last_lines("a\nb\nc\n", 2), "b\nc\n");
+        assert_eq!(last_lines("a\nb\n", 5), "a\nb\n");
+        assert_eq!(last_lines("a\n", 0), "");
+
+        let dir = TestDir::new("events");
+        let file = dir.join("events.jsonl");
+        fs::write(&file, "one\ntw").unwrap();
+        let (text, offset) = read_new(&file, 0).unwrap();
+        assert_eq!((text.as_str(), offset), ("one\n", 4));
+        let mut log = OpenOptions::new().append(true).open(&file).unwrap();
+        write!(log, "o\nthree\n").unwrap();
+        assert_eq!(read_new(&file, offset).unwrap().0, "two\nthree\n");
+        // Replaced by a shorter log
+        fs::write(&file, "new\n").unwrap();
+        assert_eq!(read_new(&file, 14).unwrap(), ("new\n".to_string(), 4));
+    }
+}
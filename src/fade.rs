@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+use rodio::{Source, source::SeekError};
+
+/// Where a track's volume ramps, in track time: up from silence at `start` over `fade_in`, and down to silence at
+/// `end` over `fade_out`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Envelope {
+    pub start: Duration,
+    pub fade_in: Option<Duration>,
+    pub end: Option<Duration>,
+    pub fade_out: Option<Duration>,
+}
+
+impl Envelope {
+    pub fn is_flat(&self) -> bool {
+        self.fade_in.is_none_or(|fade| fade.is_zero()) && (self.end.is_none() || self.fade_out.is_none_or(|fade| fade.is_zero()))
+    }
+
+    /// Volume factor at `seconds` into the track.
+    pub fn gain(&self, seconds: f64) -> f32 {
+        let mut gain = 1.0;
+        if let Some(fade_in) = self.fade_in.filter(|fade| !fade.is_zero()) {
+            gain = ((seconds - self.start.as_secs_f64()) / fade_in.as_secs_f64()).clamp(0.0, 1.0);
+        }
+        if let (Some(end), Some(fade_out)) = (self.end, self.fade_out.filter(|fade| !fade.is_zero())) {
+            gain = gain.min(((end.as_secs_f64() - seconds) / fade_out.as_secs_f64()).clamp(0.0, 1.0));
+        }
+        gain as f32
+    }
+}
+
+/// Applies a track's fades while it plays, so an abrupt start or end is smoothed without touching the file.
+pub struct Fade<S> {
+    inner: S,
+    envelope: Envelope,
+    // Samples since the start of the track, over all channels
+    samples: u64,
+}
+
+impl<S: Source<Item = i16>> Fade<S> {
+    pub fn new(inner: S, envelope: Envelope) -> Self {
+        Fade { inner, envelope, samples: 0 }
+    }
+
+    /// For a source that starts `start` into the track rather than at its beginning.
+    pub fn starting_at(inner: S, envelope: Envelope, start: Duration) -> Self {
+        let per_second = inner.sample_rate() as f64 * inner.channels().max(1) as f64;
+        Fade {
+            samples: (start.as_secs_f64() * per_second) as u64,
+            ..Fade::new(inner, envelope)
+        }
+    }
+
+    fn seconds(&self) -> f64 {
+        let per_second = self.inner.sample_rate() as f64 * self.inner.channels().max(1) as f64;
+        self.samples as f64 / per_second
+    }
+}
+
+impl<S: Source<Item = i16>> Iterator for Fade<S> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.inner.next()?;
+        let gain = if self.envelope.is_flat() { 1.0 } else { self.envelope.gain(self.seconds()) };
+        self.samples += 1;
+        Some((sample as f32 * gain) as i16)
+    }
+}
+
+impl<S: Source<Item = i16>> Source for Fade<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        let per_second = self.inner.sample_rate() as f64 * self.inner.channels().max(1) as f64;
+        self.samples = (pos.as_secs_f64() * per_second) as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    #[test]
+    fn test_fades_ramp_in_and_out() {
+        let envelope = Envelope {
+            start: Duration::from_secs(10),
+            fade_in: Some(Duration::from_secs(4)),
+            end: Some(Duration::from_secs(100)),
+            fade_out: Some(Duration::from_secs(10)),
+        };
+        assert_eq!(envelope.gain(5.0), 0.0);
+        assert_eq!(envelope.gain(12.0), 0.5);
+        assert_eq!(envelope.gain(50.0), 1.0);
+        assert_eq!(envelope.gain(95.0), 0.5);
+        assert_eq!(envelope.gain(120.0), 0.0);
+        assert!(Envelope::default().is_flat());
+
+        // One channel at 4 samples a second, fading in over the first second
+        let source = SamplesBuffer::new(1, 4, vec![1000i16; 8]);
+        let fade_in = Envelope {
+            fade_in: Some(Duration::from_secs(1)),
+            ..Envelope::default()
+        };
+        let samples: Vec<i16> = Fade::new(source, fade_in).collect();
+        assert_eq!(samples, vec![0, 250, 500, 750, 1000, 1000, 1000, 1000]);
+
+        // Seeking moves along the envelope
+        let mut faded = Fade::new(SamplesBuffer::new(1, 4, vec![1000i16; 8]), fade_in);
+        faded.try_seek(Duration::from_millis(500)).unwrap();
+        assert_eq!(faded.next(), Some(500));
+    }
+
+    #[test]
+    fn test_sources_opened_part_way_in_start_along_the_envelope() {
+        let fade_in = Envelope {
+            fade_in: Some(Duration::from_secs(1)),
+            ..Envelope::default()
+        };
+        let mut later = Fade::starting_at(SamplesBuffer::new(1, 4, vec![1000i16; 4]), fade_in, Duration::from_millis(750));
+        assert_eq!(later.next(), Some(750));
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use lofty::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::paths;
+
+const HISTORY_FILE: &str = "history.jsonl";
+// A play counts as completed once this much of the track was reached
+const COMPLETION_RATIO: f64 = 0.9;
+
+/// One listening session of a track, appended to the history log when it ends.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PlayEvent {
+    pub path: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    // Unix timestamp of when playback started
+    pub started: i64,
+    // Seconds actually heard, excluding pauses
+    pub listened: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    pub completed: bool,
+}
+
+pub fn history_path() -> PathBuf {
+    paths::file_in(paths::data_dir(), HISTORY_FILE)
+}
+
+/// Append an event as one JSON line; the log is never rewritten.
+pub fn append(file: &Path, event: &PlayEvent) -> io::Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let line = serde_json::to_string(event).map_err(io::Error::other)?;
+    let mut log = OpenOptions::new().create(true).append(true).open(file)?;
+    writeln!(log, "{line}")
+}
+
+/// Read every event of the log, skipping lines that fail to parse (e.g. one cut short by a crash).
+pub fn load(file: &Path) -> Vec<PlayEvent> {
+    fs::read_to_string(file)
+        .map(|text| text.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+        .unwrap_or_default()
+}
+
+/// The track currently being listened to, accumulating heard time tick by tick.
+pub struct Listen {
+    event: PlayEvent,
+    listened: Duration,
+    last_tick: Instant,
+}
+
+impl Listen {
+    pub fn start(title: String, path: &Path, duration: Option<Duration>, started: i64) -> Self {
+        let (artist, album) = read_artist_album(path);
+        Listen {
+            event: PlayEvent {
+                path: path.to_string_lossy().into_owned(),
+                title,
+                artist,
+                album,
+                started,
+                listened: 0.0,
+                duration: duration.map(|duration| duration.as_secs_f64()),
+                completed: false,
+            },
+            listened: Duration::ZERO,
+            last_tick: Instant::now(),
+        }
+    }
+
+    pub fn event(&self) -> &PlayEvent {
+        &self.event
+    }
+
+    // Called once per UI tick; only time spent playing counts
+    pub fn tick(&mut self, playing: bool) {
+        let now = Instant::now();
+        if playing {
+            self.listened += now - self.last_tick;
+        }
+        self.last_tick = now;
+    }
+
+    /// Close the session; `position` is where playback stopped, or None if the track played to its end.
+    pub fn finish(mut self, position: Option<Duration>) -> PlayEvent {
+        self.event.listened = self.listened.as_secs_f64();
+        self.event.completed = match (position, self.event.duration) {
+            (None, _) => true,
+            (Some(position), Some(duration)) => position.as_secs_f64() >= duration * COMPLETION_RATIO,
+            (Some(_), None) => false,
+        };
+        self.event
+    }
+}
+
+// Artist and album from the tags; the album falls back to the folder name, matching how rgscan groups albums
+fn read_artist_album(path: &Path) -> (Option<String>, Option<String>) {
+    let tagged = lofty::read_from_path(path).ok();
+    let tag = tagged.as_ref().and_then(|file| file.primary_tag().or_else(|| file.first_tag()));
+    let artist = tag.and_then(|tag| tag.artist()).map(|artist| artist.into_owned());
+    let album = tag
+        .and_then(|tag| tag.album())
+        .map(|album| album.into_owned())
+        .or_else(|| path.parent().and_then(Path::file_name).map(|name| name.to_string_lossy().into_owned()));
+    (artist, album)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_history_log_round_trip() {
+        let dir = TestDir::new("history");
+        let file = dir.join("history.jsonl");
+        let listen = Listen::start("a".to_string(), Path::new("/music/Album/a.mp3"), Some(Duration::from_secs(200)), 1000);
+        let skipped = listen.finish(Some(Duration::from_secs(30)));
+        assert!(!skipped.completed);
+        assert_eq!(skipped.album.as_deref(), Some("Album"));
+
+        let listen = Listen::start("b".to_string(), Path::new("/music/Album/b.mp3"), None, 2000);
+        let finished = listen.finish(None);
+        assert!(finished.completed);
+
+        append(&file, &skipped).unwrap();
+        append(&file, &finished).unwrap();
+        let mut log = fs::OpenOptions::new().append(true).open(&file).unwrap();
+        write!(log, "{{\"path\":").unwrap();
+
+        assert_eq!(load(&file), vec![skipped, finished]);
+    }
+}
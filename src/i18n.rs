@@ -0,0 +1,104 @@
+use std::{collections::HashMap, env, sync::OnceLock};
+
+include!(concat!(env!("OUT_DIR"), "/locales.rs"));
+
+const FALLBACK_LOCALE: &str = "en";
+
+struct Catalog {
+    messages: HashMap<String, String>,
+    fallback: HashMap<String, String>,
+}
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+fn catalog() -> &'static Catalog {
+    CATALOG.get_or_init(|| {
+        let fallback = builtin(FALLBACK_LOCALE).unwrap_or_default();
+        let messages = detect_locale().and_then(|lang| builtin(&lang)).unwrap_or_default();
+        Catalog { messages, fallback }
+    })
+}
+
+fn builtin(lang: &str) -> Option<HashMap<String, String>> {
+    BUILTIN_LOCALES
+        .iter()
+        .find(|(name, _)| *name == lang)
+        .and_then(|(_, source)| parse_locale(source).ok())
+}
+
+// MUSIX_LANG wins over the usual POSIX locale variables, e.g. "ja_JP.UTF-8" -> "ja"
+fn detect_locale() -> Option<String> {
+    ["MUSIX_LANG", "LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .map(|value| language_code(&value))
+}
+
+fn language_code(locale: &str) -> String {
+    locale.split(['_', '.', '@', '-']).next().unwrap_or(locale).to_lowercase()
+}
+
+// Flatten `[section] key = "text"` tables into "section.key" lookups
+fn parse_locale(source: &str) -> Result<HashMap<String, String>, toml::de::Error> {
+    fn flatten(prefix: &str, table: &toml::Table, messages: &mut HashMap<String, String>) {
+        for (key, value) in table {
+            let full_key = if prefix.is_empty() { key.clone() } else { format!("{prefix}.{key}") };
+            match value {
+                toml::Value::String(text) => {
+                    messages.insert(full_key, text.clone());
+                }
+                toml::Value::Table(inner) => flatten(&full_key, inner, messages),
+                _ => {}
+            }
+        }
+    }
+
+    let table: toml::Table = source.parse()?;
+    let mut messages = HashMap::new();
+    flatten("", &table, &mut messages);
+    Ok(messages)
+}
+
+/// Look up a UI string, falling back to English and then to the key itself.
+pub fn t(key: &'static str) -> &'static str {
+    let catalog = catalog();
+    catalog
+        .messages
+        .get(key)
+        .or_else(|| catalog.fallback.get(key))
+        .map(String::as_str)
+        .unwrap_or(key)
+}
+
+/// Look up a UI string and substitute `{name}` placeholders.
+pub fn t_args(key: &'static str, args: &[(&str, &dyn std::fmt::Display)]) -> String {
+    let mut text = t(key).to_string();
+    for (name, value) in args {
+        text = text.replace(&format!("{{{name}}}"), &value.to_string());
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_language_code() {
+        assert_eq!(language_code("ja_JP.UTF-8"), "ja");
+        assert_eq!(language_code("en"), "en");
+        assert_eq!(language_code("zh-Hans"), "zh");
+    }
+
+    #[test]
+    fn test_builtin_locales_cover_english_keys() {
+        let english = builtin(FALLBACK_LOCALE).expect("English locale must parse");
+        for (lang, source) in BUILTIN_LOCALES {
+            let messages = parse_locale(source).unwrap_or_else(|e| panic!("{lang}.toml does not parse: {e}"));
+            for key in messages.keys() {
+                assert!(english.contains_key(key), "{lang}.toml has unknown key {key}");
+            }
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::row_colors::is_lossless;
+
+/// Which glyphs mark playback state, file types and the like in the list and status bar.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum IconStyle {
+    // Symbols found in any Unicode font
+    #[default]
+    Unicode,
+    // Nerd Font glyphs from the private use area; needs a patched font
+    Nerd,
+    // Plain characters for terminals and fonts without either, also forced by `--ascii`
+    Ascii,
+}
+
+impl IconStyle {
+    pub fn playing(self) -> &'static str {
+        match self {
+            IconStyle::Unicode => "♪",
+            IconStyle::Nerd => "\u{f04b}",
+            IconStyle::Ascii => ">",
+        }
+    }
+
+    pub fn paused(self) -> &'static str {
+        match self {
+            IconStyle::Unicode => "⏸",
+            IconStyle::Nerd => "\u{f04c}",
+            IconStyle::Ascii => "=",
+        }
+    }
+
+    pub fn stopped(self) -> &'static str {
+        match self {
+            IconStyle::Unicode => "⏹",
+            IconStyle::Nerd => "\u{f04d}",
+            IconStyle::Ascii => "-",
+        }
+    }
+
+    pub fn error(self) -> &'static str {
+        match self {
+            IconStyle::Unicode => "✗",
+            IconStyle::Nerd => "\u{f00d}",
+            IconStyle::Ascii => "x",
+        }
+    }
+
+    // Tracks that arrived in the inbox and haven't been played yet
+    pub fn new_track(self) -> &'static str {
+        match self {
+            IconStyle::Unicode => "✦",
+            IconStyle::Nerd => "\u{f0395}",
+            IconStyle::Ascii => "+",
+        }
+    }
+
+    pub fn locked(self) -> &'static str {
+        match self {
+            IconStyle::Unicode => "🔒",
+            IconStyle::Nerd => "\u{f023}",
+            IconStyle::Ascii => "#",
+        }
+    }
+
+    pub fn recording(self) -> &'static str {
+        match self {
+            IconStyle::Unicode => "●",
+            IconStyle::Nerd => "\u{f111}",
+            IconStyle::Ascii => "*",
+        }
+    }
+
+    pub fn analyzing(self) -> &'static str {
+        match self {
+            IconStyle::Unicode => "♩",
+            IconStyle::Nerd => "\u{f0e4}",
+            IconStyle::Ascii => "~",
+        }
+    }
+
+    pub fn upload(self) -> &'static str {
+        match self {
+            IconStyle::Unicode => "⇡",
+            IconStyle::Nerd => "\u{f093}",
+            IconStyle::Ascii => "^",
+        }
+    }
+
+    pub fn album(self) -> &'static str {
+        match self {
+            IconStyle::Unicode => "💿",
+            IconStyle::Nerd => "\u{f0025}",
+            IconStyle::Ascii => "@",
+        }
+    }
+
+    /// Full and empty star for ratings.
+    pub fn stars(self) -> (&'static str, &'static str) {
+        match self {
+            IconStyle::Unicode => ("★", "☆"),
+            IconStyle::Nerd => ("\u{f005}", "\u{f006}"),
+            IconStyle::Ascii => ("*", "."),
+        }
+    }
+
+    /// Filled and empty cell of the stream buffer gauge.
+    pub fn gauge(self) -> (&'static str, &'static str) {
+        match self {
+            IconStyle::Unicode | IconStyle::Nerd => ("▰", "▱"),
+            IconStyle::Ascii => ("#", "-"),
+        }
+    }
+
+    /// File type icon in front of list rows; only Nerd Fonts have glyphs worth the column.
+    pub fn file_type(self, path: &Path, is_station: bool) -> Option<&'static str> {
+        if self != IconStyle::Nerd {
+            return None;
+        }
+        Some(if is_station {
+            // nf-md-radio
+            "\u{f0439}"
+        } else if is_lossless(path) {
+            // nf-md-quality_high
+            "\u{f0435}"
+        } else {
+            // nf-fa-file_audio_o
+            "\u{f1c7}"
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use unicode_width::UnicodeWidthStr;
+
+    use super::*;
+
+    #[test]
+    fn test_ascii_icons_stay_ascii() {
+        let style = IconStyle::Ascii;
+        let (full, empty) = style.stars();
+        let (filled, unfilled) = style.gauge();
+        let icons = [style.playing(), style.paused(), style.stopped(), style.error(), style.locked()];
+        let icons = icons.into_iter().chain([
+            style.recording(),
+            style.analyzing(),
+            style.upload(),
+            style.album(),
+            full,
+            empty,
+            filled,
+            unfilled,
+        ]);
+        for icon in icons {
+            assert!(icon.is_ascii() && icon.width() == 1, "{icon:?}");
+        }
+        assert_eq!(style.file_type(Path::new("a.flac"), false), None);
+        assert_eq!(IconStyle::Nerd.file_type(Path::new("a.flac"), false), Some("\u{f0435}"));
+    }
+}
@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+// Digits three blocks wide and five tall; each block is drawn two cells wide so they look square
+const GLYPHS: [(char, [&str; 5]); 12] = [
+    ('0', ["███", "█ █", "█ █", "█ █", "███"]),
+    ('1', ["  █", "  █", "  █", "  █", "  █"]),
+    ('2', ["███", "  █", "███", "█  ", "███"]),
+    ('3', ["███", "  █", "███", "  █", "███"]),
+    ('4', ["█ █", "█ █", "███", "  █", "  █"]),
+    ('5', ["███", "█  ", "███", "  █", "███"]),
+    ('6', ["███", "█  ", "███", "█ █", "███"]),
+    ('7', ["███", "  █", "  █", "  █", "  █"]),
+    ('8', ["███", "█ █", "███", "█ █", "███"]),
+    ('9', ["███", "█ █", "███", "  █", "███"]),
+    (':', [" ", "█", " ", "█", " "]),
+    (' ', [" ", " ", " ", " ", " "]),
+];
+
+// How long the idle view stays in one place before moving a cell
+const DRIFT_STEP: Duration = Duration::from_secs(5);
+
+/// `text` in big block digits, five rows tall; characters other than digits, `:` and spaces are left out.
+pub fn big(text: &str) -> [String; 5] {
+    let mut rows: [String; 5] = Default::default();
+    let glyphs = text.chars().filter_map(|c| GLYPHS.iter().find(|(glyph, _)| *glyph == c));
+    for (i, (_, glyph)) in glyphs.enumerate() {
+        for (row, line) in rows.iter_mut().zip(glyph) {
+            if i > 0 {
+                row.push_str("  ");
+            }
+            row.extend(line.chars().flat_map(|c| [c, c]));
+        }
+    }
+    rows
+}
+
+/// Where the idle view sits after `idle` of idling, bouncing slowly between the edges of the room it has left
+/// (`free` columns and rows) so no cell of an always-on display stays lit for long.
+pub fn drift(free: (u16, u16), idle: Duration) -> (u16, u16) {
+    let steps = idle.as_secs() / DRIFT_STEP.as_secs();
+    // Rows change half as often, so it travels on a diagonal that covers the screen over time
+    (bounce(steps, free.0), bounce(steps / 2, free.1))
+}
+
+fn bounce(steps: u64, range: u16) -> u16 {
+    if range == 0 {
+        return 0;
+    }
+    let range = range as u64;
+    let position = steps % (2 * range);
+    (if position <= range { position } else { 2 * range - position }) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_big_clock_and_drift() {
+        let rows = big("12:05");
+        assert_eq!(rows[0], "    ██  ██████      ██████  ██████");
+        assert!(rows.iter().all(|row| row.chars().count() == rows[0].chars().count()));
+        assert_eq!(big("12 05")[1].chars().count(), rows[1].chars().count());
+
+        assert_eq!(drift((10, 4), Duration::ZERO), (0, 0));
+        assert_eq!(drift((10, 4), DRIFT_STEP * 3), (3, 1));
+        // Back from the right edge
+        assert_eq!(drift((10, 4), DRIFT_STEP * 13), (7, 2));
+        assert_eq!(drift((0, 0), DRIFT_STEP * 13), (0, 0));
+    }
+}
@@ -0,0 +1,194 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Name of the per-folder file listing what the library scan skips.
+pub const IGNORE_FILE: &str = ".musixignore";
+
+/// One line of an ignore file, in gitignore syntax.
+#[derive(Debug, Clone, PartialEq)]
+struct Pattern {
+    glob: Vec<char>,
+    // `!` brings back something an earlier pattern excluded
+    negated: bool,
+    // A trailing `/` only matches folders
+    dir_only: bool,
+    // A `/` at the start or in the middle ties the pattern to the ignore file's folder;
+    // otherwise it matches names at any depth
+    anchored: bool,
+}
+
+impl Pattern {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').filter(|rest| rest.starts_with(['#', '!'])).unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        if line.is_empty() {
+            return None;
+        }
+        Some(Pattern {
+            glob: line.chars().collect(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    // `relative` uses `/` between folders, whatever the platform
+    fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let text = if self.anchored {
+            relative
+        } else {
+            relative.rsplit('/').next().unwrap_or(relative)
+        };
+        glob(&self.glob, &text.chars().collect::<Vec<_>>())
+    }
+}
+
+/// The patterns of one `.musixignore`, which apply to everything below its folder.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IgnoreFile {
+    base: PathBuf,
+    patterns: Vec<Pattern>,
+}
+
+impl IgnoreFile {
+    pub fn parse(base: &Path, text: &str) -> Self {
+        IgnoreFile {
+            base: base.to_path_buf(),
+            patterns: text.lines().filter_map(Pattern::parse).collect(),
+        }
+    }
+
+    /// The ignore file in `dir`, if it has one.
+    pub fn load(dir: &Path) -> Option<Self> {
+        let text = fs::read_to_string(dir.join(IGNORE_FILE)).ok()?;
+        Some(Self::parse(dir, &text))
+    }
+}
+
+/// Whether the ignore files from the top of the scan down to `path`'s folder exclude it. Later patterns win,
+/// so a deeper file can bring back what a higher one excluded.
+pub fn is_ignored(files: &[IgnoreFile], path: &Path, is_dir: bool) -> bool {
+    let mut ignored = false;
+    for file in files {
+        let Ok(relative) = path.strip_prefix(&file.base) else {
+            continue;
+        };
+        let relative = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        for pattern in &file.patterns {
+            if pattern.matches(&relative, is_dir) {
+                ignored = !pattern.negated;
+            }
+        }
+    }
+    ignored
+}
+
+// `*` and `?` stay within a folder name, `**` spans folders and `[a-z]`/`[!0-9]` match one character of a set
+fn glob(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', rest @ ..] => {
+            // `a/**/b` also matches `a/b`
+            let skipped = rest.strip_prefix(&['/']).is_some_and(|after| glob(after, text));
+            skipped || (0..=text.len()).any(|start| glob(rest, &text[start..]))
+        }
+        ['*', rest @ ..] => {
+            let name_end = text.iter().position(|&c| c == '/').unwrap_or(text.len());
+            (0..=name_end).any(|start| glob(rest, &text[start..]))
+        }
+        ['?', rest @ ..] => text.first().is_some_and(|&c| c != '/') && glob(rest, &text[1..]),
+        ['[', rest @ ..] if rest.iter().skip(1).any(|&c| c == ']') => {
+            let Some(&c) = text.first().filter(|&&c| c != '/') else {
+                return false;
+            };
+            let (negated, set) = match rest {
+                ['!' | '^', set @ ..] => (true, set),
+                set => (false, set),
+            };
+            // A `]` right after the `[` is part of the set
+            let end = set.iter().skip(1).position(|&c| c == ']').map_or(set.len(), |end| end + 1);
+            let (class, after) = (&set[..end], &set[(end + 1).min(set.len())..]);
+            let mut in_class = false;
+            let mut i = 0;
+            while i < class.len() {
+                if i + 2 < class.len() && class[i + 1] == '-' {
+                    in_class |= (class[i]..=class[i + 2]).contains(&c);
+                    i += 3;
+                } else {
+                    in_class |= class[i] == c;
+                    i += 1;
+                }
+            }
+            in_class != negated && glob(after, &text[1..])
+        }
+        ['\\', literal, rest @ ..] => text.first() == Some(literal) && glob(rest, &text[1..]),
+        [literal, rest @ ..] => text.first() == Some(literal) && glob(rest, &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gitignore_patterns() {
+        let root = IgnoreFile::parse(
+            Path::new("/music"),
+            "# producer folders\nsamples/\n/Projects/*/stems\n*.wav\n!keep.wav\nDemo ?.mp3\n**/bootleg [0-9]*\n",
+        );
+        let files = [root];
+        let ignored = |path: &str, is_dir| is_ignored(&files, Path::new(path), is_dir);
+
+        // Unanchored names match at any depth; a trailing slash only matches folders
+        assert!(ignored("/music/samples", true));
+        assert!(ignored("/music/Artist/samples", true));
+        assert!(!ignored("/music/Artist/samples", false));
+        // Anchored patterns only match from the ignore file's folder, and `*` stays in one name
+        assert!(ignored("/music/Projects/Song/stems", true));
+        assert!(!ignored("/music/Artist/Projects/Song/stems", true));
+        assert!(!ignored("/music/Projects/a/b/stems", true));
+
+        assert!(ignored("/music/Artist/take.wav", false));
+        assert!(!ignored("/music/Artist/keep.wav", false));
+        assert!(ignored("/music/Demo 1.mp3", false));
+        assert!(!ignored("/music/Demo 12.mp3", false));
+        assert!(ignored("/music/Live/bootleg 1999.mp3", false));
+        assert!(!ignored("/music/Live/bootleg x.mp3", false));
+        assert!(!ignored("/music/Artist/song.mp3", false));
+        // Paths outside the ignore file's folder are not its business
+        assert!(!ignored("/other/samples", true));
+    }
+
+    #[test]
+    fn test_deeper_files_override() {
+        let files = [
+            IgnoreFile::parse(Path::new("/music"), "*.flac\n"),
+            IgnoreFile::parse(Path::new("/music/Hi-Res"), "!*.flac\n"),
+        ];
+        assert!(is_ignored(&files, Path::new("/music/Album/a.flac"), false));
+        assert!(!is_ignored(&files, Path::new("/music/Hi-Res/a.flac"), false));
+        assert!(glob(&"a/**/b".chars().collect::<Vec<_>>(), &"a/b".chars().collect::<Vec<_>>()));
+        assert!(glob(&"a/**/b".chars().collect::<Vec<_>>(), &"a/x/y/b".chars().collect::<Vec<_>>()));
+    }
+}
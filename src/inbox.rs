@@ -0,0 +1,78 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::Duration,
+};
+
+/// Watches the inbox folder for tracks that appear while musix runs, such as finished downloads.
+pub struct Inbox {
+    pub arrivals: Receiver<PathBuf>,
+}
+
+impl Inbox {
+    /// Scan `folder` with `scan` every `interval` on a background thread. Files there at the start are not
+    /// arrivals, and a new file is only reported once its size held still between two scans, so a download
+    /// still being written isn't played half-finished.
+    pub fn watch(folder: PathBuf, interval: Duration, scan: fn(&Path) -> Vec<PathBuf>) -> Self {
+        let (sender, arrivals) = mpsc::channel();
+        let mut known: HashSet<PathBuf> = scan(&folder).into_iter().collect();
+        thread::spawn(move || {
+            let size = |path: &Path| fs::metadata(path).map(|metadata| metadata.len()).ok();
+            // Sizes of new files at the last scan
+            let mut pending: HashMap<PathBuf, Option<u64>> = HashMap::new();
+            loop {
+                thread::sleep(interval);
+                let found = scan(&folder);
+                // Deleted files count as new if they come back
+                let present: HashSet<&PathBuf> = found.iter().collect();
+                known.retain(|path| present.contains(path));
+                let mut still_pending = HashMap::new();
+                for path in found {
+                    if known.contains(&path) {
+                        continue;
+                    }
+                    let now = size(&path);
+                    if pending.get(&path).is_some_and(|&before| before == now && now.is_some()) {
+                        known.insert(path.clone());
+                        if sender.send(path).is_err() {
+                            return;
+                        }
+                    } else {
+                        still_pending.insert(path, now);
+                    }
+                }
+                pending = still_pending;
+            }
+        });
+        Inbox { arrivals }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    fn scan(dir: &Path) -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_reports_only_new_files_once_settled() {
+        let folder = TestDir::new("inbox");
+        fs::write(folder.join("old.mp3"), b"old").unwrap();
+
+        let inbox = Inbox::watch(folder.to_path_buf(), Duration::from_millis(20), scan);
+        thread::sleep(Duration::from_millis(50));
+        fs::write(folder.join("new.mp3"), b"new").unwrap();
+
+        let arrival = inbox.arrivals.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(arrival, folder.join("new.mp3"));
+        assert!(inbox.arrivals.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+}
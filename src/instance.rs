@@ -0,0 +1,231 @@
+use std::{
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, mpsc::Receiver},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{nowplaying::NowPlaying, paths};
+
+const SOCKET_FILE: &str = "musix.sock";
+
+/// What a second `musix` asks the running one to do, sent as one JSON line over the socket.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Request {
+    Play(PathBuf),
+    // Answered from the socket thread with the last published track, without waiting on the player
+    NowPlaying,
+}
+
+pub fn socket_path() -> PathBuf {
+    paths::file_in(paths::state_dir(), SOCKET_FILE)
+}
+
+/// Ask a yes/no question on the terminal before the player takes it over; Enter alone picks `default`.
+pub fn confirm(question: &str, default: bool) -> bool {
+    print!("{question}");
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return default;
+    }
+    match answer.trim().to_lowercase().as_str() {
+        "" => default,
+        answer => answer.starts_with('y'),
+    }
+}
+
+/// The socket of the running player, which takes requests while it plays and is removed when it quits.
+pub struct Server {
+    path: PathBuf,
+    pub requests: Receiver<Request>,
+    now_playing: Arc<Mutex<Option<NowPlaying>>>,
+}
+
+impl Server {
+    /// Keep what `musix now-playing` is told up to date.
+    pub fn publish(&self, now_playing: NowPlaying) {
+        *self.now_playing.lock().unwrap() = Some(now_playing);
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::{
+        fs,
+        io::{self, BufRead, BufReader, Write},
+        os::unix::net::{UnixListener, UnixStream},
+        path::Path,
+        sync::{Arc, Mutex, mpsc},
+        thread,
+        time::Duration,
+    };
+
+    use super::{Request, Server};
+
+    // Longest a reply is waited for from a player that stopped answering, so a status line running
+    // `musix now-playing` never hangs
+    const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+    // Longest a connection is given to send its request, well inside `QUERY_TIMEOUT`, so a client that connects
+    // and says nothing only holds up the ones after it briefly
+    const REQUEST_TIMEOUT: Duration = Duration::from_millis(500);
+
+    pub fn is_running(socket: &Path) -> bool {
+        UnixStream::connect(socket).is_ok()
+    }
+
+    pub fn send(socket: &Path, request: &Request) -> io::Result<String> {
+        let mut stream = UnixStream::connect(socket)?;
+        stream.set_read_timeout(Some(QUERY_TIMEOUT))?;
+        let line = serde_json::to_string(request).map_err(io::Error::other)?;
+        writeln!(stream, "{line}")?;
+        // Wait for the reply so the request isn't lost if this process exits first
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply)?;
+        Ok(reply)
+    }
+
+    pub fn serve(socket: &Path) -> io::Result<Server> {
+        if let Some(dir) = socket.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Left behind by a player that crashed; a live one was ruled out by `is_running`
+        let _ = fs::remove_file(socket);
+        let listener = UnixListener::bind(socket)?;
+        let (sender, requests) = mpsc::channel();
+        let now_playing = Arc::new(Mutex::new(None));
+        let published = Arc::clone(&now_playing);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if stream.set_read_timeout(Some(REQUEST_TIMEOUT)).is_err() {
+                    continue;
+                }
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+                if reader.read_line(&mut line).is_err() {
+                    continue;
+                }
+                match serde_json::from_str(&line) {
+                    Ok(Request::NowPlaying) => {
+                        let reply = serde_json::to_string(&*published.lock().unwrap()).unwrap_or_default();
+                        let _ = writeln!(&stream, "{reply}");
+                    }
+                    Ok(request) => {
+                        if sender.send(request).is_err() {
+                            break;
+                        }
+                        let _ = (&stream).write_all(b"ok\n");
+                    }
+                    Err(_) => {}
+                }
+            }
+        });
+        Ok(Server {
+            path: socket.to_path_buf(),
+            requests,
+            now_playing,
+        })
+    }
+}
+
+// No socket to share elsewhere, so every start is a player of its own
+#[cfg(not(unix))]
+mod platform {
+    use std::{io, path::Path};
+
+    use super::{Request, Server};
+
+    pub fn is_running(_socket: &Path) -> bool {
+        false
+    }
+
+    pub fn send(_socket: &Path, _request: &Request) -> io::Result<String> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    pub fn serve(_socket: &Path) -> io::Result<Server> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// Whether another player is listening on `socket`.
+pub fn is_running(socket: &Path) -> bool {
+    platform::is_running(socket)
+}
+
+pub fn send(socket: &Path, request: &Request) -> io::Result<()> {
+    platform::send(socket, request).map(drop)
+}
+
+/// The track the player on `socket` last published; None while it has published nothing yet.
+pub fn query_now_playing(socket: &Path) -> io::Result<Option<NowPlaying>> {
+    let reply = platform::send(socket, &Request::NowPlaying)?;
+    serde_json::from_str(&reply).map_err(io::Error::other)
+}
+
+/// Listen on `socket` for requests from later invocations.
+pub fn serve(socket: &Path) -> io::Result<Server> {
+    platform::serve(socket)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::nowplaying::Status;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_requests_reach_the_running_player() {
+        let dir = TestDir::new("instance");
+        let socket = dir.join("instance.sock");
+        assert!(!is_running(&socket));
+
+        let server = serve(&socket).unwrap();
+        assert!(is_running(&socket));
+        // A client that connects and never writes doesn't hold up the next one
+        let _silent = std::os::unix::net::UnixStream::connect(&socket).unwrap();
+        send(&socket, &Request::Play(PathBuf::from("/music/a.mp3"))).unwrap();
+        // The probe from `is_running` sent nothing, so the play request is the only one
+        assert_eq!(
+            server.requests.recv_timeout(Duration::from_secs(5)).unwrap(),
+            Request::Play(PathBuf::from("/music/a.mp3"))
+        );
+
+        drop(server);
+        assert!(!socket.exists());
+    }
+
+    #[test]
+    fn test_now_playing_queries_are_answered_on_the_socket() {
+        let dir = TestDir::new("query");
+        let socket = dir.join("query.sock");
+        let server = serve(&socket).unwrap();
+
+        // Answered without reaching the player
+        assert_eq!(query_now_playing(&socket).unwrap(), None);
+        let now_playing = NowPlaying {
+            status: Status::Paused,
+            title: "Song".to_string(),
+            artist: None,
+            album: Some("Album".to_string()),
+            position: 10,
+            duration: None,
+        };
+        server.publish(now_playing.clone());
+        assert_eq!(query_now_playing(&socket).unwrap(), Some(now_playing));
+        assert!(server.requests.try_recv().is_err());
+
+        drop(server);
+        assert!(!socket.exists());
+    }
+}
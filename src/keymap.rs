@@ -0,0 +1,232 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// What a key of the main view does, run by the event loop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Shuffle,
+    Repeat,
+    Rate,
+    Problems,
+    Demoted,
+    SaveForLater,
+    Revisit,
+    LoopStart,
+    LoopEnd,
+    ClearLoop,
+    Equalizer,
+    Radio,
+    Stats,
+    Record,
+    Lock,
+    Details,
+    Bpm,
+    MissingBpm,
+    Sort,
+    Performance,
+    TrackEnd,
+    SkipIntro,
+    SkipOutro,
+    YankName,
+    YankPath,
+    Reveal,
+    EditList,
+    Preview,
+    TakePreview,
+    VolumeUp,
+    VolumeDown,
+    Mute,
+    Crossfade,
+    RetryAudio,
+    AddFolder,
+    Sources,
+    CommandLine,
+    Controls,
+}
+
+impl Action {
+    // Everything else is typed into the query during a search
+    fn in_search(self) -> bool {
+        matches!(self, Action::Lock | Action::Reveal)
+    }
+}
+
+/// How a line of the help is bound.
+pub enum Keys {
+    // Run from this table
+    Table(&'static [(KeyCode, KeyModifiers, Action)]),
+    // Handled in the event loop itself, as they depend on the search, a count or a chord
+    Loop(&'static str),
+}
+
+pub struct Binding {
+    pub keys: Keys,
+    // Locale key of what they do
+    pub help: &'static str,
+}
+
+const NONE: KeyModifiers = KeyModifiers::NONE;
+const SHIFT: KeyModifiers = KeyModifiers::SHIFT;
+const CONTROL: KeyModifiers = KeyModifiers::CONTROL;
+
+const fn key(c: char, modifiers: KeyModifiers, action: Action) -> (KeyCode, KeyModifiers, Action) {
+    (KeyCode::Char(c), modifiers, action)
+}
+
+const fn bound(keys: &'static [(KeyCode, KeyModifiers, Action)], help: &'static str) -> Binding {
+    Binding { keys: Keys::Table(keys), help }
+}
+
+const fn handled(keys: &'static str, help: &'static str) -> Binding {
+    Binding { keys: Keys::Loop(keys), help }
+}
+
+/// The keys of the main view, in the order of the help popup.
+pub const BINDINGS: &[Binding] = &[
+    handled("↑/↓ or j/k", "help.navigate"),
+    handled("Space/↵", "help.play_pause"),
+    handled("←/→ or h/l", "help.prev_next"),
+    handled("gg/G", "help.first_last"),
+    handled("5j, 12G", "help.count"),
+    handled("ma / 'a", "help.marks"),
+    handled("/", "help.search"),
+    handled("n/N", "help.search_next"),
+    handled(",/.", "help.seek"),
+    handled("S-←/→", "help.scrub"),
+    bound(&[key('r', NONE, Action::Shuffle)], "help.random"),
+    bound(&[key('t', NONE, Action::Repeat)], "help.repeat"),
+    bound(&[key('*', NONE, Action::Rate), key('*', SHIFT, Action::Rate)], "help.rate"),
+    bound(&[key('!', NONE, Action::Problems)], "help.problems"),
+    bound(&[key('D', SHIFT, Action::Demoted)], "help.demoted"),
+    bound(&[key('w', NONE, Action::SaveForLater), key('W', SHIFT, Action::Revisit)], "help.revisit"),
+    bound(
+        &[
+            key('[', NONE, Action::LoopStart),
+            key(']', NONE, Action::LoopEnd),
+            key('\\', NONE, Action::ClearLoop),
+        ],
+        "help.ab_loop",
+    ),
+    handled("Qa / @a", "help.macros"),
+    bound(&[key('E', SHIFT, Action::Equalizer)], "help.equalizer"),
+    bound(&[key('R', SHIFT, Action::Radio)], "help.radio"),
+    bound(&[key('S', SHIFT, Action::Stats)], "help.stats"),
+    bound(&[key('r', CONTROL, Action::Record)], "help.record"),
+    bound(&[key('l', CONTROL, Action::Lock)], "help.lock"),
+    bound(&[key('i', NONE, Action::Details)], "help.details"),
+    bound(&[key('b', NONE, Action::Bpm), key('B', SHIFT, Action::MissingBpm)], "help.bpm"),
+    bound(&[key('s', NONE, Action::Sort)], "help.sort"),
+    bound(&[key('p', NONE, Action::Performance)], "help.performance"),
+    bound(&[key('e', NONE, Action::TrackEnd)], "help.end_action"),
+    bound(&[key('I', SHIFT, Action::SkipIntro), key('O', SHIFT, Action::SkipOutro)], "help.skip_intro"),
+    bound(&[key('y', NONE, Action::YankName), key('Y', SHIFT, Action::YankPath)], "help.yank"),
+    bound(&[key('o', CONTROL, Action::Reveal)], "help.reveal"),
+    bound(&[key('e', CONTROL, Action::EditList)], "help.edit_list"),
+    bound(&[key('v', NONE, Action::Preview), key('V', SHIFT, Action::TakePreview)], "help.preview"),
+    // `=` too, as it is `+` without Shift on most keyboards; `+` comes with Shift on some terminals
+    bound(
+        &[
+            key('+', NONE, Action::VolumeUp),
+            key('+', SHIFT, Action::VolumeUp),
+            key('=', NONE, Action::VolumeUp),
+            key('-', NONE, Action::VolumeDown),
+        ],
+        "help.volume",
+    ),
+    // Plain `m` starts a mark, as in vim
+    bound(&[key('M', SHIFT, Action::Mute)], "help.mute"),
+    bound(&[key('X', SHIFT, Action::Crossfade)], "help.crossfade"),
+    bound(&[key('A', SHIFT, Action::RetryAudio)], "help.retry_audio"),
+    bound(&[key('o', NONE, Action::AddFolder)], "help.add_folder"),
+    bound(&[key('L', SHIFT, Action::Sources)], "help.sources"),
+    bound(&[key(':', NONE, Action::CommandLine), key(':', SHIFT, Action::CommandLine)], "help.command"),
+    handled(":adddir", "help.adddir"),
+    handled(":fadein", "help.fade"),
+    handled(":start", "help.trim"),
+    handled(":share", "help.share"),
+    handled(":setlist", "help.setlist"),
+    handled("q/Esc", "help.quit"),
+    bound(&[key('x', NONE, Action::Controls)], "help.close"),
+];
+
+fn table_keys() -> impl Iterator<Item = &'static (KeyCode, KeyModifiers, Action)> {
+    BINDINGS
+        .iter()
+        .filter_map(|binding| match binding.keys {
+            Keys::Table(keys) => Some(keys),
+            Keys::Loop(_) => None,
+        })
+        .flatten()
+}
+
+/// The action the table binds `key` to; during a search only the few that don't type into the query.
+pub fn action(key: &KeyEvent, searching: bool) -> Option<Action> {
+    table_keys()
+        .find(|(code, modifiers, _)| *code == key.code && *modifiers == key.modifiers)
+        .map(|&(_, _, action)| action)
+        .filter(|action| !searching || action.in_search())
+}
+
+fn key_name(code: KeyCode, modifiers: KeyModifiers) -> String {
+    match code {
+        KeyCode::Char(c) if modifiers.contains(KeyModifiers::CONTROL) => format!("Ctrl+{c}"),
+        KeyCode::Char(c) => c.to_string(),
+        code => format!("{code:?}"),
+    }
+}
+
+/// The keys of a help line as shown: `Ctrl+r`, `w / W`; a key bound with and without Shift is shown once.
+pub fn label(keys: &Keys) -> String {
+    match keys {
+        Keys::Table(keys) => {
+            let mut names: Vec<String> = Vec::new();
+            for &(code, modifiers, _) in *keys {
+                let name = key_name(code, modifiers);
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+            names.join(" / ")
+        }
+        Keys::Loop(text) => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bindings_do_not_conflict() {
+        let keys: Vec<_> = table_keys().collect();
+        for (i, (code, modifiers, action)) in keys.iter().enumerate() {
+            assert!(
+                !keys[i + 1..]
+                    .iter()
+                    .any(|(other, other_modifiers, _)| other == code && other_modifiers == modifiers),
+                "{code:?} with {modifiers:?} is bound twice"
+            );
+            // Chord prefixes never reach the table
+            if let KeyCode::Char(c) = code
+                && *modifiers != CONTROL
+            {
+                assert!(!['g', 'm', '\'', 'Q', '@', 'd'].contains(c), "{c} is a chord prefix, bound to {action:?}");
+            }
+        }
+
+        // Every key of the table is in the README's key table too
+        let readme = include_str!("../README.md");
+        for (code, modifiers, _) in keys {
+            let name = key_name(*code, *modifiers);
+            assert!(readme.contains(&format!("`{name}`")), "{name} is missing from the README");
+        }
+
+        assert_eq!(label(&BINDINGS[0].keys), "↑/↓ or j/k");
+        let action_of = |c, modifiers| action(&KeyEvent::new(KeyCode::Char(c), modifiers), false);
+        assert_eq!(action_of('+', SHIFT), Some(Action::VolumeUp));
+        assert_eq!(action_of('w', CONTROL), None);
+        assert_eq!(action(&KeyEvent::new(KeyCode::Char('r'), NONE), true), None, "typed into the search");
+        assert_eq!(action(&KeyEvent::new(KeyCode::Char('l'), CONTROL), true), Some(Action::Lock));
+        let volume = BINDINGS.iter().find(|binding| binding.help == "help.volume").unwrap();
+        assert_eq!(label(&volume.keys), "+ / = / -");
+    }
+}
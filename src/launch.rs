@@ -0,0 +1,37 @@
+use std::{
+    ffi::OsStr,
+    io,
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+};
+
+// The desktop's opener, which hands a URL to the browser and a folder to the file manager
+#[cfg(target_os = "macos")]
+const OPENER: &str = "open";
+#[cfg(windows)]
+const OPENER: &str = "explorer";
+#[cfg(not(any(target_os = "macos", windows)))]
+const OPENER: &str = "xdg-open";
+
+/// Open a URL or a path with the application the desktop uses for it, without waiting for it to finish.
+pub fn open(target: impl AsRef<OsStr>) -> io::Result<()> {
+    spawn(Command::new(OPENER).arg(target))
+}
+
+/// Show `path` in the file manager: selected in its folder on macOS and Windows, its folder opened elsewhere.
+pub fn reveal(path: &Path) -> io::Result<()> {
+    #[cfg(target_os = "macos")]
+    return spawn(Command::new(OPENER).arg("-R").arg(path));
+    #[cfg(windows)]
+    return spawn(Command::new(OPENER).arg(format!("/select,{}", path.display())));
+    #[cfg(not(any(target_os = "macos", windows)))]
+    open(path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new(".")))
+}
+
+fn spawn(command: &mut Command) -> io::Result<()> {
+    let mut child = command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).spawn()?;
+    // Reaped in the background so it doesn't linger as a zombie
+    thread::spawn(move || child.wait());
+    Ok(())
+}
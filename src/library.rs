@@ -0,0 +1,209 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::paths;
+
+const LIBRARY_FILE: &str = "library.json";
+
+/// Per-track data musix learns about a file, keyed by its path.
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct TrackData {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bpm: Option<f32>,
+    // Hot-cue number (1-9) to position in seconds
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub cues: BTreeMap<u8, f64>,
+    // Stars, 1-5
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rating: Option<u8>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub play_count: u32,
+    // Unix timestamp of the last time playback started
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_played: Option<i64>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub end_action: EndAction,
+    // Seconds cut off the start every time the track plays, e.g. a skit before the song
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skip_intro: Option<f64>,
+    // Seconds cut off the end, e.g. a podcast's closing credits
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skip_outro: Option<f64>,
+    // Seconds the volume ramps up from silence where the track starts playing, for one that starts abruptly
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fade_in: Option<f64>,
+    // Seconds the volume ramps down to silence before the track stops, for one that ends abruptly
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fade_out: Option<f64>,
+    // Skips before `[shuffle] early_skip_percent` of the track had played, since it was last heard to the end
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub early_skips: u32,
+    // Length in seconds, cached so the list can show it without probing the file again
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+}
+
+/// What happens when a track plays to its end.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum EndAction {
+    // Carry on with the next track as the playback mode has it
+    #[default]
+    Next,
+    Stop,
+    Repeat,
+}
+
+impl EndAction {
+    pub fn next(self) -> Self {
+        match self {
+            EndAction::Next => EndAction::Stop,
+            EndAction::Stop => EndAction::Repeat,
+            EndAction::Repeat => EndAction::Next,
+        }
+    }
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+fn is_zero(count: &u32) -> bool {
+    *count == 0
+}
+
+/// The library database, stored as JSON in the musix data directory.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Library {
+    #[serde(default)]
+    tracks: BTreeMap<String, TrackData>,
+    // Where `save` writes to; None keeps the library in memory only
+    #[serde(skip)]
+    file: Option<PathBuf>,
+}
+
+fn track_key(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+impl Library {
+    /// Load the library from disk; a missing or unreadable file starts an empty one.
+    pub fn load() -> Self {
+        Self::load_from(paths::file_in(paths::data_dir(), LIBRARY_FILE))
+    }
+
+    pub fn load_from(file: PathBuf) -> Self {
+        let mut library: Library = fs::read_to_string(&file)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        library.file = Some(file);
+        library
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        paths::write_atomic(file, json)
+    }
+
+    /// The paths of every track with an entry, as stored.
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.tracks.keys().map(String::as_str)
+    }
+
+    /// Re-key the entries `rename` gives a new path for, returning how many moved; one already at the new path is
+    /// replaced, since the data from before the move is what there is to keep.
+    pub fn rename_paths(&mut self, rename: impl Fn(&str) -> Option<String>) -> usize {
+        let renamed: Vec<(String, String)> = self.tracks.keys().filter_map(|key| rename(key).map(|path| (key.clone(), path))).collect();
+        for (key, path) in &renamed {
+            if let Some(data) = self.tracks.remove(key) {
+                self.tracks.insert(path.clone(), data);
+            }
+        }
+        renamed.len()
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&TrackData> {
+        self.tracks.get(&track_key(path))
+    }
+
+    pub fn entry(&mut self, path: &Path) -> &mut TrackData {
+        self.tracks.entry(track_key(path)).or_default()
+    }
+
+    pub fn bpm(&self, path: &Path) -> Option<f32> {
+        self.get(path).and_then(|track| track.bpm)
+    }
+
+    pub fn cue(&self, path: &Path, number: u8) -> Option<f64> {
+        self.get(path).and_then(|track| track.cues.get(&number).copied())
+    }
+
+    pub fn end_action(&self, path: &Path) -> EndAction {
+        self.get(path).map(|track| track.end_action).unwrap_or_default()
+    }
+
+    pub fn skip_intro(&self, path: &Path) -> Option<f64> {
+        self.get(path).and_then(|track| track.skip_intro)
+    }
+
+    pub fn skip_outro(&self, path: &Path) -> Option<f64> {
+        self.get(path).and_then(|track| track.skip_outro)
+    }
+
+    pub fn fades(&self, path: &Path) -> (Option<f64>, Option<f64>) {
+        self.get(path).map_or((None, None), |track| (track.fade_in, track.fade_out))
+    }
+
+    pub fn duration(&self, path: &Path) -> Option<Duration> {
+        self.get(path).and_then(|track| track.duration).map(Duration::from_secs_f64)
+    }
+
+    pub fn early_skips(&self, path: &Path) -> u32 {
+        self.get(path).map_or(0, |track| track.early_skips)
+    }
+
+    pub fn record_play(&mut self, path: &Path, timestamp: i64) {
+        let track = self.entry(path);
+        track.play_count += 1;
+        track.last_played = Some(timestamp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_library_round_trip() {
+        let dir = TestDir::new("library");
+        let file = dir.join("library.json");
+        let mut library = Library::load_from(file.clone());
+        assert_eq!(library.bpm(Path::new("/music/a.mp3")), None);
+
+        library.entry(Path::new("/music/a.mp3")).bpm = Some(128.0);
+        library.entry(Path::new("/music/a.mp3")).cues.insert(3, 61.5);
+        library.entry(Path::new("/music/a.mp3")).end_action = EndAction::Repeat;
+        library.entry(Path::new("/music/a.mp3")).skip_intro = Some(20.0);
+        library.save().unwrap();
+
+        let reloaded = Library::load_from(file.clone());
+        assert_eq!(reloaded.bpm(Path::new("/music/a.mp3")), Some(128.0));
+        assert_eq!(reloaded.cue(Path::new("/music/a.mp3"), 3), Some(61.5));
+        assert_eq!(reloaded.cue(Path::new("/music/a.mp3"), 1), None);
+        assert_eq!(reloaded.end_action(Path::new("/music/a.mp3")), EndAction::Repeat);
+        assert_eq!(reloaded.skip_intro(Path::new("/music/a.mp3")), Some(20.0));
+        assert_eq!(reloaded.end_action(Path::new("/music/b.mp3")), EndAction::Next);
+        assert_eq!(reloaded.get(Path::new("/music/b.mp3")), None);
+    }
+}
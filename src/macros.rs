@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+use crossterm::event::KeyEvent;
+
+/// Key sequences recorded into registers with `Q{register}` and played back with `@{register}`, like vim's `q` and
+/// `@`; `@@` plays the register played last. Registers are `a`-`z` and `0`-`9`, and last until the player quits.
+#[derive(Default, Debug)]
+pub struct Macros {
+    registers: HashMap<char, Vec<KeyEvent>>,
+    // The register being recorded into, and the keys so far
+    recording: Option<(char, Vec<KeyEvent>)>,
+    last: Option<char>,
+}
+
+pub fn is_register(c: char) -> bool {
+    c.is_ascii_lowercase() || c.is_ascii_digit()
+}
+
+impl Macros {
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|(register, _)| *register)
+    }
+
+    pub fn start(&mut self, register: char) {
+        self.recording = Some((register, Vec::new()));
+    }
+
+    /// Keep `key` in the macro being recorded, if any.
+    pub fn record(&mut self, key: KeyEvent) {
+        if let Some((_, keys)) = &mut self.recording {
+            keys.push(key);
+        }
+    }
+
+    /// Finish recording into the register, returning it and how many keys went in. The `Q` that stopped the
+    /// recording was recorded like any key, so it is taken off again.
+    pub fn stop(&mut self) -> Option<(char, usize)> {
+        let (register, mut keys) = self.recording.take()?;
+        keys.pop();
+        let count = keys.len();
+        self.registers.insert(register, keys);
+        Some((register, count))
+    }
+
+    /// The keys in `register` (`@` for the one played last), remembered as the one played last; None for an empty
+    /// register.
+    pub fn keys(&mut self, register: char) -> Option<&[KeyEvent]> {
+        let register = if register == '@' { self.last? } else { register };
+        let keys = self.registers.get(&register).filter(|keys| !keys.is_empty())?;
+        self.last = Some(register);
+        Some(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyCode, KeyModifiers};
+
+    use super::*;
+
+    fn press(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_record_and_replay_registers() {
+        let mut macros = Macros::default();
+        macros.record(press('j'));
+        assert_eq!(macros.stop(), None);
+
+        macros.start('a');
+        assert_eq!(macros.recording(), Some('a'));
+        for c in ['*', 'j', 'Q'] {
+            macros.record(press(c));
+        }
+        assert_eq!(macros.stop(), Some(('a', 2)));
+        assert_eq!(macros.recording(), None);
+
+        assert_eq!(macros.keys('@'), None);
+        assert_eq!(macros.keys('a'), Some(&[press('*'), press('j')][..]));
+        assert_eq!(macros.keys('@'), Some(&[press('*'), press('j')][..]));
+        assert_eq!(macros.keys('b'), None);
+
+        // Recording nothing empties the register
+        macros.start('a');
+        macros.record(press('Q'));
+        assert_eq!(macros.stop(), Some(('a', 0)));
+        assert_eq!(macros.keys('a'), None);
+        assert!(is_register('z') && is_register('3') && !is_register('A'));
+    }
+}
@@ -1,14 +1,24 @@
 use std::{
-    env, fs, io,
-    path::PathBuf,
-    sync::{Arc, Mutex},
-    time::{Duration, Instant},
+    cmp::{Ordering, Reverse},
+    collections::{HashMap, HashSet, VecDeque},
+    env,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver, TryRecvError},
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
+use clap::Parser;
 use crossterm::{
+    cursor::Show,
     event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
     execute,
-    terminal::{EnterAlternateScreen, LeaveAlternateScreen, SetTitle, disable_raw_mode, enable_raw_mode},
+    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
 use ratatui::{
     Frame, Terminal,
@@ -16,22 +26,213 @@ use ratatui::{
     layout::{Alignment, Constraint, Direction, Layout},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Gauge, List, ListItem, ListState, Paragraph},
+    widgets::{BarChart, Block, Borders, Gauge, List, ListItem, ListState, Paragraph, Wrap},
 };
-use rodio::{Decoder, OutputStream, Sink, Source};
+use rodio::{Decoder, OutputStreamHandle, Sink, Source};
 use symphonia::core::formats::FormatOptions;
 use symphonia::core::io::MediaSourceStream;
 use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::Hint;
+use unicode_normalization::{UnicodeNormalization, char::is_combining_mark};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+mod alert;
+mod announce;
+mod auto_skip;
+mod bpm;
+mod chapters;
+mod check;
+mod chord;
+mod cli;
+mod clipboard;
+mod columns;
+mod config;
+mod convert;
+mod cover_art;
+mod dir_picker;
+mod editor;
+mod equalizer;
+mod error;
+mod events;
+mod fade;
+mod history;
+mod i18n;
+mod icons;
+mod idle;
+mod ignore;
+mod inbox;
+mod instance;
+mod keymap;
+mod launch;
+mod library;
+mod macros;
+mod media_controls;
+mod natural;
+mod nowplaying;
+mod output;
+mod paths;
+mod podcast;
+mod prefetch;
+mod preview;
+mod radio;
+mod recording;
+mod relocate;
+mod repeat;
+mod replaygain;
+mod revisit;
+mod rgscan;
+mod row_colors;
+mod scrobble;
+mod search_history;
+mod seek;
+mod session;
+mod setlist;
+mod share;
+mod shuffle;
+#[cfg(feature = "tts")]
+mod speech;
+mod stats;
+mod stream;
+mod sync;
+mod tags;
+mod term_title;
+#[cfg(test)]
+mod test_dir;
+mod transition;
+
+use error::MusixError;
+use i18n::{t, t_args};
 
 #[derive(Clone)]
 struct Song {
     name: String,
     path: PathBuf,
+    // Set when the file failed to open or decode; such songs are skipped by auto-advance
+    error: Option<String>,
+    // Normalized name used for matching search queries
+    search_key: String,
+    // Romaji/pinyin spelling of non-Latin names, also matched by search
+    romanized_key: Option<String>,
+    // Internet radio station URL; `path` then points at the playlist it came from
+    stream_url: Option<String>,
+}
+
+impl Song {
+    fn new(name: String, path: PathBuf) -> Self {
+        let search_key = normalize_for_search(&name);
+        let romanized_key = romanize(&name);
+        Song {
+            name,
+            path,
+            error: None,
+            search_key,
+            romanized_key,
+            stream_url: None,
+        }
+    }
+
+    // A local track named after its file
+    fn from_path(path: PathBuf) -> Self {
+        let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("Unknown").to_string();
+        Song::new(name, path)
+    }
+
+    fn station(name: String, playlist: PathBuf, url: String) -> Self {
+        Song {
+            stream_url: Some(url),
+            ..Song::new(name, playlist)
+        }
+    }
+}
+
+// Cut or pad text to exactly `width` columns, on the left for right-aligned cells
+fn fit_to_width(text: &str, width: usize, right_align: bool) -> String {
+    let text = truncate_to_width(text, width);
+    let padding = " ".repeat(width.saturating_sub(text.width()));
+    if right_align { padding + &text } else { text + &padding }
+}
+
+// Fold case, compatibility forms and diacritics so "beyonce" matches "Beyoncé"
+fn normalize_for_search(text: &str) -> String {
+    text.nfkd().filter(|c| !is_combining_mark(*c)).flat_map(char::to_lowercase).collect()
+}
+
+// Cut text to fit `max_width` terminal columns, marking the cut with an ellipsis.
+// Widths come from unicode-width so CJK and emoji count as two columns.
+fn truncate_to_width(text: &str, max_width: usize) -> String {
+    if text.width() <= max_width {
+        return text.to_string();
+    }
+    if max_width == 0 {
+        return String::new();
+    }
+
+    let mut result = String::new();
+    let mut width = 0;
+    for c in text.chars() {
+        let char_width = c.width().unwrap_or(0);
+        if width + char_width > max_width - 1 {
+            break;
+        }
+        width += char_width;
+        result.push(c);
+    }
+    result.push('…');
+    result
+}
+
+#[cfg(feature = "romanize")]
+fn romanize(text: &str) -> Option<String> {
+    if text.is_ascii() {
+        return None;
+    }
+    let romanized = normalize_for_search(&any_ascii::any_ascii(text));
+    if romanized == normalize_for_search(text) { None } else { Some(romanized) }
+}
+
+#[cfg(not(feature = "romanize"))]
+fn romanize(_text: &str) -> Option<String> {
+    None
 }
 
+// Terminal titles are cut to this many columns so tab bars stay readable
+const TITLE_MAX_WIDTH: usize = 60;
+const TOAST_DURATION: Duration = Duration::from_secs(3);
+// How long the status bar stays inverted for a flash alert
+const FLASH_DURATION: Duration = Duration::from_secs(1);
+// How often config.toml is looked at for edits made while playing
+const CONFIG_POLL: Duration = Duration::from_secs(1);
+// How often the position of a playing track is written to the sync file
+const SYNC_INTERVAL: Duration = Duration::from_secs(30);
+// Volume ramp when quitting mid-song, so headphones don't get an abrupt cut
+const QUIT_FADE: Duration = Duration::from_millis(300);
+const FADE_STEP: Duration = Duration::from_millis(10);
+// The second press of `q` or Esc that quits while playing has to come within this
+const QUIT_PRESS_WINDOW: Duration = Duration::from_secs(1);
+// Rows kept visible below the selection when scrolling the song list
+const LIST_SCROLL_PADDING: usize = 1;
+// Inbox arrivals waiting to play whose tags are read right after the ones on screen
+const TAGS_AHEAD: usize = 5;
+// The stream buffer gauge is full with this much audio waiting to play
+const BUFFER_GAUGE_FULL: Duration = Duration::from_secs(30);
+const BUFFER_GAUGE_CELLS: usize = 5;
+// A scrub is applied once no scrub key has come for this long, i.e. the keys were let go
+const SCRUB_SETTLE: Duration = Duration::from_millis(600);
+// The next track on an album is queued behind the playing one when this much of it is left
+const GAPLESS_LEAD: Duration = Duration::from_secs(5);
+// Enter or Space this soon after one that started a track, or after a track ended, counts as an accidental repeat
+const REPEAT_PRESS_WINDOW: Duration = Duration::from_millis(800);
+// How long the selection rests on a track before preview on select plays a snippet of it
+const SNIPPET_DWELL: Duration = Duration::from_secs(1);
+// How much `+` and `-` change the volume
+const VOLUME_STEP: f32 = 0.05;
+// Crossfade lengths `X` steps through, in seconds
+const CROSSFADE_STEPS: [f64; 6] = [0.0, 2.0, 4.0, 6.0, 8.0, 12.0];
+
 const HIGHLIGHT_COLOR: Color = Color::Rgb(0, 255, 150);
 const PRIMARY_COLOR: Color = Color::LightGreen;
+// The theme green, dimmed for the idle screen
+const IDLE_COLOR: Color = Color::Rgb(0, 110, 65);
 // const SECONDARY_COLOR: Color = Color::Rgb(200, 200, 200);
 
 // Smart color detection function
@@ -54,13 +255,12 @@ fn get_text_color() -> Color {
         if let Ok(colorfgbg) = env::var("COLORFGBG") {
             // COLORFGBG format is usually "foreground;background"
             // High background numbers (> 7) usually indicate light themes
-            if let Some(bg) = colorfgbg.split(';').nth(1) {
-                if let Ok(bg_num) = bg.parse::<u8>() {
-                    if bg_num > 7 {
-                        // Light background - use dark text
-                        return Color::Rgb(50, 50, 50);
-                    }
-                }
+            if let Some(bg) = colorfgbg.split(';').nth(1)
+                && let Ok(bg_num) = bg.parse::<u8>()
+                && bg_num > 7
+            {
+                // Light background - use dark text
+                return Color::Rgb(50, 50, 50);
             }
         }
 
@@ -69,27 +269,203 @@ fn get_text_color() -> Color {
     }
 }
 
+// What the folder chosen in the picker is for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FolderPick {
+    // Scan it and add its tracks to the list
+    AddTracks,
+    // Make it a library folder, scanned on every start
+    LibraryFolder,
+}
+
 struct Player {
     songs: Vec<Song>,
     current_index: usize,
     selected_index: usize,
     _stream: Option<Box<dyn std::any::Any>>,
-    _stream_handle: Option<Box<dyn std::any::Any>>,
+    stream_handle: Option<OutputStreamHandle>,
+    // What the output runs at, and every source is converted to
+    output_format: Option<output::OutputFormat>,
     sink: Option<Arc<Mutex<Sink>>>,
+    // The next track when it is already queued on the sink for a gapless start, and where in it that is
+    gapless_next: Option<(PathBuf, Duration)>,
+    // The previous track fading out under the one on `sink`
+    crossfade: Option<transition::Crossfade>,
+    // Why the audio output couldn't be opened, shown as a banner until `A` gets it working
+    audio_error: Option<String>,
+    // A track heard quietly on the side before it goes out with `V`
+    preview: Option<preview::Preview>,
+    // Preview on select: the track the selection rests on, and since when until its snippet has started
+    rested_on: usize,
+    resting_since: Option<Instant>,
     is_playing: bool,
     is_paused: bool,
-    loop_mode: bool,
-    random_mode: bool,
+    // 0.0 to 1.0 on the main output, kept across sessions
+    volume: f32,
+    // Silent without losing the volume to go back to
+    muted: bool,
+    repeat_mode: repeat::RepeatMode,
+    shuffle_mode: shuffle::ShuffleMode,
     list_state: ListState,
     playback_start: Option<Instant>,
     song_duration: Option<Duration>,
+    // Chapters of the playing track, marked on the progress bar
+    chapters: Vec<chapters::Chapter>,
     seek_offset: Duration,
+    // Where a scrub with Shift+←/→ or Alt+,/. will seek to, and when its key was last pressed
+    scrub: Option<(Duration, Instant)>,
+    // Points A and B set with `[` and `]`; once both are set, playback past B goes back to A
+    ab_loop: Option<(Duration, Option<Duration>)>,
+    // The track that last played to its end and when, which `avoid_repeats` keeps from playing again right away
+    just_finished: Option<(PathBuf, Instant)>,
+    // When Enter or Space last started a track
+    started_by_key: Option<Instant>,
     pause_time: Option<Instant>,
     show_controls_popup: bool,
+    show_problems_popup: bool,
+    problems_state: ListState,
+    // Tracks left out of shuffle for being skipped early, to put back with Enter
+    show_demoted_popup: bool,
+    demoted_state: ListState,
+    // Shared with every source playing, so the `E` panel is heard right away
+    equalizer: equalizer::Controls,
+    show_equalizer: bool,
+    equalizer_band: usize,
+    // Tracks put aside with `w` to play another time, popped from the `W` popup
+    revisit: revisit::Revisit,
+    show_revisit: bool,
+    revisit_state: ListState,
+    show_radio_browser: bool,
+    dir_picker: Option<(dir_picker::DirPicker, FolderPick)>,
+    // Library folders popup, with the highlighted folder
+    show_sources: bool,
+    sources_state: ListState,
+    // What is being typed after `:`
+    command_input: Option<String>,
+    radio_browser: radio::RadioBrowser,
     search_mode: bool,
     search_query: String,
     filtered_songs: Vec<usize>,
-    g_pressed: bool,
+    // Where the cursor was when the search began, and the query `n`/`N` repeat afterwards
+    search_origin: usize,
+    last_search: String,
+    search_history: search_history::SearchHistory,
+    // Count and prefix key of a vim-style sequence being typed (`5j`, `gg`, `ma`)
+    chords: chord::Chords,
+    macros: macros::Macros,
+    // Keys of a macro being played back, fed to the loop ahead of new input
+    replay: VecDeque<KeyEvent>,
+    // `m{a-z}` marks, by track so they survive re-sorting
+    marks: HashMap<char, (PathBuf, Option<String>)>,
+    // The track that plays after the current one, picked ahead so shuffle can show it before it starts
+    up_next: Option<(PathBuf, Option<String>)>,
+    // ICY details of the radio stream currently playing, if any
+    stream_info: Option<stream::SharedStreamInfo>,
+    stream_opening: Option<StreamOpening>,
+    // Times the playing stream ran dry so far, to tell about each new one
+    stream_stalls: u32,
+    stream_title: Option<String>,
+    record_tap: recording::RecordTap,
+    recording: Option<Recording>,
+    // Short-lived message shown in the status bar
+    toast: Option<(String, Instant)>,
+    // When the status bar last flashed for an alert
+    flash: Option<Instant>,
+    library: library::Library,
+    bpm_analyzer: bpm::BpmAnalyzer,
+    show_details: bool,
+    sort_by_bpm: bool,
+    // A list column the songs are sorted by instead of the name
+    sort_column: Option<columns::Column>,
+    // The list is in the order it was saved in from the editor, which sorting keeps until `s` picks a sort again
+    manual_order: bool,
+    // Number keys trigger hot cues of the current track
+    performance_mode: bool,
+    config: config::Config,
+    // The play in progress, logged to the history file once it ends
+    listen: Option<history::Listen>,
+    history_file: Option<PathBuf>,
+    // Unix timestamp of when the player started, where `:setlist` begins
+    opened: i64,
+    // Playback events for analysis, one JSON line each
+    event_log: Option<PathBuf>,
+    // Where changes made in the UI, like the library folders, are written back
+    config_file: Option<PathBuf>,
+    // When the config file was last looked at, and its modification time then
+    config_checked: Instant,
+    config_modified: Option<SystemTime>,
+    scrobbler: scrobble::Scrobbler,
+    show_stats_popup: bool,
+    stats: stats::Stats,
+    // Positions shared with other devices through a synced folder
+    sync: Option<sync::SyncFile>,
+    last_sync_save: Instant,
+    // Kiosk mode: quitting and library changes are disabled until unlocked with the PIN
+    locked: bool,
+    // `--ascii`, which holds over the config file's icons, also after a reload
+    ascii: bool,
+    pin_input: Option<String>,
+    // First press of a quit key while playing, waiting for the second
+    quit_pressed: Option<Instant>,
+    // The idle screen is shown after `[ui] idle_minutes` without a key press, since the time in it
+    last_input: Instant,
+    idle_since: Option<Instant>,
+    show_quit_prompt: bool,
+    now_playing_writer: Option<nowplaying::NowPlayingWriter>,
+    // Track changes as plain lines for screen readers, and spoken
+    announcer: Option<announce::Announcer>,
+    #[cfg(feature = "tts")]
+    speaker: Option<speech::Speaker>,
+    last_announcement: Option<String>,
+    // Snapshot for crash recovery and resuming on the next start
+    session_file: Option<PathBuf>,
+    last_snapshot: Instant,
+    // System media overlay (Windows SMTC)
+    media_controls: Option<media_controls::MediaControls>,
+    // Tags for the list columns and the row color rules, read in the background
+    tags: HashMap<PathBuf, tags::TrackTags>,
+    tag_reader: Option<tags::TagReader>,
+    // Files added while the reader was busy, read once it is done
+    tag_backlog: Vec<PathBuf>,
+    // What the reader was last told to read first, so it is only told again when that changes
+    tag_priority: Vec<PathBuf>,
+    // Socket later `musix <file>` invocations send their file to
+    instance: Option<instance::Server>,
+    inbox: Option<inbox::Inbox>,
+    // Folders added at runtime, walked on their own threads so input carries on meanwhile
+    folder_scans: Vec<(PathBuf, FolderScan)>,
+    // Inbox arrivals waiting to play, ahead of the playback mode's pick
+    inbox_queue: VecDeque<PathBuf>,
+    // Inbox arrivals not played yet, marked in the list
+    new_tracks: HashSet<PathBuf>,
+    // Lowercased artists of the last plays, newest first, which shuffle holds back
+    recent_artists: VecDeque<Option<String>>,
+    // Lengths were added to the library cache and are waiting to be saved
+    durations_changed: bool,
+    // Track count and total length of the list as shown, and whether some lengths are unknown; None when stale
+    view_summary: Option<(usize, Duration, bool)>,
+    // Cover of the track in the details panel
+    cover: cover_art::CoverLoader,
+}
+
+struct Recording {
+    path: PathBuf,
+    started: Instant,
+    // Streams are saved as received; local tracks go through the decoded-sample tap
+    is_stream: bool,
+}
+
+// The tracks of a folder being walked, or why it couldn't be
+type FolderScan = Receiver<Result<Vec<Song>, MusixError>>;
+
+// A station still connecting
+struct StreamOpening {
+    // Not its index, as the list may be re-sorted or cut down meanwhile
+    path: PathBuf,
+    url: String,
+    opening: stream::Opening,
+    // The recording to carry on into it
+    recording: Option<BufWriter<File>>,
 }
 
 impl Player {
@@ -98,1341 +474,7181 @@ impl Player {
             return;
         }
 
+        let name = truncate_to_width(&self.now_playing_name(), TITLE_MAX_WIDTH);
         let title = if self.is_playing {
-            format!("MUSIX - ♪ {}", self.songs[self.current_index].name)
+            format!("MUSIX - {} {name}", self.config.ui.icons.playing())
         } else {
-            format!("MUSIX - {} (Paused)", self.songs[self.current_index].name)
+            format!("MUSIX - {}", t_args("title.paused", &[("name", &name)]))
         };
 
-        let _ = execute!(io::stdout(), SetTitle(&title));
+        term_title::set(self.config.ui.terminal_title, &title);
     }
-    fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let songs = load_mp3_files()?;
-        if songs.is_empty() {
-            return Err("No MP3 files found".into());
+    fn new() -> Result<Self, MusixError> {
+        let config = config::Config::load().unwrap_or_else(|e| {
+            eprintln!("{}", t_args("message.config_invalid", &[("error", &e)]));
+            config::Config::default()
+        });
+        // An empty library still starts, so folders can be added from inside
+        let songs = scan_folders(&config.library.paths())?;
+
+        let mut player = Player::from_songs(songs);
+        player.library = library::Library::load();
+        player.history_file = Some(history::history_path());
+        player.event_log = Some(events::events_path());
+        player.equalizer = equalizer::Controls::new(config.equalizer.enabled, config.equalizer.gains());
+        player.config = config;
+        player.config_file = Some(config::config_path());
+        player.config_modified = player.config_file.as_deref().and_then(modified_time);
+        if let Some(folder) = player.config.inbox.path() {
+            // What is already in an inbox outside the music folders joins the library like any other track
+            if !player.config.library.paths().iter().any(|dir| folder.starts_with(dir)) {
+                player.songs.extend(scan_tracks(&folder).into_iter().map(Song::from_path));
+                player.sort_songs();
+            }
+            let interval = Duration::from_secs(player.config.inbox.interval.max(1));
+            player.inbox = Some(inbox::Inbox::watch(folder, interval, scan_tracks));
+        }
+        // Every file's tags, for the album order, columns and genre rules
+        let paths: Vec<PathBuf> = player
+            .songs
+            .iter()
+            .filter(|song| song.stream_url.is_none())
+            .map(|song| song.path.clone())
+            .collect();
+        if !paths.is_empty() {
+            player.tag_reader = Some(tags::TagReader::start(paths));
+        }
+        player.scrobbler = scrobble::Scrobbler::start(&player.config.scrobble, scrobble::queue_path());
+        player.now_playing_writer = player.config.now_playing.path().map(nowplaying::NowPlayingWriter::new);
+        player.open_announcer();
+        if let Some(path) = player.config.sync.path() {
+            player.sync = Some(sync::SyncFile::open(path));
+        }
+        player.search_history = search_history::SearchHistory::load(&search_history::search_history_path());
+        player.revisit = revisit::Revisit::load(&revisit::revisit_path());
+        let session_file = session::session_path();
+        let session = session::load(&session_file);
+        if let Some(session) = &session {
+            player.restore_session(session);
+        }
+        // A track played more recently on another device wins over the one left here
+        let synced_later = player
+            .sync
+            .as_ref()
+            .and_then(|sync| sync.state.last_played.as_ref())
+            .is_some_and(|last_played| session.as_ref().is_none_or(|session| last_played.updated > session.saved));
+        if synced_later {
+            player.restore_last_played();
+        } else if let Some(session) = &session {
+            if !session.clean_exit {
+                player.show_toast(t("message.session_recovered").to_string());
+            } else if player.seek_offset >= Duration::from_secs(1) {
+                let track = player.songs[player.current_index].name.clone();
+                let position = Player::format_duration(player.seek_offset);
+                player.show_toast(t_args("message.session_resumed", &[("track", &track), ("position", &position)]));
+            }
+        }
+        player.session_file = Some(session_file);
+        player.media_controls = media_controls::MediaControls::start();
+
+        // Without an output the player still browses, searches and edits the library; the banner offers a retry
+        if let Err(e) = player.init_audio() {
+            player.audio_error = Some(e.to_string());
+        }
+
+        // Set initial terminal title
+        if !player.songs.is_empty() {
+            let name = truncate_to_width(&player.songs[0].name, TITLE_MAX_WIDTH);
+            term_title::set(player.config.ui.terminal_title, &format!("MUSIX - {name}"));
+        } else {
+            term_title::set(player.config.ui.terminal_title, "MUSIX");
         }
 
+        Ok(player)
+    }
+
+    // Player state for a song list, without any audio output attached
+    fn from_songs(songs: Vec<Song>) -> Self {
         let mut list_state = ListState::default();
         list_state.select(Some(0));
 
-        // Initialize audio system with Rodio 0.20 API
-        let (stream, stream_handle, sink) = match OutputStream::try_default() {
-            Ok((stream, stream_handle)) => match Sink::try_new(&stream_handle) {
-                Ok(sink) => (
-                    Some(Box::new(stream) as Box<dyn std::any::Any>),
-                    Some(Box::new(stream_handle) as Box<dyn std::any::Any>),
-                    Some(Arc::new(Mutex::new(sink))),
-                ),
-                Err(e) => {
-                    eprintln!("Warning: Could not create audio sink: {e}");
-                    (
-                        Some(Box::new(stream) as Box<dyn std::any::Any>),
-                        Some(Box::new(stream_handle) as Box<dyn std::any::Any>),
-                        None,
-                    )
-                }
-            },
-            Err(e) => {
-                eprintln!("Warning: Could not initialize audio output: {e}");
-                eprintln!("The application will continue but audio playback may not work.");
-                (None, None, None)
-            }
-        };
-
         let filtered_songs: Vec<usize> = (0..songs.len()).collect();
 
-        let player = Player {
+        Player {
             songs,
             current_index: 0,
             selected_index: 0,
-            _stream: stream,
-            _stream_handle: stream_handle,
-            sink,
+            _stream: None,
+            stream_handle: None,
+            output_format: None,
+            sink: None,
+            gapless_next: None,
+            crossfade: None,
+            audio_error: None,
+            preview: None,
+            rested_on: 0,
+            resting_since: None,
             is_playing: false,
             is_paused: false,
-            loop_mode: true,
-            random_mode: false,
+            volume: 1.0,
+            muted: false,
+            repeat_mode: repeat::RepeatMode::All,
+            shuffle_mode: shuffle::ShuffleMode::Off,
             list_state,
             playback_start: None,
             song_duration: None,
+            chapters: Vec::new(),
             seek_offset: Duration::from_secs(0),
+            scrub: None,
+            ab_loop: None,
+            just_finished: None,
+            started_by_key: None,
             pause_time: None,
             show_controls_popup: false,
+            show_problems_popup: false,
+            problems_state: ListState::default(),
+            show_demoted_popup: false,
+            equalizer: equalizer::Controls::new(false, [0.0; 10]),
+            show_equalizer: false,
+            equalizer_band: 0,
+            revisit: revisit::Revisit::default(),
+            show_revisit: false,
+            revisit_state: ListState::default(),
+            demoted_state: ListState::default(),
+            show_radio_browser: false,
+            dir_picker: None,
+            show_sources: false,
+            sources_state: ListState::default().with_selected(Some(0)),
+            command_input: None,
+            radio_browser: radio::RadioBrowser::new(),
             search_mode: false,
+            search_origin: 0,
+            last_search: String::new(),
+            search_history: search_history::SearchHistory::default(),
             search_query: String::new(),
             filtered_songs,
-            g_pressed: false,
+            chords: chord::Chords::default(),
+            macros: macros::Macros::default(),
+            replay: VecDeque::new(),
+            marks: HashMap::new(),
+            up_next: None,
+            stream_info: None,
+            stream_opening: None,
+            stream_stalls: 0,
+            stream_title: None,
+            record_tap: recording::RecordTap::new(),
+            recording: None,
+            toast: None,
+            flash: None,
+            library: library::Library::default(),
+            bpm_analyzer: bpm::BpmAnalyzer::new(),
+            show_details: false,
+            sort_by_bpm: false,
+            sort_column: None,
+            manual_order: false,
+            performance_mode: false,
+            config: config::Config::default(),
+            listen: None,
+            history_file: None,
+            opened: chrono::Utc::now().timestamp(),
+            event_log: None,
+            config_file: None,
+            config_checked: Instant::now(),
+            config_modified: None,
+            scrobbler: scrobble::Scrobbler::disabled(),
+            show_stats_popup: false,
+            stats: stats::Stats::default(),
+            sync: None,
+            last_sync_save: Instant::now(),
+            locked: false,
+            ascii: false,
+            pin_input: None,
+            quit_pressed: None,
+            last_input: Instant::now(),
+            idle_since: None,
+            show_quit_prompt: false,
+            now_playing_writer: None,
+            announcer: None,
+            #[cfg(feature = "tts")]
+            speaker: None,
+            last_announcement: None,
+            session_file: None,
+            last_snapshot: Instant::now(),
+            media_controls: None,
+            tags: HashMap::new(),
+            tag_reader: None,
+            tag_priority: Vec::new(),
+            tag_backlog: Vec::new(),
+            instance: None,
+            inbox: None,
+            folder_scans: Vec::new(),
+            inbox_queue: VecDeque::new(),
+            new_tracks: HashSet::new(),
+            recent_artists: VecDeque::new(),
+            durations_changed: false,
+            view_summary: None,
+            cover: cover_art::CoverLoader::new(),
+        }
+    }
+
+    // Decode a local file levelled by its ReplayGain tags and with the track's fades, converted to the output format
+    // and routed through the recording tap
+    fn open_source(&self, path: &PathBuf) -> Result<AudioSource, MusixError> {
+        let source = create_audio_source(path)?.amplify(self.replaygain(path));
+        let source = fade::Fade::new(source, self.fade_envelope(path));
+        Ok(self.decode_ahead(self.to_output(source)))
+    }
+
+    // The track from `start`, for when its own decoder can't seek: symphonia's sample-accurate seek where it reads
+    // the format, otherwise decoding through to `start`, which takes a while that far into a long file
+    fn open_source_at(&self, path: &PathBuf, start: Duration) -> Result<AudioSource, MusixError> {
+        let (envelope, gain) = (self.fade_envelope(path), self.replaygain(path));
+        let source: AudioSource = match seek::open_at(path, start) {
+            Ok(source) => Box::new(fade::Fade::starting_at(source.amplify(gain), envelope, start)),
+            Err(_) => Box::new(fade::Fade::new(create_audio_source(path)?.amplify(gain), envelope).skip_duration(start)),
         };
+        Ok(self.decode_ahead(self.to_output(source)))
+    }
 
-        // Set initial terminal title
-        if !player.songs.is_empty() {
-            let _ = execute!(io::stdout(), SetTitle(&format!("MUSIX - {}", player.songs[0].name)));
-        } else {
-            let _ = execute!(io::stdout(), SetTitle("MUSIX"));
+    // The last step for a local track: recording taps it, and with `decode_ahead` set everything up to here runs on
+    // a decoding thread of its own rather than in the output callback
+    fn decode_ahead(&self, source: AudioSource) -> AudioSource {
+        let source = self.record_tap.wrap(source);
+        let ahead = Duration::try_from_secs_f64(self.config.playback.decode_ahead).unwrap_or_default();
+        if ahead.is_zero() {
+            return self.equalize(source);
+        }
+        match prefetch::Prefetch::new(source, ahead) {
+            Ok(prefetched) => self.equalize(prefetched),
+            // Decoded in the output callback after all, as without `decode_ahead`
+            Err(source) => self.equalize(source),
         }
+    }
 
-        Ok(player)
+    // After the decode-ahead buffer, so a change in the equalizer panel isn't held up behind what is buffered
+    fn equalize<S>(&self, source: S) -> AudioSource
+    where
+        S: Source<Item = i16> + Send + 'static,
+    {
+        Box::new(self.equalizer.wrap(source))
+    }
+
+    fn to_output<S>(&self, source: S) -> AudioSource
+    where
+        S: Source<Item = i16> + Send + 'static,
+    {
+        match self.output_format {
+            Some(format) => Box::new(format.convert(source)),
+            None => Box::new(source),
+        }
+    }
+
+    // Applied to the samples rather than the sink, whose volume is the listener's and which a gapless album
+    // shares between tracks
+    fn replaygain(&self, path: &Path) -> f32 {
+        let playback = &self.config.playback;
+        if playback.replaygain == replaygain::Mode::Off {
+            return 1.0;
+        }
+        replaygain::read(path).factor(playback.replaygain, playback.replaygain_preamp)
+    }
+
+    // The fades set for a track, placed on the part of it that plays: after a skipped intro, before a skipped outro
+    fn fade_envelope(&self, path: &Path) -> fade::Envelope {
+        let (fade_in, fade_out) = self.library.fades(path);
+        let seconds = |fade: Option<f64>| fade.filter(|&seconds| seconds > 0.0).map(Duration::from_secs_f64);
+        let fade_out = seconds(fade_out);
+        // Probing the file is only worth it when there is a fade-out to place
+        let end = fade_out
+            .and_then(|_| {
+                self.tags
+                    .get(path)
+                    .and_then(|tags| tags.duration)
+                    .or_else(|| self.library.duration(path))
+                    .or_else(|| get_audio_duration(&path.to_path_buf()))
+            })
+            .map(|duration| duration.saturating_sub(self.outro_skip(path).unwrap_or_default()));
+        fade::Envelope {
+            start: self.intro_offset(path),
+            fade_in: seconds(fade_in),
+            end,
+            fade_out,
+        }
+    }
+
+    // Open the default output device with Rodio 0.20 API, at the configured rate if there is one
+    fn init_audio(&mut self) -> Result<(), MusixError> {
+        let (stream, stream_handle, format) = output::open(self.config.playback.sample_rate)?;
+        let sink = Sink::try_new(&stream_handle)?;
+        sink.set_volume(self.output_volume());
+        self._stream = Some(Box::new(stream));
+        self.stream_handle = Some(stream_handle);
+        self.output_format = Some(format);
+        self.sink = Some(Arc::new(Mutex::new(sink)));
+        Ok(())
+    }
+
+    // Try the output again, e.g. after plugging in headphones or starting the sound server
+    fn retry_audio(&mut self) {
+        if self.audio_error.is_none() {
+            return;
+        }
+        match self.init_audio() {
+            Ok(()) => {
+                self.audio_error = None;
+                self.show_toast(t("message.audio_ready").to_string());
+            }
+            Err(e) => {
+                let error = e.to_string();
+                self.show_toast(t_args("message.audio_retry_failed", &[("error", &error)]));
+                self.audio_error = Some(error);
+            }
+        }
     }
 
-    fn play_song(&mut self, index: usize) -> Result<(), Box<dyn std::error::Error>> {
+    fn play_song(&mut self, index: usize) -> Result<(), MusixError> {
         if index >= self.songs.len() {
             return Ok(());
         }
 
         let is_same_song = self.current_index == index;
+        self.just_finished = None;
+        // Restarts for seeking or resuming are not new plays
+        let is_new_play = !is_same_song || self.playback_start.is_none();
+        self.end_crossfade();
+        let gapless = self
+            .gapless_next
+            .take()
+            .filter(|(queued, _)| !is_same_song && *queued == self.songs[index].path);
+        if is_new_play {
+            self.finish_listen(events::End::Skipped);
+        }
         self.current_index = index;
         self.selected_index = index;
         self.list_state.select(Some(self.selected_index));
+        let path = &self.songs[index].path;
+        self.inbox_queue.retain(|queued| queued != path);
+        self.new_tracks.remove(path);
+        if is_new_play {
+            let artist = self.tags.get(path).and_then(|tags| tags.artist.as_ref()).map(|artist| artist.to_lowercase());
+            self.recent_artists.push_front(artist);
+            self.recent_artists.truncate(self.config.shuffle.artist_spread);
+        }
+
+        // Only reset seek_offset if it's a different song; long tracks left unfinished pick up where they stopped
+        if !is_same_song {
+            let path = &self.songs[index].path;
+            self.seek_offset = self
+                .sync
+                .as_ref()
+                .and_then(|sync| sync.resume_position(&sync::track_key(path, &self.config.library.paths())))
+                .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                .unwrap_or_else(|| self.intro_offset(path));
+        }
+        if let Some((_, start)) = &gapless {
+            self.seek_offset = *start;
+        }
 
-        // Only reset seek_offset if it's a different song
         if !is_same_song {
-            self.seek_offset = Duration::from_secs(0);
+            self.stop_recording();
+            self.up_next = None;
+            self.ab_loop = None;
+            let song = &self.songs[index];
+            self.chapters = if song.stream_url.is_none() { chapters::read(&song.path) } else { Vec::new() };
+        }
+        // The previewed track is going out for real, so it would play twice
+        if self.preview.as_ref().is_some_and(|preview| preview.index == index) {
+            self.preview = None;
         }
 
         // Reset pause state when playing a song
         self.is_paused = false;
         self.pause_time = None;
+        // A reconnect to the same station keeps writing to the same recording, also while it was still connecting
+        let connecting = self.stream_opening.take().and_then(|opening| opening.recording);
+        let stream_recording = self.stream_info.take().and_then(|info| info.lock().unwrap().recording.take()).or(connecting);
+        self.stream_title = None;
+
+        if let Some(url) = self.songs[index].stream_url.clone() {
+            return self.play_stream(index, &url, stream_recording);
+        }
 
         if let Some(ref sink) = self.sink {
             let song = &self.songs[index];
-            match create_audio_source(&song.path) {
-                Ok(source) => {
-                    // Try to get duration from symphonia first, fallback to source
-                    let total_duration = get_audio_duration(&song.path).or_else(|| source.total_duration());
-
-                    let sink = sink.lock().unwrap();
-                    sink.stop();
-
-                    // Optimized seeking logic
-                    if self.seek_offset > Duration::from_secs(0) {
-                        // First try the fast path: append source and use try_seek
-                        sink.append(source);
-
-                        match sink.try_seek(self.seek_offset) {
-                            Ok(()) => {
-                                // Fast seek succeeded, we're done
-                            }
-                            Err(_) => {
-                                // Fast seek failed, fall back to skip_duration
-                                // But first we need to reload the source since it was consumed
-                                sink.stop();
+            if gapless.is_some() {
+                // Already queued behind the track that just ended, and playing
+                self.song_duration = get_audio_duration(&song.path);
+                self.is_playing = true;
+                self.playback_start = Some(Instant::now());
+                self.songs[index].error = None;
+                self.update_terminal_title();
+            } else {
+                match self.open_source(&song.path) {
+                    Ok(source) => {
+                        // Try to get duration from symphonia first, fallback to source
+                        let total_duration = get_audio_duration(&song.path).or_else(|| source.total_duration());
+
+                        let sink = sink.lock().unwrap();
+                        sink.stop();
+
+                        // Optimized seeking logic
+                        if self.seek_offset > Duration::from_secs(0) {
+                            // First try the fast path: append source and use try_seek
+                            sink.append(source);
+
+                            match sink.try_seek(self.seek_offset) {
+                                Ok(()) => {
+                                    // Fast seek succeeded, we're done
+                                }
+                                Err(_) => {
+                                    // Fast seek failed, so open the file again positioned where it should start
+                                    // But first we need to reload the source since it was consumed
+                                    sink.stop();
 
-                                if let Ok(source) = create_audio_source(&song.path) {
-                                    let skipped_source = source.skip_duration(self.seek_offset);
-                                    sink.append(skipped_source);
-                                } else {
-                                    // If we can't reload, reset seek offset and play from beginning
-                                    self.seek_offset = Duration::from_secs(0);
-                                    if let Ok(source) = create_audio_source(&song.path) {
+                                    if let Ok(source) = self.open_source_at(&song.path, self.seek_offset) {
                                         sink.append(source);
+                                    } else {
+                                        // If we can't reload, reset seek offset and play from beginning
+                                        self.seek_offset = Duration::from_secs(0);
+                                        if let Ok(source) = self.open_source(&song.path) {
+                                            sink.append(source);
+                                        }
                                     }
                                 }
                             }
+                        } else {
+                            sink.append(source);
                         }
-                    } else {
-                        sink.append(source);
-                    }
 
-                    sink.play();
-                    self.is_playing = true;
-                    self.playback_start = Some(Instant::now());
-                    self.song_duration = total_duration;
-                    self.update_terminal_title();
-                }
-                Err(e) => {
-                    eprintln!("Warning: Could not decode audio file '{}': {e}", song.name);
+                        sink.play();
+                        self.is_playing = true;
+                        self.playback_start = Some(Instant::now());
+                        self.song_duration = total_duration;
+                        self.songs[index].error = None;
+                        self.update_terminal_title();
+                    }
+                    Err(e) => {
+                        // Quarantine the file instead of printing over the TUI
+                        self.songs[index].error = Some(e.to_string());
+                        self.is_playing = false;
+                        self.playback_start = None;
+                        self.song_duration = None;
+                    }
                 }
             }
         } else {
-            eprintln!("Warning: No audio sink available. Cannot play '{}'", self.songs[index].name);
+            self.show_toast(t_args("message.no_sink", &[("name", &self.songs[index].name)]));
+        }
+
+        if is_new_play && self.is_playing {
+            let path = self.songs[index].path.clone();
+            let started = chrono::Utc::now().timestamp();
+            self.library.record_play(&path, started);
+            self.save_library();
+            self.listen = Some(history::Listen::start(self.songs[index].name.clone(), &path, self.song_duration, started));
+            self.log_event(events::Event::Started);
         }
 
         Ok(())
     }
 
-    fn play_or_pause(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        // If no songs are loaded, do nothing
-        if self.songs.is_empty() {
-            return Ok(());
-        }
-
-        // If no song has ever been played (initial state), play the selected song
-        if self.playback_start.is_none() && !self.is_playing && !self.is_paused {
-            self.play_song(self.selected_index)?;
+    fn play_stream(&mut self, index: usize, url: &str, recording: Option<BufWriter<File>>) -> Result<(), MusixError> {
+        let Some(ref sink) = self.sink else {
+            self.show_toast(t_args("message.no_sink", &[("name", &self.songs[index].name)]));
             return Ok(());
-        }
+        };
 
-        // If selected song is different from current playing song, play the selected song
-        if self.selected_index != self.current_index {
-            self.play_song(self.selected_index)?;
-        } else {
-            // If selected song is the same as current playing song, toggle play/pause
-            if self.is_playing {
-                self.pause_playback();
-            } else {
-                self.resume_playback();
-            }
-        }
+        // Live streams always start at the live edge
+        self.seek_offset = Duration::from_secs(0);
+
+        // What played before stops now rather than once the station answers
+        sink.lock().unwrap().stop();
+        self.is_playing = false;
+        self.playback_start = None;
+        self.song_duration = None;
+        self.stream_opening = Some(StreamOpening {
+            path: self.songs[index].path.clone(),
+            url: url.to_string(),
+            opening: stream::Opening::start(url),
+            recording,
+        });
+        self.show_toast(t_args("message.stream_connecting", &[("name", &self.songs[index].name)]));
         Ok(())
     }
 
-    fn next_song(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        if self.songs.is_empty() {
-            return Ok(());
-        }
-
-        let next_index = if self.random_mode {
-            // Simple random selection using timestamp
-            let timestamp = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_nanos() as usize;
-            let mut indices: Vec<usize> = (0..self.songs.len()).collect();
-            indices.retain(|&i| i != self.current_index);
-            if indices.is_empty() {
-                self.current_index
-            } else {
-                indices[timestamp % indices.len()]
+    // Play a station picked in the radio browser, adding it to the song list for this session
+    fn play_station(&mut self, station: &radio::Station) -> Result<(), MusixError> {
+        let url = station.stream_url();
+        let index = match self.songs.iter().position(|song| song.stream_url.as_deref() == Some(url)) {
+            Some(index) => index,
+            None => {
+                self.songs.push(Song::station(station.name.clone(), PathBuf::new(), url.to_string()));
+                let index = self.songs.len() - 1;
+                if !self.search_mode {
+                    self.filtered_songs.push(index);
+                }
+                self.view_summary = None;
+                index
             }
-        } else if self.current_index + 1 >= self.songs.len() {
-            if self.loop_mode { 0 } else { self.current_index }
-        } else {
-            self.current_index + 1
         };
 
-        self.play_song(next_index)
+        self.show_radio_browser = false;
+        self.play_song(index)
     }
 
-    fn previous_song(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        if self.songs.is_empty() {
-            return Ok(());
-        }
-
-        let prev_index = if self.random_mode {
-            // Simple random selection using timestamp
-            let timestamp = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_nanos() as usize;
-            let mut indices: Vec<usize> = (0..self.songs.len()).collect();
-            indices.retain(|&i| i != self.current_index);
-            if indices.is_empty() {
-                self.current_index
-            } else {
-                indices[timestamp % indices.len()]
+    // Start the station once it has answered and its format is known
+    fn tick_stream_opening(&mut self) -> bool {
+        let Some(result) = self.stream_opening.as_ref().and_then(|opening| opening.opening.poll()) else {
+            return false;
+        };
+        let Some(StreamOpening { path, url, recording, .. }) = self.stream_opening.take() else {
+            return false;
+        };
+        // The station was removed from the list while it connected
+        let Some(index) = self.index_of(&path, &Some(url)) else {
+            if recording.is_some() {
+                drop(recording);
+                self.stop_recording();
             }
-        } else if self.current_index == 0 {
-            if self.loop_mode { self.songs.len() - 1 } else { 0 }
-        } else {
-            self.current_index - 1
+            return true;
         };
+        match result {
+            Ok((source, info)) => {
+                if let Some(sink) = &self.sink {
+                    let sink = sink.lock().unwrap();
+                    sink.stop();
+                    sink.append(self.equalize(self.to_output(source)));
+                    sink.play();
+                }
 
-        self.play_song(prev_index)
+                self.is_playing = true;
+                self.playback_start = Some(Instant::now());
+                self.song_duration = None;
+                info.lock().unwrap().recording = recording;
+                self.stream_info = Some(info);
+                self.stream_stalls = 0;
+                self.songs[index].error = None;
+                self.update_terminal_title();
+            }
+            Err(e) => {
+                self.songs[index].error = Some(e.to_string());
+                if recording.is_some() {
+                    drop(recording);
+                    self.stop_recording();
+                }
+                let name = self.songs[index].name.clone();
+                self.show_toast(t_args("message.stream_failed", &[("name", &name), ("error", &e)]));
+            }
+        }
+        true
     }
 
-    fn move_selection(&mut self, direction: i32) {
-        if self.songs.is_empty() {
-            return;
+    fn is_stream_playing(&self) -> bool {
+        self.stream_info.is_some()
+    }
+
+    // "Live title (Station)" for radio streams that announce one, otherwise the song name
+    fn now_playing_name(&self) -> String {
+        let song = &self.songs[self.current_index];
+        match &self.stream_title {
+            Some(title) => format!("{title} ({})", song.name),
+            None => song.name.clone(),
         }
+    }
 
-        let len = self.songs.len();
-        if direction > 0 {
-            self.selected_index = (self.selected_index + 1) % len;
-        } else if direction < 0 {
-            self.selected_index = if self.selected_index == 0 { len - 1 } else { self.selected_index - 1 };
+    // "Artist – Title" from the tags, the live title of the station playing, or else the file name
+    fn track_label(&self, index: usize) -> String {
+        let song = &self.songs[index];
+        if index == self.current_index
+            && let Some(title) = &self.stream_title
+        {
+            return title.clone();
+        }
+        let tags = self.tags.get(&song.path);
+        match (tags.and_then(|tags| tags.artist.as_deref()), tags.and_then(|tags| tags.title.as_deref())) {
+            (Some(artist), Some(title)) => format!("{artist} – {title}"),
+            (None, Some(title)) => title.to_string(),
+            _ => song.name.clone(),
         }
-        self.list_state.select(Some(self.selected_index));
     }
 
-    fn get_playback_progress(&self) -> (Duration, Option<Duration>) {
-        if let Some(start_time) = self.playback_start {
-            let elapsed = start_time.elapsed() + self.seek_offset;
-            (elapsed, self.song_duration)
+    // The track copying and sharing act on: the one in the details panel while it is open, otherwise the one playing
+    fn focused_index(&self) -> usize {
+        if self.show_details || !self.is_playing && !self.is_paused {
+            self.selected_index
         } else {
-            (self.seek_offset, self.song_duration)
+            self.current_index
         }
     }
 
-    fn format_duration(duration: Duration) -> String {
-        let total_seconds = duration.as_secs();
-        let minutes = total_seconds / 60;
-        let seconds = total_seconds % 60;
-        format!("{minutes:02}:{seconds:02}")
+    // Copy the track's label, or with `path` its file path or station URL, to the clipboard
+    fn yank(&mut self, path: bool) {
+        let index = self.focused_index();
+        let Some(song) = self.songs.get(index) else {
+            return;
+        };
+        let text = if path {
+            song.stream_url.clone().unwrap_or_else(|| song.path.display().to_string())
+        } else {
+            self.track_label(index)
+        };
+        match clipboard::copy(&text) {
+            Ok(()) => self.show_toast(t_args("message.copied", &[("text", &text)])),
+            Err(e) => self.show_toast(t_args("message.copy_failed", &[("error", &e)])),
+        }
     }
 
-    fn pause_playback(&mut self) {
-        if self.is_playing && !self.is_paused {
-            if let Some(ref sink) = self.sink {
-                let sink = sink.lock().unwrap();
-                sink.pause();
+    // The song list for the editor: a path, or a station's URL, on each line
+    fn list_for_editor(&self) -> String {
+        let mut text = t("message.edit_list_header").lines().map(|line| format!("# {line}\n")).collect::<String>();
+        for song in &self.songs {
+            match &song.stream_url {
+                Some(url) => text.push_str(url),
+                None => text.push_str(&song.path.to_string_lossy()),
             }
-            self.is_playing = false;
-            self.is_paused = true;
-            self.pause_time = Some(Instant::now());
+            text.push('\n');
+        }
+        text
+    }
 
-            // Update seek_offset only if we have a valid playback_start
-            if let Some(start_time) = self.playback_start {
-                self.seek_offset += start_time.elapsed();
+    // The list as saved from the editor: its songs in their new order, files not in the list yet added, and the
+    // rest dropped from the list for this session. The song playing stays, at the top if its line was deleted.
+    fn apply_edited_list(&mut self, text: &str) {
+        let mut new_songs = Vec::new();
+        let mut new_paths = Vec::new();
+        let mut unknown = 0;
+        let mut old_index = Vec::new();
+        // Looked up once rather than searched for each line, as lists run to tens of thousands
+        let mut by_path = HashMap::new();
+        let mut by_url = HashMap::new();
+        for (index, song) in self.songs.iter().enumerate() {
+            match &song.stream_url {
+                Some(url) => by_url.entry(url.as_str()).or_insert(index),
+                None => by_path.entry(song.path.as_path()).or_insert(index),
+            };
+        }
+        let mut seen = HashSet::new();
+        let mut seen_new = HashSet::new();
+        for entry in editor::entries(text) {
+            let is_url = entry.starts_with("http://") || entry.starts_with("https://");
+            let found = if is_url { by_url.get(entry) } else { by_path.get(Path::new(entry)) };
+            match found {
+                Some(&index) => {
+                    if seen.insert(index) {
+                        old_index.push(Some(index));
+                    }
+                }
+                None if !is_url && Path::new(entry).is_file() => {
+                    let path = PathBuf::from(entry);
+                    if seen_new.insert(path.clone()) {
+                        new_songs.push(Song::from_path(path.clone()));
+                        new_paths.push(path);
+                        old_index.push(None);
+                    }
+                }
+                None => unknown += 1,
             }
+        }
+        let playing = self.is_playing || self.is_paused;
+        if playing && !seen.contains(&self.current_index) {
+            old_index.insert(0, Some(self.current_index));
+        }
+        if old_index.is_empty() {
+            self.show_toast(t("message.edit_list_empty").to_string());
+            return;
+        }
 
-            self.playback_start = None;
-            self.update_terminal_title();
+        let mut old: Vec<Option<Song>> = std::mem::take(&mut self.songs).into_iter().map(Some).collect();
+        let mut new_songs = new_songs.into_iter();
+        let position_of = |index: usize| old_index.iter().position(|&old| old == Some(index));
+        let (current, selected) = (position_of(self.current_index), position_of(self.selected_index));
+        self.songs = old_index
+            .iter()
+            .filter_map(|&index| match index {
+                Some(index) => old[index].take(),
+                None => new_songs.next(),
+            })
+            .collect();
+        self.current_index = current.unwrap_or(0);
+        self.selected_index = selected.unwrap_or(self.current_index);
+        self.manual_order = true;
+        self.filtered_songs = (0..self.songs.len()).collect();
+        self.list_state.select(Some(self.selected_index));
+        // Indices may now be other songs'
+        self.preview = None;
+        self.view_summary = None;
+        self.read_tags(new_paths);
+
+        let count = self.songs.len();
+        if unknown > 0 {
+            self.show_toast(t_args("message.edit_list_unknown", &[("count", &count), ("unknown", &unknown)]));
+        } else {
+            self.show_toast(t_args("message.edit_list_applied", &[("count", &count)]));
         }
     }
 
-    fn resume_playback(&mut self) {
-        if !self.is_playing && self.is_paused && !self.songs.is_empty() {
-            if let Some(ref sink) = self.sink {
-                let sink = sink.lock().unwrap();
+    // Ctrl+o: the selected track's file in the system file manager
+    fn reveal_selected(&mut self) {
+        let Some(song) = self.songs.get(self.selected_index) else {
+            return;
+        };
+        if song.stream_url.is_some() {
+            self.show_toast(t("message.reveal_station").to_string());
+            return;
+        }
+        let path = song.path.clone();
+        if let Err(e) = launch::reveal(&path) {
+            self.show_toast(t_args("message.open_failed", &[("error", &e)]));
+        }
+    }
 
-                // Try to resume directly first
-                if !sink.empty() {
-                    sink.play();
-                    self.is_playing = true;
-                    self.is_paused = false;
-                    self.playback_start = Some(Instant::now());
-                    self.pause_time = None;
-                    self.update_terminal_title();
-                    return;
-                }
+    // `:share` / `:browse [service]`: a search for the track on a music site, copied or opened in the browser
+    fn share(&mut self, open: bool, argument: &str) {
+        let service = if argument.trim().is_empty() {
+            self.config.share.service
+        } else if let Some(service) = share::Service::parse(argument) {
+            service
+        } else {
+            self.show_toast(t("message.share_usage").to_string());
+            return;
+        };
+        let index = self.focused_index();
+        if index >= self.songs.len() {
+            return;
+        }
+        let tags = self.tags.get(&self.songs[index].path);
+        let query = match (tags.and_then(|tags| tags.artist.as_deref()), tags.and_then(|tags| tags.title.as_deref())) {
+            (Some(artist), Some(title)) => format!("{artist} {title}"),
+            _ => self.track_label(index),
+        };
+        let url = service.search_url(&query);
+        let result = if open { launch::open(&url) } else { clipboard::copy(&url) };
+        match result {
+            Ok(()) if open => self.show_toast(t_args("message.opened", &[("url", &url)])),
+            Ok(()) => self.show_toast(t_args("message.copied", &[("text", &url)])),
+            Err(e) if open => self.show_toast(t_args("message.open_failed", &[("error", &e)])),
+            Err(e) => self.show_toast(t_args("message.copy_failed", &[("error", &e)])),
+        }
+    }
 
-                // If sink is empty, try to seek to current position using try_seek
-                drop(sink);
+    fn analyze_selected_bpm(&mut self) {
+        if let Some(song) = self.songs.get(self.selected_index)
+            && song.stream_url.is_none()
+        {
+            self.bpm_analyzer.request(song.path.clone());
+        }
+    }
 
-                // Load fresh audio source and seek to position
-                if let Ok(source) = create_audio_source(&self.songs[self.current_index].path) {
-                    let sink = self.sink.as_ref().unwrap().lock().unwrap();
+    // Queue every local track whose tempo is not known yet
+    fn analyze_missing_bpm(&mut self) {
+        for song in &self.songs {
+            if song.stream_url.is_none() && song.error.is_none() && self.library.bpm(&song.path).is_none() {
+                self.bpm_analyzer.request(song.path.clone());
+            }
+        }
+    }
 
-                    // Clear the sink and add new source
-                    sink.stop();
+    fn collect_bpm_results(&mut self) {
+        let results = self.bpm_analyzer.poll();
+        if results.is_empty() {
+            return;
+        }
 
-                    // If we have a seek offset, try to use try_seek first
-                    if self.seek_offset > Duration::from_secs(0) {
-                        sink.append(source);
+        for (path, bpm) in results {
+            let name = self
+                .songs
+                .iter()
+                .find(|song| song.path == path)
+                .map(|song| song.name.clone())
+                .unwrap_or_default();
+            match bpm {
+                Some(bpm) => {
+                    self.library.entry(&path).bpm = Some(bpm);
+                    self.show_toast(t_args("message.bpm_detected", &[("bpm", &bpm.round()), ("name", &name)]));
+                }
+                None => self.show_toast(t_args("message.bpm_failed", &[("name", &name)])),
+            }
+        }
 
-                        // Try seeking with try_seek - this is much faster than skip_duration
-                        match sink.try_seek(self.seek_offset) {
-                            Ok(()) => {
-                                // Seeking succeeded
-                                sink.play();
-                                self.is_playing = true;
-                                self.is_paused = false;
-                                self.playback_start = Some(Instant::now());
-                                self.pause_time = None;
-                                self.update_terminal_title();
-                                return;
-                            }
-                            Err(_) => {
-                                // try_seek failed, fall back to skip_duration but optimize it
-                                sink.stop();
+        self.save_library();
+        if self.search_mode {
+            let query = self.search_query.clone();
+            self.fuzzy_search(&query);
+        }
+    }
 
-                                // Reload with skip_duration as fallback
-                                if let Ok(source) = create_audio_source(&self.songs[self.current_index].path) {
-                                    let skipped_source = source.skip_duration(self.seek_offset);
-                                    sink.append(skipped_source);
-                                    sink.play();
-                                }
-                            }
-                        }
-                    } else {
-                        // No seek needed, just play from beginning
-                        sink.append(source);
-                        sink.play();
-                    }
+    // Keep the details panel's cover in step with the selection; returns whether a new cover arrived
+    fn tick_cover_art(&mut self) -> bool {
+        if !self.show_details || !self.config.ui.cover_art {
+            return false;
+        }
+        if let Some(song) = self.songs.get(self.selected_index)
+            && song.stream_url.is_none()
+        {
+            self.cover.request(&song.path);
+        }
+        self.cover.poll()
+    }
 
-                    self.is_playing = true;
-                    self.is_paused = false;
-                    self.playback_start = Some(Instant::now());
-                    self.pause_time = None;
-                    self.update_terminal_title();
+    // Have the tag reader do the tracks on screen in a list of `rows` rows first, then the next few to play, so
+    // their lengths fill in before the rest of the library's
+    fn prioritize_tags(&mut self, rows: usize) {
+        let Some(reader) = &self.tag_reader else {
+            return;
+        };
+        let window = visible_window(self.display_len(), self.list_state.selected(), rows);
+        let upcoming = self.planned_next().into_iter().map(|index| &self.songs[index].path);
+        let paths: Vec<PathBuf> = self
+            .display_songs(window)
+            .into_iter()
+            .map(|(_, song)| song)
+            .filter(|song| song.stream_url.is_none())
+            .map(|song| &song.path)
+            .chain(upcoming)
+            .chain(self.inbox_queue.iter().take(TAGS_AHEAD))
+            .cloned()
+            .collect();
+        if paths != self.tag_priority {
+            reader.prioritize(paths.clone());
+            self.tag_priority = paths;
+        }
+    }
+
+    // Take in tags read so far; returns whether any arrived. The list is sorted again once all are in
+    fn collect_tags(&mut self) -> bool {
+        let Some(reader) = &self.tag_reader else {
+            return false;
+        };
+        let mut arrived = false;
+        loop {
+            match reader.results.try_recv() {
+                Ok((path, tags)) => {
+                    if let Some(duration) = tags.duration
+                        && self.library.duration(&path).is_none()
+                    {
+                        self.library.entry(&path).duration = Some(duration.as_secs_f64());
+                        self.durations_changed = true;
+                    }
+                    self.tags.insert(path, tags);
+                    arrived = true;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.tag_reader = None;
+                    self.tag_priority.clear();
+                    // Newly probed lengths are written once, not per file
+                    if std::mem::take(&mut self.durations_changed) {
+                        self.save_library();
+                    }
+                    // Album, track number and column sorts can only be finished with the tags
+                    self.sort_songs();
+                    if !self.tag_backlog.is_empty() {
+                        self.tag_reader = Some(tags::TagReader::start(std::mem::take(&mut self.tag_backlog)));
+                    }
+                    break;
                 }
             }
-        } else if !self.is_playing && !self.is_paused && !self.songs.is_empty() {
-            // Handle initial play state (not paused, just stopped)
-            let _ = self.play_song(self.current_index);
         }
+        arrived
     }
 
-    fn seek(&mut self, offset_seconds: i32) {
-        if self.songs.is_empty() {
-            return;
+    // Color from the first `[[row_colors]]` rule the track matches; stations have nothing to match on
+    fn row_color(&self, song: &Song) -> Option<Color> {
+        if self.config.row_colors.is_empty() || song.stream_url.is_some() {
+            return None;
         }
+        let track = self.library.get(&song.path);
+        let facts = row_colors::RowFacts {
+            genre: self.tags.get(&song.path).and_then(|tags| tags.genre.as_deref()),
+            rating: track.and_then(|track| track.rating),
+            play_count: track.map_or(0, |track| track.play_count),
+            lossless: row_colors::is_lossless(&song.path),
+        };
+        row_colors::color_for(&self.config.row_colors, &facts)
+    }
 
-        // Calculate current position based on play state
-        let current_position = if self.is_playing {
-            if let Some(start_time) = self.playback_start {
-                self.seek_offset + start_time.elapsed()
-            } else {
-                self.seek_offset
-            }
-        } else {
-            // When paused, use the stored seek_offset
-            self.seek_offset
+    // Name -> BPM -> each sortable list column -> name
+    fn cycle_sort(&mut self) {
+        let sortable: Vec<columns::Column> = self
+            .config
+            .ui
+            .columns
+            .iter()
+            .map(|config| config.column)
+            .filter(|column| column.sortable())
+            .collect();
+        let next_column = |from: usize| sortable.get(from).copied();
+        self.manual_order = false;
+        (self.sort_by_bpm, self.sort_column) = match (self.sort_by_bpm, self.sort_column) {
+            (false, None) => (true, None),
+            (true, _) => (false, next_column(0)),
+            (false, Some(column)) => (false, sortable.iter().position(|&c| c == column).and_then(|i| next_column(i + 1))),
         };
+        self.sort_songs();
+    }
 
-        let seek_duration = Duration::from_secs(offset_seconds.unsigned_abs().into());
-        let new_position = if offset_seconds < 0 {
-            // Seek backward
-            if current_position > seek_duration {
-                current_position - seek_duration
-            } else {
-                Duration::from_secs(0)
+    // How two songs compare under a column sort; missing values go last, ties fall back to the name
+    fn compare_by_column(&self, column: columns::Column, a: &Song, b: &Song) -> Ordering {
+        let tags_a = self.tags.get(&a.path);
+        let tags_b = self.tags.get(&b.path);
+        let text = |tags: Option<&tags::TrackTags>, field: fn(&tags::TrackTags) -> &Option<String>| {
+            tags.and_then(|tags| field(tags).as_deref()).map(str::to_lowercase)
+        };
+        let album = || none_last(text(tags_a, |tags| &tags.album), text(tags_b, |tags| &tags.album));
+        let track = || none_last(tags_a.and_then(|tags| tags.track), tags_b.and_then(|tags| tags.track));
+        let order = match column {
+            columns::Column::Number => Ordering::Equal,
+            columns::Column::Track => album().then_with(track),
+            columns::Column::Title => {
+                let title = |song: &Song, tags: Option<&tags::TrackTags>| text(tags, |tags| &tags.title).unwrap_or_else(|| song.name.to_lowercase());
+                natural::compare(&title(a, tags_a), &title(b, tags_b))
+            }
+            columns::Column::Artist => none_last(text(tags_a, |tags| &tags.artist), text(tags_b, |tags| &tags.artist))
+                .then_with(album)
+                .then_with(track),
+            columns::Column::Album => album().then_with(track),
+            columns::Column::Duration => none_last(self.duration_of(a), self.duration_of(b)),
+            // Best rated first
+            columns::Column::Rating => {
+                let rating = |song: &Song| self.library.get(&song.path).and_then(|track| track.rating).map(Reverse);
+                none_last(rating(a), rating(b))
             }
-        } else {
-            // Seek forward
-            current_position + seek_duration
         };
+        order.then_with(|| natural::compare(&a.name, &b.name))
+    }
 
-        // Don't seek beyond song duration if we know it
-        let final_position = if let Some(duration) = self.song_duration {
-            new_position.min(duration)
-        } else {
-            new_position
+    // Requests from later invocations of musix; returns whether any arrived
+    fn collect_requests(&mut self) -> Result<bool, MusixError> {
+        let Some(server) = &self.instance else {
+            return Ok(false);
         };
+        let requests: Vec<instance::Request> = server.requests.try_iter().collect();
+        for request in &requests {
+            match request {
+                instance::Request::Play(path) => {
+                    self.play_path(path)?;
+                    let name = self.songs[self.current_index].name.clone();
+                    self.show_toast(t_args("message.instance_playing", &[("name", &name)]));
+                }
+                // Answered by the socket thread itself
+                instance::Request::NowPlaying => {}
+            }
+        }
+        Ok(!requests.is_empty())
+    }
 
-        // Update seek_offset immediately to provide instant feedback
-        self.seek_offset = final_position;
-
-        if let Some(ref sink) = self.sink {
-            let sink = sink.lock().unwrap();
-
-            if self.is_playing {
-                // When playing, try smooth seeking first
-                match sink.try_seek(final_position) {
-                    Ok(()) => {
-                        // Smooth seek succeeded, just update timing
-                        self.playback_start = Some(Instant::now());
-                    }
-                    Err(_) => {
-                        // Smooth seek failed, do a quick restart without audio glitches
-                        drop(sink);
-
-                        // Temporarily pause to avoid audio artifacts
-                        self.is_playing = false;
-
-                        // Quick restart from new position
-                        let _ = self.play_song(self.current_index);
-                    }
+    // Play a file given on the command line, adding it to the list when it is outside the music folders
+    fn play_path(&mut self, path: &Path) -> Result<(), MusixError> {
+        let canonical = fs::canonicalize(path).ok();
+        let index = self
+            .songs
+            .iter()
+            .position(|song| song.stream_url.is_none() && (song.path == path || canonical.is_some() && fs::canonicalize(&song.path).ok() == canonical));
+        let index = match index {
+            Some(index) => index,
+            None if path.is_file() => {
+                self.songs.push(Song::from_path(path.to_path_buf()));
+                if !self.search_mode {
+                    self.filtered_songs = (0..self.songs.len()).collect();
                 }
-            } else if self.is_paused {
-                // When paused, just update the seek position
-                // The position will be applied when resuming
-                // No need to modify the sink while paused
+                self.view_summary = None;
+                self.songs.len() - 1
             }
+            None => {
+                self.show_toast(t_args("message.instance_missing", &[("file", &path.display())]));
+                return Ok(());
+            }
+        };
+        if self.search_mode {
+            self.exit_search_mode();
         }
+        self.play_song(index)
     }
 
-    fn fuzzy_search(&mut self, query: &str) {
-        if query.is_empty() {
-            self.filtered_songs = (0..self.songs.len()).collect();
-        } else {
-            let query_lower = query.to_lowercase();
-            let mut matches: Vec<(usize, f32)> = self
-                .songs
-                .iter()
-                .enumerate()
-                .filter_map(|(index, song)| {
-                    let song_name_lower = song.name.to_lowercase();
-                    let score = Self::fuzzy_match_score(&query_lower, &song_name_lower);
-                    if score > 0.0 { Some((index, score)) } else { None }
-                })
-                .collect();
+    // From the tags read this run, or the library's cache of earlier ones
+    fn duration_of(&self, song: &Song) -> Option<Duration> {
+        self.tags
+            .get(&song.path)
+            .and_then(|tags| tags.duration)
+            .or_else(|| self.library.duration(&song.path))
+    }
 
-            matches.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-            self.filtered_songs = matches.into_iter().map(|(index, _)| index).collect();
+    fn summarize_view(&self) -> (usize, Duration, bool) {
+        let count = self.display_len();
+        let mut total = Duration::ZERO;
+        let mut unknown = false;
+        for (_, song) in self.display_songs(0..count) {
+            match self.duration_of(song) {
+                Some(duration) => total += duration,
+                None => unknown = true,
+            }
         }
+        (count, total, unknown)
+    }
 
-        if !self.filtered_songs.is_empty() {
-            self.selected_index = self.filtered_songs[0];
-            self.list_state.select(Some(0));
+    // The text of one list column for the song at `index`
+    fn column_text(&self, column: columns::Column, index: usize, song: &Song) -> String {
+        let tags = self.tags.get(&song.path);
+        match column {
+            columns::Column::Number => format!("{}.", index + 1),
+            columns::Column::Track => tags.and_then(|tags| tags.track).map(|track| track.to_string()).unwrap_or_default(),
+            columns::Column::Title => tags.and_then(|tags| tags.title.clone()).unwrap_or_else(|| song.name.clone()),
+            columns::Column::Artist => tags.and_then(|tags| tags.artist.clone()).unwrap_or_default(),
+            columns::Column::Album => tags.and_then(|tags| tags.album.clone()).unwrap_or_default(),
+            columns::Column::Duration => self.duration_of(song).map(Player::format_duration).unwrap_or_default(),
+            columns::Column::Rating => self
+                .library
+                .get(&song.path)
+                .and_then(|track| track.rating)
+                .map(|rating| format_stars(Some(rating), self.config.ui.icons))
+                .unwrap_or_default(),
         }
     }
 
-    fn fuzzy_match_score(query: &str, text: &str) -> f32 {
-        if query.is_empty() {
-            return 1.0;
+    // Reorder the song list, keeping the playing and selected songs pointed at the same tracks
+    fn sort_songs(&mut self) {
+        let mut order: Vec<usize> = (0..self.songs.len()).collect();
+        if self.manual_order {
+            // Left as it is
+        } else if let Some(column) = self.sort_column {
+            order.sort_by(|&a, &b| self.compare_by_column(column, &self.songs[a], &self.songs[b]));
+        } else if self.sort_by_bpm {
+            // Tracks without a known tempo go last
+            let bpm_key = |index: usize| self.library.bpm(&self.songs[index].path).unwrap_or(f32::INFINITY);
+            order.sort_by(|&a, &b| {
+                bpm_key(a)
+                    .total_cmp(&bpm_key(b))
+                    .then_with(|| natural::compare(&self.songs[a].name, &self.songs[b].name))
+            });
+        } else {
+            order.sort_by(|&a, &b| album_order(&self.songs[a], &self.songs[b], &self.tags));
         }
 
-        if text.contains(query) {
-            let exact_match_bonus = if text == query { 2.0 } else { 1.5 };
-            let starts_with_bonus = if text.starts_with(query) { 1.2 } else { 1.0 };
-            return exact_match_bonus * starts_with_bonus;
+        let mut new_position = vec![0; order.len()];
+        for (position, &old_index) in order.iter().enumerate() {
+            new_position[old_index] = position;
         }
+        let mut songs: Vec<Option<Song>> = std::mem::take(&mut self.songs).into_iter().map(Some).collect();
+        self.songs = order.iter().filter_map(|&old_index| songs[old_index].take()).collect();
 
-        let mut score = 0.0;
-        let query_chars: Vec<char> = query.chars().collect();
-        let text_chars: Vec<char> = text.chars().collect();
-        let mut query_index = 0;
-
-        for (text_index, text_char) in text_chars.iter().enumerate() {
-            if query_index < query_chars.len() && *text_char == query_chars[query_index] {
-                score += 1.0 / (text_index as f32 + 1.0);
-                query_index += 1;
-            }
+        if let Some(&position) = new_position.get(self.current_index) {
+            self.current_index = position;
+        }
+        if let Some(&position) = new_position.get(self.selected_index) {
+            self.selected_index = position;
         }
 
-        if query_index == query_chars.len() {
-            score / query_chars.len() as f32
+        if self.search_mode {
+            let query = self.search_query.clone();
+            self.fuzzy_search(&query);
+            if let Some(position) = self.list_position(self.selected_index) {
+                self.list_state.select(Some(position));
+            }
         } else {
-            0.0
+            self.filtered_songs = (0..self.songs.len()).collect();
+            self.list_state.select(Some(self.selected_index));
         }
+        self.view_summary = None;
     }
 
-    fn enter_search_mode(&mut self) {
-        self.search_mode = true;
-        self.search_query.clear();
-        self.fuzzy_search("");
+    fn toggle_performance_mode(&mut self) {
+        self.performance_mode = !self.performance_mode;
+        self.chords.reset();
     }
 
-    fn exit_search_mode(&mut self) {
-        self.search_mode = false;
-        self.search_query.clear();
-        self.filtered_songs = (0..self.songs.len()).collect();
-        self.list_state.select(Some(self.selected_index));
+    // Jump to hot cue `number` of the current track, or set it at the playhead if it is empty
+    fn trigger_cue(&mut self, number: u8) {
+        let Some(song) = self.songs.get(self.current_index) else {
+            return;
+        };
+        if song.stream_url.is_some() {
+            return;
+        }
+        let path = song.path.clone();
+
+        match self.library.cue(&path, number) {
+            Some(seconds) => {
+                let position = Duration::from_secs_f64(seconds);
+                if self.playback_start.is_none() && !self.is_paused {
+                    // Nothing started yet: begin playback right at the cue
+                    self.seek_offset = position;
+                    let _ = self.play_song(self.current_index);
+                } else {
+                    self.seek_to(position);
+                }
+            }
+            None => {
+                let position = self.current_position();
+                self.library.entry(&path).cues.insert(number, position.as_secs_f64());
+                self.save_library();
+                let position = Player::format_duration(position);
+                self.show_toast(t_args("message.cue_set", &[("number", &number), ("position", &position)]));
+            }
+        }
     }
 
-    fn get_display_songs(&self) -> Vec<(usize, &Song)> {
-        if self.search_mode {
-            self.filtered_songs.iter().map(|&index| (index, &self.songs[index])).collect()
-        } else {
-            self.songs.iter().enumerate().collect()
-        }
+    // Where playback of a track starts: past its intro when the track or its folder skips one
+    fn intro_offset(&self, path: &Path) -> Duration {
+        let rule = auto_skip::rule_for(&self.config.auto_skip, path).map(|rule| rule.intro);
+        let seconds = self.library.skip_intro(path).or(rule).unwrap_or(0.0);
+        Duration::from_secs_f64(seconds.max(0.0))
     }
 
-    fn move_selection_in_search(&mut self, direction: i32) {
-        if self.filtered_songs.is_empty() {
-            return;
-        }
+    // How much of the end of a track is skipped, set for the track or its folder
+    fn outro_skip(&self, path: &Path) -> Option<Duration> {
+        let rule = auto_skip::rule_for(&self.config.auto_skip, path).map(|rule| rule.outro);
+        self.library
+            .skip_outro(path)
+            .or(rule)
+            .filter(|&seconds| seconds > 0.0)
+            .map(Duration::from_secs_f64)
+    }
 
-        let current_filtered_index = self.filtered_songs.iter().position(|&index| index == self.selected_index).unwrap_or(0);
+    // Whether the playhead reached the outro to skip, which ends the track early
+    fn outro_reached(&self) -> bool {
+        let (Some(song), Some(duration)) = (self.songs.get(self.current_index), self.song_duration) else {
+            return false;
+        };
+        !self.is_paused && song.stream_url.is_none() && self.outro_skip(&song.path).is_some_and(|outro| self.current_position() + outro >= duration)
+    }
 
-        let new_filtered_index = if direction > 0 {
-            (current_filtered_index + 1) % self.filtered_songs.len()
-        } else if direction < 0 {
-            if current_filtered_index == 0 {
-                self.filtered_songs.len() - 1
-            } else {
-                current_filtered_index - 1
+    // The current track played to its end; carry on as its end action says
+    fn track_finished(&mut self) -> Result<(), MusixError> {
+        self.finish_listen(events::End::Finished);
+        self.is_playing = false;
+        self.is_paused = false;
+        self.playback_start = None;
+        self.seek_offset = Duration::from_secs(0);
+
+        let Some(song) = self.songs.get(self.current_index) else {
+            return Ok(());
+        };
+        let (path, name, is_stream) = (song.path.clone(), song.name.clone(), song.stream_url.is_some());
+        let finished = self.current_index;
+        self.just_finished = Some((path.clone(), Instant::now()));
+        let result = match self.library.end_action(&path) {
+            library::EndAction::Next if self.repeat_mode != repeat::RepeatMode::One => self.next_song(),
+            library::EndAction::Stop => {
+                self.show_toast(t_args("message.stopped_after", &[("name", &name)]));
+                Ok(())
+            }
+            library::EndAction::Next | library::EndAction::Repeat => {
+                self.seek_offset = self.intro_offset(&path);
+                self.play_song(self.current_index)
             }
-        } else {
-            current_filtered_index
         };
 
-        self.selected_index = self.filtered_songs[new_filtered_index];
-        self.list_state.select(Some(new_filtered_index));
+        let config::AlertsConfig {
+            track_change,
+            queue_empty,
+            stream_dropped,
+        } = self.config.alerts;
+        if is_stream && stream_dropped != alert::Alert::Off {
+            self.alert(stream_dropped);
+            self.show_toast(t_args("message.stream_dropped", &[("name", &name)]));
+        } else if !self.is_playing {
+            self.alert(queue_empty);
+        } else if self.current_index != finished {
+            self.alert(track_change);
+        }
+        result
     }
 
-    fn jump_to_first(&mut self) {
-        if self.songs.is_empty() {
-            return;
+    // Ring the bell and/or flash the status bar, as `[alerts]` says for the event
+    fn alert(&mut self, alert: alert::Alert) {
+        if alert.rings() {
+            alert::ring();
         }
-
-        if self.search_mode {
-            if !self.filtered_songs.is_empty() {
-                self.selected_index = self.filtered_songs[0];
-                self.list_state.select(Some(0));
-            }
-        } else {
-            self.selected_index = 0;
-            self.list_state.select(Some(0));
+        if alert.flashes() {
+            self.flash = Some(Instant::now());
         }
     }
 
-    fn jump_to_last(&mut self) {
-        if self.songs.is_empty() {
+    // Next -> stop after -> repeat for the selected track
+    fn cycle_end_action(&mut self) {
+        let Some(song) = self.songs.get(self.selected_index) else {
+            return;
+        };
+        if song.stream_url.is_some() {
             return;
         }
+        let (path, name) = (song.path.clone(), song.name.clone());
+        let track = self.library.entry(&path);
+        track.end_action = track.end_action.next();
+        let action = end_action_label(track.end_action);
+        self.save_library();
+        self.show_toast(t_args("message.end_action", &[("name", &name), ("action", &action)]));
+    }
 
-        if self.search_mode {
-            if !self.filtered_songs.is_empty() {
-                let last_index = self.filtered_songs.len() - 1;
-                self.selected_index = self.filtered_songs[last_index];
-                self.list_state.select(Some(last_index));
+    // Skip the playing track's intro up to the playhead from now on, or play it whole again when a skip is set
+    fn toggle_skip_intro(&mut self) {
+        let Some(song) = self.songs.get(self.current_index) else {
+            return;
+        };
+        if song.stream_url.is_some() || self.playback_start.is_none() && !self.is_paused {
+            return;
+        }
+        let (path, name) = (song.path.clone(), song.name.clone());
+        let position = self.current_position();
+        let track = self.library.entry(&path);
+        if track.skip_intro.take().is_some() {
+            self.save_library();
+            self.show_toast(t_args("message.intro_cleared", &[("name", &name)]));
+        } else if position >= Duration::from_secs(1) {
+            track.skip_intro = Some(position.as_secs_f64());
+            self.save_library();
+            let position = Player::format_duration(position);
+            self.show_toast(t_args("message.intro_set", &[("position", &position), ("name", &name)]));
+        }
+    }
+
+    // Skip the playing track from the playhead to its end from now on, or play it whole again when a skip is set
+    fn toggle_skip_outro(&mut self) {
+        let (Some(song), Some(duration)) = (self.songs.get(self.current_index), self.song_duration) else {
+            return;
+        };
+        if song.stream_url.is_some() || self.playback_start.is_none() && !self.is_paused {
+            return;
+        }
+        let (path, name) = (song.path.clone(), song.name.clone());
+        let remaining = duration.saturating_sub(self.current_position());
+        let track = self.library.entry(&path);
+        if track.skip_outro.take().is_some() {
+            self.save_library();
+            self.show_toast(t_args("message.outro_cleared", &[("name", &name)]));
+        } else if remaining >= Duration::from_secs(1) {
+            track.skip_outro = Some(remaining.as_secs_f64());
+            self.save_library();
+            let remaining = Player::format_duration(remaining);
+            self.show_toast(t_args("message.outro_set", &[("length", &remaining), ("name", &name)]));
+        }
+    }
+
+    // Start previewing the selected track, or stop the preview when it is the one selected
+    fn toggle_preview(&mut self) {
+        if self.preview.take().is_some_and(|preview| preview.index == self.selected_index) {
+            return;
+        }
+        let Some(song) = self.songs.get(self.selected_index) else {
+            return;
+        };
+        if song.stream_url.is_some() {
+            self.show_toast(t("message.preview_stream").to_string());
+            return;
+        }
+        if self.selected_index == self.current_index && (self.is_playing || self.is_paused) {
+            self.show_toast(t("message.preview_playing").to_string());
+            return;
+        }
+        let offset = self.intro_offset(&song.path);
+        match self.start_preview(self.selected_index, offset, None) {
+            Ok(preview) => self.preview = Some(preview),
+            Err(e) => self.show_toast(t_args("message.preview_failed", &[("error", &e)])),
+        }
+    }
+
+    fn start_preview(&self, index: usize, offset: Duration, length: Option<Duration>) -> Result<preview::Preview, MusixError> {
+        let config = &self.config.preview;
+        preview::Preview::start(
+            create_audio_source(&self.songs[index].path)?,
+            index,
+            offset,
+            length,
+            config.volume,
+            self.stream_handle.as_ref(),
+            config.device.as_deref(),
+        )
+    }
+
+    // Preview on select: once the selection has rested on a local track for a moment, a snippet from part way in
+    // plays quietly, and stops as soon as the selection moves on. Nothing is said when a track can't be previewed;
+    // the selection is only passing through.
+    fn tick_snippet(&mut self) {
+        let config = &self.config.preview;
+        if !config.on_select {
+            return;
+        }
+        if self.selected_index != self.rested_on {
+            self.rested_on = self.selected_index;
+            self.resting_since = Some(Instant::now());
+            if self.preview.as_ref().is_some_and(preview::Preview::is_snippet) {
+                self.preview = None;
+            }
+            return;
+        }
+        if self.resting_since.is_none_or(|since| since.elapsed() < SNIPPET_DWELL) || self.preview.is_some() {
+            return;
+        }
+        self.resting_since = None;
+        let index = self.selected_index;
+        let Some(song) = self.songs.get(index).filter(|song| song.stream_url.is_none() && song.error.is_none()) else {
+            return;
+        };
+        if index == self.current_index && (self.is_playing || self.is_paused) {
+            return;
+        }
+        let at = (config.snippet_at / 100.0).clamp(0.0, 1.0);
+        let offset = self
+            .duration_of(song)
+            .map_or_else(|| self.intro_offset(&song.path), |duration| duration.mul_f64(at));
+        let length = Duration::from_secs_f64(config.snippet_length.max(1.0));
+        self.preview = self.start_preview(index, offset, Some(length)).ok();
+    }
+
+    // Hand the previewed track to the main output, carrying on from where the preview is
+    fn take_preview(&mut self) -> Result<(), MusixError> {
+        let Some(preview) = self.preview.take() else {
+            return Ok(());
+        };
+        let (index, position) = (preview.index, preview.position());
+        // Stopped first so the two don't overlap
+        drop(preview);
+        self.play_song(index)?;
+        self.seek_to(position);
+        Ok(())
+    }
+
+    fn tick_preview(&mut self) -> bool {
+        if self.preview.as_ref().is_some_and(|preview| preview.finished()) {
+            self.preview = None;
+            return true;
+        }
+        false
+    }
+
+    fn clear_cue(&mut self, number: u8) {
+        let Some(song) = self.songs.get(self.current_index) else {
+            return;
+        };
+        let path = song.path.clone();
+        if self.library.entry(&path).cues.remove(&number).is_some() {
+            self.save_library();
+            self.show_toast(t_args("message.cue_cleared", &[("number", &number)]));
+        }
+    }
+
+    fn save_library(&mut self) {
+        if let Err(e) = self.library.save() {
+            self.show_toast(t_args("message.library_save_failed", &[("error", &e)]));
+        }
+    }
+
+    // Select the track last played on any device, ready to continue from where it stopped
+    fn restore_last_played(&mut self) {
+        let Some(last_played) = self.sync.as_ref().and_then(|sync| sync.state.last_played.clone()) else {
+            return;
+        };
+        let dirs = self.config.library.paths();
+        if let Some(index) = self
+            .songs
+            .iter()
+            .position(|song| song.stream_url.is_none() && sync::track_key(&song.path, &dirs) == last_played.track)
+        {
+            self.current_index = index;
+            self.selected_index = index;
+            self.list_state.select(Some(index));
+            // From another device's sync file, so a position that isn't one starts the track from the top
+            self.seek_offset = Duration::try_from_secs_f64(last_played.seconds).unwrap_or_default();
+        }
+    }
+
+    // Write the position of the current track to the sync file; `ended` clears it
+    fn sync_position(&mut self, ended: bool) {
+        let position = self.current_position().as_secs_f64();
+        let duration = self.song_duration.map(|duration| duration.as_secs_f64());
+        let Some(song) = self.songs.get(self.current_index) else {
+            return;
+        };
+        let Some(sync) = &mut self.sync else {
+            return;
+        };
+        if song.stream_url.is_some() {
+            return;
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        sync.record(
+            sync::track_key(&song.path, &self.config.library.paths()),
+            (!ended).then_some(position),
+            duration,
+            now,
+        );
+        let result = sync.save(now);
+        self.last_sync_save = Instant::now();
+        if let Err(e) = result {
+            self.show_toast(t_args("message.sync_failed", &[("error", &e)]));
+        }
+    }
+
+    fn session(&self, clean_exit: bool) -> session::Session {
+        let path_of = |index: usize| {
+            self.songs
+                .get(index)
+                .filter(|song| song.stream_url.is_none())
+                .map(|song| song.path.to_string_lossy().into_owned())
+        };
+        let track = path_of(self.current_index);
+        let position = self.current_position();
+        // A track that played to its end starts over rather than resuming at the last second
+        let finished = self.song_duration.is_some_and(|duration| position + Duration::from_secs(1) >= duration);
+        session::Session {
+            position: if track.is_some() && !finished { position.as_secs_f64() } else { 0.0 },
+            track,
+            selected: path_of(self.selected_index),
+            shuffle: self.shuffle_mode,
+            repeat: self.repeat_mode,
+            sort_by_bpm: self.sort_by_bpm,
+            sort_column: self.sort_column,
+            volume: self.volume,
+            saved: chrono::Utc::now().timestamp(),
+            clean_exit,
+        }
+    }
+
+    // Pick up where the last session left off, paused at the saved position
+    fn restore_session(&mut self, session: &session::Session) {
+        self.shuffle_mode = session.shuffle;
+        self.repeat_mode = session.repeat;
+        self.change_volume(session.volume - self.volume);
+        if session.sort_by_bpm != self.sort_by_bpm || session.sort_column != self.sort_column {
+            self.sort_by_bpm = session.sort_by_bpm;
+            self.sort_column = session.sort_column;
+            self.sort_songs();
+        }
+
+        let index_of = |path: &Option<String>| {
+            path.as_ref().and_then(|path| {
+                self.songs
+                    .iter()
+                    .position(|song| song.stream_url.is_none() && song.path.to_string_lossy() == path.as_str())
+            })
+        };
+        if let Some(index) = index_of(&session.track) {
+            self.current_index = index;
+            self.selected_index = index;
+            self.seek_offset = Duration::try_from_secs_f64(session.position).unwrap_or_default();
+        }
+        if let Some(index) = index_of(&session.selected) {
+            self.selected_index = index;
+        }
+        self.list_state.select(Some(self.selected_index));
+    }
+
+    // Switch to the idle screen once no key has been pressed for long enough
+    fn tick_idle(&mut self) -> bool {
+        let minutes = self.config.ui.idle_minutes;
+        if minutes == 0 || self.idle_since.is_some() || self.last_input.elapsed() < Duration::from_secs(minutes * 60) {
+            return false;
+        }
+        self.idle_since = Some(Instant::now());
+        true
+    }
+
+    fn tick_session(&mut self) {
+        let interval = self.config.session.snapshot_interval;
+        if interval == 0 || self.last_snapshot.elapsed() < Duration::from_secs(interval) {
+            return;
+        }
+        self.last_snapshot = Instant::now();
+        if let Some(file) = &self.session_file
+            && let Err(e) = session::save(file, &self.session(false))
+        {
+            self.show_toast(t_args("message.session_failed", &[("error", &e)]));
+        }
+    }
+
+    fn tick_sync(&mut self) {
+        if self.sync.is_some() && self.is_playing && self.last_sync_save.elapsed() >= SYNC_INTERVAL {
+            self.sync_position(false);
+        }
+    }
+
+    fn kiosk_pin(&self) -> Option<&str> {
+        self.config.kiosk.pin.as_deref().filter(|pin| !pin.is_empty())
+    }
+
+    // Ctrl+l: locking needs a PIN to be configured, unlocking asks for it
+    fn toggle_lock(&mut self) {
+        if !self.locked {
+            if self.kiosk_pin().is_some() {
+                self.locked = true;
+                self.performance_mode = false;
+                self.show_toast(t("message.locked").to_string());
+            } else {
+                self.show_toast(t("message.lock_needs_pin").to_string());
+            }
+        } else if self.kiosk_pin().is_some() {
+            self.pin_input = Some(String::new());
+        } else {
+            self.show_toast(t("message.locked_for_session").to_string());
+        }
+    }
+
+    fn submit_pin(&mut self) {
+        let Some(input) = self.pin_input.take() else {
+            return;
+        };
+        if self.kiosk_pin() == Some(input.as_str()) {
+            self.locked = false;
+            self.show_toast(t("message.unlocked").to_string());
+        } else {
+            self.show_toast(t("message.wrong_pin").to_string());
+        }
+    }
+
+    // Whether `q` or Esc quits now; while something plays, the configured confirmation comes first
+    fn confirm_quit(&mut self) -> bool {
+        if !self.is_playing {
+            return true;
+        }
+        match self.config.ui.quit_confirm {
+            config::QuitConfirm::Off => true,
+            config::QuitConfirm::Double => {
+                if self.quit_pressed.take().is_some_and(|pressed| pressed.elapsed() <= QUIT_PRESS_WINDOW) {
+                    return true;
+                }
+                self.quit_pressed = Some(Instant::now());
+                self.show_toast(t("message.quit_again").to_string());
+                false
+            }
+            config::QuitConfirm::Prompt => {
+                self.show_quit_prompt = true;
+                false
+            }
+        }
+    }
+
+    // Keys that would quit or change the library while locked; transport, browsing and search stay available
+    fn kiosk_blocks(&self, key: &KeyEvent) -> bool {
+        if !self.locked {
+            return false;
+        }
+        match key.code {
+            KeyCode::Char('c' | 'r' | 'o' | 'e') if key.modifiers.contains(KeyModifiers::CONTROL) => true,
+            KeyCode::Esc => !self.search_mode && !self.show_controls_popup,
+            // `X` and the equalizer panel write the config
+            KeyCode::Char('q' | '!' | 'D' | 'b' | 'B' | 'p' | '*' | 'R' | 'e' | 'I' | 'O' | 'o' | 'L' | ':' | 'X' | 'E') => !self.search_mode,
+            _ => false,
+        }
+    }
+
+    fn now_playing(&self) -> nowplaying::NowPlaying {
+        let status = if self.is_playing {
+            nowplaying::Status::Playing
+        } else if self.is_paused {
+            nowplaying::Status::Paused
+        } else {
+            nowplaying::Status::Stopped
+        };
+        let event = self.listen.as_ref().map(history::Listen::event);
+        nowplaying::NowPlaying {
+            status,
+            title: if self.songs.is_empty() { String::new() } else { self.now_playing_name() },
+            artist: event.and_then(|event| event.artist.clone()),
+            album: event.and_then(|event| event.album.clone()),
+            position: self.current_position().as_secs(),
+            duration: self.song_duration.map(|duration| duration.as_secs()),
+        }
+    }
+
+    // Where `[announce]` says announcements go, and a speech synthesizer if they are spoken
+    fn open_announcer(&mut self) {
+        self.announcer = None;
+        self.last_announcement = None;
+        if let Some(target) = self.config.announce.target() {
+            match announce::Announcer::open(&target) {
+                Ok(announcer) => self.announcer = Some(announcer),
+                Err(e) => self.show_toast(t_args("message.announce_failed", &[("error", &e)])),
+            }
+        }
+        #[cfg(feature = "tts")]
+        {
+            self.speaker = None;
+        }
+        if self.config.announce.speak {
+            #[cfg(feature = "tts")]
+            {
+                self.speaker = Some(speech::Speaker::start(self.config.announce.speech_command.clone()));
+            }
+            #[cfg(not(feature = "tts"))]
+            self.show_toast(t("message.speech_unavailable").to_string());
+        }
+    }
+
+    // Pick up edits to config.toml while playing, once a second; a file that doesn't parse leaves the settings as
+    // they were. Returns whether anything changed
+    fn tick_config(&mut self) -> bool {
+        let Some(file) = &self.config_file else {
+            return false;
+        };
+        if self.config_checked.elapsed() < CONFIG_POLL {
+            return false;
+        }
+        self.config_checked = Instant::now();
+        let modified = modified_time(file);
+        if modified == self.config_modified {
+            return false;
+        }
+        self.config_modified = modified;
+        match config::Config::load_from(file) {
+            Ok(config) => self.apply_config(config),
+            Err(e) => {
+                self.show_toast(t_args("message.config_invalid", &[("error", &e)]));
+                true
+            }
+        }
+    }
+
+    // Command line flags over the settings from the file
+    fn override_config(config: &mut config::Config, ascii: bool) {
+        if ascii {
+            config.ui.icons = icons::IconStyle::Ascii;
+        }
+    }
+
+    // Most settings are read where they are used, so replacing the config is enough for them; the outputs set up
+    // from it at the start are set up again when their section changed. The library folders stay the ones scanned
+    // at the start, and the inbox, scrobbling and sync are left for the next start
+    fn apply_config(&mut self, mut config: config::Config) -> bool {
+        Self::override_config(&mut config, self.ascii);
+        config.library = self.config.library.clone();
+        // The player's own writes, like the crossfade or the equalizer, are already in effect
+        if config == self.config {
+            return false;
+        }
+        let old = std::mem::replace(&mut self.config, config);
+        if old.equalizer != self.config.equalizer {
+            self.equalizer.set_gains(self.config.equalizer.gains());
+            self.equalizer.set_enabled(self.config.equalizer.enabled);
+        }
+        if old.now_playing != self.config.now_playing {
+            self.now_playing_writer = self.config.now_playing.path().map(nowplaying::NowPlayingWriter::new);
+        }
+        if old.announce != self.config.announce {
+            self.open_announcer();
+        }
+        if old.ui.terminal_title != self.config.ui.terminal_title {
+            self.update_terminal_title();
+        }
+        self.view_summary = None;
+        self.show_toast(t("message.config_reloaded").to_string());
+        true
+    }
+
+    // Keep the status bar file current; a failing output is turned off after one warning
+    fn write_now_playing(&mut self, now_playing: nowplaying::NowPlaying) {
+        let Some(writer) = &mut self.now_playing_writer else {
+            return;
+        };
+        let line = now_playing.render(self.config.now_playing.format, &self.config.now_playing.template);
+        if let Err(e) = writer.update(line) {
+            self.now_playing_writer = None;
+            self.show_toast(t_args("message.now_playing_failed", &[("error", &e)]));
+        }
+    }
+
+    fn tick_now_playing(&mut self) {
+        if self.now_playing_writer.is_some() {
+            self.write_now_playing(self.now_playing());
+        }
+        // Every tick, so `musix now-playing` gets the position right
+        if let Some(server) = &self.instance {
+            server.publish(self.now_playing());
+        }
+    }
+
+    // "Now playing: Title by Artist" once per new track or live title; the title comes from the tags when the
+    // track has them
+    fn tick_announce(&mut self) {
+        if !self.is_announcing() || !self.is_playing || self.songs.is_empty() {
+            return;
+        }
+        let event = self.listen.as_ref().map(history::Listen::event).filter(|_| !self.is_stream_playing());
+        let title = event.map_or_else(|| self.now_playing_name(), |event| event.title.clone());
+        let line = announce::line(&title, event.and_then(|event| event.artist.as_deref()));
+        // Pausing and resuming the same track is no transition
+        if self.last_announcement.as_ref() == Some(&line) {
+            return;
+        }
+        if let Some(announcer) = &self.announcer {
+            announcer.write(&line);
+        }
+        #[cfg(feature = "tts")]
+        if let Some(speaker) = &self.speaker {
+            speaker.say(&line);
+        }
+        self.last_announcement = Some(line);
+    }
+
+    fn is_announcing(&self) -> bool {
+        #[cfg(feature = "tts")]
+        if self.speaker.is_some() {
+            return true;
+        }
+        self.announcer.is_some()
+    }
+
+    fn tick_media_controls(&mut self) -> Result<(), MusixError> {
+        let now_playing = self.now_playing();
+        let Some(controls) = &mut self.media_controls else {
+            return Ok(());
+        };
+        controls.update(now_playing);
+        while let Some(command) = self.media_controls.as_ref().and_then(media_controls::MediaControls::poll) {
+            self.handle_media_command(command)?;
+        }
+        Ok(())
+    }
+
+    // Buttons of the system media overlay act on the current track, not the selection
+    fn handle_media_command(&mut self, command: media_controls::Command) -> Result<(), MusixError> {
+        use media_controls::Command;
+        match command {
+            Command::Play => self.play_current()?,
+            // musix has no stopped state of its own once a track is loaded
+            Command::Pause | Command::Stop => self.pause_playback(),
+            Command::Toggle if self.is_playing => self.pause_playback(),
+            Command::Toggle => self.play_current()?,
+            Command::Next => self.next_song()?,
+            Command::Previous => self.previous_song()?,
+            Command::SeekBy(seconds) => self.seek(seconds),
+            Command::SeekTo(position) => self.seek_to(position),
+        }
+        Ok(())
+    }
+
+    fn play_current(&mut self) -> Result<(), MusixError> {
+        if self.is_paused {
+            self.resume_playback();
+        } else if !self.is_playing && !self.songs.is_empty() {
+            self.play_song(self.current_index)?;
+        }
+        Ok(())
+    }
+
+    fn tick_listen(&mut self) {
+        if let Some(listen) = &mut self.listen {
+            listen.tick(self.is_playing);
+        }
+    }
+
+    // Log the play in progress; `ended` means the track ran to its end rather than being skipped or stopped
+    fn finish_listen(&mut self, end: events::End) {
+        let ended = end == events::End::Finished;
+        if self.listen.is_some() {
+            self.count_skip(end);
+            self.sync_position(ended);
+            self.log_event(events::Event::ended(end, self.current_position(), self.song_duration));
+        }
+        let Some(listen) = self.listen.take() else {
+            return;
+        };
+        let event = listen.finish((!ended).then(|| self.current_position()));
+        if let Some(file) = &self.history_file
+            && let Err(e) = history::append(file, &event)
+        {
+            self.show_toast(t_args("message.history_save_failed", &[("error", &e)]));
+        }
+        if let Some(scrobble) = scrobble::Scrobble::from_event(&event) {
+            self.scrobbler.submit(scrobble);
+        }
+    }
+
+    // A skip before `[shuffle] early_skip_percent` of the track has played counts against it, and playing it to the
+    // end starts the count over
+    fn count_skip(&mut self, end: events::End) {
+        let (Some(song), Some(duration)) = (self.songs.get(self.current_index), self.song_duration) else {
+            return;
+        };
+        if song.stream_url.is_some() || duration.is_zero() {
+            return;
+        }
+        let path = song.path.clone();
+        let played = self.current_position().as_secs_f64() / duration.as_secs_f64() * 100.0;
+        match end {
+            events::End::Skipped if played < self.config.shuffle.early_skip_percent => self.library.entry(&path).early_skips += 1,
+            events::End::Finished if self.library.early_skips(&path) > 0 => self.library.entry(&path).early_skips = 0,
+            _ => return,
+        }
+        self.save_library();
+    }
+
+    // Skipped early `[shuffle] demote_after` times, and so left out of shuffle
+    fn is_demoted(&self, path: &Path) -> bool {
+        let demote_after = self.config.shuffle.demote_after;
+        demote_after > 0 && self.library.early_skips(path) >= demote_after
+    }
+
+    // Add a line about the current track to the event log
+    fn log_event(&mut self, event: events::Event) {
+        let (Some(file), Some(song)) = (&self.event_log, self.songs.get(self.current_index)) else {
+            return;
+        };
+        let entry = events::LogEntry::new(event, &song.path, &song.name, self.current_position());
+        if let Err(e) = events::append(file, &entry) {
+            self.show_toast(t_args("message.event_log_failed", &[("error", &e)]));
+        }
+    }
+
+    fn toggle_stats_popup(&mut self) {
+        self.show_stats_popup = !self.show_stats_popup;
+        if self.show_stats_popup {
+            self.stats = stats::Stats::load();
+        }
+    }
+
+    fn show_toast(&mut self, message: String) {
+        self.toast = Some((message, Instant::now()));
+    }
+
+    fn toggle_recording(&mut self) {
+        if self.recording.is_some() {
+            self.stop_recording();
+        } else if self.is_playing {
+            self.start_recording();
+        }
+    }
+
+    fn start_recording(&mut self) {
+        let is_stream = self.stream_info.is_some();
+        let extension = match &self.stream_info {
+            Some(info) => recording::extension_for_content_type(info.lock().unwrap().content_type.as_deref()),
+            None => "wav",
+        };
+
+        match recording::create_file(&self.songs[self.current_index].name, extension) {
+            Ok((path, file)) => {
+                match &self.stream_info {
+                    Some(info) => info.lock().unwrap().recording = Some(BufWriter::new(file)),
+                    None => self.record_tap.start(file),
+                }
+                self.recording = Some(Recording {
+                    path,
+                    started: Instant::now(),
+                    is_stream,
+                });
+            }
+            Err(e) => self.show_toast(t_args("message.recording_failed", &[("error", &e)])),
+        }
+    }
+
+    fn stop_recording(&mut self) {
+        let Some(recording) = self.recording.take() else {
+            return;
+        };
+
+        let result = if recording.is_stream {
+            let writer = self.stream_info.as_ref().and_then(|info| info.lock().unwrap().recording.take());
+            writer.map_or(Ok(()), |mut writer| writer.flush())
+        } else {
+            self.record_tap.stop()
+        };
+
+        let message = match result {
+            Ok(()) => t_args("message.recording_saved", &[("path", &recording.path.display())]),
+            Err(e) => t_args("message.recording_failed", &[("error", &e)]),
+        };
+        self.show_toast(message);
+    }
+
+    // Pick up ICY title changes from the stream thread
+    fn refresh_stream_title(&mut self) {
+        let Some(info) = &self.stream_info else {
+            return;
+        };
+
+        let title = info.lock().unwrap().title.clone();
+        if title != self.stream_title {
+            self.stream_title = title;
+            self.update_terminal_title();
+        }
+    }
+
+    // A toast each time the playing stream runs dry, so a network problem isn't taken for the player hanging
+    fn tick_stream_health(&mut self) {
+        let Some(info) = &self.stream_info else {
+            return;
+        };
+        let stalls = info.lock().unwrap().buffer.as_ref().map_or(0, |buffer| buffer.stalls());
+        if stalls > self.stream_stalls {
+            self.stream_stalls = stalls;
+            self.show_toast(t("message.rebuffering").to_string());
+        }
+    }
+
+    // Audio buffered ahead of the playing stream and its bitrate, once the bitrate is known
+    fn stream_buffer(&self) -> Option<(Duration, u32)> {
+        let info = self.stream_info.as_ref()?.lock().unwrap();
+        let kbps = info.bitrate.filter(|&kbps| kbps > 0)?;
+        let bytes = info.buffer.as_ref()?.buffered();
+        Some((Duration::from_secs_f64(bytes as f64 * 8.0 / (f64::from(kbps) * 1000.0)), kbps))
+    }
+
+    fn play_or_pause(&mut self) -> Result<(), MusixError> {
+        // If no songs are loaded, do nothing
+        if self.songs.is_empty() || self.config.playback.avoid_repeats && self.is_repeat_press() {
+            return Ok(());
+        }
+
+        // If no song has ever been played (initial state), play the selected song
+        if self.playback_start.is_none() && !self.is_playing && !self.is_paused {
+            self.started_by_key = Some(Instant::now());
+            self.play_song(self.selected_index)?;
+            return Ok(());
+        }
+
+        // If selected song is different from current playing song, play the selected song
+        if self.selected_index != self.current_index {
+            self.started_by_key = Some(Instant::now());
+            self.play_song(self.selected_index)?;
+        } else {
+            // If selected song is the same as current playing song, toggle play/pause
+            if self.is_playing {
+                self.pause_playback();
+            } else {
+                self.resume_playback();
+            }
+        }
+        Ok(())
+    }
+
+    // A second Enter on the track the first one just started, which would pause it (or, while a station is still
+    // connecting, start it over), or a press on the track that ended a moment ago, which would play it again
+    fn is_repeat_press(&self) -> bool {
+        if self.selected_index != self.current_index {
+            return false;
+        }
+        let recent = |at: Instant| at.elapsed() < REPEAT_PRESS_WINDOW;
+        let finished = self
+            .just_finished
+            .as_ref()
+            .is_some_and(|(path, at)| recent(*at) && *path == self.songs[self.current_index].path);
+        finished || self.started_by_key.is_some_and(recent)
+    }
+
+    // Whether playing `index` would play the track that just ended over again
+    fn repeats_just_finished(&self, index: usize) -> bool {
+        self.config.playback.avoid_repeats
+            && self
+                .just_finished
+                .as_ref()
+                .is_some_and(|(path, _)| self.songs.get(index).is_some_and(|song| song.path == *path))
+    }
+
+    fn next_song(&mut self) -> Result<(), MusixError> {
+        self.advance(true)
+    }
+
+    fn previous_song(&mut self) -> Result<(), MusixError> {
+        self.advance(false)
+    }
+
+    // Play the next/previous song, stepping over files that turn out to be unplayable
+    fn advance(&mut self, forward: bool) -> Result<(), MusixError> {
+        if self.songs.is_empty() {
+            return Ok(());
+        }
+
+        for _ in 0..self.songs.len() {
+            // The track shown as next is the one that plays, even in shuffle
+            let planned = if forward { self.planned_next() } else { None };
+            let Some(index) = planned.or_else(|| self.pick_index(forward)) else {
+                // Skipping past the last track with repeat off ends the list, as playing to its end does
+                if forward && (self.is_playing || self.is_paused) {
+                    self.stop_playback();
+                    self.alert(self.config.alerts.queue_empty);
+                }
+                break;
+            };
+            if self.songs[index].error.is_some() || self.repeats_just_finished(index) {
+                // Nothing playable left, or only the track that just ended
+                break;
+            }
+
+            self.play_song(index)?;
+            if self.songs[index].error.is_none() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn pick_index(&self, forward: bool) -> Option<usize> {
+        if forward && let Some(index) = self.queued_index() {
+            return Some(index);
+        }
+        match self.shuffle_mode {
+            shuffle::ShuffleMode::Off => self.neighbour_index(forward),
+            shuffle::ShuffleMode::Random => Some(self.random_index()),
+            shuffle::ShuffleMode::Weighted => Some(self.weighted_random_index()),
+            shuffle::ShuffleMode::Rediscover => Some(self.least_recent_index()),
+        }
+    }
+
+    fn stop_playback(&mut self) {
+        self.finish_listen(events::End::Skipped);
+        self.end_crossfade();
+        if let Some(sink) = &self.sink {
+            sink.lock().unwrap().stop();
+        }
+        self.is_playing = false;
+        self.is_paused = false;
+        self.playback_start = None;
+        self.seek_offset = Duration::from_secs(0);
+        self.update_terminal_title();
+    }
+
+    // The planned next track, if it is still in the list, playable and not the one playing now
+    fn planned_next(&self) -> Option<usize> {
+        let (path, url) = self.up_next.as_ref()?;
+        self.index_of(path, url)
+            .filter(|&index| index != self.current_index && self.songs[index].error.is_none())
+    }
+
+    // While playing, pick the next track ahead of time when there is none planned; nothing is next at the end of a
+    // list that doesn't loop
+    fn plan_next(&mut self) {
+        if !self.is_playing || self.songs.is_empty() || self.planned_next().is_some() {
+            return;
+        }
+        // The playing track comes round again, with no hand-over to another
+        if self.repeat_mode == repeat::RepeatMode::One {
+            return;
+        }
+        let Some(index) = self.pick_index(true) else {
+            self.up_next = None;
+            return;
+        };
+        self.up_next = (index != self.current_index && self.songs[index].error.is_none()).then(|| {
+            let song = &self.songs[index];
+            (song.path.clone(), song.stream_url.clone())
+        });
+    }
+
+    // Hand over to the planned next track before the playing one ends: queued right behind it when it continues
+    // the album, or faded in over its last seconds
+    fn tick_transition(&mut self) -> Result<(), MusixError> {
+        self.ramp_crossfade();
+        if !self.is_playing || self.gapless_next.is_some() || self.crossfade.is_some() {
+            return Ok(());
+        }
+        let (Some(song), Some(duration)) = (self.songs.get(self.current_index), self.song_duration) else {
+            return Ok(());
+        };
+        // A loop with its B near the end would otherwise hand over to the next track before going round
+        if song.stream_url.is_some() || self.library.end_action(&song.path) != library::EndAction::Next || self.ab_loop.is_some() {
+            return Ok(());
+        }
+        let Some(next) = self.planned_next().filter(|&next| self.songs[next].stream_url.is_none()) else {
+            return Ok(());
+        };
+        let outro = self.outro_skip(&song.path);
+        let end = duration.saturating_sub(outro.unwrap_or_default());
+        let remaining = end.saturating_sub(self.current_position());
+        let (previous, upcoming) = (self.tags.get(&song.path), self.tags.get(&self.songs[next].path));
+        match transition::choose(&self.config.playback, previous, upcoming) {
+            // A skipped outro cuts the track short, which the queue can't do
+            transition::Transition::Gapless if remaining <= GAPLESS_LEAD && outro.is_none() => self.queue_gapless(next),
+            // Never more than half the track, so a short one isn't faded from its start
+            transition::Transition::Crossfade(length) if remaining <= length.min(end / 2) => return self.start_crossfade(length.min(end / 2)),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Append `next` to the sink behind the playing track; play_song picks it up when the playing track ends
+    fn queue_gapless(&mut self, next: usize) {
+        let Some(sink) = &self.sink else {
+            return;
+        };
+        let path = &self.songs[next].path;
+        let start = self.intro_offset(path);
+        // Seeking now, so the skipped intro isn't decoded in one go at the moment of the handover
+        let opened = self.open_source(path).and_then(|mut source| {
+            if start.is_zero() || source.try_seek(start).is_ok() {
+                Ok(source)
+            } else {
+                self.open_source_at(path, start)
+            }
+        });
+        let source = match opened {
+            Ok(source) => source,
+            Err(e) => {
+                // Quarantined now rather than when it comes up, so another track is planned instead
+                self.songs[next].error = Some(e.to_string());
+                return;
+            }
+        };
+        sink.lock().unwrap().append(source);
+        self.gapless_next = Some((path.clone(), start));
+    }
+
+    // Move the playing track to a sink of its own to fade out, and start the next one on a new sink from silence
+    fn start_crossfade(&mut self, length: Duration) -> Result<(), MusixError> {
+        let Some(handle) = &self.stream_handle else {
+            return Ok(());
+        };
+        let incoming = Sink::try_new(handle)?;
+        incoming.set_volume(0.0);
+        let Some(outgoing) = self.sink.replace(Arc::new(Mutex::new(incoming))) else {
+            return Ok(());
+        };
+        self.track_finished()?;
+        self.crossfade = Some(transition::Crossfade::new(outgoing, length));
+        Ok(())
+    }
+
+    fn ramp_crossfade(&mut self) {
+        let Some(crossfade) = &self.crossfade else {
+            return;
+        };
+        let progress = crossfade.progress();
+        crossfade.sink.lock().unwrap().set_volume((1.0 - progress) * self.output_volume());
+        if let Some(sink) = &self.sink {
+            sink.lock().unwrap().set_volume(progress * self.output_volume());
+        }
+        if progress >= 1.0 {
+            self.end_crossfade();
+        }
+    }
+
+    // Stop the outgoing track of a crossfade and bring the incoming one up to the volume set
+    fn end_crossfade(&mut self) {
+        let Some(crossfade) = self.crossfade.take() else {
+            return;
+        };
+        crossfade.sink.lock().unwrap().stop();
+        if let Some(sink) = &self.sink {
+            sink.lock().unwrap().set_volume(self.output_volume());
+        }
+    }
+
+    fn output_volume(&self) -> f32 {
+        if self.muted { 0.0 } else { self.volume }
+    }
+
+    // `+` and `-`, which also unmute; a crossfade under way picks the new volume up on its next step
+    fn change_volume(&mut self, delta: f32) {
+        // Rounded to whole steps so the percentage shown stays even after many presses
+        self.volume = ((self.volume + delta) / VOLUME_STEP).round().clamp(0.0, 1.0 / VOLUME_STEP) * VOLUME_STEP;
+        self.muted = false;
+        self.apply_volume();
+    }
+
+    // `M`: silence right away, and back to the volume from before on the second press
+    fn toggle_mute(&mut self) {
+        self.muted = !self.muted;
+        self.apply_volume();
+    }
+
+    fn apply_volume(&self) {
+        if self.crossfade.is_none()
+            && let Some(sink) = &self.sink
+        {
+            sink.lock().unwrap().set_volume(self.output_volume());
+        }
+    }
+
+    // The first inbox arrival still waiting that can be played
+    fn queued_index(&self) -> Option<usize> {
+        self.inbox_queue
+            .iter()
+            .filter_map(|path| self.index_of(path, &None))
+            .find(|&index| index != self.current_index && self.songs[index].error.is_none())
+    }
+
+    // Tracks that settled in the inbox folder: added to the list, flagged as new and queued to play next;
+    // returns whether any arrived
+    fn collect_inbox(&mut self) -> bool {
+        let Some(inbox) = &self.inbox else {
+            return false;
+        };
+        let arrivals: Vec<PathBuf> = inbox.arrivals.try_iter().collect();
+        let Some(first) = arrivals.first() else {
+            return false;
+        };
+        let message = match arrivals.len() {
+            1 => t_args("message.inbox_new", &[("name", &Song::from_path(first.clone()).name)]),
+            count => t_args("message.inbox_new_count", &[("count", &count)]),
+        };
+        for path in &arrivals {
+            if self.index_of(path, &None).is_none() {
+                self.songs.push(Song::from_path(path.clone()));
+            }
+            self.new_tracks.insert(path.clone());
+            self.inbox_queue.push_back(path.clone());
+        }
+        self.sort_songs();
+        self.read_tags(arrivals);
+        // Whatever was planned gives way to the inbox
+        self.up_next = None;
+        self.show_toast(message);
+        true
+    }
+
+    // Read the tags of files added to the list, after whatever the reader is busy with
+    fn read_tags(&mut self, paths: Vec<PathBuf>) {
+        if self.tag_reader.is_some() {
+            self.tag_backlog.extend(paths);
+        } else if !paths.is_empty() {
+            self.tag_reader = Some(tags::TagReader::start(paths));
+        }
+    }
+
+    fn open_dir_picker(&mut self, purpose: FolderPick) {
+        let start = Some(paths::music_dir()).filter(|dir| dir.is_dir()).unwrap_or_else(paths::home_dir);
+        self.dir_picker = Some((dir_picker::DirPicker::open(start), purpose));
+    }
+
+    // Tab in the folder picker: use the folder it shows
+    fn pick_folder(&mut self) {
+        let Some((picker, purpose)) = self.dir_picker.take() else {
+            return;
+        };
+        match purpose {
+            FolderPick::AddTracks => self.add_folder(&picker.dir),
+            FolderPick::LibraryFolder => self.add_library_folder(&picker.dir.display().to_string()),
+        }
+    }
+
+    // The configured library folders, or the default ones when none are set, so they can be edited as a list
+    fn library_folders(&self) -> Vec<String> {
+        if !self.config.library.folders.is_empty() {
+            return self.config.library.folders.clone();
+        }
+        self.config
+            .library
+            .paths()
+            .into_iter()
+            .filter(|dir| dir.is_dir())
+            .map(|dir| dir.display().to_string())
+            .collect()
+    }
+
+    // Add a folder to the library for good: written to the config and scanned right away, not the whole library
+    fn add_library_folder(&mut self, folder: &str) {
+        let dir = paths::expand_home(folder.trim());
+        if !dir.is_dir() {
+            self.show_toast(t_args("message.not_a_folder", &[("dir", &dir.display())]));
+            return;
+        }
+        let mut folders = self.library_folders();
+        if folders.iter().any(|existing| paths::expand_home(existing) == dir) {
+            self.show_toast(t_args("message.folder_exists", &[("dir", &dir.display())]));
+            return;
+        }
+        folders.push(folder.trim().to_string());
+        if self.save_library_folders(folders) {
+            self.add_folder(&dir);
+        }
+    }
+
+    // Take the highlighted folder out of the library, with the tracks that only it provided
+    fn remove_library_folder(&mut self) {
+        let mut folders = self.library_folders();
+        let Some(position) = self.sources_state.selected().filter(|&position| position < folders.len()) else {
+            return;
+        };
+        if folders.len() == 1 {
+            self.show_toast(t("message.last_folder").to_string());
+            return;
+        }
+        let removed = paths::expand_home(&folders.remove(position));
+        if !self.save_library_folders(folders) {
+            return;
+        }
+        let remaining = self.config.library.paths();
+        let before = self.songs.len();
+        // The playing track stays until it is done
+        let playing = (self.is_playing || self.is_paused).then_some(self.current_index);
+        self.retain_songs(|index, song| {
+            Some(index) == playing || song.stream_url.is_some() || !song.path.starts_with(&removed) || remaining.iter().any(|dir| song.path.starts_with(dir))
+        });
+        self.sources_state.select(Some(position.saturating_sub(1)));
+        let count = before - self.songs.len();
+        self.show_toast(t_args("message.folder_removed", &[("dir", &removed.display()), ("count", &count)]));
+    }
+
+    // Write the new folder list; returns whether it was saved
+    fn save_library_folders(&mut self, folders: Vec<String>) -> bool {
+        if let Some(file) = &self.config_file
+            && let Err(e) = config::save_library_folders(file, &folders)
+        {
+            self.show_toast(t_args("message.folders_save_failed", &[("error", &e)]));
+            return false;
+        }
+        self.config.library.folders = folders;
+        true
+    }
+
+    // `X`: the next of CROSSFADE_STEPS after the length set, back to none after the longest
+    fn cycle_crossfade(&mut self) {
+        let current = self.config.playback.crossfade;
+        let next = CROSSFADE_STEPS.iter().copied().find(|&step| step > current + 1e-9).unwrap_or(0.0);
+        self.set_crossfade(next);
+    }
+
+    // `:crossfade <seconds>` and `X`; kept in the config file for the next start. A crossfade under way finishes
+    // at its old length.
+    fn set_crossfade(&mut self, seconds: f64) {
+        if let Some(file) = &self.config_file
+            && let Err(e) = config::save_crossfade(file, seconds)
+        {
+            self.show_toast(t_args("message.crossfade_save_failed", &[("error", &e)]));
+            return;
+        }
+        self.config.playback.crossfade = seconds;
+        if seconds > 0.0 {
+            let seconds = format!("{seconds:.1}");
+            self.show_toast(t_args("message.crossfade_set", &[("seconds", &seconds)]));
+        } else {
+            self.show_toast(t("message.crossfade_off").to_string());
+        }
+    }
+
+    // Drop the songs `keep` turns down, keeping the current and selected tracks pointing at the same songs
+    fn retain_songs(&mut self, keep: impl Fn(usize, &Song) -> bool) {
+        let kept: Vec<bool> = self.songs.iter().enumerate().map(|(index, song)| keep(index, song)).collect();
+        // Each old index to the number of kept songs before it, which is its new index if it stays
+        let new_index = |old: usize| kept[..old].iter().filter(|&&kept| kept).count();
+        let last = kept.iter().filter(|&&kept| kept).count().saturating_sub(1);
+        self.current_index = new_index(self.current_index.min(kept.len())).min(last);
+        self.selected_index = new_index(self.selected_index.min(kept.len())).min(last);
+        let mut index = 0;
+        self.songs.retain(|_| {
+            index += 1;
+            kept[index - 1]
+        });
+        if self.search_mode {
+            let query = self.search_query.clone();
+            self.fuzzy_search(&query);
+        } else {
+            self.filtered_songs = (0..self.songs.len()).collect();
+            self.list_state.select(Some(self.selected_index));
+        }
+        // Its index may now be another song's
+        self.preview = None;
+        self.view_summary = None;
+    }
+
+    // Enter after `:`; commands take the rest of the line as their argument
+    fn run_command(&mut self, line: &str) {
+        let (command, argument) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        match command {
+            "" => {}
+            "adddir" if argument.trim().is_empty() => self.show_toast(t("message.adddir_usage").to_string()),
+            "adddir" => self.add_library_folder(argument),
+            "fadein" | "fadeout" => self.set_fade(command == "fadein", argument),
+            "start" | "end" => self.set_trim(command == "start", argument),
+            "crossfade" => match argument.trim().parse::<f64>().ok().filter(|seconds| seconds.is_finite() && *seconds >= 0.0) {
+                Some(seconds) => self.set_crossfade(seconds),
+                None => self.show_toast(t("message.crossfade_usage").to_string()),
+            },
+            "share" | "browse" => self.share(command == "browse", argument),
+            "setlist" if argument.trim().is_empty() => self.show_toast(t("message.setlist_usage").to_string()),
+            "setlist" => self.export_setlist(argument),
+            "art" | "albumart" => self.embed_art(command == "albumart", argument),
+            _ => self.show_toast(t_args("message.unknown_command", &[("command", &command)])),
+        }
+    }
+
+    // `:fadein` / `:fadeout <seconds>` for the selected track; 0 takes the fade off again
+    fn set_fade(&mut self, fade_in: bool, argument: &str) {
+        let Some(seconds) = argument.trim().parse::<f64>().ok().filter(|seconds| seconds.is_finite() && *seconds >= 0.0) else {
+            self.show_toast(t("message.fade_usage").to_string());
+            return;
+        };
+        let Some(song) = self.songs.get(self.selected_index).filter(|song| song.stream_url.is_none()) else {
+            return;
+        };
+        let (path, name) = (song.path.clone(), song.name.clone());
+        let track = self.library.entry(&path);
+        let fade = if fade_in { &mut track.fade_in } else { &mut track.fade_out };
+        *fade = (seconds > 0.0).then_some(seconds);
+        self.save_library();
+        let message = match (fade_in, seconds > 0.0) {
+            (true, true) => "message.fade_in_set",
+            (false, true) => "message.fade_out_set",
+            (true, false) => "message.fade_in_cleared",
+            (false, false) => "message.fade_out_cleared",
+        };
+        let seconds = format!("{seconds:.1}");
+        self.show_toast(t_args(message, &[("name", &name), ("seconds", &seconds)]));
+    }
+
+    // `:start <time>` plays the selected track from `time` on and `:end <time>` cuts that much off its end, as
+    // seconds or m:ss; the same skips `I` and `O` set at the playhead, given exactly. 0 takes one off again.
+    fn set_trim(&mut self, start: bool, argument: &str) {
+        let Some(offset) = chapters::parse_timestamp(argument) else {
+            self.show_toast(t("message.trim_usage").to_string());
+            return;
+        };
+        let Some(song) = self.songs.get(self.selected_index).filter(|song| song.stream_url.is_none()) else {
+            return;
+        };
+        let (path, name) = (song.path.clone(), song.name.clone());
+        // Nothing would be left to play, and a skipped outro that long would end the track the moment it starts
+        let other = if start {
+            self.library.skip_outro(&path)
+        } else {
+            self.library.skip_intro(&path)
+        };
+        let kept = Duration::from_secs_f64(other.unwrap_or(0.0).max(0.0)) + offset;
+        if let Some(duration) = self.library.duration(&path).filter(|&duration| kept >= duration) {
+            let length = Player::format_duration(duration);
+            self.show_toast(t_args("message.trim_too_long", &[("name", &name), ("length", &length)]));
+            return;
+        }
+        let track = self.library.entry(&path);
+        let skip = if start { &mut track.skip_intro } else { &mut track.skip_outro };
+        *skip = (!offset.is_zero()).then_some(offset.as_secs_f64());
+        self.save_library();
+        let formatted = Player::format_duration(offset);
+        let message = match (start, offset.is_zero()) {
+            (true, false) => t_args("message.intro_set", &[("position", &formatted), ("name", &name)]),
+            (false, false) => t_args("message.outro_set", &[("length", &formatted), ("name", &name)]),
+            (true, true) => t_args("message.intro_cleared", &[("name", &name)]),
+            (false, true) => t_args("message.outro_cleared", &[("name", &name)]),
+        };
+        self.show_toast(message);
+    }
+
+    // `:setlist <file>`: everything played since the player started, up to the track playing now, as M3U or JSON
+    fn export_setlist(&mut self, argument: &str) {
+        let file = paths::expand_home(argument.trim().trim_matches(|c| c == '"' || c == '\''));
+        let mut events = self.history_file.as_deref().map(history::load).unwrap_or_default();
+        events.extend(self.listen.as_ref().map(|listen| listen.event().clone()));
+        let plays = setlist::plays_since(&events, self.opened);
+        if plays.is_empty() {
+            self.show_toast(t("setlist.empty").to_string());
+            return;
+        }
+        match setlist::write(&file, &plays) {
+            Ok(()) => self.show_toast(t_args("setlist.written", &[("count", &plays.len()), ("file", &file.display())])),
+            Err(e) => self.show_toast(t_args("message.setlist_failed", &[("error", &e)])),
+        }
+    }
+
+    // `:art <image>` embeds the image as the front cover of the selected track, `:albumart <image>` of every track
+    // of its album in the same folder. The playing track is left out, as rewriting its tags would pull the file
+    // out from under the decoder.
+    fn embed_art(&mut self, whole_album: bool, argument: &str) {
+        let image = argument.trim().trim_matches(|c| c == '"' || c == '\'');
+        if image.is_empty() {
+            self.show_toast(t("message.art_usage").to_string());
+            return;
+        }
+        let Some(song) = self.songs.get(self.selected_index).filter(|song| song.stream_url.is_none()) else {
+            return;
+        };
+        let mut tracks = vec![song.path.clone()];
+        if whole_album {
+            let album = |path: &Path| self.tags.get(path).and_then(|tags| tags.album.clone());
+            let Some(name) = album(&song.path) else {
+                self.show_toast(t("message.art_no_album").to_string());
+                return;
+            };
+            let folder = song.path.parent();
+            tracks = (self.songs.iter())
+                .filter(|other| other.stream_url.is_none() && other.path.parent() == folder && album(&other.path).as_ref() == Some(&name))
+                .map(|other| other.path.clone())
+                .collect();
+        }
+        let playing = (self.is_playing || self.is_paused).then(|| self.songs[self.current_index].path.clone());
+        let skipped = playing.as_ref().is_some_and(|playing| tracks.contains(playing));
+        tracks.retain(|track| Some(track) != playing.as_ref());
+        let picture = match cover_art::read_picture(&paths::expand_home(image)) {
+            Ok(picture) => picture,
+            Err(e) => {
+                self.show_toast(t_args("message.art_failed", &[("error", &e)]));
+                return;
+            }
+        };
+        let mut embedded = 0;
+        for track in &tracks {
+            if let Err(e) = cover_art::embed(track, &picture) {
+                self.show_toast(t_args("message.art_failed", &[("error", &e)]));
+                return;
+            }
+            embedded += 1;
+            // Loaded again with the new picture
+            if self.cover.path.as_ref() == Some(track) {
+                self.cover.path = None;
+            }
+        }
+        let message = if skipped {
+            "message.art_embedded_but_playing"
+        } else {
+            "message.art_embedded"
+        };
+        self.show_toast(t_args(message, &[("count", &embedded)]));
+    }
+
+    // Scan `dir` in the background; its tracks not in the list yet are added for this session once it is done
+    fn add_folder(&mut self, dir: &Path) {
+        let (sender, receiver) = mpsc::channel();
+        let walked = dir.to_path_buf();
+        std::thread::spawn(move || {
+            let mut found = Vec::new();
+            let _ = sender.send(visit_dir(&walked, &mut found).map(|()| found));
+        });
+        self.folder_scans.push((dir.to_path_buf(), receiver));
+        self.show_toast(t_args("message.folder_scanning", &[("dir", &dir.display())]));
+    }
+
+    // Take in the folders scanned so far; returns whether any finished
+    fn collect_folder_scans(&mut self) -> bool {
+        let mut finished = Vec::new();
+        self.folder_scans.retain(|(dir, receiver)| match receiver.try_recv() {
+            Ok(result) => {
+                finished.push((dir.clone(), result));
+                false
+            }
+            Err(TryRecvError::Empty) => true,
+            Err(TryRecvError::Disconnected) => false,
+        });
+        let any = !finished.is_empty();
+        for (dir, result) in finished {
+            match result {
+                Ok(found) => self.add_found(&dir, found),
+                Err(e) => self.show_toast(t_args("message.dir_access", &[("dir", &dir.display()), ("error", &e)])),
+            }
+        }
+        any
+    }
+
+    fn add_found(&mut self, dir: &Path, mut found: Vec<Song>) {
+        let known: HashSet<(&Path, Option<&str>)> = self.songs.iter().map(|song| (song.path.as_path(), song.stream_url.as_deref())).collect();
+        found.retain(|song| !known.contains(&(song.path.as_path(), song.stream_url.as_deref())));
+        let paths = found.iter().filter(|song| song.stream_url.is_none()).map(|song| song.path.clone()).collect();
+        let count = found.len();
+        self.songs.extend(found);
+        self.sort_songs();
+        self.read_tags(paths);
+        self.show_toast(t_args("message.folder_added", &[("count", &count), ("dir", &dir.display())]));
+    }
+
+    fn index_of(&self, path: &Path, url: &Option<String>) -> Option<usize> {
+        self.songs.iter().position(|song| song.path == path && &song.stream_url == url)
+    }
+
+    // Playable tracks other than the current one and the demoted ones, without the artists of the last few plays
+    fn shuffle_candidates(&self) -> Vec<usize> {
+        let candidates = (0..self.songs.len())
+            .filter(|&i| i != self.current_index && self.songs[i].error.is_none() && !self.is_demoted(&self.songs[i].path))
+            .collect();
+        let artist = |i: usize| self.tags.get(&self.songs[i].path).and_then(|tags| tags.artist.as_deref());
+        shuffle::spread_artists(candidates, artist, &self.recent_artists)
+    }
+
+    fn random_index(&self) -> usize {
+        // Simple random selection using timestamp
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as usize;
+        let indices = self.shuffle_candidates();
+        if indices.is_empty() {
+            self.current_index
+        } else {
+            indices[timestamp % indices.len()]
+        }
+    }
+
+    fn weighted_random_index(&self) -> usize {
+        let now = chrono::Utc::now().timestamp();
+        let candidates = self.shuffle_candidates();
+        let weights: Vec<f64> = candidates
+            .iter()
+            .map(|&i| {
+                let track = self.library.get(&self.songs[i].path);
+                shuffle::track_weight(
+                    track.and_then(|track| track.rating),
+                    track.and_then(|track| track.last_played),
+                    now,
+                    &self.config.shuffle,
+                )
+            })
+            .collect();
+
+        match shuffle::pick_weighted(&weights, shuffle::random_fraction()) {
+            Some(pick) => candidates[pick],
+            // Everything was played moments ago; fall back to a plain shuffle
+            None => self.random_index(),
+        }
+    }
+
+    // The playable track that has gone unheard the longest, for rediscovering forgotten corners of the library
+    fn least_recent_index(&self) -> usize {
+        let mut candidates = self.shuffle_candidates();
+        candidates.retain(|&i| self.songs[i].stream_url.is_none());
+        let last_played: Vec<Option<i64>> = candidates
+            .iter()
+            .map(|&i| self.library.get(&self.songs[i].path).and_then(|track| track.last_played))
+            .collect();
+
+        match shuffle::pick_least_recent(&last_played) {
+            Some(pick) => candidates[pick],
+            None => self.random_index(),
+        }
+    }
+
+    fn cycle_shuffle_mode(&mut self) {
+        self.shuffle_mode = self.shuffle_mode.next();
+        self.up_next = None;
+    }
+
+    // `t`: repeat all -> one -> off
+    fn cycle_repeat_mode(&mut self) {
+        self.repeat_mode = self.repeat_mode.next();
+        self.up_next = None;
+    }
+
+    // 1 -> 5 stars, then back to unrated
+    fn cycle_rating(&mut self) {
+        let Some(song) = self.songs.get(self.selected_index) else {
+            return;
+        };
+        if song.stream_url.is_some() {
+            return;
+        }
+        let path = song.path.clone();
+        let name = song.name.clone();
+
+        let track = self.library.entry(&path);
+        track.rating = match track.rating {
+            None => Some(1),
+            Some(stars) if stars < 5 => Some(stars + 1),
+            Some(_) => None,
+        };
+        let stars = format_stars(track.rating, self.config.ui.icons);
+        self.save_library();
+        self.show_toast(t_args("message.rated", &[("stars", &stars), ("name", &name)]));
+    }
+
+    // None past either end of the list with repeat off
+    fn neighbour_index(&self, forward: bool) -> Option<usize> {
+        let len = self.songs.len();
+        let mut index = self.current_index;
+
+        for _ in 0..len {
+            index = if forward {
+                if index + 1 >= len {
+                    if self.repeat_mode == repeat::RepeatMode::Off {
+                        return None;
+                    }
+                    0
+                } else {
+                    index + 1
+                }
+            } else if index == 0 {
+                if self.repeat_mode == repeat::RepeatMode::Off {
+                    return None;
+                }
+                len - 1
+            } else {
+                index - 1
+            };
+
+            if self.songs[index].error.is_none() {
+                return Some(index);
+            }
+        }
+
+        Some(self.current_index)
+    }
+
+    fn problem_songs(&self) -> Vec<usize> {
+        self.songs
+            .iter()
+            .enumerate()
+            .filter(|(_, song)| song.error.is_some())
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    fn toggle_problems_popup(&mut self) {
+        self.show_problems_popup = !self.show_problems_popup;
+        self.problems_state.select(if self.problem_songs().is_empty() { None } else { Some(0) });
+    }
+
+    fn move_problem_selection(&mut self, direction: i32) {
+        let count = self.problem_songs().len();
+        step_selection(&mut self.problems_state, count, direction);
+    }
+
+    fn selected_problem(&self) -> Option<usize> {
+        let problems = self.problem_songs();
+        self.problems_state.selected().and_then(|i| problems.get(i).copied())
+    }
+
+    // Give a quarantined file another chance, e.g. after fixing it on disk
+    fn retry_problem(&mut self) -> Result<(), MusixError> {
+        if let Some(index) = self.selected_problem() {
+            self.songs[index].error = None;
+            self.play_song(index)?;
+            self.clamp_problem_selection();
+        }
+        Ok(())
+    }
+
+    fn remove_problem(&mut self) {
+        if let Some(index) = self.selected_problem() {
+            self.remove_song(index);
+            self.clamp_problem_selection();
+        }
+    }
+
+    fn clamp_problem_selection(&mut self) {
+        let count = self.problem_songs().len();
+        let selected = self.problems_state.selected().unwrap_or(0);
+        self.problems_state.select(if count == 0 { None } else { Some(selected.min(count - 1)) });
+    }
+
+    fn demoted_songs(&self) -> Vec<usize> {
+        (0..self.songs.len()).filter(|&index| self.is_demoted(&self.songs[index].path)).collect()
+    }
+
+    fn toggle_demoted_popup(&mut self) {
+        if self.config.shuffle.demote_after == 0 {
+            self.show_toast(t("message.demote_off").to_string());
+            return;
+        }
+        self.show_demoted_popup = !self.show_demoted_popup;
+        self.demoted_state.select(if self.demoted_songs().is_empty() { None } else { Some(0) });
+    }
+
+    fn move_demoted_selection(&mut self, direction: i32) {
+        let count = self.demoted_songs().len();
+        step_selection(&mut self.demoted_state, count, direction);
+    }
+
+    // Put the selected demoted track back into shuffle, its early skips forgotten
+    fn restore_demoted(&mut self) {
+        let demoted = self.demoted_songs();
+        let Some(&index) = self.demoted_state.selected().and_then(|i| demoted.get(i)) else {
+            return;
+        };
+        let (path, name) = (self.songs[index].path.clone(), self.songs[index].name.clone());
+        self.library.entry(&path).early_skips = 0;
+        self.save_library();
+        let count = demoted.len() - 1;
+        self.demoted_state.select(if count == 0 {
+            None
+        } else {
+            Some(self.demoted_state.selected().unwrap_or(0).min(count - 1))
+        });
+        self.show_toast(t_args("message.demote_restored", &[("name", &name)]));
+    }
+
+    fn toggle_equalizer_panel(&mut self) {
+        self.show_equalizer = !self.show_equalizer;
+        if !self.show_equalizer {
+            self.save_equalizer();
+        }
+    }
+
+    // Up and down pick a band, left and right move it a dB
+    fn move_equalizer_band(&mut self, direction: i32) {
+        self.equalizer_band = (self.equalizer_band as i32 + direction).rem_euclid(equalizer::BANDS.len() as i32) as usize;
+    }
+
+    fn change_equalizer_gain(&mut self, delta: f32) {
+        let mut gains = self.equalizer.gains();
+        gains[self.equalizer_band] = (gains[self.equalizer_band] + delta).clamp(-equalizer::MAX_GAIN, equalizer::MAX_GAIN);
+        self.equalizer.set_gains(gains);
+        // Moving a band is asking to hear it
+        self.equalizer.set_enabled(true);
+    }
+
+    // Flat -> rock -> bass -> treble -> vocal; from custom settings, back to the first
+    fn cycle_equalizer_preset(&mut self) {
+        let next = match equalizer::Preset::matching(&self.equalizer.gains()) {
+            Some(preset) => {
+                let position = equalizer::PRESETS.iter().position(|&other| other == preset).unwrap_or(0);
+                equalizer::PRESETS[(position + 1) % equalizer::PRESETS.len()]
+            }
+            None => equalizer::PRESETS[0],
+        };
+        self.equalizer.set_gains(next.gains());
+        self.equalizer.set_enabled(next != equalizer::Preset::Flat);
+    }
+
+    // Kept for the next start when the panel closes
+    fn save_equalizer(&mut self) {
+        if let Some(file) = &self.config_file
+            && let Err(e) = config::save_equalizer(file, self.equalizer.is_enabled(), &self.equalizer.gains())
+        {
+            self.show_toast(t_args("message.equalizer_save_failed", &[("error", &e)]));
+            return;
+        }
+        self.config.equalizer = config::EqualizerConfig {
+            enabled: self.equalizer.is_enabled(),
+            bands: self.equalizer.gains().to_vec(),
+        };
+    }
+
+    // `w`: put the playing track on top of the revisit stack, for something good that came up at the wrong moment
+    fn save_for_later(&mut self) {
+        let Some(song) = self.songs.get(self.current_index) else {
+            return;
+        };
+        if song.stream_url.is_some() || self.playback_start.is_none() && !self.is_paused {
+            return;
+        }
+        let name = song.name.clone();
+        match self.revisit.push(&song.path) {
+            Ok(()) => self.show_toast(t_args("message.revisit_saved", &[("name", &name)])),
+            Err(e) => self.show_toast(t_args("message.revisit_failed", &[("error", &e)])),
+        }
+    }
+
+    fn toggle_revisit_popup(&mut self) {
+        self.show_revisit = !self.show_revisit;
+        self.revisit_state.select(if self.revisit.entries().is_empty() { None } else { Some(0) });
+    }
+
+    fn move_revisit_selection(&mut self, direction: i32) {
+        step_selection(&mut self.revisit_state, self.revisit.entries().len(), direction);
+    }
+
+    // Enter plays the selected entry and takes it off the stack; `d` only takes it off. A track no longer in the
+    // library stays until dropped, in case its folder is just not mounted.
+    fn pop_revisit(&mut self, play: bool) -> Result<(), MusixError> {
+        let Some(position) = self.revisit_state.selected() else {
+            return Ok(());
+        };
+        let Some(entry) = self.revisit.entries().get(position) else {
+            return Ok(());
+        };
+        let index = self.index_of(Path::new(entry), &None);
+        if play && index.is_none() {
+            self.show_toast(t_args("message.revisit_missing", &[("path", entry)]));
+            return Ok(());
+        }
+        if let Err(e) = self.revisit.remove(position) {
+            self.show_toast(t_args("message.revisit_failed", &[("error", &e)]));
+        }
+        let count = self.revisit.entries().len();
+        self.revisit_state.select(if count == 0 { None } else { Some(position.min(count - 1)) });
+        match index {
+            Some(index) if play => self.play_song(index),
+            _ => Ok(()),
+        }
+    }
+
+    // Drop a song from the library list (the file itself is left untouched)
+    fn remove_song(&mut self, index: usize) {
+        if index >= self.songs.len() || self.songs.len() == 1 {
+            return;
+        }
+
+        self.songs.remove(index);
+
+        let shift = |i: usize| if i > index { i - 1 } else { i };
+        self.current_index = shift(self.current_index).min(self.songs.len() - 1);
+        self.selected_index = shift(self.selected_index).min(self.songs.len() - 1);
+        self.filtered_songs.retain(|&i| i != index);
+        for i in self.filtered_songs.iter_mut() {
+            *i = shift(*i);
+        }
+
+        self.list_state.select(Some(self.list_position(self.selected_index).unwrap_or(0)));
+    }
+
+    fn move_selection(&mut self, direction: i32) {
+        if self.songs.is_empty() {
+            return;
+        }
+
+        let len = self.songs.len();
+        if direction > 0 {
+            self.selected_index = (self.selected_index + 1) % len;
+        } else if direction < 0 {
+            self.selected_index = if self.selected_index == 0 { len - 1 } else { self.selected_index - 1 };
+        }
+        self.list_state.select(Some(self.selected_index));
+    }
+
+    fn get_playback_progress(&self) -> (Duration, Option<Duration>) {
+        if let Some(start_time) = self.playback_start {
+            let elapsed = start_time.elapsed() + self.seek_offset;
+            (elapsed, self.song_duration)
+        } else {
+            (self.seek_offset, self.song_duration)
+        }
+    }
+
+    fn format_duration(duration: Duration) -> String {
+        let total_seconds = duration.as_secs();
+        let minutes = total_seconds / 60;
+        let seconds = total_seconds % 60;
+        format!("{minutes:02}:{seconds:02}")
+    }
+
+    fn pause_playback(&mut self) {
+        if self.is_playing && !self.is_paused {
+            self.end_crossfade();
+            if let Some(ref sink) = self.sink {
+                let sink = sink.lock().unwrap();
+                sink.pause();
+            }
+            self.is_playing = false;
+            self.is_paused = true;
+            self.pause_time = Some(Instant::now());
+
+            // Update seek_offset only if we have a valid playback_start
+            if let Some(start_time) = self.playback_start {
+                self.seek_offset += start_time.elapsed();
+            }
+            self.sync_position(false);
+
+            self.playback_start = None;
+            self.update_terminal_title();
+        }
+    }
+
+    fn resume_playback(&mut self) {
+        if !self.is_playing && self.is_paused && !self.songs.is_empty() {
+            if let Some(ref sink) = self.sink {
+                let sink = sink.lock().unwrap();
+
+                // Try to resume directly first
+                if !sink.empty() {
+                    sink.play();
+                    self.is_playing = true;
+                    self.is_paused = false;
+                    self.playback_start = Some(Instant::now());
+                    self.pause_time = None;
+                    self.update_terminal_title();
+                    return;
+                }
+
+                // If sink is empty, try to seek to current position using try_seek
+                drop(sink);
+
+                // A live stream that dropped out has no position to return to, so reconnect
+                if self.songs[self.current_index].stream_url.is_some() {
+                    let _ = self.play_song(self.current_index);
+                    return;
+                }
+
+                // Load fresh audio source and seek to position
+                self.gapless_next = None;
+                if let Ok(source) = self.open_source(&self.songs[self.current_index].path) {
+                    let sink = self.sink.as_ref().unwrap().lock().unwrap();
+
+                    // Clear the sink and add new source
+                    sink.stop();
+
+                    // If we have a seek offset, try to use try_seek first
+                    if self.seek_offset > Duration::from_secs(0) {
+                        sink.append(source);
+
+                        // Try seeking with try_seek - this is much faster than skip_duration
+                        match sink.try_seek(self.seek_offset) {
+                            Ok(()) => {
+                                // Seeking succeeded
+                                sink.play();
+                                self.is_playing = true;
+                                self.is_paused = false;
+                                self.playback_start = Some(Instant::now());
+                                self.pause_time = None;
+                                self.update_terminal_title();
+                                return;
+                            }
+                            Err(_) => {
+                                // try_seek failed, so reload positioned at the offset
+                                sink.stop();
+
+                                if let Ok(source) = self.open_source_at(&self.songs[self.current_index].path, self.seek_offset) {
+                                    sink.append(source);
+                                    sink.play();
+                                }
+                            }
+                        }
+                    } else {
+                        // No seek needed, just play from beginning
+                        sink.append(source);
+                        sink.play();
+                    }
+
+                    self.is_playing = true;
+                    self.is_paused = false;
+                    self.playback_start = Some(Instant::now());
+                    self.pause_time = None;
+                    self.update_terminal_title();
+                }
+            }
+        } else if !self.is_playing && !self.is_paused && !self.songs.is_empty() {
+            // Handle initial play state (not paused, just stopped)
+            let _ = self.play_song(self.current_index);
+        }
+    }
+
+    fn current_position(&self) -> Duration {
+        if self.is_playing {
+            if let Some(start_time) = self.playback_start {
+                self.seek_offset + start_time.elapsed()
+            } else {
+                self.seek_offset
+            }
+        } else {
+            // When paused, use the stored seek_offset
+            self.seek_offset
+        }
+    }
+
+    fn seek(&mut self, offset_seconds: i32) {
+        // Live radio cannot be seeked
+        if self.songs.is_empty() || self.is_stream_playing() {
+            return;
+        }
+
+        let current_position = self.current_position();
+        let seek_duration = Duration::from_secs(offset_seconds.unsigned_abs().into());
+        let new_position = if offset_seconds < 0 {
+            // Seek backward
+            if current_position > seek_duration {
+                current_position - seek_duration
+            } else {
+                Duration::from_secs(0)
+            }
+        } else {
+            // Seek forward
+            current_position + seek_duration
+        };
+
+        self.seek_to(new_position);
+    }
+
+    // Whether the playing track can hold an A-B loop; live streams have no position to go back to
+    fn can_loop(&self) -> bool {
+        (self.is_playing || self.is_paused) && self.songs.get(self.current_index).is_some_and(|song| song.stream_url.is_none())
+    }
+
+    // `[`: point A where the playhead is; a B before it is dropped, since the loop would run backwards
+    fn set_loop_start(&mut self) {
+        if !self.can_loop() {
+            return;
+        }
+        let start = self.current_position();
+        let end = self.ab_loop.and_then(|(_, end)| end).filter(|&end| end > start);
+        self.ab_loop = Some((start, end));
+        let position = Player::format_duration(start);
+        self.show_toast(t_args("message.loop_start", &[("position", &position)]));
+    }
+
+    // `]`: point B where the playhead is, and straight back to A to start practising
+    fn set_loop_end(&mut self) {
+        if !self.can_loop() {
+            return;
+        }
+        let end = self.current_position();
+        let Some((start, _)) = self.ab_loop.filter(|&(start, _)| end > start) else {
+            self.show_toast(t("message.loop_needs_start").to_string());
+            return;
+        };
+        self.ab_loop = Some((start, Some(end)));
+        let (start_text, end_text) = (Player::format_duration(start), Player::format_duration(end));
+        self.show_toast(t_args("message.loop_set", &[("start", &start_text), ("end", &end_text)]));
+        self.seek_to(start);
+    }
+
+    // `\`: play on through B again
+    fn clear_loop(&mut self) {
+        if self.ab_loop.take().is_some() {
+            self.show_toast(t("message.loop_cleared").to_string());
+        }
+    }
+
+    // Back to A once the playhead passes B; returns whether it went back
+    fn tick_ab_loop(&mut self) -> bool {
+        let Some((start, Some(end))) = self.ab_loop else {
+            return false;
+        };
+        if !self.is_playing || self.current_position() < end {
+            return false;
+        }
+        self.seek_to(start);
+        true
+    }
+
+    // The track ran out before B was seen, with B at its very end; the source is gone, so it is opened again at A
+    fn restart_ab_loop(&mut self) -> bool {
+        let Some((start, Some(_))) = self.ab_loop else {
+            return false;
+        };
+        self.seek_offset = start;
+        self.is_playing = false;
+        let _ = self.play_song(self.current_index);
+        true
+    }
+
+    // Move the scrub target instead of the playhead; the seek happens once the keys are let go
+    fn scrub(&mut self, forward: bool) {
+        if self.songs.is_empty() || self.is_stream_playing() || !self.is_playing && !self.is_paused {
+            return;
+        }
+        let from = self.scrub.map_or_else(|| self.current_position(), |(target, _)| target);
+        // Long files take longer strides, so an hour-long mix is crossed in a hundred presses
+        let step = self
+            .song_duration
+            .map_or(Duration::from_secs(5), |duration| (duration / 100).max(Duration::from_secs(5)));
+        let target = if forward {
+            let target = from + step;
+            self.song_duration.map_or(target, |duration| target.min(duration))
+        } else {
+            from.saturating_sub(step)
+        };
+        self.scrub = Some((target, Instant::now()));
+    }
+
+    // Seek to the scrub target once no scrub key came for a moment; returns whether it did
+    fn tick_scrub(&mut self) -> bool {
+        if self.scrub.is_some_and(|(_, pressed)| pressed.elapsed() >= SCRUB_SETTLE) {
+            self.apply_scrub();
+            return true;
+        }
+        false
+    }
+
+    fn apply_scrub(&mut self) {
+        if let Some((target, _)) = self.scrub.take() {
+            self.seek_to(target);
+        }
+    }
+
+    fn seek_to(&mut self, new_position: Duration) {
+        // Don't seek beyond song duration if we know it
+        let final_position = if let Some(duration) = self.song_duration {
+            new_position.min(duration)
+        } else {
+            new_position
+        };
+
+        // Update seek_offset immediately to provide instant feedback
+        self.seek_offset = final_position;
+
+        if let Some(ref sink) = self.sink {
+            let sink = sink.lock().unwrap();
+
+            if self.is_playing {
+                // When playing, try smooth seeking first
+                match sink.try_seek(final_position) {
+                    Ok(()) => {
+                        // Smooth seek succeeded, just update timing
+                        self.playback_start = Some(Instant::now());
+                    }
+                    Err(_) => {
+                        // Smooth seek failed, do a quick restart without audio glitches
+                        drop(sink);
+
+                        // Temporarily pause to avoid audio artifacts
+                        self.is_playing = false;
+
+                        // Quick restart from new position
+                        let _ = self.play_song(self.current_index);
+                    }
+                }
+            } else if self.is_paused {
+                // When paused, just update the seek position
+                // The position will be applied when resuming
+                // No need to modify the sink while paused
+            }
+        }
+    }
+
+    // Indexes of the songs matching `query`, best match first
+    fn search_matches(&self, query: &str) -> Vec<usize> {
+        // `bpm:` tokens filter by tempo; the rest of the query is matched against names
+        let mut bpm_range = None;
+        let mut words = Vec::new();
+        for token in query.split_whitespace() {
+            match bpm::parse_filter(token) {
+                Some(range) => bpm_range = Some(range),
+                None => words.push(token),
+            }
+        }
+
+        if query.is_empty() {
+            (0..self.songs.len()).collect()
+        } else {
+            let query_key = normalize_for_search(&words.join(" "));
+            let mut matches: Vec<(usize, f32)> = self
+                .songs
+                .iter()
+                .enumerate()
+                .filter(|(_, song)| {
+                    bpm_range
+                        .as_ref()
+                        .is_none_or(|range| self.library.bpm(&song.path).is_some_and(|bpm| range.contains(&(bpm.round() as u32))))
+                })
+                .filter_map(|(index, song)| {
+                    let romanized_score = song.romanized_key.as_deref().map_or(0.0, |key| Self::fuzzy_match_score(&query_key, key));
+                    let score = Self::fuzzy_match_score(&query_key, &song.search_key).max(romanized_score);
+                    if score > 0.0 { Some((index, score)) } else { None }
+                })
+                .collect();
+
+            matches.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+            matches.into_iter().map(|(index, _)| index).collect()
+        }
+    }
+
+    fn fuzzy_search(&mut self, query: &str) {
+        self.filtered_songs = self.search_matches(query);
+        self.view_summary = None;
+        match self.config.search.mode {
+            config::SearchMode::Filter => {
+                if !self.filtered_songs.is_empty() {
+                    self.selected_index = self.filtered_songs[0];
+                    self.list_state.select(Some(0));
+                }
+            }
+            // The whole list stays in view; the cursor goes to the first match from where the search began, like vim
+            config::SearchMode::Jump => {
+                self.filtered_songs.sort_unstable();
+                let first = self.filtered_songs.iter().find(|&&index| index >= self.search_origin);
+                if let Some(&index) = first.or(self.filtered_songs.first()) {
+                    self.selected_index = index;
+                    self.list_state.select(Some(index));
+                }
+            }
+        }
+    }
+
+    // `n`/`N` outside search: the next or previous match of the last query in list order, wrapping around
+    fn search_next(&mut self, forward: bool) {
+        if self.last_search.is_empty() {
+            return;
+        }
+        let mut matches = self.search_matches(&self.last_search);
+        matches.sort_unstable();
+        let next = if forward {
+            matches.iter().find(|&&index| index > self.selected_index).or(matches.first())
+        } else {
+            matches.iter().rev().find(|&&index| index < self.selected_index).or(matches.last())
+        };
+        match next {
+            Some(&index) => {
+                self.selected_index = index;
+                self.list_state.select(Some(index));
+            }
+            None => {
+                let query = self.last_search.clone();
+                self.show_toast(t_args("message.search_not_found", &[("query", &query)]));
+            }
+        }
+    }
+
+    fn fuzzy_match_score(query: &str, text: &str) -> f32 {
+        if query.is_empty() {
+            return 1.0;
+        }
+
+        if text.contains(query) {
+            let exact_match_bonus = if text == query { 2.0 } else { 1.5 };
+            let starts_with_bonus = if text.starts_with(query) { 1.2 } else { 1.0 };
+            return exact_match_bonus * starts_with_bonus;
+        }
+
+        let mut score = 0.0;
+        let query_chars: Vec<char> = query.chars().collect();
+        let text_chars: Vec<char> = text.chars().collect();
+        let mut query_index = 0;
+
+        for (text_index, text_char) in text_chars.iter().enumerate() {
+            if query_index < query_chars.len() && *text_char == query_chars[query_index] {
+                score += 1.0 / (text_index as f32 + 1.0);
+                query_index += 1;
+            }
+        }
+
+        if query_index == query_chars.len() {
+            score / query_chars.len() as f32
+        } else {
+            0.0
+        }
+    }
+
+    fn enter_search_mode(&mut self) {
+        self.search_mode = true;
+        self.search_origin = self.selected_index;
+        self.search_query.clear();
+        self.search_history.reset();
+        self.fuzzy_search("");
+    }
+
+    fn exit_search_mode(&mut self) {
+        self.search_mode = false;
+        if let Err(e) = self.search_history.push(&self.search_query) {
+            self.show_toast(t_args("message.search_history_failed", &[("error", &e)]));
+        }
+        if !self.search_query.trim().is_empty() {
+            self.last_search = std::mem::take(&mut self.search_query);
+        }
+        self.search_query.clear();
+        self.filtered_songs = (0..self.songs.len()).collect();
+        self.list_state.select(Some(self.selected_index));
+        self.view_summary = None;
+    }
+
+    // Esc in search mode: leave it, back on the song selected before the search unless configured to stay on the
+    // match. The query is kept for `n`/`N` either way
+    fn cancel_search(&mut self) {
+        if self.config.search.restore_on_esc && self.search_origin < self.songs.len() {
+            self.selected_index = self.search_origin;
+        }
+        self.exit_search_mode();
+    }
+
+    // Up/Down in search mode: step through past queries while the query is empty or one recalled untouched,
+    // so they still move between matches once something was typed. Returns whether a query was recalled
+    fn recall_search(&mut self, older: bool) -> bool {
+        if !self.search_query.is_empty() && !self.search_history.is_recalled(&self.search_query) {
+            return false;
+        }
+        let query = if older {
+            match self.search_history.older() {
+                Some(query) => query.to_string(),
+                None => return false,
+            }
+        } else if self.search_query.is_empty() {
+            return false;
+        } else {
+            self.search_history.newer().to_string()
+        };
+        self.search_query = query.clone();
+        self.fuzzy_search(&query);
+        true
+    }
+
+    // Whether the list shows only the search matches rather than every song
+    fn is_filtering(&self) -> bool {
+        self.search_mode && self.config.search.mode == config::SearchMode::Filter
+    }
+
+    // Where the song at `index` appears in the list as currently shown
+    fn list_position(&self, index: usize) -> Option<usize> {
+        if self.is_filtering() {
+            self.filtered_songs.iter().position(|&i| i == index)
+        } else {
+            Some(index)
+        }
+    }
+
+    fn display_len(&self) -> usize {
+        if self.is_filtering() { self.filtered_songs.len() } else { self.songs.len() }
+    }
+
+    // The songs at these list positions, paired with their index in `songs`
+    fn display_songs(&self, positions: Range<usize>) -> Vec<(usize, &Song)> {
+        if self.is_filtering() {
+            self.filtered_songs[positions].iter().map(|&index| (index, &self.songs[index])).collect()
+        } else {
+            positions.map(|index| (index, &self.songs[index])).collect()
+        }
+    }
+
+    fn move_selection_in_search(&mut self, direction: i32) {
+        if self.filtered_songs.is_empty() {
+            return;
+        }
+
+        let current_filtered_index = self.filtered_songs.iter().position(|&index| index == self.selected_index).unwrap_or(0);
+
+        let new_filtered_index = if direction > 0 {
+            (current_filtered_index + 1) % self.filtered_songs.len()
+        } else if direction < 0 {
+            if current_filtered_index == 0 {
+                self.filtered_songs.len() - 1
+            } else {
+                current_filtered_index - 1
+            }
+        } else {
+            current_filtered_index
+        };
+
+        self.selected_index = self.filtered_songs[new_filtered_index];
+        self.list_state.select(self.list_position(self.selected_index));
+    }
+
+    // A finished chord from the key handler; combinations without a meaning are ignored
+    fn run_chord(&mut self, count: Option<u32>, prefix: char, key: char) {
+        match (prefix, key) {
+            ('Q', register) if macros::is_register(register) => self.start_macro(register),
+            ('@', register) if register == '@' || macros::is_register(register) => self.play_macro(register, count.unwrap_or(1)),
+            ('g', 'g') => match count {
+                Some(line) => self.go_to_line(line),
+                None => self.jump_to_first(),
+            },
+            ('m', mark) if mark.is_ascii_lowercase() => self.set_mark(mark),
+            ('\'', mark) if mark.is_ascii_lowercase() => self.jump_to_mark(mark),
+            ('d', digit @ '1'..='9') if self.performance_mode => self.clear_cue(digit as u8 - b'0'),
+            _ => {}
+        }
+    }
+
+    // `Q{register}`: every key from here until the next `Q` goes into the register
+    fn start_macro(&mut self, register: char) {
+        self.macros.start(register);
+        self.show_toast(t_args("message.macro_recording", &[("register", &register)]));
+    }
+
+    fn stop_macro(&mut self) {
+        if let Some((register, count)) = self.macros.stop() {
+            self.show_toast(t_args("message.macro_recorded", &[("count", &count), ("register", &register)]));
+        }
+    }
+
+    // `{count}@{register}`: the keys go in front of anything still to be played, so a macro calling another runs
+    // it in place, as in vim
+    fn play_macro(&mut self, register: char, times: u32) {
+        let Some(keys) = self.macros.keys(register) else {
+            self.show_toast(t_args("message.macro_empty", &[("register", &register)]));
+            return;
+        };
+        let keys = keys.repeat(times as usize);
+        for key in keys.into_iter().rev() {
+            self.replay.push_front(key);
+        }
+    }
+
+    // A key pressed while a macro plays stops it, which is also the way out of one that calls itself
+    fn stop_replay(&mut self) {
+        if !self.replay.is_empty() {
+            self.replay.clear();
+            self.show_toast(t("message.macro_stopped").to_string());
+        }
+    }
+
+    // `{count}G` and `{count}gg`: the song at that 1-based position in the list
+    fn go_to_line(&mut self, line: u32) {
+        if self.songs.is_empty() {
+            return;
+        }
+        self.selected_index = (line as usize).clamp(1, self.songs.len()) - 1;
+        self.list_state.select(Some(self.selected_index));
+    }
+
+    fn set_mark(&mut self, mark: char) {
+        let Some(song) = self.songs.get(self.selected_index) else {
+            return;
+        };
+        let name = song.name.clone();
+        self.marks.insert(mark, (song.path.clone(), song.stream_url.clone()));
+        self.show_toast(t_args("message.mark_set", &[("mark", &mark), ("name", &name)]));
+    }
+
+    fn jump_to_mark(&mut self, mark: char) {
+        let index = self.marks.get(&mark).and_then(|(path, url)| self.index_of(path, url));
+        match index {
+            Some(index) => {
+                self.selected_index = index;
+                self.list_state.select(Some(index));
+            }
+            None => self.show_toast(t_args("message.mark_unset", &[("mark", &mark)])),
+        }
+    }
+
+    fn jump_to_first(&mut self) {
+        if self.songs.is_empty() {
+            return;
+        }
+
+        if self.search_mode {
+            if !self.filtered_songs.is_empty() {
+                self.selected_index = self.filtered_songs[0];
+                self.list_state.select(self.list_position(self.selected_index));
+            }
+        } else {
+            self.selected_index = 0;
+            self.list_state.select(Some(0));
+        }
+    }
+
+    fn jump_to_last(&mut self) {
+        if self.songs.is_empty() {
+            return;
+        }
+
+        if self.search_mode {
+            if !self.filtered_songs.is_empty() {
+                let last_index = self.filtered_songs.len() - 1;
+                self.selected_index = self.filtered_songs[last_index];
+                self.list_state.select(self.list_position(self.selected_index));
+            }
+        } else {
+            self.selected_index = self.songs.len() - 1;
+            self.list_state.select(Some(self.selected_index));
+        }
+    }
+}
+
+// The library in the configured folders, for the subcommands
+fn load_mp3_files() -> Result<Vec<Song>, MusixError> {
+    let config = config::Config::load().unwrap_or_default();
+    scan_folders(&config.library.paths())
+}
+
+fn scan_folders(dirs: &[PathBuf]) -> Result<Vec<Song>, MusixError> {
+    let mut songs = Vec::new();
+
+    for data_dir in dirs {
+        if data_dir.exists() {
+            match visit_dir(data_dir, &mut songs) {
+                Ok(_) => {
+                    //eprintln!("Loaded {} MP3 files from: {data_dir:?}", songs.len());  // break;
+                }
+                Err(e) => {
+                    eprintln!("{}", t_args("message.dir_access", &[("dir", &data_dir.display()), ("error", &e)]));
+                    continue;
+                }
+            }
+        }
+    }
+
+    // Tags come later, so this is folder and file name order until then
+    songs.sort_by(|a, b| album_order(a, b, &HashMap::new()));
+    Ok(songs)
+}
+
+// Command-line track selection: existing paths are taken as-is (directories recursively),
+// anything else is looked up by name in the library
+fn resolve_selection(selection: &[String]) -> Result<Vec<Song>, MusixError> {
+    let mut songs = Vec::new();
+    let mut library: Option<Vec<Song>> = None;
+
+    for item in selection {
+        let path = PathBuf::from(item);
+        if path.is_dir() {
+            let mut found = Vec::new();
+            visit_dir(&path, &mut found)?;
+            found.sort_by(|a, b| album_order(a, b, &HashMap::new()));
+            songs.extend(found);
+        } else if path.is_file() {
+            let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("Unknown").to_string();
+            songs.push(Song::new(name, path));
+        } else {
+            let library = match &library {
+                Some(library) => library,
+                None => library.insert(load_mp3_files()?),
+            };
+            songs.extend(match_selection(item, library)?);
+        }
+    }
+
+    let mut seen = HashSet::new();
+    songs.retain(|song| song.stream_url.is_none() && seen.insert(song.path.clone()));
+    Ok(songs)
+}
+
+// How many tracks an ambiguous name lists
+const MAX_CANDIDATES: usize = 10;
+
+// The tracks `query` names: those called exactly that, or else the one track whose name contains it. Not the
+// fuzzy search of the list, which would take in unrelated tracks that merely have the letters in order
+fn match_selection(query: &str, library: &[Song]) -> Result<Vec<Song>, MusixError> {
+    let key = normalize_for_search(query);
+    fn keys(song: &Song) -> [Option<&str>; 2] {
+        [Some(song.search_key.as_str()), song.romanized_key.as_deref()]
+    }
+    let tracks = library.iter().filter(|song| song.stream_url.is_none());
+    let exact: Vec<Song> = tracks.clone().filter(|song| keys(song).contains(&Some(key.as_str()))).cloned().collect();
+    if !exact.is_empty() {
+        return Ok(exact);
+    }
+
+    let found: Vec<&Song> = tracks.filter(|song| keys(song).into_iter().flatten().any(|name| name.contains(&key))).collect();
+    match found.as_slice() {
+        [] => Err(MusixError::Config(t_args("message.selection_none", &[("query", &query)]))),
+        [song] => Ok(vec![(*song).clone()]),
+        several => {
+            let mut candidates: Vec<String> = several.iter().take(MAX_CANDIDATES).map(|song| format!("  {}", song.path.display())).collect();
+            if several.len() > MAX_CANDIDATES {
+                candidates.push("  …".to_string());
+            }
+            Err(MusixError::Config(t_args(
+                "message.selection_several",
+                &[("query", &query), ("candidates", &candidates.join("\n"))],
+            )))
+        }
+    }
+}
+
+type AudioSource = Box<dyn Source<Item = i16> + Send>;
+
+// Waits before trying a file again after a failure that may pass; short, since the UI waits too
+const OPEN_RETRY_DELAYS: [Duration; 2] = [Duration::from_millis(100), Duration::from_millis(300)];
+
+fn create_audio_source(path: &PathBuf) -> Result<AudioSource, MusixError> {
+    // Files on network mounts sometimes fail to open or read once and then work
+    error::retry_transient(&OPEN_RETRY_DELAYS, || {
+        // For now, just use rodio's built-in decoder
+        // AAC support would require more complex implementation
+        let file = std::fs::File::open(path)?;
+        let source = Decoder::new(file)?;
+        Ok(Box::new(source) as AudioSource)
+    })
+}
+
+fn get_audio_duration(path: &PathBuf) -> Option<Duration> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(_) => return None,
+    };
+
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension()
+        && let Some(ext_str) = extension.to_str()
+    {
+        hint.with_extension(ext_str);
+    }
+
+    let meta_opts: MetadataOptions = Default::default();
+    let fmt_opts: FormatOptions = Default::default();
+
+    match symphonia::default::get_probe().format(&hint, mss, &fmt_opts, &meta_opts) {
+        Ok(mut probed) => {
+            let format = &mut probed.format;
+            let track = format
+                .tracks()
+                .iter()
+                .find(|t| t.codec_params.codec != symphonia::core::codecs::CODEC_TYPE_NULL)?;
+
+            // Try multiple methods to get duration
+            if let (Some(time_base), Some(n_frames)) = (track.codec_params.time_base, track.codec_params.n_frames) {
+                let duration_secs = n_frames as f64 * time_base.numer as f64 / time_base.denom as f64;
+                return Some(Duration::from_secs_f64(duration_secs));
+            }
+
+            // Alternative method for formats like AAC/FLAC/OPUS that might not have n_frames
+            if let Some(sample_rate) = track.codec_params.sample_rate {
+                // Store codec type to avoid borrowing issues
+                let codec_type = track.codec_params.codec;
+
+                // Try to read through the entire format to count samples
+                let mut packet_count = 0u64;
+                let mut sample_count = 0u64;
+
+                while let Ok(_packet) = format.next_packet() {
+                    packet_count += 1;
+                    // Estimate samples per packet based on codec
+                    let samples_per_packet = match codec_type {
+                        symphonia::core::codecs::CODEC_TYPE_AAC => 1024,
+                        symphonia::core::codecs::CODEC_TYPE_FLAC => 4096, // Variable, but reasonable estimate
+                        symphonia::core::codecs::CODEC_TYPE_VORBIS => 1024,
+                        _ => 1152, // Default for MP3
+                    };
+                    sample_count += samples_per_packet;
+                    // Limit iteration to prevent infinite loops on corrupted files
+                    if packet_count > 1000000 {
+                        break;
+                    }
+                }
+
+                if sample_count > 0 {
+                    let duration_secs = sample_count as f64 / sample_rate as f64;
+                    return Some(Duration::from_secs_f64(duration_secs));
+                }
+            }
+
+            None
+        }
+        Err(_) => None,
+    }
+}
+
+// Local tracks below `dir`, for the inbox watcher
+fn scan_tracks(dir: &Path) -> Vec<PathBuf> {
+    let mut songs = Vec::new();
+    let _ = visit_dir(&dir.to_path_buf(), &mut songs);
+    songs.into_iter().filter(|song| song.stream_url.is_none()).map(|song| song.path).collect()
+}
+
+fn visit_dir(dir: &PathBuf, songs: &mut Vec<Song>) -> Result<(), MusixError> {
+    visit_dir_ignoring(dir, &mut Vec::new(), songs)
+}
+
+// `ignores` holds the `.musixignore` files of the folders above `dir`
+fn visit_dir_ignoring(dir: &PathBuf, ignores: &mut Vec<ignore::IgnoreFile>, songs: &mut Vec<Song>) -> Result<(), MusixError> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    let depth = ignores.len();
+    ignores.extend(ignore::IgnoreFile::load(dir));
+    let result = visit_entries(dir, ignores, songs);
+    ignores.truncate(depth);
+    result
+}
+
+fn visit_entries(dir: &PathBuf, ignores: &mut Vec<ignore::IgnoreFile>, songs: &mut Vec<Song>) -> Result<(), MusixError> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let is_dir = path.is_dir();
+        if ignore::is_ignored(ignores, &path, is_dir) {
+            continue;
+        }
+
+        if is_dir {
+            visit_dir_ignoring(&path, ignores, songs)?;
+        } else if let Some(extension) = path.extension() {
+            let ext_lower = extension.to_str().unwrap_or("").to_lowercase();
+            if ext_lower == "mp3" || ext_lower == "m4a" || ext_lower == "wav" || ext_lower == "flac" || ext_lower == "opus" {
+                let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("Unknown").to_string();
+
+                songs.push(Song::new(name, path.clone()));
+            } else if ext_lower == "m3u" || ext_lower == "m3u8" || ext_lower == "pls" {
+                for (name, url) in stream::read_station_playlist(&path) {
+                    songs.push(Song::station(name, path.clone(), url));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Like `format_duration`, with hours once there are any: playlists run longer than tracks
+fn format_total(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    } else {
+        Player::format_duration(duration)
+    }
+}
+
+// Folder by folder, then by album and track number where the tags are known, then by name with numbers in order
+fn album_order(a: &Song, b: &Song, tags: &HashMap<PathBuf, tags::TrackTags>) -> Ordering {
+    let folder = |song: &Song| song.path.parent().map(|folder| folder.to_string_lossy().into_owned()).unwrap_or_default();
+    let album = |song: &Song| tags.get(&song.path).and_then(|tags| tags.album.as_deref()).unwrap_or_default().to_string();
+    let track = |song: &Song| tags.get(&song.path).and_then(|tags| tags.track).unwrap_or(u32::MAX);
+    natural::compare(&folder(a), &folder(b))
+        .then_with(|| natural::compare(&album(a), &album(b)))
+        .then_with(|| track(a).cmp(&track(b)))
+        .then_with(|| natural::compare(&a.name, &b.name))
+}
+
+fn none_last<T: Ord>(a: Option<T>, b: Option<T>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+fn format_stars(rating: Option<u8>, icons: icons::IconStyle) -> String {
+    let stars = rating.unwrap_or(0).min(5) as usize;
+    let (full, empty) = icons.stars();
+    format!("{}{}", full.repeat(stars), empty.repeat(5 - stars))
+}
+
+fn render_details(f: &mut Frame, player: &Player, area: ratatui::layout::Rect) {
+    let ui_config = &player.config.ui;
+    let block = panel(ui_config).title(t("panel.details"));
+    let inner = block.inner(area);
+    let width = inner.width as usize;
+    let label_style = Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD);
+    let field = |label: &str, value: String| {
+        Line::from(vec![
+            Span::styled(format!("{label}: "), label_style),
+            Span::raw(truncate_to_width(&value, width.saturating_sub(label.width() + 2))),
+        ])
+    };
+
+    let mut lines = Vec::new();
+    if let Some(song) = player.songs.get(player.selected_index) {
+        // The cover takes up to half the panel, leaving the rest for the fields
+        if player.config.ui.cover_art
+            && player.cover.path.as_ref() == Some(&song.path)
+            && let Some(cover) = &player.cover.image
+        {
+            let art_height = inner.height / 2;
+            let art = cover_art::half_blocks(cover, width as u16, art_height);
+            if !art.is_empty() {
+                lines.extend(art.into_iter().map(Line::centered));
+                lines.push(Line::from(""));
+            }
+        }
+        lines.push(Line::from(Span::styled(
+            truncate_to_width(&song.name, width),
+            Style::default().fg(HIGHLIGHT_COLOR).add_modifier(Modifier::BOLD),
+        )));
+        lines.push(Line::from(""));
+
+        if let Some(url) = &song.stream_url {
+            lines.push(field(t("details.stream"), url.clone()));
+        } else {
+            let folder = song.path.parent().map(|dir| dir.display().to_string()).unwrap_or_default();
+            let format = song.path.extension().map(|ext| ext.to_string_lossy().to_uppercase()).unwrap_or_default();
+            lines.push(field(t("details.folder"), format!("{} {folder}", player.config.ui.icons.album())));
+            lines.push(field(t("details.format"), format));
+
+            let bpm = match player.library.bpm(&song.path) {
+                Some(bpm) => format!("{bpm:.0}"),
+                None if player.bpm_analyzer.is_pending(&song.path) => t("details.analyzing").to_string(),
+                None => t("details.bpm_unknown").to_string(),
+            };
+            lines.push(field(t("details.bpm"), bpm));
+
+            let track = player.library.get(&song.path);
+            lines.push(field(
+                t("details.rating"),
+                format_stars(track.and_then(|track| track.rating), player.config.ui.icons),
+            ));
+            lines.push(field(t("details.plays"), track.map_or(0, |track| track.play_count).to_string()));
+            if let Some(skips) = track.map(|track| track.early_skips).filter(|skips| *skips > 0) {
+                let skips = if player.is_demoted(&song.path) {
+                    t_args("details.early_skips_demoted", &[("count", &skips)])
+                } else {
+                    skips.to_string()
+                };
+                lines.push(field(t("details.early_skips"), skips));
+            }
+            if let Some(played) = track.and_then(|track| track.last_played)
+                && let Some(played) = chrono::DateTime::from_timestamp(played, 0)
+            {
+                let played = played.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string();
+                lines.push(field(t("details.last_played"), played));
+            }
+            if let Some(track) = track.filter(|track| track.end_action != library::EndAction::Next) {
+                lines.push(field(t("details.end_action"), end_action_label(track.end_action).to_string()));
+            }
+            if let Some(seconds) = track.and_then(|track| track.skip_intro) {
+                let skip = Player::format_duration(Duration::from_secs_f64(seconds));
+                lines.push(field(t("details.skip_intro"), skip));
+            }
+            if let Some(seconds) = track.and_then(|track| track.skip_outro) {
+                let skip = Player::format_duration(Duration::from_secs_f64(seconds));
+                lines.push(field(t("details.skip_outro"), skip));
+            }
+            if let Some(seconds) = track.and_then(|track| track.fade_in) {
+                lines.push(field(t("details.fade_in"), format!("{seconds:.1}s")));
+            }
+            if let Some(seconds) = track.and_then(|track| track.fade_out) {
+                lines.push(field(t("details.fade_out"), format!("{seconds:.1}s")));
+            }
+        }
+
+        if let Some(error) = &song.error {
+            lines.push(field(t("details.error"), error.clone()));
+        }
+    }
+
+    let details = Paragraph::new(lines).block(block);
+    f.render_widget(details, area);
+}
+
+fn end_action_label(action: library::EndAction) -> &'static str {
+    match action {
+        library::EndAction::Next => t("details.end_next"),
+        library::EndAction::Stop => t("details.end_stop"),
+        library::EndAction::Repeat => t("details.end_repeat"),
+    }
+}
+
+// Gains that match no preset were set by hand
+fn preset_label(preset: Option<equalizer::Preset>) -> &'static str {
+    match preset {
+        Some(equalizer::Preset::Flat) => t("panel.preset_flat"),
+        Some(equalizer::Preset::Rock) => t("panel.preset_rock"),
+        Some(equalizer::Preset::Bass) => t("panel.preset_bass"),
+        Some(equalizer::Preset::Treble) => t("panel.preset_treble"),
+        Some(equalizer::Preset::Vocal) => t("panel.preset_vocal"),
+        None => t("panel.preset_custom"),
+    }
+}
+
+fn format_listening_time(seconds: f64) -> String {
+    let minutes = (seconds / 60.0).round() as u64;
+    format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
+
+// One row per band with a slider from -12 to +12 dB, the band being moved highlighted
+fn render_equalizer(f: &mut Frame, player: &Player, ui_config: &config::UiConfig) {
+    let popup_area = centered_rect(60, 60, f.area());
+    f.render_widget(ratatui::widgets::Clear, popup_area);
+
+    let gains = player.equalizer.gains();
+    let state = if player.equalizer.is_enabled() {
+        t("panel.equalizer_on")
+    } else {
+        t("panel.equalizer_off")
+    };
+    let preset = preset_label(equalizer::Preset::matching(&gains));
+    let block = popup_panel(ui_config)
+        .title(t_args("panel.equalizer", &[("state", &state), ("preset", &preset)]))
+        .title_bottom(t("panel.equalizer_hint"));
+    let inner = block.inner(popup_area);
+    f.render_widget(block, popup_area);
+
+    // A cell for each dB either side of the centre
+    let half = equalizer::MAX_GAIN as usize;
+    let mut lines = vec![Line::from("")];
+    for (band, (&frequency, &gain)) in equalizer::BANDS.iter().zip(&gains).enumerate() {
+        let label = if frequency >= 1000.0 {
+            format!("{:>3}k", frequency / 1000.0)
+        } else {
+            format!("{frequency:>4}")
+        };
+        let position = (half as f32 + gain).round() as usize;
+        let slider: String = (0..=2 * half)
+            .map(|cell| match cell {
+                _ if cell == position => '●',
+                _ if cell == half => '┼',
+                _ => '─',
+            })
+            .collect();
+        let style = if band == player.equalizer_band {
+            Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD)
+        } else if player.equalizer.is_enabled() {
+            Style::default()
+        } else {
+            Style::default().fg(Color::DarkGray)
+        };
+        lines.push(Line::from(Span::styled(format!(" {label} Hz  {slider}  {gain:+.0} dB"), style)));
+    }
+    f.render_widget(Paragraph::new(lines), inner);
+}
+
+fn render_stats(f: &mut Frame, stats: &stats::Stats, ui_config: &config::UiConfig) {
+    let popup_area = centered_rect(90, 90, f.area());
+    f.render_widget(ratatui::widgets::Clear, popup_area);
+
+    let block = popup_panel(ui_config).title(t("panel.stats")).title_bottom(t("panel.stats_hint"));
+    let inner = block.inner(popup_area);
+    f.render_widget(block, popup_area);
+
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(2), Constraint::Percentage(50), Constraint::Min(4)])
+        .split(inner);
+
+    let completion = stats.completion_rate().map_or_else(|| "-".to_string(), |rate| format!("{:.0}%", rate * 100.0));
+    let summary = t_args(
+        "stats.summary",
+        &[
+            ("time", &format_listening_time(stats.total_listened)),
+            ("plays", &stats.plays),
+            ("completion", &completion),
+        ],
+    );
+    f.render_widget(Paragraph::new(truncate_to_width(&format!(" {summary}"), inner.width as usize)), rows[0]);
+
+    let charts = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(rows[1]);
+    for (area, title, data) in [(charts[0], t("stats.daily"), &stats.daily), (charts[1], t("stats.weekly"), &stats.weekly)] {
+        // Bars need room for their 5-character date labels; on narrow screens only the most recent ones are shown
+        let inner_width = area.width.saturating_sub(2);
+        let shown = data.len().min(usize::from((inner_width + 1) / 6));
+        let data: Vec<(&str, u64)> = data[data.len() - shown..].iter().map(|(label, minutes)| (label.as_str(), *minutes)).collect();
+        let bar_width = ((inner_width + 1) / shown.max(1) as u16).saturating_sub(1).max(5);
+        let chart = BarChart::default()
+            .block(popup_panel(ui_config).title(title))
+            .data(&data)
+            .bar_width(bar_width)
+            .bar_gap(1)
+            .bar_style(Style::default().fg(HIGHLIGHT_COLOR))
+            .value_style(Style::default().fg(Color::Black).bg(HIGHLIGHT_COLOR))
+            .label_style(Style::default().fg(Color::DarkGray));
+        f.render_widget(chart, area);
+    }
+
+    let tops = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Ratio(1, 3), Constraint::Ratio(1, 3), Constraint::Ratio(1, 3)])
+        .split(rows[2]);
+    for (area, title, ranked) in [
+        (tops[0], t("stats.top_artists"), &stats.top_artists),
+        (tops[1], t("stats.top_albums"), &stats.top_albums),
+        (tops[2], t("stats.top_tracks"), &stats.top_tracks),
+    ] {
+        let width = area.width.saturating_sub(2) as usize;
+        let lines: Vec<Line> = if ranked.is_empty() {
+            vec![Line::from(Span::styled(t("stats.empty"), Style::default().fg(Color::DarkGray)))]
+        } else {
+            ranked
+                .iter()
+                .enumerate()
+                .flat_map(|(rank, entry)| {
+                    let details = t_args(
+                        "stats.entry",
+                        &[
+                            ("time", &format_listening_time(entry.listened)),
+                            ("plays", &entry.plays),
+                            ("completion", &format!("{:.0}%", entry.completion_rate() * 100.0)),
+                        ],
+                    );
+                    [
+                        Line::from(Span::styled(
+                            truncate_to_width(&format!("{}. {}", rank + 1, entry.name), width),
+                            Style::default().fg(PRIMARY_COLOR),
+                        )),
+                        Line::from(Span::styled(
+                            truncate_to_width(&format!("   {details}"), width),
+                            Style::default().fg(Color::DarkGray),
+                        )),
+                    ]
+                })
+                .collect()
+        };
+        let list = Paragraph::new(lines).block(popup_panel(ui_config).title(title));
+        f.render_widget(list, area);
+    }
+}
+
+// List positions shown in a viewport of `height` rows, scrolled the way ratatui's `List` scrolls from the top:
+// just far enough to show the selection plus the scroll padding
+fn visible_window(len: usize, selected: Option<usize>, height: usize) -> Range<usize> {
+    let padding = if height > 2 * LIST_SCROLL_PADDING { LIST_SCROLL_PADDING } else { 0 };
+    let start = selected.map_or(0, |selected| ((selected + padding).min(len.saturating_sub(1)) + 1).saturating_sub(height));
+    start..(start + height).min(len)
+}
+
+// A main-screen panel in the configured border style, which may have no frame at all
+fn panel(ui_config: &config::UiConfig) -> Block<'static> {
+    let borders = if ui_config.border == config::BorderStyle::None {
+        Borders::NONE
+    } else {
+        Borders::ALL
+    };
+    Block::default()
+        .borders(borders)
+        .border_type(ui_config.border.border_type())
+        .title_alignment(ui_config.title_alignment.alignment())
+        .border_style(Style::default().fg(PRIMARY_COLOR))
+}
+
+// Popups keep a frame even without borders elsewhere, or they would blend into the list behind them
+fn popup_panel(ui_config: &config::UiConfig) -> Block<'static> {
+    panel(ui_config).borders(Borders::ALL)
+}
+
+fn ui(f: &mut Frame, player: &Player) {
+    if let Some(since) = player.idle_since {
+        draw_idle(f, player, since.elapsed());
+        return;
+    }
+    let ui_config = &player.config.ui;
+    let framed = ui_config.border != config::BorderStyle::None;
+    // Without borders, a stream's title gets a row of its own above the bar
+    let progress_title_row = !framed && player.stream_title.is_some();
+    let frame_rows = if framed { 2 } else { 0 };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(if ui_config.show_title { 1 + frame_rows } else { 0 }),  // Title
+            Constraint::Min(8),                                                         // Song list
+            Constraint::Length(1 + frame_rows + u16::from(progress_title_row)),         // Progress bar
+            Constraint::Length(if ui_config.show_status { 1 + frame_rows } else { 0 }), // Status
+        ])
+        .split(f.area());
+
+    // Title
+    if ui_config.show_title {
+        let title = Paragraph::new("MUSIX")
+            .style(Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD))
+            .alignment(Alignment::Center)
+            .block(panel(ui_config));
+        f.render_widget(title, chunks[0]);
+    }
+
+    // With no audio output, a banner over the list says why and how to retry
+    let mut main_area = chunks[1];
+    if let Some(error) = &player.audio_error {
+        let banner = t_args("message.audio_unavailable", &[("error", error)]);
+        let banner_area = ratatui::layout::Rect { height: 1, ..main_area };
+        f.render_widget(
+            Paragraph::new(truncate_to_width(&format!(" {banner}"), banner_area.width as usize))
+                .style(Style::default().fg(Color::Black).bg(Color::Yellow).add_modifier(Modifier::BOLD)),
+            banner_area,
+        );
+        main_area.y += 1;
+        main_area.height = main_area.height.saturating_sub(1);
+    }
+
+    // Song list, with the details panel beside it when open
+    let (list_area, details_area) = if player.show_details {
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(62), Constraint::Percentage(38)])
+            .split(main_area);
+        (columns[0], Some(columns[1]))
+    } else {
+        (main_area, None)
+    };
+
+    let row_width = panel(ui_config).inner(list_area).width as usize;
+    let songs_title = if player.search_mode {
+        t_args("panel.songs_search", &[("query", &player.search_query)])
+    } else if let Some(column) = player.sort_column {
+        t_args("panel.songs_by", &[("column", &column.header())])
+    } else if player.sort_by_bpm {
+        t("panel.songs_by_bpm").to_string()
+    } else {
+        t("panel.songs").to_string()
+    };
+    // How much is in view, counting only the lengths known so far
+    let (count, total, unknown) = player.view_summary.unwrap_or_else(|| player.summarize_view());
+    let summary = if total.is_zero() {
+        t_args("panel.summary_count", &[("count", &count)])
+    } else {
+        let more = if unknown { "+" } else { "" };
+        t_args("panel.summary", &[("count", &count), ("duration", &format!("{}{more}", format_total(total)))])
+    };
+    let songs_block = panel(ui_config).title(truncate_to_width(&format!("{songs_title}{summary}"), row_width));
+    let mut rows_area = songs_block.inner(list_area);
+    f.render_widget(songs_block, list_area);
+
+    // With columns, a header row over them; rows start with the playing and file type icons
+    let icons = ui_config.icons;
+    let prefix_width = if icons == icons::IconStyle::Nerd { 4 } else { 2 };
+    let column_widths = columns::widths(&ui_config.columns, rows_area.width.saturating_sub(prefix_width));
+    if !ui_config.columns.is_empty() && rows_area.height > 1 {
+        let header_style = Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD);
+        let mut header = vec![Span::raw(" ".repeat(prefix_width as usize))];
+        for (config, &width) in ui_config.columns.iter().zip(&column_widths) {
+            let style = if player.sort_column == Some(config.column) {
+                header_style.fg(HIGHLIGHT_COLOR).add_modifier(Modifier::UNDERLINED)
+            } else {
+                header_style
+            };
+            header.push(Span::styled(
+                fit_to_width(config.column.header(), width as usize, config.column.right_aligned()),
+                style,
+            ));
+            header.push(Span::raw(" "));
+        }
+        f.render_widget(Paragraph::new(Line::from(header)), ratatui::layout::Rect { height: 1, ..rows_area });
+        rows_area.y += 1;
+        rows_area.height -= 1;
+    }
+
+    // Only the rows that fit are built, so huge libraries cost the same per frame as small ones
+    let selected_position = player.list_state.selected();
+    let window = visible_window(player.display_len(), selected_position, rows_area.height as usize);
+    let mut window_state = ListState::default().with_selected(selected_position.and_then(|position| position.checked_sub(window.start)));
+    let items: Vec<ListItem> = player
+        .display_songs(window)
+        .iter()
+        .map(|&(actual_index, song)| {
+            let playing_indicator = if song.error.is_some() {
+                icons.error()
+            } else if actual_index == player.current_index && player.is_playing {
+                icons.playing()
+            } else if player.new_tracks.contains(&song.path) {
+                icons.new_track()
+            } else {
+                " "
+            };
+            let file_type = icons
+                .file_type(&song.path, song.stream_url.is_some())
+                .map(|icon| format!("{icon} "))
+                .unwrap_or_default();
+
+            let content = if ui_config.columns.is_empty() {
+                let line = format!("{playing_indicator} {file_type}{}. {}", actual_index + 1, song.name);
+                // The length sits at the right end, and the name gives way to it
+                match player.duration_of(song).filter(|_| ui_config.durations && song.stream_url.is_none()) {
+                    Some(duration) => {
+                        let duration = Player::format_duration(duration);
+                        let name_width = row_width.saturating_sub(duration.width() + 1);
+                        format!("{} {duration}", fit_to_width(&line, name_width, false))
+                    }
+                    None => truncate_to_width(&line, row_width),
+                }
+            } else {
+                let cells: Vec<String> = ui_config
+                    .columns
+                    .iter()
+                    .zip(&column_widths)
+                    .map(|(config, &width)| {
+                        let text = player.column_text(config.column, actual_index, song);
+                        fit_to_width(&text, width as usize, config.column.right_aligned())
+                    })
+                    .collect();
+                truncate_to_width(&format!("{playing_indicator} {file_type}{}", cells.join(" ")), row_width)
+            };
+
+            let text_color = get_text_color();
+            let style = if song.error.is_some() {
+                Style::default().fg(Color::DarkGray).add_modifier(Modifier::DIM)
+            } else if actual_index == player.current_index && player.is_playing {
+                Style::default().fg(HIGHLIGHT_COLOR).add_modifier(Modifier::BOLD)
+            } else if actual_index == player.selected_index {
+                Style::default().fg(PRIMARY_COLOR)
+            } else {
+                Style::default().fg(player.row_color(song).unwrap_or(text_color))
+            };
+
+            ListItem::new(content).style(style)
+        })
+        .collect();
+
+    let songs_list = List::new(items)
+        .highlight_style(Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD))
+        .scroll_padding(LIST_SCROLL_PADDING);
+
+    if player.songs.is_empty() {
+        let text = Paragraph::new(t("message.no_files")).wrap(Wrap { trim: false });
+        f.render_widget(text.style(Style::default().fg(get_text_color())), rows_area);
+    } else {
+        f.render_stateful_widget(songs_list, rows_area, &mut window_state);
+    }
+
+    if let Some(area) = details_area {
+        render_details(f, player, area);
+    }
+
+    // Progress bar
+    let (elapsed, total) = player.get_playback_progress();
+    let progress_ratio = if let Some(duration) = total {
+        if duration.as_secs() > 0 {
+            (elapsed.as_secs() as f64 / duration.as_secs() as f64).min(1.0)
+        } else {
+            0.0
+        }
+    } else {
+        0.0
+    };
+
+    let state_icon = if player.is_playing {
+        icons.playing()
+    } else if player.is_paused {
+        icons.paused()
+    } else {
+        icons.stopped()
+    };
+    let progress_label_text = if let Some((target, _)) = player.scrub {
+        let delta = if target >= elapsed {
+            format!("+{}", Player::format_duration(target - elapsed))
+        } else {
+            format!("-{}", Player::format_duration(elapsed - target))
+        };
+        let target = Player::format_duration(target);
+        format!(" {} ", t_args("panel.scrub", &[("target", &target), ("delta", &delta)]))
+    } else if let Some(duration) = total {
+        format!(" {state_icon} {}/{} ", Player::format_duration(elapsed), Player::format_duration(duration))
+    } else if let Some((buffered, kbps)) = player.stream_buffer() {
+        let seconds = buffered.as_secs();
+        let buffer = t_args("panel.stream_buffer", &[("seconds", &seconds), ("kbps", &kbps)]);
+        let gauge = buffer_gauge(icons, buffered);
+        format!(" {state_icon} {} {}  {gauge} {buffer} ", Player::format_duration(elapsed), t("panel.live"))
+    } else if player.is_stream_playing() {
+        format!(" {state_icon} {} {} ", Player::format_duration(elapsed), t("panel.live"))
+    } else {
+        format!(" {state_icon} {} ", Player::format_duration(elapsed))
+    };
+
+    let progress_title = match &player.stream_title {
+        Some(_) => Some(truncate_to_width(
+            &t_args("panel.progress_now_playing", &[("title", &player.now_playing_name())]),
+            panel(ui_config).inner(chunks[2]).width as usize,
+        )),
+        None if framed => Some(t("panel.progress").to_string()),
+        None => None,
+    };
+
+    let progress_bar_style = Style::default().fg(PRIMARY_COLOR).bg(Color::default());
+    let progress_label = Span::styled(progress_label_text, progress_bar_style);
+
+    let progress_block = match progress_title {
+        Some(title) => panel(ui_config).title(title),
+        None => panel(ui_config),
+    };
+    let progress_inner = progress_block.inner(chunks[2]);
+    let progress_bar = Gauge::default()
+        .block(progress_block)
+        .gauge_style(progress_bar_style)
+        .ratio(progress_ratio)
+        .label(progress_label);
+    f.render_widget(progress_bar, chunks[2]);
+
+    // The scrub target, marked across the bar
+    if let (Some((target, _)), Some(duration)) = (player.scrub, total)
+        && duration.as_secs_f64() > 0.0
+        && progress_inner.width > 0
+    {
+        let ratio = (target.as_secs_f64() / duration.as_secs_f64()).clamp(0.0, 1.0);
+        let x = progress_inner.x + ((ratio * f64::from(progress_inner.width - 1)) as u16);
+        let marker_style = Style::default().fg(HIGHLIGHT_COLOR).add_modifier(Modifier::BOLD);
+        for y in progress_inner.top()..progress_inner.bottom() {
+            f.buffer_mut().set_string(x, y, "┃", marker_style);
+        }
+    }
+
+    // Chapters are ticks on the bar's bottom border, or on the bar itself without borders; the first one, at the
+    // very start, tells nothing
+    if let Some(duration) = total
+        && duration.as_secs_f64() > 0.0
+        && progress_inner.width > 0
+    {
+        let (y, tick) = if framed { (chunks[2].bottom() - 1, "┴") } else { (progress_inner.y, "│") };
+        let tick_style = Style::default().fg(PRIMARY_COLOR);
+        for chapter in player.chapters.iter().filter(|chapter| !chapter.start.is_zero()) {
+            let ratio = (chapter.start.as_secs_f64() / duration.as_secs_f64()).clamp(0.0, 1.0);
+            let x = progress_inner.x + ((ratio * f64::from(progress_inner.width - 1)) as u16);
+            f.buffer_mut().set_string(x, y, tick, tick_style);
+        }
+    }
+
+    // Hot cues are marked by number on the bar's bottom border, or on the bar itself without borders
+    if let (Some(duration), Some(song)) = (total, player.songs.get(player.current_index))
+        && let Some(track) = player.library.get(&song.path)
+        && duration.as_secs_f64() > 0.0
+        && progress_inner.width > 0
+    {
+        let y = if framed { chunks[2].bottom() - 1 } else { progress_inner.y };
+        let cue_style = Style::default().fg(HIGHLIGHT_COLOR).add_modifier(Modifier::BOLD);
+        for (number, seconds) in &track.cues {
+            let ratio = (seconds / duration.as_secs_f64()).clamp(0.0, 1.0);
+            let x = progress_inner.x + ((ratio * f64::from(progress_inner.width - 1)) as u16);
+            f.buffer_mut().set_string(x, y, number.to_string(), cue_style);
+        }
+    }
+
+    // Status
+    let repeat_text = match player.repeat_mode {
+        repeat::RepeatMode::Off => t("status.repeat_off"),
+        repeat::RepeatMode::All => t("status.repeat_all"),
+        repeat::RepeatMode::One => t("status.repeat_one"),
+    };
+    let mode_text = match player.shuffle_mode {
+        shuffle::ShuffleMode::Off => t("status.normal"),
+        shuffle::ShuffleMode::Random => t("status.random"),
+        shuffle::ShuffleMode::Weighted => t("status.weighted"),
+        shuffle::ShuffleMode::Rediscover => t("status.rediscover"),
+    };
+    let song_count = if player.search_mode {
+        format!("{}/{}", player.filtered_songs.len(), player.songs.len())
+    } else {
+        player.songs.len().to_string()
+    };
+
+    let status_content = if player.search_mode {
+        vec![Line::from(vec![
+            Span::raw(format!("  {}", t_args("status.search_mode", &[("count", &song_count)]))),
+            Span::styled("Esc", Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD)),
+            Span::raw(t("status.exit_search")),
+            Span::styled("Enter", Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD)),
+            Span::raw(t("status.play")),
+        ])]
+    } else if let Some((message, _)) = player.toast.as_ref().filter(|(_, shown)| shown.elapsed() < TOAST_DURATION) {
+        vec![Line::from(Span::raw(format!(
+            "  {}",
+            truncate_to_width(message, panel(ui_config).inner(chunks[3]).width.saturating_sub(2) as usize)
+        )))]
+    } else if player.performance_mode {
+        let hint = if player.chords.prefix() == Some('d') {
+            t("status.performance_delete")
+        } else {
+            t("status.performance_hint")
+        };
+        vec![Line::from(vec![
+            Span::styled(
+                format!("  {} ", t("status.performance")),
+                Style::default().fg(HIGHLIGHT_COLOR).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(format!("| {hint}")),
+        ])]
+    } else {
+        let mut spans = Vec::new();
+        if player.locked {
+            spans.push(Span::styled(
+                format!("  {} {}", icons.locked(), t("status.locked")),
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            ));
+        }
+        if let Some(recording) = &player.recording {
+            let elapsed = Player::format_duration(recording.started.elapsed());
+            spans.push(Span::styled(
+                format!("  {} {}", icons.recording(), t_args("status.recording", &[("elapsed", &elapsed)])),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ));
+        }
+        spans.extend([
+            Span::raw(format!(
+                "  {}",
+                t_args("status.mode", &[("mode", &mode_text), ("repeat", &repeat_text), ("count", &song_count)])
+            )),
+            Span::styled("/", Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD)),
+            Span::raw(t("status.search")),
+            Span::styled("x", Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD)),
+            Span::raw(t("status.help")),
+            Span::raw(t_args(
+                if player.muted { "status.muted" } else { "status.volume" },
+                &[("volume", &(player.volume * 100.0).round())],
+            )),
+        ]);
+        if player.is_playing
+            && let Some(next) = player.planned_next()
+        {
+            spans.push(Span::raw(t_args("status.next", &[("title", &player.songs[next].name)])));
+        }
+        if let Some((start, end)) = player.ab_loop {
+            let (start, end) = (Player::format_duration(start), end.map(Player::format_duration).unwrap_or_default());
+            spans.push(Span::styled(
+                t_args("status.ab_loop", &[("start", &start), ("end", &end)]),
+                Style::default().fg(HIGHLIGHT_COLOR),
+            ));
+        }
+        if let Some(preview) = &player.preview {
+            let position = Player::format_duration(preview.position());
+            spans.push(Span::styled(
+                t_args("status.preview", &[("title", &player.songs[preview.index].name), ("position", &position)]),
+                Style::default().fg(Color::Cyan),
+            ));
+        }
+        let analyzing = player.bpm_analyzer.pending_count();
+        if analyzing > 0 {
+            spans.push(Span::raw(t_args("status.analyzing", &[("icon", &icons.analyzing()), ("count", &analyzing)])));
+        }
+        let scrobbles = player.scrobbler.pending_count();
+        if scrobbles > 0 {
+            spans.push(Span::raw(t_args("status.scrobbles", &[("icon", &icons.upload()), ("count", &scrobbles)])));
+        }
+        let problem_count = player.problem_songs().len();
+        if problem_count > 0 {
+            spans.push(Span::raw("| "));
+            spans.push(Span::styled("!", Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD)));
+            spans.push(Span::raw(t_args("status.problems", &[("count", &problem_count)])));
+        }
+        if let Some(register) = player.macros.recording() {
+            spans.push(Span::styled(
+                t_args("status.macro_recording", &[("register", &register)]),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ));
+        }
+        // Keys of an unfinished count or chord, like vim's showcmd
+        if let Some(pending) = player.chords.pending() {
+            spans.push(Span::styled(
+                format!("| {pending}"),
+                Style::default().fg(HIGHLIGHT_COLOR).add_modifier(Modifier::BOLD),
+            ));
+        }
+        vec![Line::from(spans)]
+    };
+
+    if ui_config.show_status {
+        let status_block = if framed {
+            panel(ui_config).title(t("panel.status"))
+        } else {
+            panel(ui_config)
+        };
+        let mut status = Paragraph::new(status_content).alignment(Alignment::Left).block(status_block);
+        if player.flash.is_some_and(|flashed| flashed.elapsed() < FLASH_DURATION) {
+            status = status.style(Style::default().add_modifier(Modifier::REVERSED));
+        }
+        f.render_widget(status, chunks[3]);
+    }
+
+    // Controls popup
+    if player.show_controls_popup {
+        let popup_area = centered_rect(60, 60, f.area());
+        f.render_widget(ratatui::widgets::Clear, popup_area);
+
+        let help_entries: Vec<(String, &str)> = keymap::BINDINGS
+            .iter()
+            .map(|binding| (format!(" {:<10}", keymap::label(&binding.keys)), t(binding.help)))
+            .collect();
+        let mut help_lines = vec![
+            Line::from(""),
+            Line::from(vec![Span::styled(
+                t("help.title"),
+                Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD),
+            )])
+            .alignment(Alignment::Center),
+            Line::from(""),
+        ];
+        help_lines.extend(help_entries.iter().map(|(keys, description)| {
+            Line::from(vec![
+                Span::styled(keys.as_str(), Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD)),
+                Span::raw(format!(" - {description}")),
+            ])
+        }));
+
+        let controls_popup = Paragraph::new(help_lines)
+            .alignment(Alignment::Left)
+            .block(popup_panel(ui_config).title(t("panel.help")));
+        f.render_widget(controls_popup, popup_area);
+    }
+
+    // Problem files popup
+    if player.show_problems_popup {
+        let popup_area = centered_rect(70, 60, f.area());
+        f.render_widget(ratatui::widgets::Clear, popup_area);
+
+        let problems = player.problem_songs();
+        let row_width = popup_area.width.saturating_sub(2) as usize;
+        let items: Vec<ListItem> = problems
+            .iter()
+            .map(|&index| {
+                let song = &player.songs[index];
+                let reason = song.error.as_deref().unwrap_or_default();
+                ListItem::new(vec![
+                    Line::from(Span::styled(
+                        truncate_to_width(&format!(" {} {}", icons.error(), song.name), row_width),
+                        Style::default().fg(PRIMARY_COLOR),
+                    )),
+                    Line::from(Span::styled(
+                        truncate_to_width(&format!("   {reason}"), row_width),
+                        Style::default().fg(Color::DarkGray),
+                    )),
+                ])
+            })
+            .collect();
+
+        let problems_list = List::new(items)
+            .block(
+                popup_panel(ui_config)
+                    .title(t_args("panel.problems", &[("count", &problems.len())]))
+                    .title_bottom(t("panel.problems_hint")),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+        f.render_stateful_widget(problems_list, popup_area, &mut player.problems_state.clone());
+    }
+
+    if player.show_demoted_popup {
+        let popup_area = centered_rect(70, 60, f.area());
+        f.render_widget(ratatui::widgets::Clear, popup_area);
+
+        let demoted = player.demoted_songs();
+        let row_width = popup_area.width.saturating_sub(2) as usize;
+        let items: Vec<ListItem> = demoted
+            .iter()
+            .map(|&index| {
+                let song = &player.songs[index];
+                let skips = t_args("panel.demoted_skips", &[("count", &player.library.early_skips(&song.path))]);
+                ListItem::new(vec![
+                    Line::from(Span::styled(
+                        truncate_to_width(&format!(" {}", song.name), row_width),
+                        Style::default().fg(PRIMARY_COLOR),
+                    )),
+                    Line::from(Span::styled(
+                        truncate_to_width(&format!("   {skips}"), row_width),
+                        Style::default().fg(Color::DarkGray),
+                    )),
+                ])
+            })
+            .collect();
+
+        let demoted_list = List::new(items)
+            .block(
+                popup_panel(ui_config)
+                    .title(t_args("panel.demoted", &[("count", &demoted.len())]))
+                    .title_bottom(t("panel.demoted_hint")),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+        f.render_stateful_widget(demoted_list, popup_area, &mut player.demoted_state.clone());
+    }
+
+    if player.show_equalizer {
+        render_equalizer(f, player, ui_config);
+    }
+
+    if player.show_revisit {
+        let popup_area = centered_rect(70, 60, f.area());
+        f.render_widget(ratatui::widgets::Clear, popup_area);
+
+        let row_width = popup_area.width.saturating_sub(2) as usize;
+        let items: Vec<ListItem> = player
+            .revisit
+            .entries()
+            .iter()
+            .map(|entry| {
+                let path = Path::new(entry);
+                let (name, color) = match player.index_of(path, &None) {
+                    Some(index) => (player.songs[index].name.clone(), PRIMARY_COLOR),
+                    // Gone from the library list; the file name is all there is to show
+                    None => (path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().into_owned(), Color::DarkGray),
+                };
+                ListItem::new(Line::from(Span::styled(
+                    truncate_to_width(&format!(" {name}"), row_width),
+                    Style::default().fg(color),
+                )))
+            })
+            .collect();
+
+        let revisit_list = List::new(items)
+            .block(
+                popup_panel(ui_config)
+                    .title(t_args("panel.revisit", &[("count", &player.revisit.entries().len())]))
+                    .title_bottom(t("panel.revisit_hint")),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+        f.render_stateful_widget(revisit_list, popup_area, &mut player.revisit_state.clone());
+    }
+
+    if player.show_stats_popup {
+        render_stats(f, &player.stats, ui_config);
+    }
+
+    if player.show_quit_prompt {
+        let popup_area = centered_rect(40, 20, f.area());
+        f.render_widget(ratatui::widgets::Clear, popup_area);
+        let name = player.songs.get(player.current_index).map_or("", |song| song.name.as_str());
+        let prompt = Paragraph::new(vec![
+            Line::from(""),
+            Line::from(truncate_to_width(name, popup_area.width.saturating_sub(2) as usize)),
+        ])
+        .alignment(Alignment::Center)
+        .block(popup_panel(ui_config).title(t("panel.quit")).title_bottom(t("panel.quit_hint")));
+        f.render_widget(prompt, popup_area);
+    }
+
+    if let Some(input) = &player.pin_input {
+        let popup_area = centered_rect(40, 20, f.area());
+        f.render_widget(ratatui::widgets::Clear, popup_area);
+        let prompt = Paragraph::new(vec![Line::from(""), Line::from(format!("{}▏", "•".repeat(input.chars().count())))])
+            .alignment(Alignment::Center)
+            .block(popup_panel(ui_config).title(t("panel.unlock")).title_bottom(t("panel.unlock_hint")));
+        f.render_widget(prompt, popup_area);
+    }
+
+    // Radio station browser
+    if player.show_radio_browser {
+        let browser = &player.radio_browser;
+        let popup_area = centered_rect(70, 70, f.area());
+        f.render_widget(ratatui::widgets::Clear, popup_area);
+
+        let browser_chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(3)])
+            .split(popup_area);
+        let row_width = popup_area.width.saturating_sub(2) as usize;
+
+        let query_input = Paragraph::new(truncate_to_width(&format!("{}▏", browser.query), row_width)).block(popup_panel(ui_config).title(t("panel.radio")));
+        f.render_widget(query_input, browser_chunks[0]);
+
+        let results_title = if browser.is_searching() {
+            t("radio.searching").to_string()
+        } else if let Some(error) = &browser.error {
+            t_args("radio.error", &[("error", error)])
+        } else if browser.searched_query.is_some() && browser.stations.is_empty() {
+            t("radio.no_results").to_string()
+        } else {
+            t_args("radio.results", &[("count", &browser.stations.len())])
+        };
+
+        let items: Vec<ListItem> = browser
+            .stations
+            .iter()
+            .map(|station| {
+                let details = [station.countrycode.as_str(), station.codec.as_str()]
+                    .into_iter()
+                    .filter(|detail| !detail.is_empty())
+                    .map(str::to_string)
+                    .chain((station.bitrate > 0).then(|| format!("{}kbps", station.bitrate)))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                ListItem::new(Line::from(vec![
+                    Span::styled(truncate_to_width(&format!(" {}", station.name), row_width), Style::default().fg(PRIMARY_COLOR)),
+                    Span::styled(format!("  {details}"), Style::default().fg(Color::DarkGray)),
+                ]))
+            })
+            .collect();
+
+        let stations_list = List::new(items)
+            .block(
+                popup_panel(ui_config)
+                    .title(truncate_to_width(&results_title, row_width))
+                    .title_bottom(t("radio.hint")),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+        f.render_stateful_widget(stations_list, browser_chunks[1], &mut browser.list_state.clone());
+    }
+
+    if player.show_sources {
+        render_sources(f, player, ui_config);
+    }
+
+    if let Some((picker, _)) = &player.dir_picker {
+        render_dir_picker(f, picker, ui_config);
+    }
+
+    // The command line, over the bottom of the screen whether or not the status bar is shown
+    if let Some(input) = &player.command_input {
+        let area = f.area();
+        let line_area = ratatui::layout::Rect {
+            y: area.bottom().saturating_sub(3),
+            height: 3.min(area.height),
+            ..area
+        };
+        f.render_widget(ratatui::widgets::Clear, line_area);
+        let text = truncate_to_width(&format!(":{input}▏"), line_area.width.saturating_sub(2) as usize);
+        f.render_widget(Paragraph::new(text).block(popup_panel(ui_config).title(t("panel.command"))), line_area);
+    }
+}
+
+fn render_sources(f: &mut Frame, player: &Player, ui_config: &config::UiConfig) {
+    let popup_area = centered_rect(60, 50, f.area());
+    f.render_widget(ratatui::widgets::Clear, popup_area);
+    let row_width = popup_area.width.saturating_sub(2) as usize;
+    let items: Vec<ListItem> = player
+        .library_folders()
+        .iter()
+        .map(|folder| {
+            let missing = !paths::expand_home(folder).is_dir();
+            let mut spans = vec![Span::styled(
+                truncate_to_width(&format!(" {folder}"), row_width),
+                Style::default().fg(PRIMARY_COLOR),
+            )];
+            if missing {
+                spans.push(Span::styled(format!("  {}", t("sources.missing")), Style::default().fg(Color::DarkGray)));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(popup_panel(ui_config).title(t("panel.sources")).title_bottom(t("sources.hint")))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    f.render_stateful_widget(list, popup_area, &mut player.sources_state.clone());
+}
+
+fn render_dir_picker(f: &mut Frame, picker: &dir_picker::DirPicker, ui_config: &config::UiConfig) {
+    let popup_area = centered_rect(60, 60, f.area());
+    f.render_widget(ratatui::widgets::Clear, popup_area);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(3)])
+        .split(popup_area);
+    let row_width = popup_area.width.saturating_sub(2) as usize;
+
+    let filter = Paragraph::new(truncate_to_width(&format!("{}▏", picker.filter), row_width))
+        .block(popup_panel(ui_config).title(truncate_to_width(&picker.dir.display().to_string(), row_width)));
+    f.render_widget(filter, chunks[0]);
+
+    let folders = picker.visible();
+    let title = match &picker.error {
+        Some(error) => t_args("picker.error", &[("error", error)]),
+        None if folders.is_empty() => t("picker.empty").to_string(),
+        None => t("panel.folders").to_string(),
+    };
+    let items: Vec<ListItem> = folders
+        .iter()
+        .map(|name| {
+            ListItem::new(Span::styled(
+                truncate_to_width(&format!(" {name}/"), row_width),
+                Style::default().fg(PRIMARY_COLOR),
+            ))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(popup_panel(ui_config).title(title).title_bottom(t("picker.hint")))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    f.render_stateful_widget(list, chunks[1], &mut picker.list_state.clone());
+}
+
+fn centered_rect(percent_x: u16, percent_y: u16, r: ratatui::prelude::Rect) -> ratatui::prelude::Rect {
+    let popup_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ])
+        .split(r);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(popup_layout[1])[1]
+}
+
+// Cells filled in proportion to the audio buffered, out of `BUFFER_GAUGE_FULL`
+fn buffer_gauge(icons: icons::IconStyle, buffered: Duration) -> String {
+    let (full, empty) = icons.gauge();
+    let ratio = (buffered.as_secs_f64() / BUFFER_GAUGE_FULL.as_secs_f64()).min(1.0);
+    let filled = (ratio * BUFFER_GAUGE_CELLS as f64).ceil() as usize;
+    full.repeat(filled) + &empty.repeat(BUFFER_GAUGE_CELLS - filled)
+}
+
+// Lower the volume to silence in small steps over `duration`; the sink is left muted
+fn fade_out(sink: &Sink, duration: Duration) {
+    let start = sink.volume();
+    let steps = (duration.as_millis() / FADE_STEP.as_millis()).max(1) as u32;
+    for step in (0..steps).rev() {
+        sink.set_volume(start * step as f32 / steps as f32);
+        std::thread::sleep(FADE_STEP);
+    }
+}
+
+// Leave raw mode and the alternate screen and show the cursor again; safe to call more than once
+fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let _ = execute!(io::stdout(), LeaveAlternateScreen, Show);
+}
+
+// Ctrl+e: the song list in the user's editor, with the player's screen put away until the editor exits
+fn edit_list(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, player: &mut Player) -> Result<(), MusixError> {
+    let list = player.list_for_editor();
+    restore_terminal();
+    let edited = editor::edit(&list);
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    terminal.clear()?;
+    match edited {
+        Ok(Some(text)) if text != list => player.apply_edited_list(&text),
+        Ok(_) => {}
+        Err(e) => player.show_toast(t_args("message.editor_failed", &[("error", &e)])),
+    }
+    Ok(())
+}
+
+// Move a popup list's selection one row, wrapping around at either end
+fn step_selection(state: &mut ListState, count: usize, direction: i32) {
+    if count == 0 {
+        return;
+    }
+    let current = state.selected().unwrap_or(0);
+    let new = if direction > 0 {
+        (current + 1) % count
+    } else if current == 0 {
+        count - 1
+    } else {
+        current - 1
+    };
+    state.select(Some(new));
+}
+
+// Restores the terminal when dropped, including while a panic unwinds out of the player
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+// The default hook prints the panic message right away, so the terminal has to be back to normal before it runs
+// or the message ends up garbled on the alternate screen. Panics in background threads (BPM analysis, scrobbling)
+// don't take the player down, so the terminal is left alone for those.
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if std::thread::current().name() == Some("main") {
+            restore_terminal();
+        }
+        default_hook(info);
+    }));
+}
+
+fn run_player(kiosk: bool, ascii: bool, file: Option<PathBuf>) -> Result<(), MusixError> {
+    // Relative to where this was started, not to the other player
+    let file = file.map(|file| fs::canonicalize(&file).unwrap_or(file));
+    // A second player would fight the first over the audio device and the session files
+    let socket = instance::socket_path();
+    let running = instance::is_running(&socket);
+    if running {
+        if let Some(file) = &file
+            && instance::confirm(&t_args("message.instance_send", &[("file", &file.display())]), true)
+        {
+            instance::send(&socket, &instance::Request::Play(file.clone()))?;
+            println!("{}", t("message.instance_sent"));
+            return Ok(());
+        }
+        if file.is_none() && !instance::confirm(t("message.instance_start"), false) {
+            return Ok(());
+        }
+    }
+
+    let mut player = match Player::new() {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{}", t_args("message.init_failed", &[("error", &e)]));
+            std::process::exit(1);
+        }
+    };
+    player.locked = kiosk;
+    player.ascii = ascii;
+    Player::override_config(&mut player.config, ascii);
+
+    if !running {
+        player.instance = instance::serve(&socket).ok();
+    }
+    if let Some(file) = file {
+        player.play_path(&file)?;
+    }
+
+    install_panic_hook();
+    match enable_raw_mode() {
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("Failed to enable raw mode: {e}");
+            return Err(e.into());
+        }
+    }
+    let terminal_guard = TerminalGuard;
+
+    let mut stdout = io::stdout();
+    match execute!(stdout, EnterAlternateScreen) {
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("Failed to enter alternate screen: {e}");
+            return Err(e.into());
+        }
+    }
+
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = match Terminal::new(backend) {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("Failed to create terminal: {e}");
+            return Err(e.into());
+        }
+    };
+
+    let result = main_loop(&mut terminal, &mut player);
+
+    player.finish_listen(events::End::Quit);
+    let stopped = nowplaying::NowPlaying {
+        status: nowplaying::Status::Stopped,
+        ..player.now_playing()
+    };
+    player.write_now_playing(stopped);
+    // Finish any recording in progress so the file is left playable
+    player.stop_recording();
+    // Remember the track and position for the next start
+    if let Some(file) = &player.session_file {
+        let _ = session::save(file, &player.session(true));
+    }
+
+    // Clean shutdown of audio to prevent warning messages; a playing track is faded out rather than cut off
+    if let Some(ref sink) = player.sink {
+        let sink = sink.lock().unwrap();
+        if player.is_playing && !sink.empty() {
+            fade_out(&sink, QUIT_FADE);
+        }
+        sink.stop();
+    }
+
+    drop(terminal_guard);
+
+    // Reset terminal title
+    term_title::set(player.config.ui.terminal_title, "Terminal");
+
+    result
+}
+
+// A big clock over the track playing, in dim colors and drifting slowly so an always-on display doesn't burn in
+fn draw_idle(f: &mut Frame, player: &Player, idle: Duration) {
+    let area = f.area();
+    let now = chrono::Local::now();
+    // The colon blinks with the seconds
+    let clock = now.format(if now.timestamp() % 2 == 0 { "%H:%M" } else { "%H %M" }).to_string();
+    let mut lines: Vec<Line> = idle::big(&clock)
+        .into_iter()
+        .map(|row| Line::styled(row, Style::default().fg(IDLE_COLOR)))
+        .collect();
+    if (player.is_playing || player.is_paused) && !player.songs.is_empty() {
+        let dim = Style::default().fg(Color::DarkGray);
+        let (position, duration) = (player.current_position(), player.song_duration);
+        let time = match duration {
+            Some(duration) => format!("{} / {}", Player::format_duration(position), Player::format_duration(duration)),
+            None => Player::format_duration(position),
+        };
+        let time = if player.is_paused {
+            format!("{} {time}", player.config.ui.icons.paused())
+        } else {
+            time
+        };
+        lines.push(Line::raw(""));
+        lines.push(Line::styled(
+            truncate_to_width(&player.track_label(player.current_index), area.width as usize),
+            dim,
+        ));
+        lines.push(Line::styled(time, dim));
+    }
+    let width = lines.iter().map(Line::width).max().unwrap_or(0).min(area.width as usize) as u16;
+    let height = (lines.len() as u16).min(area.height);
+    let (x, y) = idle::drift((area.width - width, area.height - height), idle);
+    let idle_area = ratatui::layout::Rect {
+        x: area.x + x,
+        y: area.y + y,
+        width,
+        height,
+    };
+    f.render_widget(Paragraph::new(lines).alignment(Alignment::Center), idle_area);
+}
+
+fn main_loop(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, player: &mut Player) -> Result<(), MusixError> {
+    let mut last_draw: Option<Instant> = None;
+    let mut needs_redraw = true;
+    loop {
+        needs_redraw |= player.tick_config();
+        // Read each pass, so a new `[ui]` preset applies as soon as it is reloaded
+        let (tick_rate, redraw_interval) = (player.config.ui.tick_rate(), player.config.ui.redraw_interval());
+        player.refresh_stream_title();
+        needs_redraw |= player.tick_stream_opening();
+        player.tick_stream_health();
+        player.radio_browser.poll();
+        player.collect_bpm_results();
+        if player.collect_tags() {
+            player.view_summary = None;
+            needs_redraw = true;
+        }
+        // The whole terminal height covers the list with a few rows to spare
+        player.prioritize_tags(terminal.size()?.height as usize);
+        needs_redraw |= player.collect_requests()?;
+        player.tick_snippet();
+        needs_redraw |= player.tick_preview();
+        needs_redraw |= player.tick_scrub();
+        needs_redraw |= player.tick_ab_loop();
+        if player.collect_inbox() {
+            player.view_summary = None;
+            needs_redraw = true;
+        }
+        needs_redraw |= player.collect_folder_scans();
+        player.tick_listen();
+        player.plan_next();
+        player.tick_transition()?;
+        player.tick_sync();
+        player.tick_now_playing();
+        player.tick_announce();
+        player.tick_session();
+        needs_redraw |= player.tick_idle();
+        player.tick_media_controls()?;
+        needs_redraw |= player.tick_cover_art();
+        if needs_redraw || last_draw.is_none_or(|drawn| drawn.elapsed() >= redraw_interval) {
+            if player.view_summary.is_none() {
+                player.view_summary = Some(player.summarize_view());
+            }
+            terminal.draw(|f| ui(f, player))?;
+            last_draw = Some(Instant::now());
+            needs_redraw = false;
+        }
+
+        // Keys of a macro go ahead of waiting for input, one per pass so everything else keeps up with them
+        let replaying = !player.replay.is_empty();
+        let event = if replaying {
+            match event::poll(Duration::ZERO) {
+                Ok(true) => match event::read().ok() {
+                    Some(Event::Key(_)) => {
+                        player.stop_replay();
+                        None
+                    }
+                    event => event,
+                },
+                _ => player.replay.pop_front().map(Event::Key),
+            }
+        } else {
+            match event::poll(tick_rate) {
+                Ok(true) => event::read().ok(),
+                _ => None,
+            }
+        };
+        // Any input, including a resize, gets a fresh frame on the next pass
+        needs_redraw |= event.is_some();
+        if let Some(Event::Key(key)) = event {
+            player.last_input = Instant::now();
+            // The first key only brings the full UI back
+            if player.idle_since.take().is_some() {
+                continue;
+            }
+            if !replaying {
+                player.macros.record(key);
+            }
+            if player.show_problems_popup {
+                match key.code {
+                    KeyCode::Esc | KeyCode::Char('!') | KeyCode::Char('q') => player.show_problems_popup = false,
+                    KeyCode::Up | KeyCode::Char('k') => player.move_problem_selection(-1),
+                    KeyCode::Down | KeyCode::Char('j') => player.move_problem_selection(1),
+                    KeyCode::Enter => player.retry_problem()?,
+                    KeyCode::Char('d') => player.remove_problem(),
+                    _ => {}
+                }
+                continue;
+            }
+
+            if player.show_demoted_popup {
+                match key.code {
+                    KeyCode::Esc | KeyCode::Char('D') | KeyCode::Char('q') => player.show_demoted_popup = false,
+                    KeyCode::Up | KeyCode::Char('k') => player.move_demoted_selection(-1),
+                    KeyCode::Down | KeyCode::Char('j') => player.move_demoted_selection(1),
+                    KeyCode::Enter => player.restore_demoted(),
+                    _ => {}
+                }
+                continue;
+            }
+
+            if player.show_equalizer {
+                match key.code {
+                    KeyCode::Esc | KeyCode::Char('E') | KeyCode::Char('q') => player.toggle_equalizer_panel(),
+                    KeyCode::Up | KeyCode::Char('k') => player.move_equalizer_band(-1),
+                    KeyCode::Down | KeyCode::Char('j') => player.move_equalizer_band(1),
+                    KeyCode::Left | KeyCode::Char('h') => player.change_equalizer_gain(-1.0),
+                    KeyCode::Right | KeyCode::Char('l') => player.change_equalizer_gain(1.0),
+                    KeyCode::Char('p') => player.cycle_equalizer_preset(),
+                    KeyCode::Char('e') | KeyCode::Char(' ') => player.equalizer.set_enabled(!player.equalizer.is_enabled()),
+                    _ => {}
+                }
+                continue;
+            }
+
+            if player.show_revisit {
+                match key.code {
+                    KeyCode::Esc | KeyCode::Char('W') | KeyCode::Char('q') => player.show_revisit = false,
+                    KeyCode::Up | KeyCode::Char('k') => player.move_revisit_selection(-1),
+                    KeyCode::Down | KeyCode::Char('j') => player.move_revisit_selection(1),
+                    KeyCode::Enter => player.pop_revisit(true)?,
+                    KeyCode::Char('d') => player.pop_revisit(false)?,
+                    _ => {}
+                }
+                continue;
+            }
+
+            if player.show_stats_popup {
+                if matches!(key.code, KeyCode::Esc | KeyCode::Char('S') | KeyCode::Char('q')) {
+                    player.show_stats_popup = false;
+                }
+                continue;
+            }
+
+            if let Some((picker, _)) = &mut player.dir_picker {
+                match key.code {
+                    KeyCode::Esc => player.dir_picker = None,
+                    KeyCode::Up => picker.move_selection(-1),
+                    KeyCode::Down => picker.move_selection(1),
+                    KeyCode::Enter | KeyCode::Right => picker.enter(),
+                    KeyCode::Left => picker.up(),
+                    KeyCode::Backspace => picker.backspace(),
+                    KeyCode::Tab => player.pick_folder(),
+                    KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => picker.push_filter(c),
+                    _ => {}
+                }
+                continue;
+            }
+
+            if player.show_sources {
+                let count = player.library_folders().len();
+                let position = player.sources_state.selected().unwrap_or(0);
+                match key.code {
+                    KeyCode::Esc | KeyCode::Char('L') => player.show_sources = false,
+                    KeyCode::Up => player.sources_state.select(Some((position + count.max(1) - 1) % count.max(1))),
+                    KeyCode::Down => player.sources_state.select(Some((position + 1) % count.max(1))),
+                    KeyCode::Char('a') => player.open_dir_picker(FolderPick::LibraryFolder),
+                    KeyCode::Char('d') | KeyCode::Delete => player.remove_library_folder(),
+                    _ => {}
+                }
+                continue;
+            }
+
+            if let Some(input) = &mut player.command_input {
+                match key.code {
+                    KeyCode::Esc => player.command_input = None,
+                    KeyCode::Enter => {
+                        let line = player.command_input.take().unwrap_or_default();
+                        player.run_command(&line);
+                    }
+                    // Backspace on an empty line leaves it, as in vim
+                    KeyCode::Backspace if input.is_empty() => player.command_input = None,
+                    KeyCode::Backspace => {
+                        input.pop();
+                    }
+                    KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => input.push(c),
+                    _ => {}
+                }
+                continue;
+            }
+
+            if player.show_radio_browser {
+                match key.code {
+                    KeyCode::Esc => player.show_radio_browser = false,
+                    KeyCode::Up => player.radio_browser.move_selection(-1),
+                    KeyCode::Down => player.radio_browser.move_selection(1),
+                    KeyCode::Enter => {
+                        if player.radio_browser.needs_search() && !player.radio_browser.query.trim().is_empty() {
+                            player.radio_browser.search();
+                        } else if let Some(station) = player.radio_browser.selected_station().cloned() {
+                            player.play_station(&station)?;
+                        }
+                    }
+                    KeyCode::Backspace => {
+                        player.radio_browser.query.pop();
+                    }
+                    KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => player.radio_browser.query.push(c),
+                    _ => {}
+                }
+                continue;
+            }
+
+            // Any answer but yes keeps playing
+            if player.show_quit_prompt {
+                player.show_quit_prompt = false;
+                if matches!(key.code, KeyCode::Char('y' | 'Y') | KeyCode::Enter) {
+                    break;
+                }
+                continue;
+            }
+
+            if let Some(input) = &mut player.pin_input {
+                match key.code {
+                    KeyCode::Esc => player.pin_input = None,
+                    KeyCode::Enter => player.submit_pin(),
+                    KeyCode::Backspace => {
+                        input.pop();
+                    }
+                    KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => input.push(c),
+                    _ => {}
+                }
+                continue;
+            }
+
+            // While scrubbing, Enter seeks at once and Esc stays where playback is
+            if player.scrub.is_some() && matches!(key.code, KeyCode::Enter | KeyCode::Esc) {
+                if key.code == KeyCode::Enter {
+                    player.apply_scrub();
+                } else {
+                    player.scrub = None;
+                }
+                continue;
+            }
+
+            if player.kiosk_blocks(&key) {
+                player.chords.reset();
+                player.show_toast(t("message.kiosk_blocked").to_string());
+                continue;
+            }
+
+            let performing = player.performance_mode && !player.search_mode && !player.show_controls_popup;
+            // Counts and chords outside of search, where every key belongs to the query; digits are hot cues while performing
+            let mut count = None;
+            if !player.search_mode {
+                let (prefixes, counts): (&[char], bool) = match (performing, player.macros.recording().is_some()) {
+                    // While recording, `Q` is a key of its own that stops it
+                    (true, false) => (&['d', 'g', 'm', '\'', 'Q', '@'], false),
+                    (true, true) => (&['d', 'g', 'm', '\'', '@'], false),
+                    (false, false) => (&['g', 'm', '\'', 'Q', '@'], true),
+                    (false, true) => (&['g', 'm', '\'', '@'], true),
+                };
+                match player.chords.feed(key, prefixes, counts) {
+                    chord::Step::Pending | chord::Step::Cancelled => continue,
+                    chord::Step::Chord { count, prefix, key } => {
+                        player.run_chord(count, prefix, key);
+                        continue;
+                    }
+                    chord::Step::Key { count: typed, .. } => count = typed,
+                }
+            }
+            let steps = count.unwrap_or(1);
+
+            if performing {
+                let handled = match key.code {
+                    KeyCode::Char(digit @ '1'..='9') => {
+                        player.trigger_cue(digit as u8 - b'0');
+                        true
+                    }
+                    KeyCode::Esc | KeyCode::Char('p') => {
+                        player.toggle_performance_mode();
+                        true
+                    }
+                    _ => false,
+                };
+                if handled {
+                    continue;
+                }
+            }
+
+            if let Some(action) = keymap::action(&key, player.search_mode) {
+                match action {
+                    keymap::Action::Shuffle => player.cycle_shuffle_mode(),
+                    keymap::Action::Repeat => player.cycle_repeat_mode(),
+                    keymap::Action::Rate => player.cycle_rating(),
+                    keymap::Action::Problems => player.toggle_problems_popup(),
+                    keymap::Action::Demoted => player.toggle_demoted_popup(),
+                    keymap::Action::SaveForLater => player.save_for_later(),
+                    keymap::Action::Revisit => player.toggle_revisit_popup(),
+                    keymap::Action::LoopStart => player.set_loop_start(),
+                    keymap::Action::LoopEnd => player.set_loop_end(),
+                    keymap::Action::ClearLoop => player.clear_loop(),
+                    keymap::Action::Equalizer => player.toggle_equalizer_panel(),
+                    keymap::Action::Radio => player.show_radio_browser = true,
+                    keymap::Action::Stats => player.toggle_stats_popup(),
+                    keymap::Action::Record => player.toggle_recording(),
+                    keymap::Action::Lock => player.toggle_lock(),
+                    keymap::Action::Details => player.show_details = !player.show_details,
+                    keymap::Action::Bpm => player.analyze_selected_bpm(),
+                    keymap::Action::MissingBpm => player.analyze_missing_bpm(),
+                    keymap::Action::Sort => player.cycle_sort(),
+                    keymap::Action::Performance => player.toggle_performance_mode(),
+                    keymap::Action::TrackEnd => player.cycle_end_action(),
+                    keymap::Action::SkipIntro => player.toggle_skip_intro(),
+                    keymap::Action::SkipOutro => player.toggle_skip_outro(),
+                    keymap::Action::YankName => player.yank(false),
+                    keymap::Action::YankPath => player.yank(true),
+                    keymap::Action::Reveal => player.reveal_selected(),
+                    keymap::Action::EditList => edit_list(terminal, player)?,
+                    keymap::Action::Preview => player.toggle_preview(),
+                    keymap::Action::TakePreview => player.take_preview()?,
+                    keymap::Action::VolumeUp => player.change_volume(VOLUME_STEP),
+                    keymap::Action::VolumeDown => player.change_volume(-VOLUME_STEP),
+                    keymap::Action::Mute => player.toggle_mute(),
+                    keymap::Action::Crossfade => player.cycle_crossfade(),
+                    keymap::Action::RetryAudio => player.retry_audio(),
+                    keymap::Action::AddFolder => player.open_dir_picker(FolderPick::AddTracks),
+                    keymap::Action::Sources => {
+                        player.show_sources = true;
+                        player.sources_state.select(Some(0));
+                    }
+                    keymap::Action::CommandLine => player.command_input = Some(String::new()),
+                    keymap::Action::Controls => player.show_controls_popup = !player.show_controls_popup,
+                }
+                continue;
+            }
+
+            match key {
+                KeyEvent {
+                    code: KeyCode::Esc,
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } => {
+                    if player.show_controls_popup {
+                        player.show_controls_popup = false;
+                    } else if player.search_mode {
+                        player.cancel_search();
+                    } else if player.confirm_quit() {
+                        break;
+                    }
+                }
+
+                KeyEvent {
+                    code: KeyCode::Char('c'),
+                    modifiers: KeyModifiers::CONTROL,
+                    ..
+                } => break,
+
+                KeyEvent {
+                    code: KeyCode::Up,
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } => {
+                    if player.search_mode {
+                        if !player.recall_search(true) {
+                            player.move_selection_in_search(-1);
+                        }
+                    } else {
+                        (0..steps).for_each(|_| player.move_selection(-1));
+                    }
+                }
+
+                KeyEvent {
+                    code: KeyCode::Char('k'),
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } => {
+                    if player.search_mode {
+                        player.search_query.push('k');
+                        let query = player.search_query.clone();
+                        player.fuzzy_search(&query);
+                    } else {
+                        (0..steps).for_each(|_| player.move_selection(-1));
+                    }
+                }
+
+                KeyEvent {
+                    code: KeyCode::Down,
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } => {
+                    if player.search_mode {
+                        if !player.recall_search(false) {
+                            player.move_selection_in_search(1);
+                        }
+                    } else {
+                        (0..steps).for_each(|_| player.move_selection(1));
+                    }
+                }
+
+                KeyEvent {
+                    code: KeyCode::Char('j'),
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } => {
+                    if player.search_mode {
+                        player.search_query.push('j');
+                        let query = player.search_query.clone();
+                        player.fuzzy_search(&query);
+                    } else {
+                        (0..steps).for_each(|_| player.move_selection(1));
+                    }
+                }
+
+                KeyEvent {
+                    code: KeyCode::Enter,
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } => {
+                    let _ = player.play_or_pause();
+                    if player.search_mode {
+                        player.exit_search_mode();
+                    }
+                }
+
+                KeyEvent {
+                    code: KeyCode::Char(' '),
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } => {
+                    if player.search_mode {
+                        player.search_query.push(' ');
+                        let query = player.search_query.clone();
+                        player.fuzzy_search(&query);
+                    } else {
+                        let _ = player.play_or_pause();
+                    }
+                }
+
+                KeyEvent {
+                    code: KeyCode::Left,
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } if !player.search_mode => {
+                    player.previous_song()?;
+                }
+
+                KeyEvent {
+                    code: KeyCode::Left | KeyCode::Right,
+                    modifiers: KeyModifiers::SHIFT,
+                    ..
+                }
+                | KeyEvent {
+                    code: KeyCode::Char(',' | '.'),
+                    modifiers: KeyModifiers::ALT,
+                    ..
+                } if !player.search_mode => {
+                    player.scrub(matches!(key.code, KeyCode::Right | KeyCode::Char('.')));
+                }
+
+                KeyEvent {
+                    code: KeyCode::Right,
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } if !player.search_mode => {
+                    player.next_song()?;
+                }
+
+                KeyEvent {
+                    code: KeyCode::Char('h'),
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } => {
+                    if player.search_mode {
+                        player.search_query.push('h');
+                        let query = player.search_query.clone();
+                        player.fuzzy_search(&query);
+                    } else {
+                        player.previous_song()?;
+                    }
+                }
+
+                KeyEvent {
+                    code: KeyCode::Char('l'),
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } => {
+                    if player.search_mode {
+                        player.search_query.push('l');
+                        let query = player.search_query.clone();
+                        player.fuzzy_search(&query);
+                    } else {
+                        player.next_song()?;
+                    }
+                }
+
+                KeyEvent {
+                    code: KeyCode::Char('n'),
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } => {
+                    if player.search_mode {
+                        player.search_query.push('n');
+                        let query = player.search_query.clone();
+                        player.fuzzy_search(&query);
+                    } else {
+                        player.search_next(true);
+                    }
+                }
+
+                KeyEvent {
+                    code: KeyCode::Char('N'),
+                    modifiers: KeyModifiers::SHIFT,
+                    ..
+                } => {
+                    if player.search_mode {
+                        player.search_query.push('N');
+                        let query = player.search_query.clone();
+                        player.fuzzy_search(&query);
+                    } else {
+                        player.search_next(false);
+                    }
+                }
+
+                // Outside of search, `g` starts the `gg` chord
+                KeyEvent {
+                    code: KeyCode::Char('g'),
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } if player.search_mode => {
+                    player.search_query.push('g');
+                    let query = player.search_query.clone();
+                    player.fuzzy_search(&query);
+                }
+
+                KeyEvent {
+                    code: KeyCode::Char('G'),
+                    modifiers: KeyModifiers::SHIFT,
+                    ..
+                } => {
+                    if player.search_mode {
+                        player.search_query.push('G');
+                        let query = player.search_query.clone();
+                        player.fuzzy_search(&query);
+                    } else {
+                        match count {
+                            Some(line) => player.go_to_line(line),
+                            None => player.jump_to_last(),
+                        }
+                    }
+                }
+
+                KeyEvent {
+                    code: KeyCode::Char('q'),
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } => {
+                    if player.search_mode {
+                        player.search_query.push('q');
+                        let query = player.search_query.clone();
+                        player.fuzzy_search(&query);
+                    } else if player.confirm_quit() {
+                        break;
+                    }
+                }
+
+                KeyEvent {
+                    code: KeyCode::Char('<') | KeyCode::Char(','),
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } => {
+                    if player.search_mode {
+                        let c = if key.code == KeyCode::Char('<') { '<' } else { ',' };
+                        player.search_query.push(c);
+                        let query = player.search_query.clone();
+                        player.fuzzy_search(&query);
+                    } else {
+                        player.seek(-5); // Seek backward 5 seconds
+                    }
+                }
+
+                KeyEvent {
+                    code: KeyCode::Char('>') | KeyCode::Char('.'),
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } => {
+                    if player.search_mode {
+                        let c = if key.code == KeyCode::Char('>') { '>' } else { '.' };
+                        player.search_query.push(c);
+                        let query = player.search_query.clone();
+                        player.fuzzy_search(&query);
+                    } else {
+                        player.seek(5); // Seek forward 5 seconds
+                    }
+                }
+
+                KeyEvent {
+                    code: KeyCode::Char('Q'),
+                    modifiers: KeyModifiers::SHIFT,
+                    ..
+                } if !player.search_mode => player.stop_macro(),
+
+                KeyEvent {
+                    code: KeyCode::Char('/'),
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } => {
+                    if !player.search_mode {
+                        player.enter_search_mode();
+                    } else {
+                        player.search_query.push('/');
+                        let query = player.search_query.clone();
+                        player.fuzzy_search(&query);
+                    }
+                }
+
+                KeyEvent {
+                    code: KeyCode::Backspace,
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } if player.search_mode => {
+                    player.search_query.pop();
+                    let query = player.search_query.clone();
+                    player.fuzzy_search(&query);
+                }
+
+                KeyEvent {
+                    code: KeyCode::Char(c),
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                } if player.search_mode => {
+                    player.search_query.push(c);
+                    let query = player.search_query.clone();
+                    player.fuzzy_search(&query);
+                }
+
+                _ => {}
             }
-        } else {
-            self.selected_index = self.songs.len() - 1;
-            self.list_state.select(Some(self.selected_index));
         }
-    }
-}
-
-fn load_mp3_files() -> Result<Vec<Song>, Box<dyn std::error::Error>> {
-    let mut songs = Vec::new();
 
-    // Try multiple directories in order of preference
-    let potential_dirs = vec![
+        // Check if current song finished and auto-play next
+        if player.is_playing
+            && let Some(ref sink) = player.sink
         {
-            // User's Music directory
-            let home_dir = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
-            PathBuf::from(format!("{home_dir}/Music"))
-        },
-        PathBuf::from("./data"),
-    ];
-
-    for data_dir in potential_dirs {
-        if data_dir.exists() {
-            match visit_dir(&data_dir, &mut songs) {
-                Ok(_) => {
-                    //eprintln!("Loaded {} MP3 files from: {data_dir:?}", songs.len());  // break;
-                }
-                Err(e) => {
-                    eprintln!("Warning: Could not access directory {data_dir:?}: {e}");
-                    continue;
+            let sink = sink.lock().unwrap();
+            // A track queued for a gapless start is left alone on the sink once the one before it ends
+            let handed_over = player.gapless_next.is_some() && sink.len() <= 1;
+            if sink.empty() || handed_over || player.outro_reached() {
+                drop(sink);
+                if !player.restart_ab_loop() {
+                    player.track_finished()?;
                 }
             }
         }
     }
 
-    songs.sort_by(|a, b| a.name.cmp(&b.name));
-    Ok(songs)
+    Ok(())
 }
 
-fn create_audio_source(path: &PathBuf) -> Result<Box<dyn Source<Item = i16> + Send>, Box<dyn std::error::Error>> {
-    // For now, just use rodio's built-in decoder
-    // AAC support would require more complex implementation
-    let file = std::fs::File::open(path)?;
-    let source = Decoder::new(file)?;
-    Ok(Box::new(source))
+// None for a missing file, which counts as a change once it appears
+fn modified_time(file: &Path) -> Option<SystemTime> {
+    fs::metadata(file).and_then(|metadata| metadata.modified()).ok()
 }
 
-fn get_audio_duration(path: &PathBuf) -> Option<Duration> {
-    let file = match std::fs::File::open(path) {
-        Ok(file) => file,
-        Err(_) => return None,
+fn main() {
+    let cli = cli::Cli::parse();
+    paths::init_portable(cli.portable);
+    let result = match cli.command {
+        Some(cli::Command::Convert(options)) => convert::run(options),
+        Some(cli::Command::Rgscan(options)) => rgscan::run(options),
+        Some(cli::Command::Check) => check::run(),
+        Some(cli::Command::Relocate { old, new }) => relocate::run(old, new),
+        Some(cli::Command::NowPlaying { format }) => nowplaying::run(format),
+        Some(cli::Command::Podcast(command)) => podcast::run(command),
+        Some(cli::Command::Log(command)) => events::run(command),
+        Some(cli::Command::Completions { shell }) => {
+            cli::print_completions(shell);
+            Ok(())
+        }
+        None => run_player(cli.kiosk, cli.ascii, cli.file),
     };
 
-    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    if let Err(e) = result {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
 
-    let mut hint = Hint::new();
-    if let Some(extension) = path.extension() {
-        if let Some(ext_str) = extension.to_str() {
-            hint.with_extension(ext_str);
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use std::path::Path;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(Player::format_duration(Duration::from_secs(0)), "00:00");
+        assert_eq!(Player::format_duration(Duration::from_secs(30)), "00:30");
+        assert_eq!(Player::format_duration(Duration::from_secs(60)), "01:00");
+        assert_eq!(Player::format_duration(Duration::from_secs(125)), "02:05");
     }
 
-    let meta_opts: MetadataOptions = Default::default();
-    let fmt_opts: FormatOptions = Default::default();
+    #[test]
+    fn test_format_total() {
+        assert_eq!(format_total(Duration::from_secs(3599)), "59:59");
+        assert_eq!(format_total(Duration::from_secs(3 * 3600 + 62)), "3:01:02");
+    }
 
-    match symphonia::default::get_probe().format(&hint, mss, &fmt_opts, &meta_opts) {
-        Ok(mut probed) => {
-            let format = &mut probed.format;
-            let track = match format
-                .tracks()
-                .iter()
-                .find(|t| t.codec_params.codec != symphonia::core::codecs::CODEC_TYPE_NULL)
-            {
-                Some(track) => track,
-                None => return None,
-            };
+    #[test]
+    fn test_view_summary_follows_the_filter() {
+        let mut player = test_player(&["rock a", "rock b", "jazz"]);
+        player.library.entry(Path::new("rock a.mp3")).duration = Some(200.0);
+        player.library.entry(Path::new("jazz.mp3")).duration = Some(100.0);
+        assert_eq!(player.summarize_view(), (3, Duration::from_secs(300), true));
+
+        player.enter_search_mode();
+        player.fuzzy_search("jazz");
+        assert_eq!(player.summarize_view(), (1, Duration::from_secs(100), false));
+    }
 
-            // Try multiple methods to get duration
-            if let (Some(time_base), Some(n_frames)) = (track.codec_params.time_base, track.codec_params.n_frames) {
-                let duration_secs = n_frames as f64 * time_base.numer as f64 / time_base.denom as f64;
-                return Some(Duration::from_secs_f64(duration_secs));
-            }
+    #[test]
+    fn test_view_summary_is_kept_until_the_list_changes() {
+        let mut player = test_player(&["rock a", "rock b", "jazz"]);
+        let summary = Some((3, Duration::ZERO, true));
+        player.view_summary = summary;
+        player.move_selection(1);
+        assert_eq!(player.view_summary, summary);
+
+        player.enter_search_mode();
+        assert!(player.view_summary.is_none());
+        player.view_summary = summary;
+        player.exit_search_mode();
+        assert!(player.view_summary.is_none());
+        player.view_summary = summary;
+        player.sort_songs();
+        assert!(player.view_summary.is_none());
+    }
 
-            // Alternative method for formats like AAC/FLAC/OPUS that might not have n_frames
-            if let Some(sample_rate) = track.codec_params.sample_rate {
-                // Store codec type to avoid borrowing issues
-                let codec_type = track.codec_params.codec;
+    fn test_player(names: &[&str]) -> Player {
+        let songs = names
+            .iter()
+            .map(|name| Song::new(name.to_string(), PathBuf::from(format!("{name}.mp3"))))
+            .collect();
+        Player::from_songs(songs)
+    }
 
-                // Try to read through the entire format to count samples
-                let mut packet_count = 0u64;
-                let mut sample_count = 0u64;
+    #[test]
+    fn test_truncate_to_width() {
+        assert_eq!(truncate_to_width("Short", 10), "Short");
+        assert_eq!(truncate_to_width("Long song name", 8), "Long so…");
+        // CJK characters are two columns wide and must not be split
+        assert_eq!(truncate_to_width("東京の夜", 6), "東京…");
+        assert_eq!(truncate_to_width("東京の夜", 5), "東京…");
+        assert_eq!(truncate_to_width("🎵🎵🎵", 4), "🎵…");
+        assert!(truncate_to_width("東京の夜とロック", 9).width() <= 9);
+    }
 
-                loop {
-                    match format.next_packet() {
-                        Ok(_packet) => {
-                            packet_count += 1;
-                            // Estimate samples per packet based on codec
-                            let samples_per_packet = match codec_type {
-                                symphonia::core::codecs::CODEC_TYPE_AAC => 1024,
-                                symphonia::core::codecs::CODEC_TYPE_FLAC => 4096, // Variable, but reasonable estimate
-                                symphonia::core::codecs::CODEC_TYPE_VORBIS => 1024,
-                                _ => 1152, // Default for MP3
-                            };
-                            sample_count += samples_per_packet;
-                        }
-                        Err(_) => break,
-                    }
-                    // Limit iteration to prevent infinite loops on corrupted files
-                    if packet_count > 1000000 {
-                        break;
-                    }
-                }
+    #[test]
+    fn test_selection_names_tracks_without_fuzzy_matches() {
+        let library: Vec<Song> = ["Blue in Green", "Blue Train", "Abbey Road Medley", "A Day in the Life"]
+            .iter()
+            .map(|name| Song::new(name.to_string(), PathBuf::from(format!("{name}.mp3"))))
+            .collect();
+        let names = |query| match_selection(query, &library).map(|songs| songs.into_iter().map(|song| song.name).collect::<Vec<_>>());
+
+        assert_eq!(names("blue train").unwrap(), vec!["Blue Train"]);
+        assert_eq!(names("medley").unwrap(), vec!["Abbey Road Medley"]);
+        // "abc" has its letters in order in two names, but is in none
+        assert!(names("abc").is_err());
+        let several = names("blue").unwrap_err().to_string();
+        assert!(several.contains("Blue in Green.mp3") && several.contains("Blue Train.mp3"), "{several}");
+    }
 
-                if sample_count > 0 {
-                    let duration_secs = sample_count as f64 / sample_rate as f64;
-                    return Some(Duration::from_secs_f64(duration_secs));
-                }
-            }
+    #[test]
+    fn test_search_ignores_accents_and_compatibility_forms() {
+        assert_eq!(normalize_for_search("Beyoncé"), "beyonce");
+        assert_eq!(normalize_for_search("Tchaïkovski"), "tchaikovski");
+        assert_eq!(normalize_for_search("ＡＢＣ"), "abc");
+
+        let mut player = test_player(&["Halo - Beyoncé", "Swan Lake - Čajkovskij", "Other"]);
+        player.fuzzy_search("beyonce");
+        assert_eq!(player.filtered_songs, vec![0]);
+        player.fuzzy_search("CAJKOVSKIJ");
+        assert_eq!(player.filtered_songs, vec![1]);
+    }
 
-            None
+    #[test]
+    fn test_jump_search_keeps_full_list() {
+        let mut player = test_player(&["rock a", "jazz a", "rock b", "pop", "jazz b"]);
+        player.config.search.mode = config::SearchMode::Jump;
+        player.selected_index = 2;
+        player.enter_search_mode();
+        assert_eq!(player.display_len(), 5);
+
+        // The first match from the cursor on, wrapping around to the top
+        player.fuzzy_search("jazz");
+        assert_eq!((player.selected_index, player.list_state.selected()), (4, Some(4)));
+        player.move_selection_in_search(1);
+        assert_eq!(player.selected_index, 1);
+        player.search_query = "jazz".to_string();
+        player.exit_search_mode();
+
+        player.search_next(true);
+        assert_eq!(player.selected_index, 4);
+        player.search_next(false);
+        assert_eq!(player.selected_index, 1);
+        player.search_next(false);
+        assert_eq!(player.selected_index, 4);
+    }
+
+    fn wait_for_folder_scans(player: &mut Player) {
+        let started = Instant::now();
+        while !player.folder_scans.is_empty() {
+            player.collect_folder_scans();
+            assert!(started.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(1));
         }
-        Err(_) => None,
     }
-}
 
-fn visit_dir(dir: &PathBuf, songs: &mut Vec<Song>) -> Result<(), Box<dyn std::error::Error>> {
-    if dir.is_dir() {
-        for entry in fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
+    #[test]
+    fn test_picked_folder_adds_its_tracks_once() {
+        let folder = TestDir::new("add-folder");
+        fs::create_dir_all(folder.join("Disc 2")).unwrap();
+        fs::write(folder.join("one.mp3"), b"").unwrap();
+        fs::write(folder.join("Disc 2").join("two.mp3"), b"").unwrap();
+
+        let mut player = test_player(&["a"]);
+        player.dir_picker = Some((dir_picker::DirPicker::open(folder.to_path_buf()), FolderPick::AddTracks));
+        player.pick_folder();
+        assert!(player.dir_picker.is_none());
+        wait_for_folder_scans(&mut player);
+        assert_eq!(player.songs.len(), 3);
+        assert_eq!(player.filtered_songs.len(), 3);
+        assert!(player.tag_reader.is_some());
+
+        // Already in the list
+        player.add_folder(&folder);
+        wait_for_folder_scans(&mut player);
+        assert_eq!(player.songs.len(), 3);
+    }
 
-            if path.is_dir() {
-                visit_dir(&path, songs)?;
-            } else if let Some(extension) = path.extension() {
-                let ext_lower = extension.to_str().unwrap_or("").to_lowercase();
-                if ext_lower == "mp3" || ext_lower == "m4a" || ext_lower == "wav" || ext_lower == "flac" || ext_lower == "opus" {
-                    let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("Unknown").to_string();
+    #[test]
+    fn test_library_folders_are_added_and_removed_live() {
+        let root = TestDir::new("sources");
+        let (music, extra) = (root.join("Music"), root.join("Extra"));
+        fs::create_dir_all(&music).unwrap();
+        fs::create_dir_all(&extra).unwrap();
+        fs::write(music.join("a.mp3"), b"").unwrap();
+        fs::write(extra.join("b.mp3"), b"").unwrap();
+        fs::write(extra.join("c.mp3"), b"").unwrap();
+
+        let mut player = Player::from_songs(vec![Song::from_path(music.join("a.mp3"))]);
+        player.config.library.folders = vec![music.display().to_string()];
+        player.config_file = Some(root.join("config.toml"));
+        player.run_command(&format!("adddir {}", extra.display()));
+        wait_for_folder_scans(&mut player);
+        assert_eq!(player.songs.len(), 3);
+        assert_eq!(player.library_folders().len(), 2);
+        let saved = config::Config::load_from(&root.join("config.toml")).unwrap();
+        assert_eq!(saved.library.folders, player.config.library.folders);
+
+        // Removing keeps the selection on the same song
+        player.selected_index = player.index_of(&music.join("a.mp3"), &None).unwrap();
+        player.sources_state.select(Some(1));
+        player.remove_library_folder();
+        assert_eq!(player.songs.len(), 1);
+        assert_eq!(player.songs[player.selected_index].path, music.join("a.mp3"));
+        assert_eq!(player.filtered_songs, vec![0]);
+        player.sources_state.select(Some(0));
+        player.remove_library_folder();
+        assert_eq!(player.library_folders().len(), 1, "the last folder stays");
+
+        player.run_command("bogus");
+        player.run_command(&format!("adddir {}", root.join("missing").display()));
+        assert_eq!(player.library_folders().len(), 1);
+    }
 
-                    songs.push(Song { name, path: path.clone() });
-                }
-            }
-        }
+    #[test]
+    fn test_fade_commands_set_the_selected_tracks_envelope() {
+        let mut player = test_player(&["a", "b"]);
+        player.selected_index = 1;
+        player.library.entry(Path::new("b.mp3")).skip_intro = Some(20.0);
+        player.library.entry(Path::new("b.mp3")).duration = Some(200.0);
+        player.run_command("fadein 3");
+        player.run_command("fadeout 5.5");
+        assert_eq!(player.library.fades(Path::new("b.mp3")), (Some(3.0), Some(5.5)));
+        assert_eq!(player.library.fades(Path::new("a.mp3")), (None, None));
+
+        // The fade-in starts where the skipped intro ends
+        let envelope = player.fade_envelope(Path::new("b.mp3"));
+        assert_eq!(envelope.start, Duration::from_secs(20));
+        assert_eq!(envelope.end, Some(Duration::from_secs(200)));
+        assert_eq!(envelope.gain(21.5), 0.5);
+
+        player.run_command("fadein 0");
+        assert_eq!(player.library.fades(Path::new("b.mp3")), (None, Some(5.5)));
+        player.run_command("fadeout soon");
+        assert_eq!(player.toast.as_ref().map(|(message, _)| message.as_str()), Some(t("message.fade_usage")));
+        assert_eq!(player.library.fades(Path::new("b.mp3")), (None, Some(5.5)));
     }
-    Ok(())
-}
 
-fn ui(f: &mut Frame, player: &Player) {
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Length(3), // Title
-            Constraint::Min(8),    // Song list
-            Constraint::Length(3), // Progress bar
-            Constraint::Length(3), // Status
-        ])
-        .split(f.area());
+    #[test]
+    fn test_start_and_end_commands_trim_the_selected_track() {
+        let mut player = test_player(&["a", "b"]);
+        player.selected_index = 1;
+        player.library.entry(Path::new("b.mp3")).duration = Some(200.0);
+        player.run_command("start 0:12.5");
+        player.run_command("end 30");
+        assert_eq!(player.intro_offset(Path::new("b.mp3")), Duration::from_millis(12500));
+        assert_eq!(player.outro_skip(Path::new("b.mp3")), Some(Duration::from_secs(30)));
+        assert_eq!(player.intro_offset(Path::new("a.mp3")), Duration::ZERO);
+
+        // Longer than what the start leaves of the track
+        player.run_command("end 3:10");
+        assert_eq!(player.outro_skip(Path::new("b.mp3")), Some(Duration::from_secs(30)));
+        player.run_command("end later");
+        assert_eq!(player.toast.as_ref().map(|(message, _)| message.as_str()), Some(t("message.trim_usage")));
+
+        player.run_command("start 0");
+        assert_eq!(player.library.skip_intro(Path::new("b.mp3")), None);
+    }
 
-    // Title
-    let title = Paragraph::new("MUSIX")
-        .style(Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD))
-        .alignment(Alignment::Center)
-        .block(Block::default().borders(Borders::ALL).border_style(Style::default().fg(PRIMARY_COLOR)));
-    f.render_widget(title, chunks[0]);
+    #[test]
+    fn test_crossfade_length_cycles_and_is_set_by_command() {
+        let mut player = test_player(&["a"]);
+        player.cycle_crossfade();
+        assert_eq!(player.config.playback.crossfade, 2.0);
+        player.run_command("crossfade 7");
+        player.cycle_crossfade();
+        assert_eq!(player.config.playback.crossfade, 8.0);
+        player.cycle_crossfade();
+        player.cycle_crossfade();
+        assert_eq!(player.config.playback.crossfade, 0.0);
+        assert_eq!(player.toast.as_ref().map(|(message, _)| message.as_str()), Some(t("message.crossfade_off")));
+
+        player.run_command("crossfade -1");
+        assert_eq!(player.toast.as_ref().map(|(message, _)| message.as_str()), Some(t("message.crossfade_usage")));
+        assert_eq!(player.config.playback.crossfade, 0.0);
+    }
 
-    // Song list
-    let display_songs = player.get_display_songs();
-    let items: Vec<ListItem> = display_songs
-        .iter()
-        .enumerate()
-        .map(|(_display_index, &(actual_index, song))| {
-            let playing_indicator = if actual_index == player.current_index && player.is_playing {
-                "♪ "
-            } else {
-                "  "
-            };
+    #[test]
+    fn test_revisit_stack_pops_into_playback() {
+        let mut player = test_player(&["a", "b", "c"]);
+        for index in [0, 2] {
+            player.current_index = index;
+            player.playback_start = Some(Instant::now());
+            player.save_for_later();
+        }
+        assert_eq!(player.revisit.entries(), ["c.mp3", "a.mp3"]);
+        player.revisit.push(Path::new("gone.mp3")).unwrap();
+
+        player.toggle_revisit_popup();
+        player.pop_revisit(true).unwrap();
+        assert_eq!(player.revisit.entries().len(), 3);
+        player.pop_revisit(false).unwrap();
+        player.move_revisit_selection(1);
+        // The file isn't there to start, but it is off the stack and the selection stays in range
+        let _ = player.pop_revisit(true);
+        assert_eq!(player.current_index, 0);
+        assert_eq!(player.revisit.entries(), ["c.mp3"]);
+        assert_eq!(player.revisit_state.selected(), Some(0));
+    }
 
-            let content = format!("{playing_indicator}{}. {}", actual_index + 1, song.name);
+    #[test]
+    fn test_setlist_command_exports_this_sessions_plays() {
+        let dir = TestDir::new("setlist-session");
+        let mut player = test_player(&["a", "b"]);
+        player.history_file = Some(dir.join("history.jsonl"));
+        let earlier = history::Listen::start("old".to_string(), Path::new("old.mp3"), None, player.opened - 60);
+        history::append(&dir.join("history.jsonl"), &earlier.finish(None)).unwrap();
+
+        let file = dir.join("party.m3u");
+        player.run_command(&format!("setlist {}", file.display()));
+        assert_eq!(player.toast.as_ref().map(|(message, _)| message.as_str()), Some(t("setlist.empty")));
+
+        player.listen = Some(history::Listen::start("b".to_string(), Path::new("b.mp3"), None, player.opened));
+        player.run_command(&format!("setlist {}", file.display()));
+        assert_eq!(fs::read_to_string(&file).unwrap(), "#EXTM3U\n#EXTINF:-1,b\nb.mp3\n");
+    }
 
-            let text_color = get_text_color();
-            let style = if actual_index == player.current_index && player.is_playing {
-                Style::default().fg(HIGHLIGHT_COLOR).add_modifier(Modifier::BOLD)
-            } else if actual_index == player.selected_index {
-                Style::default().fg(PRIMARY_COLOR)
-            } else {
-                Style::default().fg(text_color)
-            };
+    #[test]
+    fn test_equalizer_panel_moves_bands_and_cycles_presets() {
+        let mut player = test_player(&["a"]);
+        player.toggle_equalizer_panel();
+        player.move_equalizer_band(-1);
+        assert_eq!(player.equalizer_band, 9);
+        player.change_equalizer_gain(1.0);
+        player.change_equalizer_gain(1.0);
+        assert!(player.equalizer.is_enabled());
+        assert_eq!(player.equalizer.gains()[9], 2.0);
+
+        // Custom settings go back to the first preset, and flat turns the equalizer off
+        player.cycle_equalizer_preset();
+        assert!(!player.equalizer.is_enabled());
+        player.cycle_equalizer_preset();
+        assert_eq!(player.equalizer.gains(), equalizer::Preset::Rock.gains());
+        assert!(player.equalizer.is_enabled());
+        player.toggle_equalizer_panel();
+        assert!(!player.show_equalizer && player.toast.is_none());
+    }
 
-            ListItem::new(content).style(style)
-        })
-        .collect();
+    #[test]
+    fn test_alerts_flash_the_status_bar() {
+        let mut player = test_player(&["a", "radio"]);
+        player.track_finished().unwrap();
+        assert!(player.flash.is_none());
+
+        // Without an output nothing starts, so the end of a track leaves nothing playing
+        player.config.alerts.queue_empty = alert::Alert::Flash;
+        player.track_finished().unwrap();
+        assert!(player.flash.is_some());
+
+        player.flash = None;
+        player.config.alerts.stream_dropped = alert::Alert::Flash;
+        player.current_index = 1;
+        player.songs[1].stream_url = Some("http://radio.example/live".to_string());
+        player.track_finished().unwrap();
+        assert!(player.flash.is_some());
+        assert_eq!(
+            player.toast.as_ref().map(|(message, _)| message.clone()),
+            Some(t_args("message.stream_dropped", &[("name", &"radio")]))
+        );
+    }
 
-    let songs_title = if player.search_mode {
-        format!("Songs - Search: {}", player.search_query)
-    } else {
-        "Songs".to_string()
-    };
+    #[test]
+    fn test_ab_loop_goes_back_to_a_past_b() {
+        let mut player = test_player(&["a", "b"]);
+        player.is_paused = true;
+        player.seek_offset = Duration::from_secs(10);
+        player.set_loop_start();
+        player.seek_offset = Duration::from_secs(5);
+        player.set_loop_end();
+        assert_eq!(player.ab_loop, Some((Duration::from_secs(10), None)));
+
+        player.seek_offset = Duration::from_secs(20);
+        player.set_loop_end();
+        assert_eq!(player.ab_loop, Some((Duration::from_secs(10), Some(Duration::from_secs(20)))));
+        assert_eq!(player.seek_offset, Duration::from_secs(10));
+
+        player.is_paused = false;
+        player.is_playing = true;
+        player.seek_offset = Duration::from_secs(15);
+        assert!(!player.tick_ab_loop());
+        player.seek_offset = Duration::from_secs(21);
+        assert!(player.tick_ab_loop());
+        assert_eq!(player.seek_offset, Duration::from_secs(10));
+
+        // A new A past B drops B
+        player.seek_offset = Duration::from_secs(30);
+        player.set_loop_start();
+        assert_eq!(player.ab_loop, Some((Duration::from_secs(30), None)));
+        player.play_song(1).unwrap();
+        assert_eq!(player.ab_loop, None);
+    }
 
-    let songs_list = List::new(items)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title(songs_title)
-                .border_style(Style::default().fg(PRIMARY_COLOR)),
-        )
-        .highlight_style(Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD))
-        .scroll_padding(1);
+    #[test]
+    fn test_config_edits_apply_while_playing() {
+        let dir = TestDir::new("reload");
+        let file = dir.join("reload.toml");
+        let mut player = test_player(&["a"]);
+        player.config_file = Some(file.clone());
+        player.config_checked -= CONFIG_POLL;
+
+        fs::write(&file, "[ui]\nshow_status = false\n[equalizer]\nenabled = true\nbands = [3]\n").unwrap();
+        assert!(player.tick_config());
+        assert!(!player.config.ui.show_status);
+        assert!(player.equalizer.is_enabled() && player.equalizer.gains()[0] == 3.0);
+        assert_eq!(player.toast.as_ref().map(|(message, _)| message.as_str()), Some(t("message.config_reloaded")));
+
+        // Not looked at again within the second
+        fs::write(&file, "[ui\n").unwrap();
+        assert!(!player.tick_config());
+        player.config_checked -= CONFIG_POLL;
+        player.config_modified = None;
+        assert!(player.tick_config());
+        assert!(!player.config.ui.show_status);
+        assert!(player.toast.as_ref().is_some_and(|(message, _)| message.contains(&file.display().to_string())));
+
+        // Nothing to reload when the file says what the player already has
+        player.toast = None;
+        assert!(!player.apply_config(player.config.clone()));
+        assert!(player.toast.is_none());
+
+        // `--ascii` holds over the file, and the library folders stay the scanned ones
+        player.ascii = true;
+        player.config.ui.icons = icons::IconStyle::Ascii;
+        player.config.library.folders = vec!["/music".to_string()];
+        let mut edited = player.config.clone();
+        edited.ui.icons = icons::IconStyle::Unicode;
+        edited.library.folders = vec!["/elsewhere".to_string()];
+        assert!(!player.apply_config(edited.clone()));
+        edited.ui.show_status = true;
+        assert!(player.apply_config(edited));
+        assert_eq!(player.config.ui.icons, icons::IconStyle::Ascii);
+        assert_eq!(player.config.library.folders, ["/music"]);
+    }
 
-    f.render_stateful_widget(songs_list, chunks[1], &mut player.list_state.clone());
+    #[test]
+    fn test_repeat_one_replays_the_track_and_skips_still_move_on() {
+        let mut player = test_player(&["a", "b", "c"]);
+        player.cycle_repeat_mode();
+        assert_eq!(player.repeat_mode, repeat::RepeatMode::One);
+        player.library.entry(Path::new("a.mp3")).skip_intro = Some(5.0);
+        player.track_finished().unwrap();
+        assert_eq!((player.current_index, player.seek_offset), (0, Duration::from_secs(5)));
+        player.next_song().unwrap();
+        assert_eq!(player.current_index, 1);
+
+        // Off stops after the last track, all goes back to the first
+        player.cycle_repeat_mode();
+        player.current_index = 2;
+        player.track_finished().unwrap();
+        assert_eq!(player.current_index, 2);
+        player.cycle_repeat_mode();
+        player.track_finished().unwrap();
+        assert_eq!(player.current_index, 0);
+
+        // Without the guard against the track that just ended, off still stops there, and skipping past the end too
+        player.config.playback.avoid_repeats = false;
+        player.cycle_repeat_mode();
+        player.cycle_repeat_mode();
+        player.current_index = 2;
+        player.is_playing = true;
+        player.track_finished().unwrap();
+        assert_eq!((player.current_index, player.is_playing), (2, false));
+        player.is_playing = true;
+        player.next_song().unwrap();
+        assert_eq!((player.current_index, player.is_playing), (2, false));
+    }
 
-    // Progress bar
-    let (elapsed, total) = player.get_playback_progress();
-    let progress_ratio = if let Some(duration) = total {
-        if duration.as_secs() > 0 {
-            (elapsed.as_secs() as f64 / duration.as_secs() as f64).min(1.0)
-        } else {
-            0.0
+    #[test]
+    fn test_macros_replay_recorded_keys() {
+        let mut player = test_player(&["a", "b"]);
+        player.run_chord(None, 'Q', 'a');
+        assert_eq!(player.macros.recording(), Some('a'));
+        for c in ['*', 'j', 'Q'] {
+            player.macros.record(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
         }
-    } else {
-        0.0
-    };
+        player.stop_macro();
+        assert_eq!(player.macros.recording(), None);
+
+        player.run_chord(Some(2), '@', 'a');
+        let keys: Vec<KeyCode> = player.replay.iter().map(|key| key.code).collect();
+        assert_eq!(keys, [KeyCode::Char('*'), KeyCode::Char('j'), KeyCode::Char('*'), KeyCode::Char('j')]);
+
+        // A macro played from inside another runs before the rest of it
+        player.replay.pop_front();
+        player.run_chord(None, '@', '@');
+        assert_eq!(player.replay.len(), 5);
+        player.stop_replay();
+        assert!(player.replay.is_empty());
+        assert_eq!(player.toast.as_ref().map(|(message, _)| message.as_str()), Some(t("message.macro_stopped")));
+
+        player.run_chord(None, '@', 'z');
+        assert!(player.replay.is_empty());
+    }
 
-    let progress_label_text = if let Some(duration) = total {
-        format!(" {}/{} ", Player::format_duration(elapsed), Player::format_duration(duration))
-    } else {
-        format!(" {} ", Player::format_duration(elapsed))
-    };
+    #[test]
+    fn test_esc_restores_the_selection_from_before_the_search() {
+        let mut player = test_player(&["rock a", "jazz a", "pop", "jazz b"]);
+        player.selected_index = 2;
+        player.list_state.select(Some(2));
+        player.enter_search_mode();
+        player.search_query = "jazz".to_string();
+        player.fuzzy_search("jazz");
+        assert_ne!(player.selected_index, 2);
+        player.cancel_search();
+        assert_eq!((player.selected_index, player.list_state.selected()), (2, Some(2)));
+        assert_eq!(player.last_search, "jazz");
+
+        player.config.search.restore_on_esc = false;
+        player.enter_search_mode();
+        player.fuzzy_search("rock");
+        player.cancel_search();
+        assert_eq!(player.selected_index, 0);
+    }
 
-    let progress_bar_style = Style::default().fg(PRIMARY_COLOR).bg(Color::default());
-    let progress_label = Span::styled(progress_label_text, progress_bar_style);
+    #[test]
+    fn test_search_history_recall() {
+        let mut player = test_player(&["rock a", "jazz a", "pop"]);
+        for query in ["rock", "jazz"] {
+            player.enter_search_mode();
+            player.search_query = query.to_string();
+            player.exit_search_mode();
+        }
 
-    let progress_bar = Gauge::default()
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title("Progress")
-                .border_style(Style::default().fg(PRIMARY_COLOR)),
-        )
-        .gauge_style(progress_bar_style)
-        .ratio(progress_ratio)
-        .label(progress_label);
-    f.render_widget(progress_bar, chunks[2]);
+        player.enter_search_mode();
+        assert!(player.recall_search(true));
+        assert_eq!((player.search_query.as_str(), player.filtered_songs.clone()), ("jazz", vec![1]));
+        assert!(player.recall_search(true));
+        assert_eq!(player.search_query, "rock");
+        assert!(player.recall_search(false));
+        assert_eq!(player.search_query, "jazz");
+
+        // Once the query is edited, Up/Down go back to moving between matches
+        player.search_query.push(' ');
+        assert!(!player.recall_search(true));
+        player.search_query.clear();
+        assert!(!player.recall_search(false));
+    }
 
-    // Status
-    let mode_text = if player.random_mode { "RANDOM" } else { "NORMAL" };
-    let song_count = if player.search_mode {
-        format!("{}/{}", player.filtered_songs.len(), player.songs.len())
-    } else {
-        player.songs.len().to_string()
-    };
+    #[test]
+    fn test_up_next_is_what_plays() {
+        let mut player = test_player(&["a", "b", "c"]);
+        player.is_playing = true;
+        player.plan_next();
+        assert_eq!(player.planned_next(), Some(1));
+
+        // A shuffle pick stays put until it plays
+        player.cycle_shuffle_mode();
+        player.plan_next();
+        let next = player.planned_next().unwrap();
+        assert_ne!(next, 0);
+        player.plan_next();
+        assert_eq!(player.planned_next(), Some(next));
+
+        // Nothing follows the last track when the list doesn't loop
+        player.cycle_shuffle_mode();
+        player.cycle_shuffle_mode();
+        player.cycle_shuffle_mode();
+        player.repeat_mode = repeat::RepeatMode::Off;
+        player.current_index = 2;
+        player.plan_next();
+        assert_eq!(player.planned_next(), None);
+    }
 
-    let status_content = if player.search_mode {
-        vec![Line::from(vec![
-            Span::raw(format!("  Search Mode | Songs: {} | ", song_count)),
-            Span::styled("Esc", Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD)),
-            Span::raw(": Exit Search | "),
-            Span::styled("Enter", Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD)),
-            Span::raw(": Play  "),
-        ])]
-    } else {
-        vec![Line::from(vec![
-            Span::raw(format!("  Mode: {} | Songs: {} | ", mode_text, song_count)),
-            Span::styled("/", Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD)),
-            Span::raw(": Search | "),
-            Span::styled("x", Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD)),
-            Span::raw(": Help  "),
-        ])]
-    };
+    #[test]
+    fn test_end_actions() {
+        let mut player = test_player(&["a", "b", "c"]);
+        player.track_finished().unwrap();
+        assert_eq!(player.current_index, 1);
+
+        player.selected_index = 1;
+        player.cycle_end_action();
+        player.track_finished().unwrap();
+        assert_eq!(player.current_index, 1);
+
+        // Repeating starts over past the intro
+        player.cycle_end_action();
+        player.library.entry(Path::new("b.mp3")).skip_intro = Some(20.0);
+        player.track_finished().unwrap();
+        assert_eq!((player.current_index, player.seek_offset), (1, Duration::from_secs(20)));
+
+        // Any other way of starting the track skips the intro too
+        player.play_song(0).unwrap();
+        player.play_song(1).unwrap();
+        assert_eq!(player.seek_offset, Duration::from_secs(20));
+    }
+
+    #[test]
+    fn test_folder_skips_intro_and_outro() {
+        let mut player = test_player(&["show/ep1", "show/ep2", "song"]);
+        player.config.auto_skip = vec![auto_skip::AutoSkipRule {
+            folder: "show".to_string(),
+            intro: 30.0,
+            outro: 60.0,
+        }];
+        player.library.entry(Path::new("show/ep2.mp3")).skip_intro = Some(5.0);
+        assert_eq!(player.intro_offset(Path::new("show/ep1.mp3")), Duration::from_secs(30));
+        assert_eq!(player.intro_offset(Path::new("show/ep2.mp3")), Duration::from_secs(5));
+        assert_eq!(player.intro_offset(Path::new("song.mp3")), Duration::ZERO);
+
+        // A minute before the end counts as the end
+        player.song_duration = Some(Duration::from_secs(600));
+        player.seek_offset = Duration::from_secs(539);
+        assert!(!player.outro_reached());
+        player.seek_offset = Duration::from_secs(540);
+        assert!(player.outro_reached());
+        player.current_index = 2;
+        assert!(!player.outro_reached());
+    }
+
+    #[test]
+    fn test_marks_follow_their_track() {
+        let mut player = test_player(&["c", "a", "b"]);
+        player.selected_index = 1;
+        player.run_chord(None, 'm', 'a');
+        player.run_chord(Some(3), 'g', 'g');
+        assert_eq!(player.selected_index, 2);
+
+        // The mark points at the track, not the row, so it survives re-ordering
+        player.songs.sort_by(|a, b| a.name.cmp(&b.name));
+        player.run_chord(None, '\'', 'a');
+        assert_eq!((player.selected_index, player.list_state.selected()), (0, Some(0)));
+        player.run_chord(None, '\'', 'b');
+        assert_eq!(player.selected_index, 0);
+
+        player.go_to_line(99);
+        assert_eq!(player.selected_index, 2);
+    }
+
+    #[cfg(feature = "romanize")]
+    #[test]
+    fn test_search_matches_romanized_titles() {
+        let mut player = test_player(&["さくら", "東京", "Other"]);
+        player.fuzzy_search("sakura");
+        assert_eq!(player.filtered_songs, vec![0]);
+        player.fuzzy_search("dongjing");
+        assert_eq!(player.filtered_songs, vec![1]);
+    }
 
-    let status = Paragraph::new(status_content).alignment(Alignment::Left).block(
-        Block::default()
-            .borders(Borders::ALL)
-            .title("Status")
-            .border_style(Style::default().fg(PRIMARY_COLOR)),
-    );
-    f.render_widget(status, chunks[3]);
+    #[test]
+    fn test_neighbour_index_skips_problem_files() {
+        let mut player = test_player(&["a", "b", "c", "d"]);
+        player.songs[1].error = Some("corrupt".to_string());
+        player.songs[2].error = Some("corrupt".to_string());
+
+        assert_eq!(player.neighbour_index(true), Some(3));
+        player.current_index = 3;
+        assert_eq!(player.neighbour_index(false), Some(0));
+        assert_eq!(player.neighbour_index(true), Some(0));
+
+        player.repeat_mode = repeat::RepeatMode::Off;
+        assert_eq!(player.neighbour_index(true), None);
+        player.current_index = 0;
+        assert_eq!(player.neighbour_index(false), None);
+    }
 
-    // Controls popup
-    if player.show_controls_popup {
-        let popup_area = centered_rect(60, 60, f.area());
-        f.render_widget(ratatui::widgets::Clear, popup_area);
+    #[test]
+    fn test_remove_song_keeps_indices_consistent() {
+        let mut player = test_player(&["a", "b", "c"]);
+        player.current_index = 2;
+        player.selected_index = 2;
+        player.remove_song(0);
+
+        assert_eq!(player.songs.len(), 2);
+        assert_eq!(player.current_index, 1);
+        assert_eq!(player.selected_index, 1);
+        assert_eq!(player.filtered_songs, vec![0, 1]);
+    }
 
-        let controls_popup = Paragraph::new(vec![
-            Line::from(""),
-            Line::from(vec![Span::styled("CONTROLS", Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD))]).alignment(Alignment::Center),
-            Line::from(""),
-            Line::from(vec![
-                Span::styled(" ↑/↓ or j/k", Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD)),
-                Span::raw(" - Navigate songs"),
-            ]),
-            Line::from(vec![
-                Span::styled(" Space/↵   ", Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD)),
-                Span::raw(" - Play/Pause"),
-            ]),
-            Line::from(vec![
-                Span::styled(" ←/→ or h/l", Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD)),
-                Span::raw(" - Play prev/next song"),
-            ]),
-            Line::from(vec![
-                Span::styled(" g/G      ", Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD)),
-                Span::raw(" - Jump to first/last"),
-            ]),
-            Line::from(vec![
-                Span::styled(" /         ", Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD)),
-                Span::raw(" - Enter search mode"),
-            ]),
-            Line::from(vec![
-                Span::styled(" n/N       ", Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD)),
-                Span::raw(" - Next/prev search"),
-            ]),
-            Line::from(vec![
-                Span::styled(" ,/.       ", Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD)),
-                Span::raw(" - Seek ±5 seconds"),
-            ]),
-            Line::from(vec![
-                Span::styled(" r         ", Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD)),
-                Span::raw(" - Toggle random mode"),
-            ]),
-            Line::from(vec![
-                Span::styled(" q/Esc     ", Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD)),
-                Span::raw(" - Exit application"),
-            ]),
-            Line::from(vec![
-                Span::styled(" x         ", Style::default().fg(PRIMARY_COLOR).add_modifier(Modifier::BOLD)),
-                Span::raw(" - Close this popup"),
-            ]),
-        ])
-        .alignment(Alignment::Left)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title("Help")
-                .border_style(Style::default().fg(PRIMARY_COLOR)),
-        );
-        f.render_widget(controls_popup, popup_area);
+    #[test]
+    fn test_bpm_sort_and_filter() {
+        let mut player = test_player(&["a", "b", "c", "d"]);
+        player.library.entry(Path::new("a.mp3")).bpm = Some(140.0);
+        player.library.entry(Path::new("b.mp3")).bpm = Some(90.2);
+        player.library.entry(Path::new("d.mp3")).bpm = Some(124.6);
+        player.current_index = 0;
+        player.selected_index = 1;
+
+        player.cycle_sort();
+        let names: Vec<&str> = player.songs.iter().map(|song| song.name.as_str()).collect();
+        assert_eq!(names, ["b", "d", "a", "c"]);
+        assert_eq!(player.songs[player.current_index].name, "a");
+        assert_eq!(player.songs[player.selected_index].name, "b");
+
+        player.cycle_sort();
+        assert_eq!(player.songs[0].name, "a");
+        assert_eq!(player.current_index, 0);
+
+        player.fuzzy_search("bpm:120-140");
+        assert_eq!(player.filtered_songs, vec![0, 3]);
+        player.fuzzy_search("bpm:>100 d");
+        assert_eq!(player.filtered_songs, vec![3]);
     }
-}
 
-fn centered_rect(percent_x: u16, percent_y: u16, r: ratatui::prelude::Rect) -> ratatui::prelude::Rect {
-    let popup_layout = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Percentage((100 - percent_y) / 2),
-            Constraint::Percentage(percent_y),
-            Constraint::Percentage((100 - percent_y) / 2),
-        ])
-        .split(r);
+    #[test]
+    fn test_chapters_are_ticked_on_the_progress_bar() {
+        use ratatui::backend::TestBackend;
+
+        let mut player = test_player(&["book"]);
+        player.is_paused = true;
+        player.song_duration = Some(Duration::from_secs(100));
+        player.chapters = [0, 50]
+            .map(|seconds| chapters::Chapter {
+                start: Duration::from_secs(seconds),
+                title: None,
+            })
+            .to_vec();
+
+        let mut terminal = Terminal::new(TestBackend::new(42, 20)).unwrap();
+        terminal.draw(|f| ui(f, &player)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let ticks: Vec<(u16, u16)> = (0..20)
+            .flat_map(|y| (0..42).map(move |x| (x, y)))
+            .filter(|&(x, y)| buffer[(x, y)].symbol() == "┴")
+            .collect();
+        // Halfway along the bar's 40 columns; nothing for the chapter at the start
+        assert_eq!(ticks.len(), 1, "{ticks:?}");
+        assert_eq!(ticks[0].0, 1 + 19);
+    }
 
-    Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Percentage((100 - percent_x) / 2),
-            Constraint::Percentage(percent_x),
-            Constraint::Percentage((100 - percent_x) / 2),
-        ])
-        .split(popup_layout[1])[1]
-}
+    #[test]
+    fn test_empty_library_tells_how_to_add_songs() {
+        use ratatui::backend::TestBackend;
+
+        let mut player = test_player(&[]);
+        let mut terminal = Terminal::new(TestBackend::new(80, 30)).unwrap();
+        terminal.draw(|f| ui(f, &player)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let screen: String = buffer.content().iter().map(|cell| cell.symbol()).collect();
+        assert!(screen.contains(t("message.no_files").lines().next().unwrap()));
+
+        // Keys that act on the list do nothing rather than fail
+        player.move_selection(1);
+        player.play_or_pause().unwrap();
+        player.next_song().unwrap();
+        player.previous_song().unwrap();
+        assert!(!player.is_playing);
+    }
 
-fn run_player() -> Result<(), Box<dyn std::error::Error>> {
-    let mut player = match Player::new() {
-        Ok(p) => p,
-        Err(e) => {
-            eprintln!("Player initialization failed: {e}");
-            eprintln!("Error details: {e:?}");
-            std::process::exit(1);
+    #[test]
+    fn test_hot_cues_set_jump_and_clear() {
+        let mut player = test_player(&["a"]);
+        player.is_paused = true;
+        player.seek_offset = Duration::from_secs(30);
+
+        // An empty cue is set at the playhead
+        player.trigger_cue(2);
+        assert_eq!(player.library.cue(Path::new("a.mp3"), 2), Some(30.0));
+
+        // A set cue moves the playhead back to it
+        player.seek_offset = Duration::from_secs(95);
+        player.trigger_cue(2);
+        assert_eq!(player.seek_offset, Duration::from_secs(30));
+
+        player.clear_cue(2);
+        assert_eq!(player.library.cue(Path::new("a.mp3"), 2), None);
+    }
+
+    #[test]
+    fn test_weighted_shuffle_skips_just_played_tracks() {
+        let mut player = test_player(&["a", "b", "c"]);
+        player.cycle_shuffle_mode();
+        player.cycle_shuffle_mode();
+        assert_eq!(player.shuffle_mode, shuffle::ShuffleMode::Weighted);
+
+        let now = chrono::Utc::now().timestamp();
+        player.library.record_play(Path::new("b.mp3"), now);
+        player.selected_index = 2;
+        player.cycle_rating();
+        assert_eq!(player.library.get(Path::new("c.mp3")).unwrap().rating, Some(1));
+
+        // a is playing and b was just played, so c is the only real candidate
+        for _ in 0..20 {
+            assert_eq!(player.weighted_random_index(), 2);
         }
-    };
 
-    if player.songs.is_empty() {
-        println!("No MP3 files found in any accessible directory.");
-        println!("MUSIX searched for MP3 files in:");
-        println!("  - ~/Music (user's music directory)");
-        println!("  - ./data (current directory)");
-        println!();
-        println!("To test MUSIX, you can:");
-        println!("Copy MP3 files to ./data directory");
-        return Ok(());
+        player.cycle_shuffle_mode();
+        player.cycle_shuffle_mode();
+        assert_eq!(player.shuffle_mode, shuffle::ShuffleMode::Off);
     }
 
-    match enable_raw_mode() {
-        Ok(_) => {}
-        Err(e) => {
-            eprintln!("Failed to enable raw mode: {e}");
-            return Err(e.into());
-        }
+    #[test]
+    fn test_restore_last_played_from_sync_file() {
+        let dir = TestDir::new("sync-restore");
+        let path = dir.join("sync-restore.json");
+        let mut other_device = sync::SyncFile::open(path.clone());
+        other_device.record("b.mp3".to_string(), Some(42.0), Some(3600.0), 1000);
+        other_device.save(1000).unwrap();
+
+        let mut player = test_player(&["a", "b", "c"]);
+        player.sync = Some(sync::SyncFile::open(path.clone()));
+        player.restore_last_played();
+        assert_eq!(player.selected_index, 1);
+        assert_eq!(player.current_index, 1);
+        assert_eq!(player.seek_offset, Duration::from_secs(42));
     }
 
-    let mut stdout = io::stdout();
-    match execute!(stdout, EnterAlternateScreen) {
-        Ok(_) => {}
-        Err(e) => {
-            eprintln!("Failed to enter alternate screen: {e}");
-            return Err(e.into());
-        }
+    #[test]
+    fn test_restore_from_the_top_past_any_duration() {
+        let dir = TestDir::new("sync-restore-huge");
+        let path = dir.join("sync-restore-huge.json");
+        let mut other_device = sync::SyncFile::open(path.clone());
+        other_device.record("b.mp3".to_string(), Some(1e30), Some(3600.0), 1000);
+        other_device.save(1000).unwrap();
+
+        let mut player = test_player(&["a", "b", "c"]);
+        player.sync = Some(sync::SyncFile::open(path));
+        player.restore_last_played();
+        assert_eq!(player.current_index, 1);
+        assert_eq!(player.seek_offset, Duration::ZERO);
     }
 
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = match Terminal::new(backend) {
-        Ok(t) => t,
-        Err(e) => {
-            eprintln!("Failed to create terminal: {e}");
-            return Err(e.into());
+    #[test]
+    fn test_visible_window_matches_full_list_scrolling() {
+        use ratatui::{backend::TestBackend, buffer::Buffer};
+
+        let rows: Vec<String> = (0..40).map(|index| format!("song {index}")).collect();
+        let render = |items: &[String], selected: usize, height: u16| -> Buffer {
+            let mut terminal = Terminal::new(TestBackend::new(12, height)).unwrap();
+            let list = List::new(items.iter().map(|row| ListItem::new(row.as_str()))).scroll_padding(LIST_SCROLL_PADDING);
+            terminal
+                .draw(|f| f.render_stateful_widget(list, f.area(), &mut ListState::default().with_selected(Some(selected))))
+                .unwrap();
+            terminal.backend().buffer().clone()
+        };
+
+        for height in [1, 2, 5, 12] {
+            for selected in [0, 3, 4, 20, 38, 39] {
+                let window = visible_window(rows.len(), Some(selected), height as usize);
+                assert_eq!(
+                    render(&rows[window.clone()], selected - window.start, height),
+                    render(&rows, selected, height),
+                    "height {height}, selected {selected}"
+                );
+            }
         }
-    };
+        assert_eq!(visible_window(0, None, 10), 0..0);
+        assert_eq!(visible_window(3, Some(2), 10), 0..3);
+    }
 
-    let result = main_loop(&mut terminal, &mut player);
+    #[test]
+    fn test_yank_label_is_artist_and_title() {
+        let mut player = test_player(&["01 intro", "untagged"]);
+        player.tags.insert(
+            PathBuf::from("01 intro.mp3"),
+            tags::TrackTags {
+                title: Some("Voyager".to_string()),
+                artist: Some("Daft Punk".to_string()),
+                ..tags::TrackTags::default()
+            },
+        );
+        assert_eq!(player.track_label(0), "Daft Punk – Voyager");
+        assert_eq!(player.track_label(1), "untagged");
 
-    // Clean shutdown of audio to prevent warning messages
-    if let Some(ref sink) = player.sink {
-        let sink = sink.lock().unwrap();
-        sink.stop();
+        player.tags.get_mut(Path::new("01 intro.mp3")).unwrap().artist = None;
+        assert_eq!(player.track_label(0), "Voyager");
+        player.stream_title = Some("Now on air".to_string());
+        assert_eq!(player.track_label(0), "Now on air");
     }
 
-    disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-    terminal.show_cursor()?;
-
-    // Reset terminal title
-    let _ = execute!(io::stdout(), SetTitle("Terminal"));
+    #[test]
+    fn test_edited_list_reorders_and_drops_songs() {
+        let mut player = test_player(&["a", "b", "c"]);
+        let list = player.list_for_editor();
+        assert!(list.starts_with("# ") && list.ends_with("a.mp3\nb.mp3\nc.mp3\n"));
+
+        player.apply_edited_list("c.mp3\n# b.mp3\na.mp3\nc.mp3\nmissing.mp3\n");
+        let names = |player: &Player| player.songs.iter().map(|song| song.name.as_str()).collect::<Vec<_>>().join(",");
+        assert_eq!(names(&player), "c,a");
+        assert_eq!((player.current_index, player.selected_index), (1, 1), "still on a");
+        assert!(player.toast.as_ref().is_some_and(|(message, _)| message.contains('1')));
+
+        // Sorting keeps the edited order until another sort is picked
+        player.sort_songs();
+        assert_eq!(names(&player), "c,a");
+        player.cycle_sort();
+        player.cycle_sort();
+        assert!(!player.manual_order);
+
+        player.apply_edited_list("# nothing\n");
+        assert_eq!(player.songs.len(), 2, "an empty list is not applied");
+    }
 
-    result
-}
+    #[test]
+    fn test_repeats_of_the_track_that_just_ended_are_avoided() {
+        let mut player = test_player(&["a", "b"]);
+        player.repeat_mode = repeat::RepeatMode::Off;
+        player.current_index = 1;
+        player.selected_index = 1;
+        player.just_finished = Some((player.songs[1].path.clone(), Instant::now()));
+        // Nothing follows the end of the list without looping
+        assert_eq!(player.pick_index(true), None);
+        assert!(player.repeats_just_finished(1) && !player.repeats_just_finished(0));
+        player.next_song().unwrap();
+        assert!(player.playback_start.is_none() && player.just_finished.is_some());
+        assert!(player.is_repeat_press(), "Enter as the track ends");
+
+        player.just_finished = None;
+        assert!(!player.is_repeat_press());
+        player.started_by_key = Some(Instant::now());
+        assert!(player.is_repeat_press(), "a quick second Enter");
+        player.selected_index = 0;
+        assert!(!player.is_repeat_press(), "Enter on another track");
+
+        player.config.playback.avoid_repeats = false;
+        player.just_finished = Some((player.songs[1].path.clone(), Instant::now()));
+        assert!(!player.repeats_just_finished(1));
+    }
 
-fn main_loop(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, player: &mut Player) -> Result<(), Box<dyn std::error::Error>> {
-    loop {
-        terminal.draw(|f| ui(f, player))?;
-
-        if let Ok(true) = event::poll(Duration::from_millis(100)) {
-            if let Ok(Event::Key(key)) = event::read() {
-                // Reset g_pressed state for any key except 'g'
-                if key.code != KeyCode::Char('g') || key.modifiers != KeyModifiers::NONE {
-                    player.g_pressed = false;
-                }
-
-                match key {
-                    KeyEvent {
-                        code: KeyCode::Esc,
-                        modifiers: KeyModifiers::NONE,
-                        ..
-                    } => {
-                        if player.show_controls_popup {
-                            player.show_controls_popup = false;
-                        } else if player.search_mode {
-                            player.exit_search_mode();
-                        } else {
-                            break;
-                        }
-                    }
+    #[test]
+    fn test_early_skips_demote_a_track_from_shuffle() {
+        let mut player = test_player(&["a", "b", "c"]);
+        player.config.shuffle.demote_after = 2;
+        player.config.shuffle.artist_spread = 0;
+        player.song_duration = Some(Duration::from_secs(200));
+        player.seek_offset = Duration::from_secs(20);
+        player.count_skip(events::End::Skipped);
+        player.seek_offset = Duration::from_secs(120);
+        player.count_skip(events::End::Skipped);
+        assert_eq!(player.library.early_skips(Path::new("a.mp3")), 1, "skipped past a quarter");
+        player.seek_offset = Duration::from_secs(5);
+        player.count_skip(events::End::Skipped);
+        assert!(player.is_demoted(Path::new("a.mp3")));
+
+        player.current_index = 1;
+        assert_eq!(player.shuffle_candidates(), vec![2]);
+        player.toggle_demoted_popup();
+        assert_eq!(player.demoted_songs(), vec![0]);
+        player.restore_demoted();
+        assert!(!player.is_demoted(Path::new("a.mp3")) && player.demoted_state.selected().is_none());
+
+        // Played to the end, a track starts its count over
+        player.current_index = 0;
+        player.count_skip(events::End::Skipped);
+        player.count_skip(events::End::Finished);
+        assert_eq!(player.library.early_skips(Path::new("a.mp3")), 0);
+    }
 
-                    KeyEvent {
-                        code: KeyCode::Char('c'),
-                        modifiers: KeyModifiers::CONTROL,
-                        ..
-                    } => break,
-
-                    KeyEvent {
-                        code: KeyCode::Up,
-                        modifiers: KeyModifiers::NONE,
-                        ..
-                    } => {
-                        if player.search_mode {
-                            player.move_selection_in_search(-1);
-                        } else {
-                            player.move_selection(-1);
-                        }
-                    }
+    #[test]
+    fn test_idle_screen_after_idle_minutes() {
+        let mut player = test_player(&["a", "b"]);
+        player.last_input = Instant::now() - Duration::from_secs(600);
+        assert!(!player.tick_idle());
+        player.config.ui.idle_minutes = 15;
+        assert!(!player.tick_idle());
+        player.config.ui.idle_minutes = 10;
+        assert!(player.tick_idle());
+        assert!(player.idle_since.is_some());
+        // Already idle: nothing new to draw
+        assert!(!player.tick_idle());
+    }
 
-                    KeyEvent {
-                        code: KeyCode::Char('k'),
-                        modifiers: KeyModifiers::NONE,
-                        ..
-                    } => {
-                        if player.search_mode {
-                            player.search_query.push('k');
-                            let query = player.search_query.clone();
-                            player.fuzzy_search(&query);
-                        } else {
-                            player.move_selection(-1);
-                        }
-                    }
+    #[test]
+    fn test_snippet_waits_for_the_selection_to_rest() {
+        let mut player = test_player(&["a", "b", "c"]);
+        player.selected_index = 1;
+        player.tick_snippet();
+        assert!(player.resting_since.is_none());
+
+        player.config.preview.on_select = true;
+        player.tick_snippet();
+        assert_eq!(player.rested_on, 1);
+        assert!(player.resting_since.is_some());
+        // Not for a second yet
+        player.tick_snippet();
+        assert!(player.resting_since.is_some());
+
+        // Tried once, though there is no output to play it on here
+        player.resting_since = Some(Instant::now() - SNIPPET_DWELL);
+        player.tick_snippet();
+        assert!(player.resting_since.is_none());
+        assert!(player.preview.is_none());
+        assert!(player.toast.is_none());
+    }
 
-                    KeyEvent {
-                        code: KeyCode::Down,
-                        modifiers: KeyModifiers::NONE,
-                        ..
-                    } => {
-                        if player.search_mode {
-                            player.move_selection_in_search(1);
-                        } else {
-                            player.move_selection(1);
-                        }
-                    }
+    #[test]
+    fn test_volume_steps_stay_in_range() {
+        let mut player = test_player(&["a"]);
+        let (sink, _output) = Sink::new_idle();
+        player.sink = Some(Arc::new(Mutex::new(sink)));
+        player.change_volume(VOLUME_STEP);
+        assert_eq!(player.volume, 1.0);
+        for _ in 0..7 {
+            player.change_volume(-VOLUME_STEP);
+        }
+        assert!((player.volume - 0.65).abs() < 1e-6);
+        assert_eq!(player.sink.as_ref().unwrap().lock().unwrap().volume(), player.volume);
+        for _ in 0..30 {
+            player.change_volume(-VOLUME_STEP);
+        }
+        assert_eq!(player.volume, 0.0);
+        assert_eq!(player.session(false).volume, 0.0);
+    }
 
-                    KeyEvent {
-                        code: KeyCode::Char('j'),
-                        modifiers: KeyModifiers::NONE,
-                        ..
-                    } => {
-                        if player.search_mode {
-                            player.search_query.push('j');
-                            let query = player.search_query.clone();
-                            player.fuzzy_search(&query);
-                        } else {
-                            player.move_selection(1);
-                        }
-                    }
+    #[test]
+    fn test_mute_keeps_the_volume() {
+        let mut player = test_player(&["a"]);
+        let (sink, _output) = Sink::new_idle();
+        player.sink = Some(Arc::new(Mutex::new(sink)));
+        let sink_volume = |player: &Player| player.sink.as_ref().unwrap().lock().unwrap().volume();
+        player.change_volume(-VOLUME_STEP * 4.0);
+
+        player.toggle_mute();
+        assert_eq!(sink_volume(&player), 0.0);
+        assert!((player.volume - 0.8).abs() < 1e-6);
+        player.toggle_mute();
+        assert_eq!(sink_volume(&player), player.volume);
+
+        // Turning it up while muted brings the sound back
+        player.toggle_mute();
+        player.change_volume(VOLUME_STEP);
+        assert!(!player.muted);
+        assert!((sink_volume(&player) - 0.85).abs() < 1e-6);
+    }
 
-                    KeyEvent {
-                        code: KeyCode::Enter,
-                        modifiers: KeyModifiers::NONE,
-                        ..
-                    } => {
-                        let _ = player.play_or_pause();
-                        if player.search_mode {
-                            player.exit_search_mode();
-                        }
-                    }
+    #[test]
+    fn test_columns_and_column_sort() {
+        use ratatui::backend::TestBackend;
+
+        let mut player = test_player(&["01 intro", "02 song", "untagged"]);
+        player.config.ui = toml::from_str(r#"columns = ["number", "title", { column = "artist", width = 8 }, "duration"]"#).unwrap();
+        let tagged = |title: &str, artist: &str, seconds| tags::TrackTags {
+            title: Some(title.to_string()),
+            artist: Some(artist.to_string()),
+            duration: Some(Duration::from_secs(seconds)),
+            ..tags::TrackTags::default()
+        };
+        player.tags.insert(PathBuf::from("01 intro.mp3"), tagged("Intro", "Zed", 65));
+        player.tags.insert(PathBuf::from("02 song.mp3"), tagged("Song", "Abba", 200));
+
+        let mut terminal = Terminal::new(TestBackend::new(50, 20)).unwrap();
+        terminal.draw(|f| ui(f, &player)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let row = |y: u16| (0..50).map(|x| buffer[(x, y)].symbol()).collect::<String>();
+        assert!(row(4).contains("Title") && row(4).contains("Artist") && row(4).contains("Time"));
+        assert!(row(5).contains("1. Intro") && row(5).contains("Zed") && row(5).contains("01:05"));
+        assert!(row(7).contains("3. untagged"));
+
+        // Name -> BPM -> title -> artist -> duration -> name; untagged files go last
+        let order = |player: &Player| player.songs.iter().map(|song| song.name.as_str()).collect::<Vec<_>>().join(",");
+        player.cycle_sort();
+        player.cycle_sort();
+        assert_eq!(player.sort_column, Some(columns::Column::Title));
+        player.cycle_sort();
+        assert_eq!(order(&player), "02 song,01 intro,untagged");
+        player.cycle_sort();
+        assert_eq!(
+            (player.sort_column, order(&player).as_str()),
+            (Some(columns::Column::Duration), "01 intro,02 song,untagged")
+        );
+        player.cycle_sort();
+        assert_eq!(player.sort_column, None);
+        assert!(!player.sort_by_bpm);
+    }
 
-                    KeyEvent {
-                        code: KeyCode::Char(' '),
-                        modifiers: KeyModifiers::NONE,
-                        ..
-                    } => {
-                        if player.search_mode {
-                            player.search_query.push(' ');
-                            let query = player.search_query.clone();
-                            player.fuzzy_search(&query);
-                        } else {
-                            let _ = player.play_or_pause();
-                        }
-                    }
+    #[test]
+    fn test_durations_are_cached_and_right_aligned() {
+        use ratatui::backend::TestBackend;
+
+        let mut player = test_player(&["a", "b"]);
+        let (sender, reader) = std::sync::mpsc::channel();
+        let mut tag_reader = tags::TagReader::start(Vec::new());
+        tag_reader.results = reader;
+        player.tag_reader = Some(tag_reader);
+        let tags = tags::TrackTags {
+            duration: Some(Duration::from_secs(200)),
+            ..tags::TrackTags::default()
+        };
+        sender.send((PathBuf::from("a.mp3"), tags)).unwrap();
+        drop(sender);
+        assert!(player.collect_tags());
+        assert!(player.tag_reader.is_none() && !player.durations_changed);
+        assert_eq!(player.library.duration(Path::new("a.mp3")), Some(Duration::from_secs(200)));
+
+        // Cached lengths show without tags read this run
+        player.tags.clear();
+        let mut terminal = Terminal::new(TestBackend::new(30, 20)).unwrap();
+        terminal.draw(|f| ui(f, &player)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let row = |y: u16| (0..30).map(|x| buffer[(x, y)].symbol()).collect::<String>();
+        assert!(row(4).ends_with("1. a                 03:20│"), "{:?}", row(4));
+        assert!(row(5).trim_end_matches('│').trim_end().ends_with("2. b"));
+    }
 
-                    KeyEvent {
-                        code: KeyCode::Left,
-                        modifiers: KeyModifiers::NONE,
-                        ..
-                    } => {
-                        if !player.search_mode {
-                            player.previous_song()?;
-                        }
-                    }
+    #[test]
+    fn test_chromeless_layout() {
+        use ratatui::backend::TestBackend;
+
+        let mut player = test_player(&["a", "b", "c"]);
+        player.config.ui = toml::from_str("border = \"none\"\nshow_title = false\nshow_status = false\n").unwrap();
+        let mut terminal = Terminal::new(TestBackend::new(30, 12)).unwrap();
+        terminal.draw(|f| ui(f, &player)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let row = |y: u16| (0..30).map(|x| buffer[(x, y)].symbol()).collect::<String>();
+
+        // The list starts at the very top and the progress bar takes the last row
+        assert!(row(0).starts_with(t("panel.songs")));
+        assert!(row(1).contains("1. a"));
+        assert!(row(11).contains("00:00"));
+        assert!((0..12).all(|y| !row(y).contains('─')));
+
+        player.config.ui = toml::from_str("border = \"rounded\"\ntitle_alignment = \"right\"\n").unwrap();
+        terminal.draw(|f| ui(f, &player)).unwrap();
+        let buffer = terminal.backend().buffer();
+        assert_eq!(buffer[(0, 0)].symbol(), "╭");
+        let songs_title = (0..30).map(|x| buffer[(x, 3)].symbol()).collect::<String>();
+        let summary = t_args("panel.summary_count", &[("count", &3)]);
+        assert!(songs_title.ends_with(&format!("{}{summary}╮", t("panel.songs"))));
+    }
 
-                    KeyEvent {
-                        code: KeyCode::Right,
-                        modifiers: KeyModifiers::NONE,
-                        ..
-                    } => {
-                        if !player.search_mode {
-                            player.next_song()?;
-                        }
-                    }
+    #[test]
+    fn test_next_album_track_is_queued_for_a_gapless_start() {
+        let dir = TestDir::new("gapless");
+        let mut player = test_player(&["a", "b"]);
+        for (number, song) in player.songs.iter_mut().enumerate() {
+            song.path = dir.join(format!("{}.wav", song.name));
+            let mut wav = recording::WavWriter::new(File::create(&song.path).unwrap(), 1, 8000).unwrap();
+            wav.write_samples(&[0; 8000]).unwrap();
+            wav.finish().unwrap();
+            let tags = tags::TrackTags {
+                album: Some("Live".to_string()),
+                track: Some(number as u32 + 1),
+                ..tags::TrackTags::default()
+            };
+            player.tags.insert(song.path.clone(), tags);
+        }
+        let (sink, _output) = Sink::new_idle();
+        sink.append(player.open_source(&player.songs[0].path).unwrap());
+        player.sink = Some(Arc::new(Mutex::new(sink)));
+        player.is_playing = true;
+        player.playback_start = Some(Instant::now());
+        player.song_duration = Some(Duration::from_secs(1));
+        player.up_next = Some((player.songs[1].path.clone(), None));
+
+        player.tick_transition().unwrap();
+        assert_eq!(player.gapless_next, Some((player.songs[1].path.clone(), Duration::ZERO)));
+        assert_eq!(player.sink.as_ref().unwrap().lock().unwrap().len(), 2);
+
+        // The handover starts the queued track without touching the sink
+        player.track_finished().unwrap();
+        assert_eq!((player.current_index, player.is_playing), (1, true));
+        assert_eq!(player.gapless_next, None);
+        assert_eq!(player.sink.as_ref().unwrap().lock().unwrap().len(), 2);
+    }
 
-                    KeyEvent {
-                        code: KeyCode::Char('h'),
-                        modifiers: KeyModifiers::NONE,
-                        ..
-                    } => {
-                        if player.search_mode {
-                            player.search_query.push('h');
-                            let query = player.search_query.clone();
-                            player.fuzzy_search(&query);
-                        } else {
-                            player.previous_song()?;
-                        }
-                    }
+    #[test]
+    fn test_opened_tracks_are_converted_to_the_output_format() {
+        let dir = TestDir::new("output-format");
+        let mut player = test_player(&["a"]);
+        player.songs[0].path = dir.join("a.wav");
+        let mut wav = recording::WavWriter::new(File::create(&player.songs[0].path).unwrap(), 1, 8000).unwrap();
+        wav.write_samples(&[0; 8000]).unwrap();
+        wav.finish().unwrap();
+
+        // 8 kHz mono comes out at the device's rate, so queued tracks never change rate
+        player.output_format = Some(output::OutputFormat {
+            channels: 2,
+            sample_rate: 48000,
+        });
+        let source = player.open_source(&player.songs[0].path).unwrap();
+        assert_eq!((source.channels(), source.sample_rate()), (2, 48000));
+    }
 
-                    KeyEvent {
-                        code: KeyCode::Char('l'),
-                        modifiers: KeyModifiers::NONE,
-                        ..
-                    } => {
-                        if player.search_mode {
-                            player.search_query.push('l');
-                            let query = player.search_query.clone();
-                            player.fuzzy_search(&query);
-                        } else {
-                            player.next_song()?;
-                        }
-                    }
+    #[test]
+    fn test_scrub_seeks_only_once_the_keys_are_let_go() {
+        let mut player = test_player(&["mix"]);
+        player.is_paused = true;
+        player.song_duration = Some(Duration::from_secs(3600));
+        player.seek_offset = Duration::from_secs(60);
+
+        // An hour-long mix moves 36 seconds a press
+        player.scrub(true);
+        player.scrub(true);
+        player.scrub(false);
+        assert_eq!(player.scrub.map(|(target, _)| target), Some(Duration::from_secs(96)));
+        assert!(!player.tick_scrub());
+        assert_eq!(player.seek_offset, Duration::from_secs(60));
+
+        player.scrub = Some((Duration::from_secs(96), Instant::now() - SCRUB_SETTLE));
+        assert!(player.tick_scrub());
+        assert_eq!((player.seek_offset, player.scrub), (Duration::from_secs(96), None));
+
+        // Clamped to the track
+        player.seek_offset = Duration::from_secs(3590);
+        player.scrub(true);
+        player.apply_scrub();
+        assert_eq!(player.seek_offset, Duration::from_secs(3600));
+    }
 
-                    KeyEvent {
-                        code: KeyCode::Char('n'),
-                        modifiers: KeyModifiers::NONE,
-                        ..
-                    } => {
-                        if player.search_mode {
-                            player.search_query.push('n');
-                            let query = player.search_query.clone();
-                            player.fuzzy_search(&query);
-                        }
-                        // In normal mode, 'n' has no special meaning
-                    }
+    #[test]
+    fn test_buffer_gauge_fills_up_to_half_a_minute() {
+        let gauge = |seconds| buffer_gauge(icons::IconStyle::Ascii, Duration::from_secs(seconds));
+        assert_eq!(gauge(0), "-----");
+        assert_eq!(gauge(1), "#----");
+        assert_eq!(gauge(15), "###--");
+        assert_eq!(gauge(300), "#####");
+    }
 
-                    KeyEvent {
-                        code: KeyCode::Char('N'),
-                        modifiers: KeyModifiers::SHIFT,
-                        ..
-                    } => {
-                        if player.search_mode {
-                            player.search_query.push('N');
-                            let query = player.search_query.clone();
-                            player.fuzzy_search(&query);
-                        }
-                        // In normal mode, 'N' has no special meaning, ignore
-                    }
+    #[test]
+    fn test_fade_out_ends_silent() {
+        let (sink, _output) = Sink::new_idle();
+        sink.set_volume(0.8);
+        let started = Instant::now();
+        fade_out(&sink, Duration::from_millis(50));
+        assert_eq!(sink.volume(), 0.0);
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
 
-                    KeyEvent {
-                        code: KeyCode::Char('g'),
-                        modifiers: KeyModifiers::NONE,
-                        ..
-                    } => {
-                        if player.search_mode {
-                            player.search_query.push('g');
-                            let query = player.search_query.clone();
-                            player.fuzzy_search(&query);
-                        } else {
-                            player.jump_to_first();
-                            player.g_pressed = false;
-                        }
-                    }
+    #[test]
+    fn test_session_snapshot_restores_track_and_modes() {
+        let mut crashed = test_player(&["a", "b", "c"]);
+        crashed.current_index = 2;
+        crashed.selected_index = 0;
+        crashed.seek_offset = Duration::from_secs(75);
+        crashed.shuffle_mode = shuffle::ShuffleMode::Rediscover;
+        crashed.repeat_mode = repeat::RepeatMode::One;
+        let snapshot = crashed.session(false);
+        assert_eq!(snapshot.track.as_deref(), Some("c.mp3"));
+
+        let mut player = test_player(&["a", "b", "c"]);
+        player.restore_session(&snapshot);
+        assert_eq!((player.current_index, player.selected_index), (2, 0));
+        assert_eq!(player.seek_offset, Duration::from_secs(75));
+        assert_eq!(player.shuffle_mode, shuffle::ShuffleMode::Rediscover);
+        assert_eq!(player.repeat_mode, repeat::RepeatMode::One);
+
+        // A track that is gone keeps the player at the start
+        let mut smaller = test_player(&["a", "b"]);
+        smaller.restore_session(&snapshot);
+        assert_eq!((smaller.current_index, smaller.seek_offset), (0, Duration::ZERO));
+    }
 
-                    KeyEvent {
-                        code: KeyCode::Char('G'),
-                        modifiers: KeyModifiers::SHIFT,
-                        ..
-                    } => {
-                        if player.search_mode {
-                            player.search_query.push('G');
-                            let query = player.search_query.clone();
-                            player.fuzzy_search(&query);
-                        } else {
-                            player.jump_to_last();
-                            player.g_pressed = false; // Reset g_pressed state
-                        }
-                    }
+    #[test]
+    fn test_quit_snapshot_resumes_mid_track() {
+        let mut quit = test_player(&["mix", "short"]);
+        quit.seek_offset = Duration::from_secs(3725);
+        quit.song_duration = Some(Duration::from_secs(7200));
+        let snapshot = quit.session(true);
+        assert!(snapshot.clean_exit);
+
+        let mut player = test_player(&["mix", "short"]);
+        player.restore_session(&snapshot);
+        assert_eq!(player.seek_offset, Duration::from_secs(3725));
+
+        // A track that played to its end starts over next time
+        quit.seek_offset = Duration::from_secs(7200);
+        assert_eq!(quit.session(true).position, 0.0);
+    }
 
-                    KeyEvent {
-                        code: KeyCode::Char('q'),
-                        modifiers: KeyModifiers::NONE,
-                        ..
-                    } => {
-                        if player.search_mode {
-                            player.search_query.push('q');
-                            let query = player.search_query.clone();
-                            player.fuzzy_search(&query);
-                        } else {
-                            break; // Quit the application
-                        }
-                    }
+    #[test]
+    fn test_row_color_rules_use_library_and_genres() {
+        let mut player = test_player(&["a", "b"]);
+        player.config =
+            toml::from_str("[[row_colors]]\ncolor = \"yellow\"\nmin_rating = 4\n\n[[row_colors]]\ncolor = \"blue\"\ngenre = \"Ambient\"\n").unwrap();
+        assert_eq!(player.row_color(&player.songs[0]), None);
+
+        player.library.entry(&PathBuf::from("a.mp3")).rating = Some(5);
+        let ambient = tags::TrackTags {
+            genre: Some("ambient".to_string()),
+            ..tags::TrackTags::default()
+        };
+        player.tags.insert(PathBuf::from("b.mp3"), ambient);
+        assert_eq!(player.row_color(&player.songs[0]), Some(Color::Yellow));
+        assert_eq!(player.row_color(&player.songs[1]), Some(Color::Blue));
+    }
 
-                    KeyEvent {
-                        code: KeyCode::Char('r'),
-                        modifiers: KeyModifiers::NONE,
-                        ..
-                    } => {
-                        if player.search_mode {
-                            player.search_query.push('r');
-                            let query = player.search_query.clone();
-                            player.fuzzy_search(&query);
-                        } else {
-                            player.random_mode = !player.random_mode;
-                        }
-                    }
+    #[test]
+    fn test_quitting_while_playing_needs_confirming() {
+        let mut player = test_player(&["a"]);
+        assert!(player.confirm_quit(), "nothing playing");
+
+        player.is_playing = true;
+        assert!(!player.confirm_quit());
+        assert!(player.confirm_quit(), "second press");
+        player.quit_pressed = Some(Instant::now() - QUIT_PRESS_WINDOW * 2);
+        assert!(!player.confirm_quit(), "too slow for a double press");
+
+        player.config.ui.quit_confirm = config::QuitConfirm::Prompt;
+        assert!(!player.confirm_quit() && player.show_quit_prompt);
+        player.config.ui.quit_confirm = config::QuitConfirm::Off;
+        assert!(player.confirm_quit());
+    }
 
-                    KeyEvent {
-                        code: KeyCode::Char('x'),
-                        modifiers: KeyModifiers::NONE,
-                        ..
-                    } => {
-                        if player.search_mode {
-                            player.search_query.push('x');
-                            let query = player.search_query.clone();
-                            player.fuzzy_search(&query);
-                        } else {
-                            player.show_controls_popup = !player.show_controls_popup;
-                        }
-                    }
+    #[test]
+    fn test_kiosk_lock_blocks_quit_and_library_changes() {
+        let quit = KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE);
+        let rate = KeyEvent::new(KeyCode::Char('*'), KeyModifiers::NONE);
+        let next = KeyEvent::new(KeyCode::Char('l'), KeyModifiers::NONE);
+
+        let mut player = test_player(&["a"]);
+        player.toggle_lock();
+        assert!(!player.locked, "locking needs a PIN");
+
+        player.config.kiosk.pin = Some("1234".to_string());
+        player.toggle_lock();
+        assert!(player.locked);
+        assert!(player.kiosk_blocks(&quit) && player.kiosk_blocks(&rate));
+        assert!(!player.kiosk_blocks(&next));
+        for settings in ['X', 'E'] {
+            assert!(
+                player.kiosk_blocks(&KeyEvent::new(KeyCode::Char(settings), KeyModifiers::SHIFT)),
+                "no config writes"
+            );
+        }
+        player.search_mode = true;
+        assert!(!player.kiosk_blocks(&quit), "q is just a letter in a search");
+        player.search_mode = false;
+
+        player.toggle_lock();
+        player.pin_input = Some("0000".to_string());
+        player.submit_pin();
+        assert!(player.locked);
+
+        player.toggle_lock();
+        player.pin_input = Some("1234".to_string());
+        player.submit_pin();
+        assert!(!player.locked && !player.kiosk_blocks(&quit));
+    }
 
-                    KeyEvent {
-                        code: KeyCode::Char('<') | KeyCode::Char(','),
-                        modifiers: KeyModifiers::NONE,
-                        ..
-                    } => {
-                        if player.search_mode {
-                            let c = if key.code == KeyCode::Char('<') { '<' } else { ',' };
-                            player.search_query.push(c);
-                            let query = player.search_query.clone();
-                            player.fuzzy_search(&query);
-                        } else {
-                            player.seek(-5); // Seek backward 5 seconds
-                        }
-                    }
+    #[test]
+    fn test_kiosk_lock_blocks_the_file_manager() {
+        let mut player = test_player(&["a"]);
+        player.config.kiosk.pin = Some("1234".to_string());
+        player.toggle_lock();
+        assert!(player.kiosk_blocks(&KeyEvent::new(KeyCode::Char('o'), KeyModifiers::CONTROL)));
+    }
 
-                    KeyEvent {
-                        code: KeyCode::Char('>') | KeyCode::Char('.'),
-                        modifiers: KeyModifiers::NONE,
-                        ..
-                    } => {
-                        if player.search_mode {
-                            let c = if key.code == KeyCode::Char('>') { '>' } else { '.' };
-                            player.search_query.push(c);
-                            let query = player.search_query.clone();
-                            player.fuzzy_search(&query);
-                        } else {
-                            player.seek(5); // Seek forward 5 seconds
-                        }
-                    }
+    #[test]
+    fn test_shuffle_spreads_artists() {
+        let mut player = test_player(&["a", "b", "c", "d"]);
+        for (name, artist) in [("a", "Various"), ("b", "various"), ("c", "Various"), ("d", "Solo")] {
+            let tags = tags::TrackTags {
+                artist: Some(artist.to_string()),
+                ..tags::TrackTags::default()
+            };
+            player.tags.insert(PathBuf::from(format!("{name}.mp3")), tags);
+        }
+        player.play_song(0).unwrap();
+        assert_eq!(player.shuffle_candidates(), vec![3]);
+        assert_eq!(player.random_index(), 3);
+
+        // With d just played too, only Various is left within a window of one
+        player.play_song(3).unwrap();
+        assert_eq!(player.shuffle_candidates(), vec![0, 1, 2]);
+        player.config.shuffle.artist_spread = 2;
+        player.play_song(0).unwrap();
+        assert_eq!(player.recent_artists.len(), 2);
+        // Every other track is by one of the last two artists, so rather than stopping none is held back
+        assert_eq!(player.shuffle_candidates(), vec![1, 2, 3]);
+    }
 
-                    KeyEvent {
-                        code: KeyCode::Char('/'),
-                        modifiers: KeyModifiers::NONE,
-                        ..
-                    } => {
-                        if !player.search_mode {
-                            player.enter_search_mode();
-                        } else {
-                            player.search_query.push('/');
-                            let query = player.search_query.clone();
-                            player.fuzzy_search(&query);
-                        }
-                    }
+    #[test]
+    fn test_rediscover_queues_least_recently_played() {
+        let mut player = test_player(&["a", "b", "c", "d"]);
+        let now = chrono::Utc::now().timestamp();
+        player.library.record_play(Path::new("b.mp3"), now - 3 * 86400);
+        player.library.record_play(Path::new("c.mp3"), now - 30 * 86400);
+        player.library.record_play(Path::new("d.mp3"), now - 86400);
+
+        // a is playing; c has gone unheard the longest
+        assert_eq!(player.least_recent_index(), 2);
+
+        // Never-played tracks come before anything in the history
+        player.current_index = 2;
+        assert_eq!(player.least_recent_index(), 0);
+    }
 
-                    KeyEvent {
-                        code: KeyCode::Backspace,
-                        modifiers: KeyModifiers::NONE,
-                        ..
-                    } => {
-                        if player.search_mode {
-                            player.search_query.pop();
-                            let query = player.search_query.clone();
-                            player.fuzzy_search(&query);
-                        }
-                    }
+    #[test]
+    fn test_play_path_finds_or_adds_the_track() {
+        let mut player = test_player(&["a", "b"]);
+        player.play_path(Path::new("b.mp3")).unwrap();
+        assert_eq!(player.current_index, 1);
+
+        // Files outside the music folders join the list
+        let dir = TestDir::new("remote");
+        let file = dir.join("remote.mp3");
+        fs::write(&file, b"").unwrap();
+        player.play_path(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+        assert_eq!(player.songs.len(), 3);
+        assert_eq!(player.current_index, 2);
+        assert_eq!(player.filtered_songs, vec![0, 1, 2]);
+
+        player.play_path(Path::new("missing.mp3")).unwrap();
+        assert_eq!(player.songs.len(), 3);
+        assert_eq!(player.current_index, 2);
+    }
 
-                    KeyEvent {
-                        code: KeyCode::Char(c),
-                        modifiers: KeyModifiers::NONE,
-                        ..
-                    } => {
-                        if player.search_mode {
-                            player.search_query.push(c);
-                            let query = player.search_query.clone();
-                            player.fuzzy_search(&query);
-                        }
-                    }
+    #[test]
+    fn test_preview_refuses_stations_and_the_playing_song() {
+        let toast = |player: &Player| player.toast.as_ref().map(|(message, _)| message.clone());
+        let mut player = test_player(&["a", "b"]);
+        player
+            .songs
+            .push(Song::station("Radio".to_string(), PathBuf::new(), "http://radio.invalid/live".to_string()));
+
+        player.selected_index = 2;
+        player.toggle_preview();
+        assert!(player.preview.is_none());
+        assert_eq!(toast(&player).as_deref(), Some(t("message.preview_stream")));
+
+        player.play_song(0).unwrap();
+        player.is_playing = true;
+        player.toggle_preview();
+        assert_eq!(toast(&player).as_deref(), Some(t("message.preview_playing")));
+
+        // Nothing to hand off
+        player.take_preview().unwrap();
+        assert_eq!(player.current_index, 0);
+    }
 
-                    _ => {}
-                }
+    #[test]
+    fn test_stations_are_found_again_once_connected() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/live", listener.local_addr().unwrap());
+        drop(listener);
+        let connect = |player: &mut Player, url: &str| {
+            player.stream_opening = Some(StreamOpening {
+                path: PathBuf::new(),
+                url: url.to_string(),
+                opening: stream::Opening::start(url),
+                recording: None,
+            });
+        };
+        let wait = |player: &mut Player| {
+            let started = Instant::now();
+            while !player.tick_stream_opening() {
+                assert!(started.elapsed() < Duration::from_secs(5));
+                std::thread::sleep(Duration::from_millis(1));
             }
-        }
+        };
 
-        // Check if current song finished and auto-play next
-        if player.is_playing {
-            if let Some(ref sink) = player.sink {
-                let sink = sink.lock().unwrap();
-                if sink.empty() {
-                    drop(sink);
-                    player.is_playing = false;
-                    player.is_paused = false;
-                    player.playback_start = None;
-                    player.seek_offset = Duration::from_secs(0);
-                    player.next_song()?;
-                }
-            }
-        }
+        let mut player = test_player(&["b"]);
+        player.songs.insert(0, Song::station("a".to_string(), PathBuf::new(), url.clone()));
+        connect(&mut player, &url);
+        // Sorted behind the track while it connected
+        player.songs.swap(0, 1);
+        wait(&mut player);
+        assert!(player.songs[1].error.is_some());
+        assert!(player.songs[0].error.is_none());
+
+        // Removed while it connected
+        connect(&mut player, &url);
+        player.retain_songs(|_, song| song.stream_url.is_none());
+        wait(&mut player);
+        assert_eq!(player.songs.len(), 1);
+        assert!(player.songs[0].error.is_none());
     }
 
-    Ok(())
-}
-
-fn main() {
-    if let Err(e) = run_player() {
-        eprintln!("Error: {e}");
-        std::process::exit(1);
+    #[test]
+    fn test_album_order_uses_folders_and_track_numbers() {
+        let song = |path: &str| Song::new(Path::new(path).file_stem().unwrap().to_string_lossy().into_owned(), PathBuf::from(path));
+        let mut player = Player::from_songs(vec![
+            song("/music/Album 10/1 Intro.mp3"),
+            song("/music/Album 2/Zebra.mp3"),
+            song("/music/Album 2/10 Finale.mp3"),
+            song("/music/Album 2/9 Middle.mp3"),
+            song("/music/Album 2/Apple.mp3"),
+        ]);
+        let names = |player: &Player| player.songs.iter().map(|song| song.name.as_str()).collect::<Vec<_>>().join(",");
+        player.sort_songs();
+        assert_eq!(names(&player), "9 Middle,10 Finale,Apple,Zebra,1 Intro");
+
+        // Track tags win over file names within the album
+        for (path, track) in [("/music/Album 2/Zebra.mp3", 1), ("/music/Album 2/Apple.mp3", 2)] {
+            let tags = tags::TrackTags {
+                track: Some(track),
+                ..tags::TrackTags::default()
+            };
+            player.tags.insert(PathBuf::from(path), tags);
+        }
+        player.sort_songs();
+        assert_eq!(names(&player), "Zebra,Apple,9 Middle,10 Finale,1 Intro");
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_inbox_arrivals_are_flagged_and_play_next() {
+        let mut player = test_player(&["a", "b", "c"]);
+        let (sender, arrivals) = std::sync::mpsc::channel();
+        player.inbox = Some(inbox::Inbox { arrivals });
+        player.play_song(0).unwrap();
+        assert!(!player.collect_inbox());
+
+        let new = PathBuf::from("inbox/new.mp3");
+        sender.send(new.clone()).unwrap();
+        assert!(player.collect_inbox());
+        let index = player.index_of(&new, &None).unwrap();
+        assert!(player.new_tracks.contains(&new));
+        // Ahead of the next song in the list
+        assert_eq!(player.pick_index(true), Some(index));
+
+        player.play_song(index).unwrap();
+        assert!(player.new_tracks.is_empty() && player.inbox_queue.is_empty());
+        assert_ne!(player.pick_index(true), Some(index));
+    }
 
     #[test]
-    fn test_format_duration() {
-        assert_eq!(Player::format_duration(Duration::from_secs(0)), "00:00");
-        assert_eq!(Player::format_duration(Duration::from_secs(30)), "00:30");
-        assert_eq!(Player::format_duration(Duration::from_secs(60)), "01:00");
-        assert_eq!(Player::format_duration(Duration::from_secs(125)), "02:05");
+    fn test_audio_unavailable_banner() {
+        use ratatui::backend::TestBackend;
+
+        let mut player = test_player(&["a"]);
+        player.audio_error = Some("no device".to_string());
+        let mut terminal = Terminal::new(TestBackend::new(60, 20)).unwrap();
+        terminal.draw(|f| ui(f, &player)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let row = |y: u16| (0..60).map(|x| buffer[(x, y)].symbol()).collect::<String>();
+        assert!(row(3).contains("no device"), "{}", row(3));
+        // The list moves down a row to make room
+        assert!(row(5).contains("1. a"), "{}", row(5));
+
+        player.play_song(0).unwrap();
+        assert!(!player.is_playing);
+        assert!(player.toast.is_some());
     }
 }
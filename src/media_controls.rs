@@ -0,0 +1,239 @@
+use std::{
+    sync::mpsc::{self, Receiver, Sender},
+    time::Duration,
+};
+
+use crate::nowplaying::NowPlaying;
+
+/// A button pressed in the system media overlay.
+// Only the Windows backend creates these so far
+#[cfg_attr(not(windows), allow(dead_code))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    Play,
+    Pause,
+    Toggle,
+    Next,
+    Previous,
+    Stop,
+    // Seconds, like the arrow keys
+    SeekBy(i32),
+    SeekTo(Duration),
+}
+
+/// Publishes the current track to the OS media controls and relays their buttons back.
+/// Backed by the System Media Transport Controls on Windows; other platforms have no backend yet.
+pub struct MediaControls {
+    updates: Sender<NowPlaying>,
+    commands: Receiver<Command>,
+    last: Option<NowPlaying>,
+}
+
+impl MediaControls {
+    /// Connect to the system media controls; None where they are unsupported or unavailable.
+    pub fn start() -> Option<Self> {
+        let (updates, update_receiver) = mpsc::channel();
+        let (command_sender, commands) = mpsc::channel();
+        platform::spawn(update_receiver, command_sender).then_some(MediaControls { updates, commands, last: None })
+    }
+
+    /// Pass on the current state if it changed since the last update.
+    pub fn update(&mut self, now_playing: NowPlaying) {
+        if self.last.as_ref() != Some(&now_playing) {
+            let _ = self.updates.send(now_playing.clone());
+            self.last = Some(now_playing);
+        }
+    }
+
+    pub fn poll(&self) -> Option<Command> {
+        self.commands.try_recv().ok()
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use std::sync::mpsc::{Receiver, Sender};
+
+    use super::Command;
+    use crate::nowplaying::NowPlaying;
+
+    pub fn spawn(_updates: Receiver<NowPlaying>, _commands: Sender<Command>) -> bool {
+        false
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::{
+        ffi::c_void,
+        sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        thread,
+        time::Duration,
+    };
+
+    use souvlaki::{MediaControlEvent, MediaControls, MediaMetadata, MediaPlayback, MediaPosition, PlatformConfig, SeekDirection};
+    use windows::{
+        Win32::{
+            Foundation::{HWND, LPARAM, LRESULT, WPARAM},
+            System::LibraryLoader::GetModuleHandleW,
+            UI::WindowsAndMessaging::{
+                CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, MSG, PM_REMOVE, PeekMessageW, RegisterClassExW, TranslateMessage,
+                WINDOW_EX_STYLE, WINDOW_STYLE, WNDCLASSEXW,
+            },
+        },
+        core::PCWSTR,
+        w,
+    };
+
+    use super::Command;
+    use crate::nowplaying::{NowPlaying, Status};
+
+    // How often the window's message queue is drained while waiting for updates
+    const PUMP_INTERVAL: Duration = Duration::from_millis(50);
+    // Seek step for the overlay's skip-ahead/back buttons
+    const SEEK_STEP: i32 = 5;
+
+    extern "system" fn window_proc(hwnd: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        unsafe { DefWindowProcW(hwnd, message, wparam, lparam) }
+    }
+
+    // SMTC belongs to a window and a console program has none of its own, so an invisible one is created
+    struct HiddenWindow(HWND);
+
+    impl HiddenWindow {
+        fn create() -> Option<Self> {
+            unsafe {
+                let instance = GetModuleHandleW(PCWSTR::null()).ok()?;
+                let class_name = w!("musix-media-controls");
+                let class = WNDCLASSEXW {
+                    cbSize: std::mem::size_of::<WNDCLASSEXW>() as u32,
+                    lpfnWndProc: Some(window_proc),
+                    hInstance: instance,
+                    lpszClassName: class_name,
+                    ..Default::default()
+                };
+                if RegisterClassExW(&class) == 0 {
+                    return None;
+                }
+                let hwnd = CreateWindowExW(
+                    WINDOW_EX_STYLE::default(),
+                    class_name,
+                    w!("musix"),
+                    WINDOW_STYLE::default(),
+                    0,
+                    0,
+                    0,
+                    0,
+                    None,
+                    None,
+                    instance,
+                    None,
+                );
+                (hwnd.0 != 0).then_some(HiddenWindow(hwnd))
+            }
+        }
+
+        // Button presses reach SMTC through the window's message queue
+        fn pump(&self) {
+            unsafe {
+                let mut message = MSG::default();
+                while PeekMessageW(&mut message, None, 0, 0, PM_REMOVE).as_bool() {
+                    TranslateMessage(&message);
+                    DispatchMessageW(&message);
+                }
+            }
+        }
+    }
+
+    impl Drop for HiddenWindow {
+        fn drop(&mut self) {
+            unsafe {
+                DestroyWindow(self.0);
+            }
+        }
+    }
+
+    fn command_for(event: MediaControlEvent) -> Option<Command> {
+        Some(match event {
+            MediaControlEvent::Play => Command::Play,
+            MediaControlEvent::Pause => Command::Pause,
+            MediaControlEvent::Toggle => Command::Toggle,
+            MediaControlEvent::Next => Command::Next,
+            MediaControlEvent::Previous => Command::Previous,
+            MediaControlEvent::Stop => Command::Stop,
+            MediaControlEvent::Seek(SeekDirection::Forward) => Command::SeekBy(SEEK_STEP),
+            MediaControlEvent::Seek(SeekDirection::Backward) => Command::SeekBy(-SEEK_STEP),
+            MediaControlEvent::SetPosition(MediaPosition(position)) => Command::SeekTo(position),
+            _ => return None,
+        })
+    }
+
+    fn publish(controls: &mut MediaControls, now_playing: &NowPlaying, previous: Option<&NowPlaying>) {
+        // The overlay only needs new metadata when the track changes; the position is sent every time
+        let track_changed = previous.is_none_or(|previous| {
+            (&previous.title, &previous.artist, &previous.album, previous.duration)
+                != (&now_playing.title, &now_playing.artist, &now_playing.album, now_playing.duration)
+        });
+        if track_changed {
+            let _ = controls.set_metadata(MediaMetadata {
+                title: Some(&now_playing.title),
+                artist: now_playing.artist.as_deref(),
+                album: now_playing.album.as_deref(),
+                duration: now_playing.duration.map(Duration::from_secs),
+                cover_url: None,
+            });
+        }
+        let progress = Some(MediaPosition(Duration::from_secs(now_playing.position)));
+        let _ = controls.set_playback(match now_playing.status {
+            Status::Playing => MediaPlayback::Playing { progress },
+            Status::Paused => MediaPlayback::Paused { progress },
+            Status::Stopped => MediaPlayback::Stopped,
+        });
+    }
+
+    // The window, and with it SMTC, lives on its own thread so its message queue can be pumped steadily
+    pub fn spawn(updates: Receiver<NowPlaying>, commands: Sender<Command>) -> bool {
+        let (ready_sender, ready) = mpsc::channel();
+        thread::spawn(move || {
+            let Some(window) = HiddenWindow::create() else {
+                let _ = ready_sender.send(false);
+                return;
+            };
+            let config = PlatformConfig {
+                display_name: "musix",
+                dbus_name: "musix",
+                hwnd: Some(window.0.0 as *mut c_void),
+            };
+            let controls = MediaControls::new(config).ok().and_then(|mut controls| {
+                controls
+                    .attach(move |event| {
+                        if let Some(command) = command_for(event) {
+                            let _ = commands.send(command);
+                        }
+                    })
+                    .ok()
+                    .map(|()| controls)
+            });
+            let Some(mut controls) = controls else {
+                let _ = ready_sender.send(false);
+                return;
+            };
+            let _ = ready_sender.send(true);
+
+            let mut previous: Option<NowPlaying> = None;
+            loop {
+                window.pump();
+                match updates.recv_timeout(PUMP_INTERVAL) {
+                    Ok(now_playing) => {
+                        publish(&mut controls, &now_playing, previous.as_ref());
+                        previous = Some(now_playing);
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    // The player quit
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        });
+        ready.recv().unwrap_or(false)
+    }
+}
@@ -0,0 +1,55 @@
+use std::{cmp::Ordering, iter::Peekable, str::Chars};
+
+/// Compare names the way people count: runs of digits by their value, so "2 - Song" comes before "10 - Song",
+/// and letters without regard to case. Names equal that way fall back to plain order, keeping the sort total.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    let (mut a_chars, mut b_chars) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        let ordering = match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => compare_numbers(&digits(&mut a_chars), &digits(&mut b_chars)),
+            (Some(x), Some(y)) => {
+                a_chars.next();
+                b_chars.next();
+                x.to_lowercase().cmp(y.to_lowercase())
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn digits(chars: &mut Peekable<Chars>) -> String {
+    let mut run = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        run.push(c);
+    }
+    run
+}
+
+// Any length of number, without overflowing: fewer significant digits is smaller
+fn compare_numbers(a: &str, b: &str) -> Ordering {
+    let (a, b) = (a.trim_start_matches('0'), b.trim_start_matches('0'));
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numbers_sort_by_value() {
+        let mut names = vec!["10 - Outro", "2 - Song", "1 - intro", "Track 100", "track 9", "02 - Song", "B side", "a side"];
+        names.sort_by(|a, b| compare(a, b));
+        assert_eq!(
+            names,
+            vec!["1 - intro", "02 - Song", "2 - Song", "10 - Outro", "a side", "B side", "track 9", "Track 100"]
+        );
+        assert_eq!(compare("99999999999999999999999", "100000000000000000000000"), Ordering::Less);
+        assert_eq!(compare("Song", "Song"), Ordering::Equal);
+        assert_ne!(compare("song", "Song"), Ordering::Equal);
+    }
+}
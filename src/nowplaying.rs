@@ -0,0 +1,276 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Sender},
+    thread,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, error::MusixError, instance, paths};
+
+/// Output format of the now-playing file.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    // The configured template, e.g. for polybar's `tail = true` scripts
+    #[default]
+    Plain,
+    // Every field as one JSON object per line
+    Json,
+    // Waybar custom module JSON (`"return-type": "json"`)
+    Waybar,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::Playing => "playing",
+            Status::Paused => "paused",
+            Status::Stopped => "stopped",
+        }
+    }
+
+    fn icon(self) -> &'static str {
+        match self {
+            Status::Playing => "▶",
+            Status::Paused => "⏸",
+            Status::Stopped => "⏹",
+        }
+    }
+}
+
+/// What a status bar gets to show.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NowPlaying {
+    pub status: Status,
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    // Seconds
+    pub position: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u64>,
+}
+
+fn format_time(seconds: u64) -> String {
+    format!("{:02}:{:02}", seconds / 60, seconds % 60)
+}
+
+impl NowPlaying {
+    // Fill `{status}`, `{icon}`, `{title}`, `{artist}`, `{album}`, `{position}` and `{duration}` into a template
+    fn expand(&self, template: &str) -> String {
+        if self.status == Status::Stopped {
+            return String::new();
+        }
+        template
+            .replace("{status}", self.status.as_str())
+            .replace("{icon}", self.status.icon())
+            .replace("{title}", &self.title)
+            .replace("{artist}", self.artist.as_deref().unwrap_or_default())
+            .replace("{album}", self.album.as_deref().unwrap_or_default())
+            .replace("{position}", &format_time(self.position))
+            .replace("{duration}", &self.duration.map(format_time).unwrap_or_default())
+    }
+
+    /// One line of output; an empty text while stopped lets status bars hide the module.
+    pub fn render(&self, format: Format, template: &str) -> String {
+        match format {
+            Format::Plain => self.expand(template),
+            Format::Json => serde_json::to_string(self).unwrap_or_default(),
+            Format::Waybar => {
+                let tooltip = [Some(self.title.clone()), self.artist.clone(), self.album.clone()]
+                    .into_iter()
+                    .flatten()
+                    .filter(|line| !line.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let percentage = self
+                    .duration
+                    .filter(|&duration| duration > 0)
+                    .map_or(0, |duration| (self.position * 100 / duration).min(100));
+                serde_json::json!({
+                    "text": self.expand(template),
+                    "tooltip": tooltip,
+                    "class": self.status.as_str(),
+                    "alt": self.status.as_str(),
+                    "percentage": percentage,
+                })
+                .to_string()
+            }
+        }
+    }
+}
+
+// tmux's own `#{artist}` spelling of the fields is taken as well, so a format reads the same as the rest of
+// `status-right`
+fn template(format: &str) -> String {
+    format.replace("#{", "{")
+}
+
+/// Entry point for `musix now-playing`: one line about the running player's track, for tmux's `status-right` or a
+/// shell prompt. Nothing is printed while no player runs or nothing plays, so the prompt stays clean.
+pub fn run(format: Option<String>) -> Result<(), MusixError> {
+    let socket = instance::socket_path();
+    if !instance::is_running(&socket) {
+        return Ok(());
+    }
+    let Some(now_playing) = instance::query_now_playing(&socket)? else {
+        return Ok(());
+    };
+    let format = format.unwrap_or_else(|| Config::load().unwrap_or_default().now_playing.template);
+    let line = now_playing.render(Format::Plain, &template(&format));
+    if !line.is_empty() {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_fifo(path: &Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_fifo())
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_path: &Path) -> bool {
+    false
+}
+
+// Replaced as a whole so readers never see a half-written line
+fn write_file(path: &Path, line: &str) -> io::Result<()> {
+    paths::write_atomic(path, format!("{line}\n"))
+}
+
+/// Keeps a file or named pipe up to date with the current track.
+pub struct NowPlayingWriter {
+    path: PathBuf,
+    // Named pipes block until a reader shows up, so they are written from a background thread
+    pipe: Option<Sender<String>>,
+    last: Option<String>,
+}
+
+impl NowPlayingWriter {
+    pub fn new(path: PathBuf) -> Self {
+        let pipe = is_fifo(&path).then(|| {
+            let (sender, lines) = mpsc::channel::<String>();
+            let pipe_path = path.clone();
+            thread::spawn(move || {
+                let mut pipe = None;
+                while let Ok(mut line) = lines.recv() {
+                    // Only the newest line matters once a reader connects
+                    while let Ok(newer) = lines.try_recv() {
+                        line = newer;
+                    }
+                    loop {
+                        let writer = match &mut pipe {
+                            Some(writer) => writer,
+                            None => match OpenOptions::new().write(true).open(&pipe_path) {
+                                Ok(writer) => pipe.insert(writer),
+                                Err(_) => break,
+                            },
+                        };
+                        if writeln!(writer, "{line}").is_ok() {
+                            break;
+                        }
+                        // The reader went away; wait for the next one
+                        pipe = None;
+                        thread::sleep(Duration::from_millis(500));
+                    }
+                }
+            });
+            sender
+        });
+        NowPlayingWriter { path, pipe, last: None }
+    }
+
+    /// Write the line if it changed since the last update.
+    pub fn update(&mut self, line: String) -> io::Result<()> {
+        if self.last.as_ref() == Some(&line) {
+            return Ok(());
+        }
+        match &self.pipe {
+            Some(pipe) => {
+                let _ = pipe.send(line.clone());
+            }
+            None => write_file(&self.path, &line)?,
+        }
+        self.last = Some(line);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    fn playing() -> NowPlaying {
+        NowPlaying {
+            status: Status::Playing,
+            title: "Song".to_string(),
+            artist: Some("Artist".to_string()),
+            album: None,
+            position: 65,
+            duration: Some(260),
+        }
+    }
+
+    #[test]
+    fn test_render_formats() {
+        let now_playing = playing();
+        assert_eq!(
+            now_playing.render(Format::Plain, "{icon} {artist} - {title} [{position}/{duration}]"),
+            "▶ Artist - Song [01:05/04:20]"
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&now_playing.render(Format::Json, "")).unwrap();
+        assert_eq!(json["status"], "playing");
+        assert_eq!(json["position"], 65);
+        assert!(json.get("album").is_none());
+
+        let waybar: serde_json::Value = serde_json::from_str(&now_playing.render(Format::Waybar, "{title}")).unwrap();
+        assert_eq!(waybar["text"], "Song");
+        assert_eq!(waybar["tooltip"], "Song\nArtist");
+        assert_eq!(waybar["class"], "playing");
+        assert_eq!(waybar["percentage"], 25);
+
+        let stopped = NowPlaying {
+            status: Status::Stopped,
+            ..now_playing
+        };
+        assert_eq!(stopped.render(Format::Plain, "{title}"), "");
+    }
+
+    #[test]
+    fn test_tmux_style_templates() {
+        assert_eq!(playing().render(Format::Plain, &template("#{artist} - #{title}")), "Artist - Song");
+    }
+
+    #[test]
+    fn test_writer_skips_unchanged_lines() {
+        let dir = TestDir::new("now-playing");
+        let path = dir.join("now-playing.txt");
+        let mut writer = NowPlayingWriter::new(path.clone());
+        writer.update("a".to_string()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\n");
+
+        fs::remove_file(&path).unwrap();
+        writer.update("a".to_string()).unwrap();
+        assert!(!path.exists());
+        writer.update("b".to_string()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "b\n");
+    }
+}
@@ -0,0 +1,88 @@
+use rodio::{
+    DeviceTrait, OutputStream, OutputStreamHandle, Source, StreamError,
+    cpal::{self, SampleRate, SupportedStreamConfig, traits::HostTrait},
+    source::UniformSourceIterator,
+};
+
+use crate::{error::MusixError, i18n::t_args};
+
+/// The one format every track is converted to before it reaches the output. Tracks queued on a sink play as a
+/// single stream, and a change of sample rate in the middle of it can pop on some backends; converted, the stream
+/// never changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutputFormat {
+    pub channels: u16,
+    pub sample_rate: u32,
+}
+
+impl OutputFormat {
+    pub fn convert<S>(self, source: S) -> UniformSourceIterator<S, i16>
+    where
+        S: Source<Item = i16>,
+    {
+        UniformSourceIterator::new(source, self.channels, self.sample_rate)
+    }
+}
+
+/// Open the default output device, or the first other one that works, at `sample_rate` when given and otherwise at
+/// the device's own rate.
+pub fn open(sample_rate: Option<u32>) -> Result<(OutputStream, OutputStreamHandle, OutputFormat), MusixError> {
+    let host = cpal::default_host();
+    let default = host.default_output_device().ok_or(StreamError::NoDevice)?;
+    open_device(&default, sample_rate).or_else(|error| {
+        let Ok(mut devices) = host.output_devices() else {
+            return Err(error);
+        };
+        devices.find_map(|device| open_device(&device, sample_rate).ok()).ok_or(error)
+    })
+}
+
+fn open_device(device: &cpal::Device, sample_rate: Option<u32>) -> Result<(OutputStream, OutputStreamHandle, OutputFormat), MusixError> {
+    let config = match sample_rate {
+        Some(rate) => config_at(device, rate)?,
+        None => device.default_output_config().map_err(StreamError::DefaultStreamConfigError)?,
+    };
+    let format = OutputFormat {
+        channels: config.channels(),
+        sample_rate: config.sample_rate().0,
+    };
+    let (stream, handle) = OutputStream::try_from_device_config(device, config)?;
+    Ok((stream, handle, format))
+}
+
+// A configuration the device supports at `rate`, preferring stereo
+fn config_at(device: &cpal::Device, rate: u32) -> Result<SupportedStreamConfig, MusixError> {
+    let mut ranges: Vec<_> = device
+        .supported_output_configs()
+        .map_err(StreamError::SupportedStreamConfigsError)?
+        .filter(|range| (range.min_sample_rate().0..=range.max_sample_rate().0).contains(&rate))
+        .collect();
+    ranges.sort_by_key(|range| range.channels() != 2);
+    ranges
+        .into_iter()
+        .next()
+        .map(|range| range.with_sample_rate(SampleRate(rate)))
+        .ok_or_else(|| MusixError::Device(t_args("message.sample_rate_unsupported", &[("rate", &rate)])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    #[test]
+    fn test_sources_are_converted_to_the_output_format() {
+        let format = OutputFormat {
+            channels: 2,
+            sample_rate: 48000,
+        };
+        for (channels, rate) in [(1, 44100), (2, 48000), (2, 96000)] {
+            let source = SamplesBuffer::new(channels, rate, vec![1000i16; rate as usize * channels as usize / 10]);
+            let converted = format.convert(source);
+            assert_eq!((converted.channels(), converted.sample_rate()), (2, 48000));
+            // A tenth of a second at the output rate, give or take the converter's edge
+            let len = converted.count() as i64;
+            assert!((len - 9600).abs() <= 4, "{channels} channels at {rate}: {len} samples");
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, Write},
+    path::{MAIN_SEPARATOR, Path, PathBuf},
+    sync::OnceLock,
+};
+
+const APP_DIR: &str = "musix";
+/// Settings of a portable install; its presence beside the executable turns portable mode on.
+pub const PORTABLE_CONFIG_FILE: &str = "musix.toml";
+// Data and state of a portable install, beside the executable
+const PORTABLE_DATA_DIR: &str = "musix-data";
+
+static PORTABLE_DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+fn portable_dir_for(exe_dir: &Path, requested: bool) -> Option<PathBuf> {
+    (requested || exe_dir.join(PORTABLE_CONFIG_FILE).is_file()).then(|| exe_dir.to_path_buf())
+}
+
+fn detect_portable(requested: bool) -> Option<PathBuf> {
+    let exe = env::current_exe().ok()?;
+    portable_dir_for(exe.parent()?, requested)
+}
+
+/// Decide on portable mode once, before any other path is looked up: `--portable` or a `musix.toml` beside the
+/// executable keeps every file next to it, e.g. on a USB stick.
+pub fn init_portable(requested: bool) {
+    let _ = PORTABLE_DIR.set(detect_portable(requested));
+}
+
+/// The executable's folder in portable mode.
+pub fn portable_dir() -> Option<&'static Path> {
+    PORTABLE_DIR.get_or_init(|| detect_portable(false)).as_deref()
+}
+
+/// The user's home folder: `$HOME` on Linux and macOS, the profile folder (`C:\Users\me`) on Windows.
+pub fn home_dir() -> PathBuf {
+    dirs::home_dir().unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// The platform Music folder, under its localized name where the system has one: the XDG `MUSIC` user dir
+/// on Linux (`~/Musik`, `~/音楽`, ...), `~/Music` on macOS and the Music known folder on Windows.
+/// A portable install uses the `Music` folder beside the executable instead.
+pub fn music_dir() -> PathBuf {
+    if let Some(dir) = portable_dir() {
+        return dir.join("Music");
+    }
+    dirs::audio_dir().unwrap_or_else(|| home_dir().join("Music"))
+}
+
+/// Settings: `$XDG_CONFIG_HOME/musix` (`~/.config/musix`), `~/Library/Application Support/musix` on macOS,
+/// `%APPDATA%\musix` on Windows.
+pub fn config_dir() -> PathBuf {
+    dirs::config_dir().unwrap_or_else(|| home_dir().join(".config")).join(APP_DIR)
+}
+
+/// Data worth keeping (library, history, subscriptions): `$XDG_DATA_HOME/musix` (`~/.local/share/musix`),
+/// `~/Library/Application Support/musix` on macOS, `%APPDATA%\musix` on Windows.
+pub fn data_dir() -> PathBuf {
+    if let Some(dir) = portable_dir() {
+        return dir.join(PORTABLE_DATA_DIR);
+    }
+    dirs::data_dir().unwrap_or_else(|| home_dir().join(".local/share")).join(APP_DIR)
+}
+
+/// Runtime state that can be lost without harm (session snapshot, scrobble queue): `$XDG_STATE_HOME/musix`
+/// (`~/.local/state/musix`); macOS and Windows have no separate state folder, so the local data folder is used.
+pub fn state_dir() -> PathBuf {
+    if let Some(dir) = portable_dir() {
+        return dir.join(PORTABLE_DATA_DIR);
+    }
+    dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .unwrap_or_else(|| home_dir().join(".local/state"))
+        .join(APP_DIR)
+}
+
+/// `dir/name`; a file still in `~/.musix`, where earlier versions kept everything, is moved over first.
+pub fn file_in(dir: PathBuf, name: &str) -> PathBuf {
+    // A portable install must not take files away from the computer it runs on
+    if portable_dir().is_some() {
+        return dir.join(name);
+    }
+    adopt_legacy(home_dir().join(".musix").join(name), dir.join(name))
+}
+
+fn adopt_legacy(legacy: PathBuf, path: PathBuf) -> PathBuf {
+    if !path.exists() && legacy.exists() {
+        let moved = match path.parent() {
+            Some(dir) => fs::create_dir_all(dir).and_then(|()| fs::rename(&legacy, &path)),
+            None => fs::rename(&legacy, &path),
+        };
+        // Keep using the old file if it can't be moved, rather than starting over empty
+        if moved.is_err() {
+            return legacy;
+        }
+    }
+    path
+}
+
+/// Replace `path` with `bytes`, creating its folder if needed. They go to a file beside it that is flushed to disk
+/// and then renamed over it, so a crash or power loss leaves either the old contents or the new, never half of them.
+pub fn write_atomic(path: &Path, bytes: impl AsRef<[u8]>) -> io::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let mut writer = File::create(&temp)?;
+    writer.write_all(bytes.as_ref())?;
+    writer.sync_all()?;
+    fs::rename(temp, path)
+}
+
+// `~/Sync/musix.json` -> `<home>/Sync/musix.json`; Windows users may write `~\Sync\musix.json` as well.
+// A portable install resolves relative paths against its own folder, since the drive letter may change.
+pub fn expand_home(path: &str) -> PathBuf {
+    if path == "~" {
+        return home_dir();
+    }
+    match path.strip_prefix("~/").or_else(|| path.strip_prefix(&format!("~{MAIN_SEPARATOR}"))) {
+        Some(rest) => home_dir().join(rest),
+        None => match portable_dir() {
+            Some(dir) => dir.join(path),
+            None => PathBuf::from(path),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_expand_home() {
+        assert_eq!(expand_home("~/Sync/musix.json"), home_dir().join("Sync/musix.json"));
+        assert_eq!(expand_home("~"), home_dir());
+        assert_eq!(expand_home("/tmp/now-playing"), PathBuf::from("/tmp/now-playing"));
+        assert_eq!(expand_home("~other/file"), PathBuf::from("~other/file"));
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_the_whole_file() {
+        let dir = TestDir::new("write-atomic");
+        let file = dir.join("state").join("library.json");
+        write_atomic(&file, "first, and longer").unwrap();
+        write_atomic(&file, "second").unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "second");
+        let names: Vec<_> = fs::read_dir(file.parent().unwrap()).unwrap().flatten().map(|entry| entry.file_name()).collect();
+        assert_eq!(names, vec!["library.json"], "no temporary file is left behind");
+    }
+
+    #[test]
+    fn test_portable_detection() {
+        let dir = TestDir::new("portable");
+        assert_eq!(portable_dir_for(&dir, false), None);
+        assert_eq!(portable_dir_for(&dir, true), Some(dir.to_path_buf()));
+
+        fs::write(dir.join(PORTABLE_CONFIG_FILE), "").unwrap();
+        assert_eq!(portable_dir_for(&dir, false), Some(dir.to_path_buf()));
+    }
+
+    #[test]
+    fn test_legacy_files_are_moved() {
+        let root = TestDir::new("paths");
+        let legacy = root.join(".musix/library.json");
+        let path = root.join("share/musix/library.json");
+        fs::create_dir_all(legacy.parent().unwrap()).unwrap();
+        fs::write(&legacy, "{}").unwrap();
+
+        assert_eq!(adopt_legacy(legacy.clone(), path.clone()), path);
+        assert!(!legacy.exists());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{}");
+
+        // A file already in the new place wins
+        fs::write(&legacy, "old").unwrap();
+        assert_eq!(adopt_legacy(legacy, path.clone()), path);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{}");
+    }
+}
@@ -0,0 +1,230 @@
+use std::{fs, io, path::PathBuf};
+
+use clap::Subcommand;
+use quick_xml::{Reader, escape::escape, events::Event};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::MusixError,
+    i18n::{t, t_args},
+    paths,
+};
+
+const SUBSCRIPTIONS_FILE: &str = "podcasts.json";
+
+/// A podcast feed musix is subscribed to.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Subscription {
+    pub title: String,
+    pub feed_url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub site_url: Option<String>,
+}
+
+/// Podcast subscriptions, stored as JSON in the musix data directory.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Subscriptions {
+    #[serde(default)]
+    pub feeds: Vec<Subscription>,
+    #[serde(skip)]
+    file: Option<PathBuf>,
+}
+
+impl Subscriptions {
+    pub fn load() -> Self {
+        Self::load_from(paths::file_in(paths::data_dir(), SUBSCRIPTIONS_FILE))
+    }
+
+    pub fn load_from(file: PathBuf) -> Self {
+        let mut subscriptions: Subscriptions = fs::read_to_string(&file)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        subscriptions.file = Some(file);
+        subscriptions
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        paths::write_atomic(file, json)
+    }
+
+    /// Add a feed unless one with the same URL is already subscribed; returns whether it was new.
+    pub fn add(&mut self, subscription: Subscription) -> bool {
+        if self.feeds.iter().any(|feed| feed.feed_url == subscription.feed_url) {
+            return false;
+        }
+        self.feeds.push(subscription);
+        true
+    }
+}
+
+/// Every feed `<outline>` of an OPML document; category outlines are walked into, not returned.
+pub fn parse_opml(text: &str) -> Result<Vec<Subscription>, MusixError> {
+    let mut reader = Reader::from_str(text);
+    let mut subscriptions = Vec::new();
+    let mut seen_opml = false;
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) | Event::Empty(element) => {
+                let name = element.local_name();
+                if name.as_ref().eq_ignore_ascii_case(b"opml") {
+                    seen_opml = true;
+                }
+                if !name.as_ref().eq_ignore_ascii_case(b"outline") {
+                    continue;
+                }
+
+                let (mut title, mut text, mut feed_url, mut site_url) = (None, None, None, None);
+                for attribute in element.attributes() {
+                    let attribute = attribute?;
+                    let value = attribute.unescape_value()?.trim().to_string();
+                    if value.is_empty() {
+                        continue;
+                    }
+                    // Clients disagree on attribute case (xmlUrl vs xmlurl)
+                    match attribute.key.local_name().as_ref().to_ascii_lowercase().as_slice() {
+                        b"title" => title = Some(value),
+                        b"text" => text = Some(value),
+                        b"xmlurl" => feed_url = Some(value),
+                        b"htmlurl" => site_url = Some(value),
+                        _ => {}
+                    }
+                }
+                if let Some(feed_url) = feed_url {
+                    subscriptions.push(Subscription {
+                        title: title.or(text).unwrap_or_else(|| feed_url.clone()),
+                        feed_url,
+                        site_url,
+                    });
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if !seen_opml {
+        return Err(MusixError::Decode(t("podcast.not_opml").to_string()));
+    }
+    Ok(subscriptions)
+}
+
+/// An OPML 2.0 document listing the subscriptions, as other podcast clients import it.
+pub fn to_opml(subscriptions: &[Subscription]) -> String {
+    let mut opml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<opml version=\"2.0\">\n");
+    opml.push_str("  <head>\n    <title>musix podcast subscriptions</title>\n  </head>\n  <body>\n");
+    for subscription in subscriptions {
+        opml.push_str(&format!(
+            "    <outline type=\"rss\" text=\"{title}\" title=\"{title}\" xmlUrl=\"{feed}\"",
+            title = escape(subscription.title.as_str()),
+            feed = escape(subscription.feed_url.as_str()),
+        ));
+        if let Some(site_url) = &subscription.site_url {
+            opml.push_str(&format!(" htmlUrl=\"{}\"", escape(site_url.as_str())));
+        }
+        opml.push_str("/>\n");
+    }
+    opml.push_str("  </body>\n</opml>\n");
+    opml
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+pub enum PodcastCommand {
+    #[command(about = t("cli.podcast_import"))]
+    Import {
+        #[arg(value_name = "FILE.opml")]
+        file: PathBuf,
+    },
+    #[command(about = t("cli.podcast_export"))]
+    Export {
+        #[arg(value_name = "FILE.opml")]
+        file: Option<PathBuf>,
+    },
+    #[command(about = t("cli.podcast_list"))]
+    List,
+}
+
+/// Entry point for `musix podcast import <file.opml>`, `musix podcast export [<file.opml>]` and `musix podcast list`.
+pub fn run(command: PodcastCommand) -> Result<(), MusixError> {
+    let mut subscriptions = Subscriptions::load();
+    match command {
+        PodcastCommand::Import { file } => {
+            let text = fs::read_to_string(file)?;
+            let feeds = parse_opml(&text)?;
+            let total = feeds.len();
+            let added = feeds.into_iter().filter(|feed| subscriptions.add(feed.clone())).count();
+            subscriptions.save()?;
+            eprintln!("{}", t_args("podcast.imported", &[("count", &added), ("skipped", &(total - added))]));
+        }
+        PodcastCommand::Export { file } => {
+            let opml = to_opml(&subscriptions.feeds);
+            match file {
+                Some(file) => {
+                    fs::write(&file, opml)?;
+                    eprintln!(
+                        "{}",
+                        t_args("podcast.exported", &[("count", &subscriptions.feeds.len()), ("file", &file.display())])
+                    );
+                }
+                None => print!("{opml}"),
+            }
+        }
+        PodcastCommand::List => {
+            if subscriptions.feeds.is_empty() {
+                eprintln!("{}", t("podcast.none"));
+            }
+            for feed in &subscriptions.feeds {
+                println!("{}\t{}", feed.title, feed.feed_url);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPML: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<opml version="1.0">
+  <head><title>Subscriptions</title></head>
+  <body>
+    <outline text="Tech">
+      <outline type="rss" text="Rust &amp; Friends" xmlUrl="https://example.com/rust.xml" htmlUrl="https://example.com/"/>
+      <outline type="rss" text="Untitled" title="Titled" xmlurl="https://example.com/titled.xml"/>
+    </outline>
+    <outline type="rss" xmlUrl="https://example.com/bare.xml"></outline>
+  </body>
+</opml>"#;
+
+    #[test]
+    fn test_parse_opml() {
+        let feeds = parse_opml(OPML).unwrap();
+        assert_eq!(feeds.len(), 3);
+        assert_eq!(feeds[0].title, "Rust & Friends");
+        assert_eq!(feeds[0].site_url.as_deref(), Some("https://example.com/"));
+        assert_eq!(feeds[1].title, "Titled");
+        assert_eq!(feeds[1].feed_url, "https://example.com/titled.xml");
+        assert_eq!(feeds[2].title, "https://example.com/bare.xml");
+
+        assert!(parse_opml("<rss><channel/></rss>").is_err());
+        assert!(parse_opml("<opml><body><outline xmlUrl=\"a").is_err());
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let mut subscriptions = Subscriptions::default();
+        for feed in parse_opml(OPML).unwrap() {
+            assert!(subscriptions.add(feed));
+        }
+        assert!(!subscriptions.add(subscriptions.feeds[0].clone()));
+
+        let exported = to_opml(&subscriptions.feeds);
+        assert!(exported.contains("Rust &amp; Friends"));
+        assert_eq!(parse_opml(&exported).unwrap(), subscriptions.feeds);
+    }
+}
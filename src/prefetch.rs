@@ -0,0 +1,283 @@
+use std::{
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TryRecvError},
+    },
+    thread,
+    time::Duration,
+};
+
+use rodio::{Source, source::SeekError};
+
+// Samples handed over at a time; a chunk never spans a change of format
+const CHUNK: usize = 4096;
+// How long a seek waits between looks at whether the decoder thread has done it
+const SEEK_POLL: Duration = Duration::from_millis(1);
+
+// An empty chunk marks the end of the source
+struct Chunk {
+    // Seeks done before it was decoded; chunks from before the latest seek are dropped
+    generation: u64,
+    samples: Vec<i16>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+type SeekReply = Sender<Result<(), SeekError>>;
+
+/// Decodes a source ahead on a thread of its own, keeping up to `ahead` of audio ready, so the output callback
+/// only copies samples. A slow read from a network mount or a disk busy with a library scan, or a costly packet,
+/// is absorbed by the buffer instead of being heard as a dropout; one the buffer can't absorb is heard as silence
+/// rather than holding up the output.
+pub struct Prefetch {
+    chunks: Receiver<Chunk>,
+    seeks: Sender<(Duration, SeekReply)>,
+    current: Vec<i16>,
+    position: usize,
+    channels: u16,
+    sample_rate: u32,
+    total_duration: Option<Duration>,
+    generation: u64,
+    ended: bool,
+}
+
+impl Prefetch {
+    /// Start decoding `inner` ahead; it comes back as it was when no thread can be started for it.
+    pub fn new<S>(inner: S, ahead: Duration) -> Result<Self, S>
+    where
+        S: Source<Item = i16> + Send + 'static,
+    {
+        let (channels, sample_rate, total_duration) = (inner.channels(), inner.sample_rate(), inner.total_duration());
+        let samples_ahead = ahead.as_secs_f64() * sample_rate as f64 * channels.max(1) as f64;
+        let capacity = (samples_ahead / CHUNK as f64).ceil().max(1.0) as usize;
+        let (chunk_sender, chunks) = mpsc::sync_channel(capacity);
+        let (seeks, seek_receiver) = mpsc::channel();
+        // Handed over through a slot, so it can be taken back if the thread never starts
+        let slot = Arc::new(Mutex::new(Some(inner)));
+        let handed = Arc::clone(&slot);
+        let spawned = thread::Builder::new().name("decode-ahead".to_string()).spawn(move || {
+            let inner = handed.lock().unwrap().take();
+            if let Some(inner) = inner {
+                decode(inner, chunk_sender, seek_receiver);
+            }
+        });
+        if spawned.is_err()
+            && let Some(inner) = slot.lock().unwrap().take()
+        {
+            return Err(inner);
+        }
+        Ok(Prefetch {
+            chunks,
+            seeks,
+            current: Vec::new(),
+            position: 0,
+            channels,
+            sample_rate,
+            total_duration,
+            generation: 0,
+            ended: false,
+        })
+    }
+
+    // The next chunk from after the latest seek, or a frame of silence while the decoder thread is behind, as this
+    // runs in the output callback; false once the source has ended
+    fn receive(&mut self) -> bool {
+        while !self.ended {
+            match self.chunks.try_recv() {
+                Ok(chunk) if chunk.generation == self.generation => return self.accept(chunk),
+                Ok(_) => {}
+                Err(TryRecvError::Empty) => {
+                    self.current.clear();
+                    self.current.resize(self.channels.max(1) as usize, 0);
+                    self.position = 0;
+                    return true;
+                }
+                Err(TryRecvError::Disconnected) => self.ended = true,
+            }
+        }
+        false
+    }
+
+    fn accept(&mut self, chunk: Chunk) -> bool {
+        self.ended = chunk.samples.is_empty();
+        self.current = chunk.samples;
+        self.position = 0;
+        self.channels = chunk.channels;
+        self.sample_rate = chunk.sample_rate;
+        !self.ended
+    }
+}
+
+// The decoder thread: fill chunks until the player drops the source. At the end of the source it waits for a seek
+// back into it.
+fn decode<S: Source<Item = i16>>(mut inner: S, chunks: SyncSender<Chunk>, seeks: Receiver<(Duration, SeekReply)>) {
+    let mut generation = 0;
+    let mut ended = false;
+    loop {
+        let seek = if ended {
+            seeks.recv().map_err(|_| TryRecvError::Disconnected)
+        } else {
+            seeks.try_recv()
+        };
+        match seek {
+            Ok((position, reply)) => {
+                generation += 1;
+                ended = false;
+                let _ = reply.send(inner.try_seek(position));
+                continue;
+            }
+            Err(TryRecvError::Disconnected) => return,
+            Err(TryRecvError::Empty) => {}
+        }
+        let (channels, sample_rate) = (inner.channels(), inner.sample_rate());
+        // Whole frames, so silence put in between chunks keeps the channels in step
+        let most = CHUNK - CHUNK % channels.max(1) as usize;
+        let len = inner.current_frame_len().filter(|&len| len > 0).map_or(most, |len| len.min(most));
+        let samples: Vec<i16> = inner.by_ref().take(len).collect();
+        ended = samples.len() < len;
+        let last = ended && !samples.is_empty();
+        let chunk = |samples| Chunk {
+            generation,
+            samples,
+            channels,
+            sample_rate,
+        };
+        if chunks.send(chunk(samples)).is_err() || last && chunks.send(chunk(Vec::new())).is_err() {
+            return;
+        }
+    }
+}
+
+impl Iterator for Prefetch {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.position >= self.current.len() && !self.receive() {
+            return None;
+        }
+        let sample = self.current[self.position];
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+impl Source for Prefetch {
+    fn current_frame_len(&self) -> Option<usize> {
+        let left = self.current.len() - self.position;
+        (left > 0).then_some(left)
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.total_duration
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let (reply, result) = mpsc::channel();
+        let gone = || SeekError::NotSupported {
+            underlying_source: "Prefetch (decoder thread ended)",
+        };
+        self.seeks.send((pos, reply)).map_err(|_| gone())?;
+        self.generation += 1;
+        self.current.clear();
+        self.position = 0;
+        self.ended = false;
+        // Chunks are taken off meanwhile so a decoder thread waiting on a full buffer gets to the seek
+        loop {
+            match result.try_recv() {
+                Ok(seeked) => return seeked,
+                Err(TryRecvError::Disconnected) => return Err(gone()),
+                Err(TryRecvError::Empty) => match self.chunks.recv_timeout(SEEK_POLL) {
+                    // Sent right after the reply, so it is the first one from the new position
+                    Ok(chunk) if chunk.generation == self.generation => {
+                        self.accept(chunk);
+                    }
+                    Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => return Err(gone()),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    // Far less than the sources, so the decoder thread waits on a full buffer
+    const AHEAD: Duration = Duration::from_millis(100);
+
+    fn prefetch<S: Source<Item = i16> + Send + 'static>(source: S) -> Prefetch {
+        Prefetch::new(source, AHEAD).ok().expect("a decoder thread")
+    }
+
+    // A source whose first sample waits until the test lets it go, like a file on a disk spinning up
+    struct Stalled(SamplesBuffer<i16>, Option<Receiver<()>>);
+
+    impl Iterator for Stalled {
+        type Item = i16;
+
+        fn next(&mut self) -> Option<i16> {
+            if let Some(gate) = self.1.take() {
+                let _ = gate.recv();
+            }
+            self.0.next()
+        }
+    }
+
+    impl Source for Stalled {
+        fn current_frame_len(&self) -> Option<usize> {
+            self.0.current_frame_len()
+        }
+
+        fn channels(&self) -> u16 {
+            self.0.channels()
+        }
+
+        fn sample_rate(&self) -> u32 {
+            self.0.sample_rate()
+        }
+
+        fn total_duration(&self) -> Option<Duration> {
+            self.0.total_duration()
+        }
+    }
+
+    #[test]
+    fn test_prefetched_samples_match_the_source() {
+        // No zeros, which are what fills in while the decoder thread is behind
+        let samples: Vec<i16> = (1..=20000).collect();
+        let heard = |prefetch: &mut Prefetch| prefetch.filter(|&sample| sample != 0).collect::<Vec<_>>();
+        assert_eq!(heard(&mut prefetch(SamplesBuffer::new(2, 8000, samples.clone()))), samples);
+
+        let mut seeking = prefetch(SamplesBuffer::new(1, 8000, samples.clone()));
+        assert_eq!(seeking.find(|&sample| sample != 0), Some(1));
+        seeking.try_seek(Duration::from_millis(1500)).unwrap();
+        assert_eq!(seeking.find(|&sample| sample != 0), Some(12001));
+        assert_eq!((seeking.channels(), seeking.sample_rate()), (1, 8000));
+        assert_eq!(heard(&mut seeking).len(), 7999);
+        // Back into the track after reaching its end
+        seeking.try_seek(Duration::from_secs(2)).unwrap();
+        assert_eq!(seeking.find(|&sample| sample != 0), Some(16001));
+        assert_eq!(heard(&mut seeking).len(), 3999);
+    }
+
+    #[test]
+    fn test_a_stalled_decoder_is_heard_as_silence() {
+        let (release, gate) = mpsc::channel();
+        let mut stalled = prefetch(Stalled(SamplesBuffer::new(2, 8000, vec![5; 4]), Some(gate)));
+        // Answered while the decoder thread is still held up
+        assert_eq!(stalled.next(), Some(0));
+        assert_eq!(stalled.current_frame_len(), Some(1), "a whole frame of silence");
+        release.send(()).unwrap();
+        assert_eq!(stalled.filter(|&sample| sample != 0).collect::<Vec<_>>(), [5; 4]);
+    }
+}
@@ -0,0 +1,85 @@
+use std::time::{Duration, Instant};
+
+use rodio::{DeviceTrait, OutputStream, OutputStreamHandle, Sink, Source, cpal::traits::HostTrait};
+
+use crate::{
+    error::MusixError,
+    i18n::{t, t_args},
+};
+
+/// A second track playing quietly next to the main one, so the next song can be picked by ear before it goes out.
+pub struct Preview {
+    pub index: usize,
+    sink: Sink,
+    started: Instant,
+    offset: Duration,
+    // How long a snippet plays; none for a preview that plays to the end
+    length: Option<Duration>,
+    // Owns the output while previewing on a device of its own, such as headphones
+    _stream: Option<OutputStream>,
+}
+
+impl Preview {
+    /// Start `source` from `offset` at `volume`, for `length` when given, on the output device whose name contains
+    /// `device`, or else beside the main track on `main`.
+    pub fn start<S>(
+        source: S,
+        index: usize,
+        offset: Duration,
+        length: Option<Duration>,
+        volume: f32,
+        main: Option<&OutputStreamHandle>,
+        device: Option<&str>,
+    ) -> Result<Self, MusixError>
+    where
+        S: Source<Item = i16> + Send + 'static,
+    {
+        let (stream, sink) = match device.filter(|device| !device.trim().is_empty()) {
+            Some(device) => {
+                let (stream, handle) = open_device(device)?;
+                (Some(stream), Sink::try_new(&handle)?)
+            }
+            None => {
+                let handle = main.ok_or_else(|| MusixError::Device(t("message.preview_no_output").to_string()))?;
+                (None, Sink::try_new(handle)?)
+            }
+        };
+        sink.set_volume(volume);
+        let source = source.skip_duration(offset);
+        match length {
+            Some(length) => sink.append(source.take_duration(length)),
+            None => sink.append(source),
+        }
+        Ok(Preview {
+            index,
+            sink,
+            started: Instant::now(),
+            offset,
+            length,
+            _stream: stream,
+        })
+    }
+
+    /// Where in the track the preview is, for carrying on from there when it goes out on the main output.
+    pub fn position(&self) -> Duration {
+        self.offset + self.started.elapsed()
+    }
+
+    pub fn finished(&self) -> bool {
+        self.sink.empty()
+    }
+
+    /// Whether this is a short snippet started by resting the selection on a track, rather than a preview asked for.
+    pub fn is_snippet(&self) -> bool {
+        self.length.is_some()
+    }
+}
+
+fn open_device(name: &str) -> Result<(OutputStream, OutputStreamHandle), MusixError> {
+    let needle = name.to_lowercase();
+    let device = rodio::cpal::default_host()
+        .output_devices()?
+        .find(|device| device.name().is_ok_and(|device| device.to_lowercase().contains(&needle)))
+        .ok_or_else(|| MusixError::Device(t_args("message.preview_no_device", &[("device", &name)])))?;
+    Ok(OutputStream::try_from_device(&device)?)
+}
@@ -0,0 +1,173 @@
+use std::{
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::Duration,
+};
+
+use ratatui::widgets::ListState;
+use serde::Deserialize;
+
+const API_BASE: &str = "https://all.api.radio-browser.info";
+const RESULT_LIMIT: usize = 100;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A station entry from the radio-browser.info directory.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct Station {
+    pub name: String,
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub url_resolved: String,
+    #[serde(default)]
+    pub countrycode: String,
+    #[serde(default)]
+    pub codec: String,
+    #[serde(default)]
+    pub bitrate: u32,
+}
+
+impl Station {
+    // radio-browser resolves playlist URLs for us; fall back to the raw one
+    pub fn stream_url(&self) -> &str {
+        if self.url_resolved.is_empty() { &self.url } else { &self.url_resolved }
+    }
+}
+
+fn parse_stations(json: &str) -> Result<Vec<Station>, serde_json::Error> {
+    let stations: Vec<Station> = serde_json::from_str(json)?;
+    Ok(stations
+        .into_iter()
+        .map(|station| Station {
+            name: station.name.trim().to_string(),
+            ..station
+        })
+        .filter(|station| !station.name.is_empty() && !station.stream_url().is_empty())
+        .collect())
+}
+
+fn search_stations(query: &str) -> Result<Vec<Station>, String> {
+    let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+    let body = agent
+        .get(&format!("{API_BASE}/json/stations/search"))
+        .set("User-Agent", concat!("musix/", env!("CARGO_PKG_VERSION")))
+        .query("name", query)
+        .query("hidebroken", "true")
+        .query("order", "votes")
+        .query("reverse", "true")
+        .query("limit", &RESULT_LIMIT.to_string())
+        .call()
+        .map_err(|e| e.to_string())?
+        .into_string()
+        .map_err(|e| e.to_string())?;
+    parse_stations(&body).map_err(|e| e.to_string())
+}
+
+/// State of the station picker popup. Searches run on a background thread.
+pub struct RadioBrowser {
+    pub query: String,
+    pub searched_query: Option<String>,
+    pub stations: Vec<Station>,
+    pub list_state: ListState,
+    pub error: Option<String>,
+    pending: Option<Receiver<Result<Vec<Station>, String>>>,
+}
+
+impl RadioBrowser {
+    pub fn new() -> Self {
+        RadioBrowser {
+            query: String::new(),
+            searched_query: None,
+            stations: Vec::new(),
+            list_state: ListState::default(),
+            error: None,
+            pending: None,
+        }
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    // Enter searches when the query changed, otherwise it picks the highlighted station
+    pub fn needs_search(&self) -> bool {
+        self.searched_query.as_deref() != Some(self.query.as_str())
+    }
+
+    pub fn search(&mut self) {
+        let (sender, receiver) = mpsc::channel();
+        let query = self.query.clone();
+        thread::spawn(move || {
+            let _ = sender.send(search_stations(&query));
+        });
+
+        self.searched_query = Some(self.query.clone());
+        self.error = None;
+        self.pending = Some(receiver);
+    }
+
+    // Collect a finished search; called once per UI tick
+    pub fn poll(&mut self) {
+        let Some(receiver) = &self.pending else {
+            return;
+        };
+
+        match receiver.try_recv() {
+            Ok(Ok(stations)) => {
+                self.list_state.select(if stations.is_empty() { None } else { Some(0) });
+                self.stations = stations;
+                self.pending = None;
+            }
+            Ok(Err(error)) => {
+                self.error = Some(error);
+                self.pending = None;
+            }
+            Err(mpsc::TryRecvError::Empty) => {}
+            Err(mpsc::TryRecvError::Disconnected) => self.pending = None,
+        }
+    }
+
+    pub fn move_selection(&mut self, direction: i32) {
+        if self.stations.is_empty() {
+            return;
+        }
+
+        let len = self.stations.len();
+        let current = self.list_state.selected().unwrap_or(0);
+        let new = if direction > 0 {
+            (current + 1) % len
+        } else if current == 0 {
+            len - 1
+        } else {
+            current - 1
+        };
+        self.list_state.select(Some(new));
+    }
+
+    pub fn selected_station(&self) -> Option<&Station> {
+        self.list_state.selected().and_then(|index| self.stations.get(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stations() {
+        let json = r#"[
+            {"name": " Groove Salad ", "url": "http://somafm.com/groovesalad.pls", "url_resolved": "http://ice1.somafm.com/groovesalad-128-mp3",
+             "countrycode": "US", "codec": "MP3", "bitrate": 128, "votes": 9000},
+            {"name": "Raw Only", "url": "http://example.com/stream", "url_resolved": ""},
+            {"name": "", "url": "http://example.com/nameless"},
+            {"name": "No URL"}
+        ]"#;
+
+        let stations = parse_stations(json).unwrap();
+        assert_eq!(stations.len(), 2);
+        assert_eq!(stations[0].name, "Groove Salad");
+        assert_eq!(stations[0].stream_url(), "http://ice1.somafm.com/groovesalad-128-mp3");
+        assert_eq!(stations[0].bitrate, 128);
+        assert_eq!(stations[1].stream_url(), "http://example.com/stream");
+    }
+}
@@ -0,0 +1,244 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use rodio::Source;
+
+use crate::paths;
+
+// Samples collected by the tap before taking the lock to write them out
+const TAP_CHUNK: usize = 4096;
+const WAV_HEADER_LEN: u32 = 44;
+
+/// Minimal 16-bit PCM WAV writer; the size fields are patched in `finish`.
+pub struct WavWriter<W: Write + Seek> {
+    inner: W,
+    data_len: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut inner: W, channels: u16, sample_rate: u32) -> io::Result<Self> {
+        let block_align = channels * 2;
+        inner.write_all(b"RIFF")?;
+        inner.write_all(&0u32.to_le_bytes())?;
+        inner.write_all(b"WAVEfmt ")?;
+        inner.write_all(&16u32.to_le_bytes())?;
+        inner.write_all(&1u16.to_le_bytes())?; // PCM
+        inner.write_all(&channels.to_le_bytes())?;
+        inner.write_all(&sample_rate.to_le_bytes())?;
+        inner.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        inner.write_all(&block_align.to_le_bytes())?;
+        inner.write_all(&16u16.to_le_bytes())?;
+        inner.write_all(b"data")?;
+        inner.write_all(&0u32.to_le_bytes())?;
+        Ok(WavWriter { inner, data_len: 0 })
+    }
+
+    pub fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        for sample in samples {
+            self.inner.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_len = self.data_len.saturating_add(samples.len() as u32 * 2);
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.seek(SeekFrom::Start(4))?;
+        self.inner.write_all(&(WAV_HEADER_LEN - 8 + self.data_len).to_le_bytes())?;
+        self.inner.seek(SeekFrom::Start(40))?;
+        self.inner.write_all(&self.data_len.to_le_bytes())?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+enum TapState {
+    Idle,
+    // File created by the player; the header is written once the source's format is known
+    Requested(BufWriter<File>),
+    Writing(WavWriter<BufWriter<File>>),
+}
+
+/// Shared switch between the player and the `Tap` wrapped around every decoded source.
+#[derive(Clone)]
+pub struct RecordTap {
+    active: Arc<AtomicBool>,
+    state: Arc<Mutex<TapState>>,
+}
+
+impl RecordTap {
+    pub fn new() -> Self {
+        RecordTap {
+            active: Arc::new(AtomicBool::new(false)),
+            state: Arc::new(Mutex::new(TapState::Idle)),
+        }
+    }
+
+    pub fn start(&self, file: File) {
+        *self.state.lock().unwrap() = TapState::Requested(BufWriter::new(file));
+        self.active.store(true, Ordering::Relaxed);
+    }
+
+    pub fn stop(&self) -> io::Result<()> {
+        self.active.store(false, Ordering::Relaxed);
+        match std::mem::replace(&mut *self.state.lock().unwrap(), TapState::Idle) {
+            TapState::Writing(writer) => writer.finish().map(drop),
+            // Nothing was played while recording; still leave a valid (empty) file
+            TapState::Requested(file) => WavWriter::new(file, 2, 44100)?.finish().map(drop),
+            TapState::Idle => Ok(()),
+        }
+    }
+
+    pub fn wrap<S: Source<Item = i16>>(&self, source: S) -> Tap<S> {
+        Tap {
+            inner: source,
+            tap: self.clone(),
+            pending: Vec::new(),
+        }
+    }
+}
+
+/// Passes samples through unchanged, copying them to the recording while one is active.
+pub struct Tap<S> {
+    inner: S,
+    tap: RecordTap,
+    pending: Vec<i16>,
+}
+
+impl<S: Source<Item = i16>> Tap<S> {
+    fn flush(&mut self) {
+        let mut state = self.tap.state.lock().unwrap();
+        if matches!(*state, TapState::Requested(_))
+            && let TapState::Requested(file) = std::mem::replace(&mut *state, TapState::Idle)
+            && let Ok(writer) = WavWriter::new(file, self.inner.channels(), self.inner.sample_rate())
+        {
+            *state = TapState::Writing(writer);
+        }
+        if let TapState::Writing(writer) = &mut *state {
+            let _ = writer.write_samples(&self.pending);
+        }
+        self.pending.clear();
+    }
+}
+
+impl<S: Source<Item = i16>> Iterator for Tap<S> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.inner.next();
+        if self.tap.active.load(Ordering::Relaxed) {
+            if let Some(sample) = sample {
+                self.pending.push(sample);
+            }
+            if self.pending.len() >= TAP_CHUNK || (sample.is_none() && !self.pending.is_empty()) {
+                self.flush();
+            }
+        } else if !self.pending.is_empty() {
+            self.pending.clear();
+        }
+        sample
+    }
+}
+
+impl<S: Source<Item = i16>> Source for Tap<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        self.inner.try_seek(pos)
+    }
+}
+
+fn recordings_dir() -> PathBuf {
+    paths::music_dir().join("musix-recordings")
+}
+
+/// Create `<recordings dir>/<timestamp> <name>.<extension>` for a new recording.
+pub fn create_file(name: &str, extension: &str) -> io::Result<(PathBuf, File)> {
+    let dir = recordings_dir();
+    fs::create_dir_all(&dir)?;
+
+    let timestamp = chrono::Local::now().format("%Y-%m-%d %H-%M-%S");
+    let path = dir.join(format!("{timestamp} {}.{extension}", sanitize_file_name(name)));
+    let file = File::create(&path)?;
+    Ok((path, file))
+}
+
+pub fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// File extension for a stream recorded as-is, from its HTTP Content-Type.
+pub fn extension_for_content_type(content_type: Option<&str>) -> &'static str {
+    let mime = content_type
+        .unwrap_or_default()
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    match mime.as_str() {
+        "audio/aac" | "audio/aacp" | "audio/x-aac" => "aac",
+        "audio/ogg" | "application/ogg" | "audio/opus" => "ogg",
+        "audio/flac" | "audio/x-flac" => "flac",
+        _ => "mp3",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_wav_writer_patches_sizes() {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 2, 44100).unwrap();
+        writer.write_samples(&[1, -1, 2, -2]).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 36 + 8);
+        assert_eq!(u32::from_le_bytes(bytes[24..28].try_into().unwrap()), 44100);
+        assert_eq!(u32::from_le_bytes(bytes[40..44].try_into().unwrap()), 8);
+        assert_eq!(i16::from_le_bytes(bytes[46..48].try_into().unwrap()), -1);
+    }
+
+    #[test]
+    fn test_recording_names() {
+        assert_eq!(sanitize_file_name("AC/DC: Live?"), "AC_DC_ Live_");
+        assert_eq!(extension_for_content_type(Some("audio/aacp")), "aac");
+        assert_eq!(extension_for_content_type(Some("audio/mpeg; charset=x")), "mp3");
+        assert_eq!(extension_for_content_type(None), "mp3");
+    }
+}
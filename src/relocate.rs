@@ -0,0 +1,207 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::{
+    check::playlist_files,
+    config::Config,
+    error::MusixError,
+    events::{self, LogEntry},
+    history::{self, PlayEvent},
+    i18n::{t, t_args},
+    instance,
+    library::Library,
+    paths,
+    revisit::{self, Revisit},
+    session,
+};
+
+/// Where `path` is after everything under `old` moved to `new`; None for a path outside `old`.
+fn moved(path: &str, old: &Path, new: &Path) -> Option<String> {
+    let rest = Path::new(path).strip_prefix(old).ok()?;
+    // Joining an empty rest would leave a trailing separator on the folder itself
+    let path = if rest.as_os_str().is_empty() { new.to_path_buf() } else { new.join(rest) };
+    Some(path.to_string_lossy().into_owned())
+}
+
+// The history and event logs, one JSON object per line; lines that don't parse are kept as they are
+fn relocate_log<T: Serialize + DeserializeOwned>(
+    file: &Path,
+    path_of: fn(&mut T) -> &mut String,
+    rename: impl Fn(&str) -> Option<String>,
+) -> io::Result<usize> {
+    let Ok(text) = fs::read_to_string(file) else {
+        return Ok(0);
+    };
+    let mut count = 0;
+    let mut rewritten = String::with_capacity(text.len());
+    for line in text.lines() {
+        let mut entry = serde_json::from_str::<T>(line).ok();
+        let path = entry.as_mut().and_then(|entry| rename(path_of(entry)));
+        match (entry, path) {
+            (Some(mut entry), Some(path)) => {
+                *path_of(&mut entry) = path;
+                rewritten.push_str(&serde_json::to_string(&entry).map_err(io::Error::other)?);
+                count += 1;
+            }
+            _ => rewritten.push_str(line),
+        }
+        rewritten.push('\n');
+    }
+    if count > 0 {
+        paths::write_atomic(file, &rewritten)?;
+    }
+    Ok(count)
+}
+
+// Absolute lines of an `.m3u` playlist; relative ones still hold if the playlist moved along with the tracks, and
+// comments and stream URLs are left alone
+fn relocate_playlist(text: &str, rename: impl Fn(&str) -> Option<String>) -> (String, usize) {
+    let mut count = 0;
+    let mut rewritten = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let body = line.trim_end_matches(['\r', '\n']);
+        let bom = if body.starts_with('\u{feff}') { "\u{feff}" } else { "" };
+        let entry = &body[bom.len()..];
+        match rename(entry) {
+            Some(path) if !entry.starts_with('#') && !entry.contains("://") && Path::new(entry).is_absolute() => {
+                rewritten.push_str(bom);
+                rewritten.push_str(&path);
+                rewritten.push_str(&line[body.len()..]);
+                count += 1;
+            }
+            _ => rewritten.push_str(line),
+        }
+    }
+    (rewritten, count)
+}
+
+/// Entry point for `musix relocate <old> <new>`, after the music folder moved: paths under `old` are changed to the
+/// same place under `new` in library.json, the listening and event logs, the last session and the library's
+/// playlists, so ratings, play counts, cues and statistics follow the files.
+pub fn run(old: PathBuf, new: PathBuf) -> Result<(), MusixError> {
+    // A player still open would write its library with the old paths back on quit
+    if instance::is_running(&instance::socket_path()) {
+        return Err(MusixError::PlayerRunning);
+    }
+    let rename = |path: &str| moved(path, &old, &new);
+
+    let mut library = Library::load();
+    let tracks = library.rename_paths(rename);
+    if tracks > 0 {
+        library.save()?;
+    }
+    eprintln!("{}", t_args("relocate.library", &[("count", &tracks)]));
+
+    let plays = relocate_log::<PlayEvent>(&history::history_path(), |event| &mut event.path, rename)?;
+    eprintln!("{}", t_args("relocate.history", &[("count", &plays)]));
+    let entries = relocate_log::<LogEntry>(&events::events_path(), |entry| &mut entry.path, rename)?;
+    eprintln!("{}", t_args("relocate.events", &[("count", &entries)]));
+    let saved = Revisit::load(&revisit::revisit_path()).rename_paths(rename)?;
+    eprintln!("{}", t_args("relocate.revisit", &[("count", &saved)]));
+
+    let session_file = session::session_path();
+    if let Some(mut snapshot) = session::load(&session_file) {
+        let mut changed = false;
+        for path in [&mut snapshot.track, &mut snapshot.selected].into_iter().flatten() {
+            if let Some(moved) = rename(path) {
+                *path = moved;
+                changed = true;
+            }
+        }
+        if changed {
+            session::save(&session_file, &snapshot)?;
+            eprintln!("{}", t("relocate.session"));
+        }
+    }
+
+    // Playlists that came along are in the new folder, and ones elsewhere in the library may point into it
+    let mut folders = Config::load().unwrap_or_default().library.paths();
+    let stale: Vec<String> = folders
+        .iter()
+        .filter(|folder| folder.starts_with(&old))
+        .map(|folder| folder.display().to_string())
+        .collect();
+    folders.push(new.clone());
+    let mut playlists = Vec::new();
+    for folder in &folders {
+        playlist_files(folder, &mut playlists);
+    }
+    playlists.sort();
+    playlists.dedup();
+    let (mut lines, mut changed) = (0, 0);
+    for playlist in &playlists {
+        let Ok(text) = fs::read_to_string(playlist) else {
+            continue;
+        };
+        let (rewritten, count) = relocate_playlist(&text, rename);
+        if count > 0 {
+            paths::write_atomic(playlist, &rewritten)?;
+            lines += count;
+            changed += 1;
+        }
+    }
+    eprintln!("{}", t_args("relocate.playlists", &[("lines", &lines), ("playlists", &changed)]));
+
+    for folder in stale {
+        eprintln!("{}", t_args("relocate.config", &[("folder", &folder)]));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_paths_under_the_old_folder_move() {
+        let (old, new) = (Path::new("/home/me/Music"), Path::new("/mnt/nas/music"));
+        assert_eq!(moved("/home/me/Music/a/b.flac", old, new).as_deref(), Some("/mnt/nas/music/a/b.flac"));
+        assert_eq!(moved("/home/me/Music", old, new).as_deref(), Some("/mnt/nas/music"));
+        // Whole components only
+        assert_eq!(moved("/home/me/Musicals/c.mp3", old, new), None);
+        assert_eq!(moved("/srv/d.mp3", old, new), None);
+
+        let rename = |path: &str| moved(path, old, new);
+        let text = "\u{feff}/home/me/Music/a.mp3\r\n#EXTINF:1,/home/me/Music\r\nb.mp3\r\n/srv/c.mp3\r\n/home/me/Music/d.mp3";
+        let (rewritten, count) = relocate_playlist(text, rename);
+        assert_eq!(count, 2);
+        assert_eq!(
+            rewritten,
+            "\u{feff}/mnt/nas/music/a.mp3\r\n#EXTINF:1,/home/me/Music\r\nb.mp3\r\n/srv/c.mp3\r\n/mnt/nas/music/d.mp3"
+        );
+    }
+
+    #[test]
+    fn test_log_lines_are_rewritten_in_place() {
+        let dir = TestDir::new("relocate");
+        let file = dir.join("relocate.jsonl");
+        let event = |path: &str| PlayEvent {
+            path: path.to_string(),
+            title: "a".to_string(),
+            artist: None,
+            album: None,
+            started: 1000,
+            listened: 30.0,
+            duration: Some(200.0),
+            completed: false,
+        };
+        let lines = [
+            serde_json::to_string(&event("/old/a.mp3")).unwrap(),
+            "{\"cut short".to_string(),
+            serde_json::to_string(&event("/other/b.mp3")).unwrap(),
+        ];
+        fs::write(&file, lines.join("\n") + "\n").unwrap();
+
+        let rename = |path: &str| moved(path, Path::new("/old"), Path::new("/new"));
+        assert_eq!(relocate_log::<PlayEvent>(&file, |event| &mut event.path, rename).unwrap(), 1);
+        let text = fs::read_to_string(&file).unwrap();
+        assert_eq!(text.lines().nth(1), Some("{\"cut short"));
+        let paths: Vec<String> = history::load(&file).into_iter().map(|event| event.path).collect();
+        assert_eq!(paths, ["/new/a.mp3", "/other/b.mp3"]);
+    }
+}
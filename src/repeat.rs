@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+/// What happens after the last track of the list, or after every track.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RepeatMode {
+    // Playback stops after the last track
+    Off,
+    // Back to the first track after the last
+    #[default]
+    All,
+    // The playing track starts over when it ends; skipping still moves on
+    One,
+}
+
+impl RepeatMode {
+    // All -> one -> off -> all
+    pub fn next(self) -> Self {
+        match self {
+            RepeatMode::All => RepeatMode::One,
+            RepeatMode::One => RepeatMode::Off,
+            RepeatMode::Off => RepeatMode::All,
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::path::Path;
+
+use lofty::prelude::*;
+use serde::Deserialize;
+
+/// Which of a track's ReplayGain tags playback levels it by.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Off,
+    // Every track at the same loudness, for shuffle
+    #[default]
+    Track,
+    // Tracks of an album keep their levels relative to each other; the track gain where there is no album gain
+    Album,
+}
+
+/// The ReplayGain tags of a track, as `musix rgscan` or another scanner wrote them.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct Gains {
+    // dB
+    pub track_gain: Option<f64>,
+    pub album_gain: Option<f64>,
+    // Linear sample peaks, 1.0 being full scale
+    pub track_peak: Option<f64>,
+    pub album_peak: Option<f64>,
+}
+
+impl Gains {
+    /// The factor samples are multiplied by in `mode`, with `preamp` dB on top; held down so the peak doesn't clip.
+    /// 1.0 for a track without the tags.
+    pub fn factor(&self, mode: Mode, preamp: f64) -> f32 {
+        let (gain, peak) = match mode {
+            Mode::Off => return 1.0,
+            Mode::Track => (self.track_gain, self.track_peak),
+            Mode::Album if self.album_gain.is_some() => (self.album_gain, self.album_peak),
+            Mode::Album => (self.track_gain, self.track_peak),
+        };
+        let Some(gain) = gain else {
+            return 1.0;
+        };
+        let mut factor = 10f64.powf((gain + preamp) / 20.0);
+        if let Some(peak) = peak.filter(|&peak| peak > 0.0) {
+            factor = factor.min(1.0 / peak);
+        }
+        factor as f32
+    }
+}
+
+// "-6.52 dB", also without the unit or with a `+`
+fn parse_gain(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let number = text.strip_suffix("dB").or_else(|| text.strip_suffix("db")).unwrap_or(text);
+    number.trim().trim_start_matches('+').parse().ok().filter(|gain: &f64| gain.is_finite())
+}
+
+fn parse_peak(text: &str) -> Option<f64> {
+    text.trim().parse().ok().filter(|peak: &f64| peak.is_finite() && *peak >= 0.0)
+}
+
+pub fn read(path: &Path) -> Gains {
+    let Ok(tagged) = lofty::read_from_path(path) else {
+        return Gains::default();
+    };
+    let mut gains = Gains::default();
+    for tag in tagged.tags() {
+        let value = |key, parse: fn(&str) -> Option<f64>| tag.get_string(key).and_then(parse);
+        gains.track_gain = gains.track_gain.or_else(|| value(ItemKey::ReplayGainTrackGain, parse_gain));
+        gains.album_gain = gains.album_gain.or_else(|| value(ItemKey::ReplayGainAlbumGain, parse_gain));
+        gains.track_peak = gains.track_peak.or_else(|| value(ItemKey::ReplayGainTrackPeak, parse_peak));
+        gains.album_peak = gains.album_peak.or_else(|| value(ItemKey::ReplayGainAlbumPeak, parse_peak));
+    }
+    gains
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gain_factors() {
+        assert_eq!(parse_gain("-6.02 dB"), Some(-6.02));
+        assert_eq!(parse_gain(" +3.5 dB "), Some(3.5));
+        assert_eq!(parse_gain("loud"), None);
+        assert_eq!(parse_peak("0.988525"), Some(0.988525));
+
+        let gains = Gains {
+            track_gain: Some(-6.0206),
+            album_gain: Some(6.0206),
+            track_peak: Some(0.9),
+            album_peak: Some(0.8),
+        };
+        assert!((gains.factor(Mode::Track, 0.0) - 0.5).abs() < 1e-4);
+        // Twice as loud would take the 0.8 peak past full scale
+        assert!((gains.factor(Mode::Album, 0.0) - 1.25).abs() < 1e-4);
+        assert!((gains.factor(Mode::Track, 6.0206) - 1.0).abs() < 1e-4);
+        assert_eq!(gains.factor(Mode::Off, 0.0), 1.0);
+
+        let track_only = Gains {
+            track_gain: Some(-6.0206),
+            ..Gains::default()
+        };
+        assert!((track_only.factor(Mode::Album, 0.0) - 0.5).abs() < 1e-4);
+        assert_eq!(Gains::default().factor(Mode::Track, 3.0), 1.0);
+    }
+}
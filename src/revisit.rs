@@ -0,0 +1,104 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::paths;
+
+const REVISIT_FILE: &str = "revisit.json";
+
+/// Tracks put aside to play again later, newest first, kept across sessions.
+#[derive(Default, Debug)]
+pub struct Revisit {
+    // None keeps the stack in memory only
+    file: Option<PathBuf>,
+    entries: Vec<String>,
+}
+
+pub fn revisit_path() -> PathBuf {
+    paths::file_in(paths::data_dir(), REVISIT_FILE)
+}
+
+impl Revisit {
+    pub fn load(file: &Path) -> Self {
+        let entries = fs::read_to_string(file)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Revisit {
+            file: Some(file.to_path_buf()),
+            entries,
+        }
+    }
+
+    /// The paths on the stack, the one pushed last first.
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Put `path` on top, moving it there if it was further down, and write the stack out.
+    pub fn push(&mut self, path: &Path) -> io::Result<()> {
+        let path = path.to_string_lossy().into_owned();
+        self.entries.retain(|entry| *entry != path);
+        self.entries.insert(0, path);
+        self.save()
+    }
+
+    /// Take the entry at `index` off the stack.
+    pub fn remove(&mut self, index: usize) -> io::Result<Option<String>> {
+        if index >= self.entries.len() {
+            return Ok(None);
+        }
+        let entry = self.entries.remove(index);
+        self.save()?;
+        Ok(Some(entry))
+    }
+
+    /// Change the entries `rename` gives a new path for, returning how many changed; for `musix relocate`.
+    pub fn rename_paths(&mut self, rename: impl Fn(&str) -> Option<String>) -> io::Result<usize> {
+        let mut count = 0;
+        for entry in &mut self.entries {
+            if let Some(path) = rename(entry) {
+                *entry = path;
+                count += 1;
+            }
+        }
+        if count > 0 {
+            self.save()?;
+        }
+        Ok(count)
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(&self.entries).map_err(io::Error::other)?;
+        fs::write(file, json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_stack_order_and_persistence() {
+        let dir = TestDir::new("revisit");
+        let file = dir.join("revisit.json");
+        let mut revisit = Revisit::load(&file);
+        for path in ["/music/a.mp3", "/music/b.mp3", "/music/a.mp3"] {
+            revisit.push(Path::new(path)).unwrap();
+        }
+        let mut revisit = Revisit::load(&file);
+        assert_eq!(revisit.entries(), ["/music/a.mp3", "/music/b.mp3"]);
+
+        assert_eq!(revisit.remove(0).unwrap().as_deref(), Some("/music/a.mp3"));
+        assert_eq!(revisit.remove(5).unwrap(), None);
+        assert_eq!(Revisit::load(&file).entries(), ["/music/b.mp3"]);
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::Args;
+use ebur128::{EbuR128, Mode};
+use lofty::{config::WriteOptions, prelude::*, tag::Tag};
+use rodio::Source;
+
+use crate::{
+    Song, create_audio_source,
+    error::MusixError,
+    i18n::{t, t_args},
+    load_mp3_files, resolve_selection,
+};
+
+// ReplayGain 2.0 reference level
+const REFERENCE_LUFS: f64 = -18.0;
+
+/// Loudness measurement of one track.
+struct TrackScan {
+    path: PathBuf,
+    meter: EbuR128,
+    peak: f64,
+}
+
+impl TrackScan {
+    fn loudness(&self) -> Result<f64, ebur128::Error> {
+        self.meter.loudness_global()
+    }
+}
+
+fn gain_for_loudness(loudness: f64) -> f64 {
+    REFERENCE_LUFS - loudness
+}
+
+fn format_gain(gain: f64) -> String {
+    format!("{gain:.2} dB")
+}
+
+fn format_peak(peak: f64) -> String {
+    format!("{peak:.6}")
+}
+
+// Albums are the tracks sharing a folder, which is how the library is laid out on disk
+fn group_albums(songs: Vec<Song>) -> BTreeMap<PathBuf, Vec<Song>> {
+    let mut albums: BTreeMap<PathBuf, Vec<Song>> = BTreeMap::new();
+    for song in songs {
+        let folder = song.path.parent().map(Path::to_path_buf).unwrap_or_default();
+        albums.entry(folder).or_default().push(song);
+    }
+    albums
+}
+
+fn has_replaygain(path: &Path) -> bool {
+    lofty::read_from_path(path).is_ok_and(|file| {
+        file.tags()
+            .iter()
+            .any(|tag| tag.get_string(ItemKey::ReplayGainTrackGain).is_some() && tag.get_string(ItemKey::ReplayGainAlbumGain).is_some())
+    })
+}
+
+fn analyze<S: Source<Item = i16>>(source: S) -> Result<(EbuR128, f64), MusixError> {
+    let channels = source.channels();
+    let mut meter = EbuR128::new(u32::from(channels), source.sample_rate(), Mode::I | Mode::SAMPLE_PEAK)?;
+
+    let chunk_len = 4096 * channels as usize;
+    let mut frames = Vec::with_capacity(chunk_len);
+    for sample in source {
+        frames.push(sample);
+        if frames.len() == chunk_len {
+            meter.add_frames_i16(&frames)?;
+            frames.clear();
+        }
+    }
+    // A decoder may end mid-frame; only whole frames can be measured
+    frames.truncate(frames.len() - frames.len() % channels as usize);
+    meter.add_frames_i16(&frames)?;
+
+    let mut peak: f64 = 0.0;
+    for channel in 0..u32::from(channels) {
+        peak = peak.max(meter.sample_peak(channel)?);
+    }
+    Ok((meter, peak))
+}
+
+fn scan_track(path: &Path) -> Result<TrackScan, MusixError> {
+    let (meter, peak) = analyze(create_audio_source(&path.to_path_buf())?)?;
+    Ok(TrackScan {
+        path: path.to_path_buf(),
+        meter,
+        peak,
+    })
+}
+
+fn write_tags(path: &Path, track_gain: f64, track_peak: f64, album_gain: f64, album_peak: f64) -> Result<(), MusixError> {
+    let mut file = lofty::read_from_path(path)?;
+    if file.primary_tag().is_none() {
+        file.insert_tag(Tag::new(file.primary_tag_type()));
+    }
+    let tag = file.primary_tag_mut().ok_or_else(|| MusixError::Decode(t("rgscan.no_tag").to_string()))?;
+
+    tag.insert_text(ItemKey::ReplayGainTrackGain, format_gain(track_gain));
+    tag.insert_text(ItemKey::ReplayGainTrackPeak, format_peak(track_peak));
+    tag.insert_text(ItemKey::ReplayGainAlbumGain, format_gain(album_gain));
+    tag.insert_text(ItemKey::ReplayGainAlbumPeak, format_peak(album_peak));
+    file.save_to_path(path, WriteOptions::default())?;
+    Ok(())
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct RgscanOptions {
+    #[arg(long, help = t("cli.rgscan_force"))]
+    pub force: bool,
+    // Files, directories, or search queries; the whole library when empty
+    #[arg(value_name = "SELECTION", help = t("cli.rgscan_selection"))]
+    pub selection: Vec<String>,
+}
+
+/// Entry point for `musix rgscan [--force] [<selection>...]`; scans the whole library by default.
+pub fn run(options: RgscanOptions) -> Result<(), MusixError> {
+    let RgscanOptions { force, selection } = options;
+
+    let songs = if selection.is_empty() {
+        load_mp3_files()?
+    } else {
+        resolve_selection(&selection)?
+    };
+    let songs: Vec<Song> = songs.into_iter().filter(|song| song.stream_url.is_none()).collect();
+    if songs.is_empty() {
+        return Err(MusixError::Config(t("rgscan.no_tracks").to_string()));
+    }
+
+    // Album gain needs every track of the album, so an album is rescanned if any of its tracks lacks tags
+    let albums: Vec<(PathBuf, Vec<Song>)> = group_albums(songs)
+        .into_iter()
+        .filter(|(_, tracks)| force || tracks.iter().any(|song| !has_replaygain(&song.path)))
+        .collect();
+    let total: usize = albums.iter().map(|(_, tracks)| tracks.len()).sum();
+
+    let mut number = 0;
+    let mut tagged = 0;
+    for (folder, tracks) in &albums {
+        let mut scans = Vec::new();
+        for song in tracks {
+            number += 1;
+            eprint!("\r\x1b[2K[{number}/{total}] {}", song.name);
+            let _ = io::stderr().flush();
+            match scan_track(&song.path) {
+                Ok(scan) => scans.push(scan),
+                Err(e) => eprintln!(
+                    "\r\x1b[2K[{number}/{total}] ✗ {}",
+                    t_args("rgscan.failed", &[("name", &song.name), ("error", &e)])
+                ),
+            }
+        }
+
+        let album_loudness = match EbuR128::loudness_global_multiple(scans.iter().map(|scan| &scan.meter)) {
+            Ok(loudness) if loudness.is_finite() => loudness,
+            _ => continue,
+        };
+        let album_peak = scans.iter().map(|scan| scan.peak).fold(0.0, f64::max);
+
+        for scan in &scans {
+            let name = scan.path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+            let result = scan.loudness().map_err(MusixError::from).and_then(|loudness| {
+                write_tags(
+                    &scan.path,
+                    gain_for_loudness(loudness),
+                    scan.peak,
+                    gain_for_loudness(album_loudness),
+                    scan.peak.max(album_peak),
+                )
+            });
+            match result {
+                Ok(()) => tagged += 1,
+                Err(e) => eprintln!("\r\x1b[2K✗ {}", t_args("rgscan.failed", &[("name", &name), ("error", &e)])),
+            }
+        }
+        eprintln!(
+            "\r\x1b[2K{}",
+            t_args(
+                "rgscan.album",
+                &[("album", &folder.display()), ("gain", &format_gain(gain_for_loudness(album_loudness)))]
+            )
+        );
+    }
+
+    eprintln!("{}", t_args("rgscan.done", &[("count", &tagged), ("albums", &albums.len())]));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::source::SineWave;
+    use std::time::Duration;
+
+    #[test]
+    fn test_gain_formatting() {
+        assert_eq!(format_gain(gain_for_loudness(-11.48)), "-6.52 dB");
+        assert_eq!(format_gain(gain_for_loudness(-23.0)), "5.00 dB");
+        assert_eq!(format_peak(0.98854732), "0.988547");
+    }
+
+    #[test]
+    fn test_group_albums_by_folder() {
+        let songs = vec![
+            Song::new("b".to_string(), PathBuf::from("/music/Album B/01.mp3")),
+            Song::new("a1".to_string(), PathBuf::from("/music/Album A/01.flac")),
+            Song::new("a2".to_string(), PathBuf::from("/music/Album A/02.flac")),
+        ];
+        let albums = group_albums(songs);
+        assert_eq!(albums.len(), 2);
+        assert_eq!(albums[Path::new("/music/Album A")].len(), 2);
+    }
+
+    #[test]
+    fn test_analyze_full_scale_sine() {
+        // A full-scale 1kHz mono sine measures about -3 LUFS
+        let sine = SineWave::new(1000.0).take_duration(Duration::from_secs(3)).convert_samples::<i16>();
+        let (meter, peak) = analyze(sine).unwrap();
+        let loudness = meter.loudness_global().unwrap();
+        assert!((loudness + 3.0).abs() < 0.5, "{loudness}");
+        assert!(peak > 0.99);
+    }
+}
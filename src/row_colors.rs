@@ -0,0 +1,103 @@
+use std::{path::Path, str::FromStr};
+
+use ratatui::style::Color;
+use serde::{Deserialize, Deserializer, de};
+
+// Formats that keep every bit of the original; the rest are lossy
+const LOSSLESS_EXTENSIONS: [&str; 2] = ["flac", "wav"];
+
+/// A `[[row_colors]]` entry: list rows matching every condition it sets are drawn in `color`.
+/// Rules are tried in order and the first match wins.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct RowColorRule {
+    // A color name ("cyan"), a 256-color index ("214") or "#rrggbb"
+    #[serde(deserialize_with = "deserialize_color")]
+    pub color: Color,
+    // Matched against the genre tag, ignoring case
+    pub genre: Option<String>,
+    pub min_rating: Option<u8>,
+    pub unplayed: Option<bool>,
+    pub lossless: Option<bool>,
+}
+
+fn deserialize_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    Color::from_str(&name).map_err(|_| de::Error::custom(format!("unknown color \"{name}\"")))
+}
+
+/// What the rules can look at for one local track.
+pub struct RowFacts<'a> {
+    pub genre: Option<&'a str>,
+    pub rating: Option<u8>,
+    pub play_count: u32,
+    pub lossless: bool,
+}
+
+impl RowColorRule {
+    fn matches(&self, facts: &RowFacts) -> bool {
+        self.genre
+            .as_ref()
+            .is_none_or(|genre| facts.genre.is_some_and(|tag| tag.eq_ignore_ascii_case(genre)))
+            && self.min_rating.is_none_or(|min| facts.rating.is_some_and(|rating| rating >= min))
+            && self.unplayed.is_none_or(|unplayed| unplayed == (facts.play_count == 0))
+            && self.lossless.is_none_or(|lossless| lossless == facts.lossless)
+    }
+}
+
+/// The color of the first rule that matches, if any.
+pub fn color_for(rules: &[RowColorRule], facts: &RowFacts) -> Option<Color> {
+    rules.iter().find(|rule| rule.matches(facts)).map(|rule| rule.color)
+}
+
+pub fn is_lossless(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| LOSSLESS_EXTENSIONS.iter().any(|lossless| ext.eq_ignore_ascii_case(lossless)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Rules {
+        row_colors: Vec<RowColorRule>,
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let rules: Rules = toml::from_str(
+            r##"
+            [[row_colors]]
+            color = "yellow"
+            min_rating = 4
+
+            [[row_colors]]
+            color = "#5fafff"
+            genre = "jazz"
+
+            [[row_colors]]
+            color = "240"
+            unplayed = true
+            lossless = false
+            "##,
+        )
+        .unwrap();
+        let rules = rules.row_colors;
+        let facts = |genre, rating, play_count, lossless| RowFacts {
+            genre,
+            rating,
+            play_count,
+            lossless,
+        };
+
+        assert_eq!(color_for(&rules, &facts(Some("Jazz"), Some(5), 3, true)), Some(Color::Yellow));
+        assert_eq!(color_for(&rules, &facts(Some("JAZZ"), Some(2), 3, true)), Some(Color::Rgb(0x5f, 0xaf, 0xff)));
+        assert_eq!(color_for(&rules, &facts(None, None, 0, false)), Some(Color::Indexed(240)));
+        assert_eq!(color_for(&rules, &facts(None, None, 0, true)), None);
+        assert_eq!(color_for(&rules, &facts(Some("Rock"), Some(3), 1, false)), None);
+
+        assert!(toml::from_str::<Rules>("[[row_colors]]\ncolor = \"sparkly\"\n").is_err());
+        assert!(is_lossless(Path::new("/music/a.FLAC")) && !is_lossless(Path::new("/music/a.mp3")));
+    }
+}
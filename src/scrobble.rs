@@ -0,0 +1,350 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, RecvTimeoutError, Sender},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::ScrobbleConfig, history::PlayEvent, paths};
+
+const QUEUE_FILE: &str = "scrobble-queue.jsonl";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+// ListenBrainz accepts up to this many listens per import request
+const BATCH_SIZE: usize = 100;
+const MIN_BACKOFF: Duration = Duration::from_secs(30);
+const MAX_BACKOFF: Duration = Duration::from_secs(30 * 60);
+// Scrobbling rules shared by Last.fm and ListenBrainz
+const MIN_TRACK_SECONDS: f64 = 30.0;
+const ENOUGH_LISTENED_SECONDS: f64 = 4.0 * 60.0;
+
+/// A listen waiting to be submitted.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Scrobble {
+    pub listened_at: i64,
+    pub artist: String,
+    pub track: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u64>,
+}
+
+impl Scrobble {
+    /// The scrobble for a finished play, if it counts: the track is over 30 seconds and at least
+    /// half of it (or four minutes) was heard. Untagged tracks have no artist and are skipped.
+    pub fn from_event(event: &PlayEvent) -> Option<Self> {
+        let duration = event.duration?;
+        let artist = event.artist.clone()?;
+        if duration < MIN_TRACK_SECONDS || event.listened < (duration / 2.0).min(ENOUGH_LISTENED_SECONDS) {
+            return None;
+        }
+        Some(Scrobble {
+            listened_at: event.started,
+            artist,
+            track: event.title.clone(),
+            release: event.album.clone(),
+            duration: Some(duration.round() as u64),
+        })
+    }
+}
+
+fn payload(batch: &[Scrobble]) -> serde_json::Value {
+    let listens: Vec<serde_json::Value> = batch
+        .iter()
+        .map(|scrobble| {
+            let mut metadata = serde_json::json!({
+                "artist_name": scrobble.artist,
+                "track_name": scrobble.track,
+                "additional_info": { "submission_client": "musix", "submission_client_version": env!("CARGO_PKG_VERSION") },
+            });
+            if let Some(release) = &scrobble.release {
+                metadata["release_name"] = release.as_str().into();
+            }
+            if let Some(duration) = scrobble.duration {
+                metadata["additional_info"]["duration"] = duration.into();
+            }
+            serde_json::json!({ "listened_at": scrobble.listened_at, "track_metadata": metadata })
+        })
+        .collect();
+    let listen_type = if batch.len() == 1 { "single" } else { "import" };
+    serde_json::json!({ "listen_type": listen_type, "payload": listens })
+}
+
+pub fn queue_path() -> PathBuf {
+    paths::file_in(paths::state_dir(), QUEUE_FILE)
+}
+
+fn load_queue(file: &Path) -> Vec<Scrobble> {
+    fs::read_to_string(file)
+        .map(|text| text.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+        .unwrap_or_default()
+}
+
+fn save_queue(file: &Path, queue: &[Scrobble]) -> io::Result<()> {
+    let mut text = String::new();
+    for scrobble in queue {
+        text.push_str(&serde_json::to_string(scrobble).map_err(io::Error::other)?);
+        text.push('\n');
+    }
+    paths::write_atomic(file, text)
+}
+
+// A new listen goes on the end of the file rather than rewriting it; a line cut short by a crash is skipped on load
+fn append_to_queue(file: &Path, scrobble: &Scrobble) -> io::Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let line = serde_json::to_string(scrobble).map_err(io::Error::other)?;
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file)?
+        .write_all(format!("{line}\n").as_bytes())
+}
+
+/// Doubling retry delay for when the scrobble service can't be reached.
+struct Backoff {
+    delay: Duration,
+}
+
+impl Backoff {
+    fn new() -> Self {
+        Backoff { delay: MIN_BACKOFF }
+    }
+
+    fn next_delay(&mut self) -> Duration {
+        let delay = self.delay;
+        self.delay = (self.delay * 2).min(MAX_BACKOFF);
+        delay
+    }
+
+    fn reset(&mut self) {
+        self.delay = MIN_BACKOFF;
+    }
+}
+
+enum SubmitError {
+    // Offline, timed out or a server error: keep the batch and try again later
+    Retry,
+    // The service refused the data itself; retrying would fail the same way
+    Rejected,
+    // The token is wrong or was revoked: nothing goes through until it is changed
+    Unauthorized,
+}
+
+fn submit(agent: &ureq::Agent, config: &ScrobbleConfig, token: &str, batch: &[Scrobble]) -> Result<(), SubmitError> {
+    let result = agent
+        .post(&format!("{}/1/submit-listens", config.api_url.trim_end_matches('/')))
+        .set("User-Agent", concat!("musix/", env!("CARGO_PKG_VERSION")))
+        .set("Authorization", &format!("Token {token}"))
+        .set("Content-Type", "application/json")
+        .send_string(&payload(batch).to_string());
+    match result {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(400, _)) => Err(SubmitError::Rejected),
+        Err(ureq::Error::Status(401 | 403, _)) => Err(SubmitError::Unauthorized),
+        Err(_) => Err(SubmitError::Retry),
+    }
+}
+
+/// Submits listens to ListenBrainz from a background thread. Listens are queued on disk first,
+/// so they survive network outages and restarts, and are flushed with backoff once the service is reachable.
+pub struct Scrobbler {
+    // Wakes the worker when a listen was queued
+    sender: Option<Sender<()>>,
+    // Shared with the worker, and written to the queue file under the same lock so neither overwrites the other
+    queue: Arc<Mutex<Vec<Scrobble>>>,
+    queue_file: PathBuf,
+}
+
+impl Scrobbler {
+    pub fn disabled() -> Self {
+        Scrobbler {
+            sender: None,
+            queue: Arc::new(Mutex::new(Vec::new())),
+            queue_file: PathBuf::new(),
+        }
+    }
+
+    /// Start the worker if scrobbling is configured; a queue left by an earlier session is flushed right away.
+    pub fn start(config: &ScrobbleConfig, queue_file: PathBuf) -> Self {
+        let Some(token) = config.listenbrainz_token.clone().filter(|token| !token.trim().is_empty()) else {
+            return Self::disabled();
+        };
+
+        let config = config.clone();
+        let queue = Arc::new(Mutex::new(load_queue(&queue_file)));
+        let worker_queue = Arc::clone(&queue);
+        let worker_file = queue_file.clone();
+        let (sender, receiver) = mpsc::channel::<()>();
+        thread::spawn(move || {
+            let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+            let mut backoff = Backoff::new();
+            let mut retry_at = Some(Instant::now());
+
+            loop {
+                // Sleep until the next retry is due or a new listen arrives
+                let waiting = !worker_queue.lock().unwrap().is_empty();
+                let received = match retry_at.filter(|_| waiting) {
+                    Some(at) => receiver.recv_timeout(at.saturating_duration_since(Instant::now())),
+                    None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                };
+                if let Err(RecvTimeoutError::Disconnected) = received {
+                    break;
+                }
+
+                if retry_at.is_none_or(|at| at <= Instant::now()) {
+                    retry_at = None;
+                    loop {
+                        // Sent without holding the lock, so queuing a listen never waits on the network
+                        let batch: Vec<Scrobble> = worker_queue.lock().unwrap().iter().take(BATCH_SIZE).cloned().collect();
+                        if batch.is_empty() {
+                            break;
+                        }
+                        match submit(&agent, &config, &token, &batch) {
+                            Ok(()) | Err(SubmitError::Rejected) => {
+                                // New listens only ever go on the end, so the batch is still at the front
+                                let mut queue = worker_queue.lock().unwrap();
+                                queue.drain(..batch.len());
+                                let _ = save_queue(&worker_file, &queue);
+                                backoff.reset();
+                            }
+                            Err(SubmitError::Retry) => {
+                                retry_at = Some(Instant::now() + backoff.next_delay());
+                                break;
+                            }
+                            // Listens keep being queued on disk, for the next session with a working token
+                            Err(SubmitError::Unauthorized) => return,
+                        }
+                    }
+                }
+            }
+        });
+
+        Scrobbler {
+            sender: Some(sender),
+            queue,
+            queue_file,
+        }
+    }
+
+    /// Queue a listen; it is on disk once this returns, so quitting right after never loses it.
+    pub fn submit(&self, scrobble: Scrobble) {
+        if let Some(sender) = &self.sender {
+            let mut queue = self.queue.lock().unwrap();
+            let _ = append_to_queue(&self.queue_file, &scrobble);
+            queue.push(scrobble);
+            drop(queue);
+            let _ = sender.send(());
+        }
+    }
+
+    /// Listens queued but not yet accepted by the service.
+    pub fn pending_count(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    fn event(artist: Option<&str>, listened: f64, duration: Option<f64>) -> PlayEvent {
+        PlayEvent {
+            path: "/music/a.mp3".to_string(),
+            title: "Song".to_string(),
+            artist: artist.map(str::to_string),
+            album: Some("Album".to_string()),
+            started: 1000,
+            listened,
+            duration,
+            completed: false,
+        }
+    }
+
+    #[test]
+    fn test_scrobble_eligibility() {
+        assert!(Scrobble::from_event(&event(Some("X"), 100.0, Some(180.0))).is_some());
+        assert!(Scrobble::from_event(&event(Some("X"), 80.0, Some(180.0))).is_none());
+        // Four minutes is enough for long tracks
+        assert!(Scrobble::from_event(&event(Some("X"), 240.0, Some(1200.0))).is_some());
+        assert!(Scrobble::from_event(&event(Some("X"), 20.0, Some(25.0))).is_none());
+        assert!(Scrobble::from_event(&event(None, 180.0, Some(180.0))).is_none());
+        assert!(Scrobble::from_event(&event(Some("X"), 180.0, None)).is_none());
+    }
+
+    #[test]
+    fn test_queue_round_trip_and_payload() {
+        let dir = TestDir::new("scrobbles");
+        let file = dir.join("scrobbles.jsonl");
+        let scrobble = Scrobble::from_event(&event(Some("X"), 180.0, Some(180.0))).unwrap();
+        save_queue(&file, &[scrobble.clone(), scrobble.clone()]).unwrap();
+        assert_eq!(load_queue(&file), vec![scrobble.clone(), scrobble.clone()]);
+        fs::remove_file(file).unwrap();
+
+        let json = payload(std::slice::from_ref(&scrobble));
+        assert_eq!(json["listen_type"], "single");
+        assert_eq!(json["payload"][0]["listened_at"], 1000);
+        assert_eq!(json["payload"][0]["track_metadata"]["release_name"], "Album");
+        assert_eq!(json["payload"][0]["track_metadata"]["additional_info"]["duration"], 180);
+        assert_eq!(payload(&[scrobble.clone(), scrobble])["listen_type"], "import");
+    }
+
+    #[test]
+    fn test_listens_are_on_disk_once_submitted() {
+        let dir = TestDir::new("scrobbles-submitted");
+        let file = dir.join("scrobbles-submitted.jsonl");
+        // Nothing listens there, so the worker keeps the listen queued
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = ScrobbleConfig {
+            listenbrainz_token: Some("token".to_string()),
+            api_url: format!("http://{}", listener.local_addr().unwrap()),
+        };
+        drop(listener);
+        let scrobbler = Scrobbler::start(&config, file.clone());
+        let scrobble = Scrobble::from_event(&event(Some("X"), 180.0, Some(180.0))).unwrap();
+        scrobbler.submit(scrobble.clone());
+        assert_eq!(load_queue(&file), vec![scrobble]);
+        assert_eq!(scrobbler.pending_count(), 1);
+    }
+
+    #[test]
+    fn test_a_refused_token_is_not_retried() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = ScrobbleConfig {
+            listenbrainz_token: Some("revoked".to_string()),
+            api_url: format!("http://{}", listener.local_addr().unwrap()),
+        };
+        let server = thread::spawn(move || {
+            let (mut connection, _) = listener.accept().unwrap();
+            let mut request = [0; 4096];
+            let _ = io::Read::read(&mut connection, &mut request);
+            connection.write_all(b"HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\n\r\n").unwrap();
+        });
+        let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+        let scrobble = Scrobble::from_event(&event(Some("X"), 180.0, Some(180.0))).unwrap();
+        assert!(matches!(submit(&agent, &config, "revoked", &[scrobble]), Err(SubmitError::Unauthorized)));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_limit() {
+        let mut backoff = Backoff::new();
+        assert_eq!(backoff.next_delay(), MIN_BACKOFF);
+        assert_eq!(backoff.next_delay(), MIN_BACKOFF * 2);
+        for _ in 0..20 {
+            backoff.next_delay();
+        }
+        assert_eq!(backoff.next_delay(), MAX_BACKOFF);
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), MIN_BACKOFF);
+    }
+}
@@ -0,0 +1,109 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::paths;
+
+const SEARCH_HISTORY_FILE: &str = "search_history.json";
+// Older queries fall off the end beyond this
+const MAX_ENTRIES: usize = 100;
+
+/// Past search queries, newest first, recalled with Up/Down in search mode and kept across sessions.
+#[derive(Default, Debug)]
+pub struct SearchHistory {
+    // None keeps the history in memory only
+    file: Option<PathBuf>,
+    entries: Vec<String>,
+    // The entry last recalled into the query
+    cursor: Option<usize>,
+}
+
+pub fn search_history_path() -> PathBuf {
+    paths::file_in(paths::state_dir(), SEARCH_HISTORY_FILE)
+}
+
+impl SearchHistory {
+    pub fn load(file: &Path) -> Self {
+        let entries = fs::read_to_string(file)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        SearchHistory {
+            file: Some(file.to_path_buf()),
+            entries,
+            cursor: None,
+        }
+    }
+
+    /// Remember `query` as the newest entry, dropping an earlier copy of it, and write the history out.
+    pub fn push(&mut self, query: &str) -> io::Result<()> {
+        self.cursor = None;
+        let query = query.trim();
+        if query.is_empty() {
+            return Ok(());
+        }
+        self.entries.retain(|entry| entry != query);
+        self.entries.insert(0, query.to_string());
+        self.entries.truncate(MAX_ENTRIES);
+
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(&self.entries).map_err(io::Error::other)?;
+        fs::write(file, json)
+    }
+
+    /// Whether `query` is still the entry last recalled, i.e. the user hasn't edited it since.
+    pub fn is_recalled(&self, query: &str) -> bool {
+        self.cursor.and_then(|cursor| self.entries.get(cursor)).is_some_and(|entry| entry == query)
+    }
+
+    /// The next older query, staying on the oldest one at the end.
+    pub fn older(&mut self) -> Option<&str> {
+        let next = self.cursor.map_or(0, |cursor| cursor + 1).min(self.entries.len().checked_sub(1)?);
+        self.cursor = Some(next);
+        self.entries.get(next).map(String::as_str)
+    }
+
+    /// The next newer query; past the newest one the query is empty again.
+    pub fn newer(&mut self) -> &str {
+        self.cursor = self.cursor.and_then(|cursor| cursor.checked_sub(1));
+        self.cursor.and_then(|cursor| self.entries.get(cursor)).map_or("", String::as_str)
+    }
+
+    pub fn reset(&mut self) {
+        self.cursor = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_recall_and_persist() {
+        let dir = TestDir::new("search-history");
+        let file = dir.join("search-history.json");
+        let mut history = SearchHistory::load(&file);
+        assert_eq!(history.older(), None);
+
+        for query in ["jazz", "rock", " jazz ", ""] {
+            history.push(query).unwrap();
+        }
+        let mut history = SearchHistory::load(&file);
+        assert_eq!(history.entries, vec!["jazz", "rock"]);
+
+        assert_eq!(history.older(), Some("jazz"));
+        assert!(history.is_recalled("jazz") && !history.is_recalled("jaz"));
+        assert_eq!(history.older(), Some("rock"));
+        assert_eq!(history.older(), Some("rock"));
+        assert_eq!(history.newer(), "jazz");
+        assert_eq!(history.newer(), "");
+        assert!(!history.is_recalled(""));
+    }
+}
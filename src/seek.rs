@@ -0,0 +1,184 @@
+use std::{fs::File, path::Path, time::Duration};
+
+use rodio::{Source, source::SeekError};
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{CODEC_TYPE_NULL, Decoder, DecoderOptions},
+    errors::Error,
+    formats::{FormatOptions, FormatReader, SeekMode, SeekTo},
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+};
+
+use crate::{error::MusixError, i18n::t};
+
+/// A track decoded with symphonia from an exact position, for files whose rodio decoder can't seek. Symphonia
+/// seeks to the packet holding the position and the samples before it are dropped, so playback starts on the
+/// sample asked for without decoding the whole track up to there.
+pub struct SeekedSource {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    // The decoded packet, interleaved, and how far into it playback is
+    buffer: Vec<i16>,
+    position: usize,
+    channels: u16,
+    sample_rate: u32,
+    // Frames still to drop after a seek, up to the one asked for
+    skip: u64,
+}
+
+/// Open `path` and seek to `start`.
+pub fn open_at(path: &Path, start: Duration) -> Result<SeekedSource, MusixError> {
+    let file = File::open(path)?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+    let options = FormatOptions {
+        enable_gapless: true,
+        ..Default::default()
+    };
+    let format = symphonia::default::get_probe()
+        .format(&hint, stream, &options, &MetadataOptions::default())?
+        .format;
+    let track = format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| MusixError::Decode(t("error.unsupported_format").to_string()))?;
+    let decoder = symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+    let mut source = SeekedSource {
+        track_id: track.id,
+        channels: track.codec_params.channels.map_or(2, |channels| channels.count() as u16),
+        sample_rate: track.codec_params.sample_rate.unwrap_or(44100),
+        format,
+        decoder,
+        buffer: Vec::new(),
+        position: 0,
+        skip: 0,
+    };
+    source.seek(start)?;
+    // The first packet sets the real channel count and rate
+    source.refill();
+    Ok(source)
+}
+
+impl SeekedSource {
+    fn seek(&mut self, position: Duration) -> Result<(), Error> {
+        let seeked = self.format.seek(
+            SeekMode::Accurate,
+            SeekTo::Time {
+                time: position.into(),
+                track_id: Some(self.track_id),
+            },
+        )?;
+        self.decoder.reset();
+        self.buffer.clear();
+        self.position = 0;
+        self.skip = seeked.required_ts.saturating_sub(seeked.actual_ts);
+        Ok(())
+    }
+
+    // Decode packets until one has samples left after the skip; at the end of the track the buffer is left as it was
+    fn refill(&mut self) {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                // The end of the file, or a stream that can't go on
+                Err(_) => return,
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                // A corrupt packet is skipped like other players do
+                Err(Error::DecodeError(_)) => continue,
+                Err(_) => return,
+            };
+            let frames = decoded.frames() as u64;
+            if self.skip >= frames {
+                self.skip -= frames;
+                continue;
+            }
+            let spec = *decoded.spec();
+            let mut samples = SampleBuffer::<i16>::new(decoded.capacity() as u64, spec);
+            samples.copy_interleaved_ref(decoded);
+            self.channels = spec.channels.count() as u16;
+            self.sample_rate = spec.rate;
+            self.buffer.clear();
+            self.buffer.extend_from_slice(samples.samples());
+            self.position = self.skip as usize * self.channels as usize;
+            self.skip = 0;
+            if self.position < self.buffer.len() {
+                return;
+            }
+        }
+    }
+}
+
+impl Iterator for SeekedSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = *self.buffer.get(self.position)?;
+        self.position += 1;
+        if self.position >= self.buffer.len() {
+            // Decoded right away, so the frame length is already the next packet's
+            self.refill();
+        }
+        Some(sample)
+    }
+}
+
+impl Source for SeekedSource {
+    // The rest of the decoded packet, as the format may change from one packet to the next
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.buffer.len() - self.position)
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.seek(pos).map_err(|error| SeekError::Other(Box::new(error)))?;
+        self.refill();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::WavWriter;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_playback_starts_on_the_sample_asked_for() {
+        // Two seconds of mono at 8 kHz, each sample holding its own index
+        let dir = TestDir::new("seek");
+        let path = dir.join("seek.wav");
+        let mut writer = WavWriter::new(File::create(&path).unwrap(), 1, 8000).unwrap();
+        writer.write_samples(&(0..16000).map(|i| i as i16).collect::<Vec<_>>()).unwrap();
+        writer.finish().unwrap();
+
+        let mut source = open_at(&path, Duration::from_millis(1250)).unwrap();
+        assert_eq!((source.channels(), source.sample_rate()), (1, 8000));
+        assert_eq!(source.next(), Some(10000));
+        source.try_seek(Duration::from_millis(500)).unwrap();
+        assert_eq!(source.next(), Some(4000));
+        assert_eq!(source.count(), 11999);
+    }
+}
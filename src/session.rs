@@ -0,0 +1,110 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{columns::Column, paths, repeat::RepeatMode, shuffle::ShuffleMode};
+
+const SESSION_FILE: &str = "session.json";
+
+/// What the player was doing, snapshotted while it runs and once more on quit.
+/// The next start picks up from it; one without `clean_exit` means the last session ended in a crash.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Session {
+    // Paths rather than indexes, since the library may have changed in between
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub track: Option<String>,
+    #[serde(default)]
+    pub position: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selected: Option<String>,
+    #[serde(default)]
+    pub shuffle: ShuffleMode,
+    // Snapshots from before repeat-one had a `loop_mode` that was always on, which is the default
+    #[serde(default)]
+    pub repeat: RepeatMode,
+    #[serde(default)]
+    pub sort_by_bpm: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort_column: Option<Column>,
+    // 0.0 to 1.0; snapshots from before volume control play at full volume
+    #[serde(default = "full_volume")]
+    pub volume: f32,
+    // Unix timestamp of the snapshot
+    pub saved: i64,
+    #[serde(default)]
+    pub clean_exit: bool,
+}
+
+fn full_volume() -> f32 {
+    1.0
+}
+
+pub fn session_path() -> PathBuf {
+    paths::file_in(paths::state_dir(), SESSION_FILE)
+}
+
+/// Replace the snapshot.
+pub fn save(file: &Path, session: &Session) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(session).map_err(io::Error::other)?;
+    paths::write_atomic(file, json)
+}
+
+pub fn load(file: &Path) -> Option<Session> {
+    fs::read_to_string(file).ok().and_then(|json| serde_json::from_str(&json).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    fn session() -> Session {
+        Session {
+            track: Some("/music/a.mp3".to_string()),
+            position: 42.5,
+            selected: Some("/music/b.mp3".to_string()),
+            shuffle: ShuffleMode::Weighted,
+            repeat: RepeatMode::One,
+            sort_by_bpm: false,
+            sort_column: Some(Column::Artist),
+            volume: 0.65,
+            saved: 1000,
+            clean_exit: false,
+        }
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let dir = TestDir::new("session");
+        let file = dir.join("session.json");
+        assert_eq!(load(&file), None);
+
+        save(&file, &session()).unwrap();
+        assert_eq!(load(&file), Some(session()));
+    }
+
+    #[test]
+    fn test_quit_snapshot_replaces_the_periodic_one() {
+        let dir = TestDir::new("session-quit");
+        let file = dir.join("session-quit.json");
+        save(&file, &session()).unwrap();
+        let quit = Session { clean_exit: true, ..session() };
+        save(&file, &quit).unwrap();
+        assert_eq!(load(&file), Some(quit));
+    }
+
+    #[test]
+    fn test_older_snapshots_play_at_full_volume() {
+        let old: Session = serde_json::from_str(r#"{"saved": 1000}"#).unwrap();
+        assert_eq!(old.volume, 1.0);
+    }
+
+    #[test]
+    fn test_older_loop_setting_repeats_all() {
+        let looping: Session = serde_json::from_str(r#"{"saved": 1000, "loop_mode": true}"#).unwrap();
+        assert_eq!(looping.repeat, RepeatMode::All);
+    }
+}
@@ -0,0 +1,94 @@
+use std::{fs, path::Path};
+
+use crate::{
+    error::MusixError,
+    history::{self, PlayEvent},
+    i18n::{t, t_args},
+};
+
+/// The plays from the listening history that started at or after `since` (a Unix timestamp), in the order heard.
+pub fn plays_since(events: &[PlayEvent], since: i64) -> Vec<&PlayEvent> {
+    events.iter().filter(|event| event.started >= since).collect()
+}
+
+// Extended M3U, which media players and most sharing sites read; paths as they were played
+fn m3u(plays: &[&PlayEvent]) -> String {
+    let mut text = String::from("#EXTM3U\n");
+    for play in plays {
+        let seconds = play.duration.map_or(-1, |duration| duration.round() as i64);
+        let name = match &play.artist {
+            Some(artist) => format!("{artist} - {}", play.title),
+            None => play.title.clone(),
+        };
+        text.push_str(&format!("#EXTINF:{seconds},{name}\n{}\n", play.path));
+    }
+    text
+}
+
+/// Write `plays` to `file` as a playlist: JSON for a `.json` file, with when each track started and how long it was
+/// heard, and M3U for `.m3u` or `.m3u8`.
+pub fn write(file: &Path, plays: &[&PlayEvent]) -> Result<(), MusixError> {
+    let extension = file.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase);
+    let text = match extension.as_deref() {
+        Some("json") => serde_json::to_string_pretty(plays).map_err(|e| MusixError::Decode(e.to_string()))?,
+        Some("m3u" | "m3u8") => m3u(plays),
+        _ => return Err(MusixError::Config(t_args("setlist.format", &[("file", &file.display())]))),
+    };
+    if let Some(dir) = file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(file, text)?;
+    Ok(())
+}
+
+/// Entry point for `musix log setlist <file>`: the plays of the last `hours`, so a party that ran past midnight is
+/// there in full.
+pub fn run(file: &Path, hours: u32) -> Result<(), MusixError> {
+    let events = history::load(&history::history_path());
+    let since = chrono::Utc::now().timestamp() - i64::from(hours) * 3600;
+    let plays = plays_since(&events, since);
+    if plays.is_empty() {
+        return Err(MusixError::Config(t("setlist.empty").to_string()));
+    }
+    write(file, &plays)?;
+    eprintln!("{}", t_args("setlist.written", &[("count", &plays.len()), ("file", &file.display())]));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    fn play(title: &str, artist: Option<&str>, started: i64) -> PlayEvent {
+        PlayEvent {
+            path: format!("/music/{title}.flac"),
+            title: title.to_string(),
+            artist: artist.map(str::to_string),
+            album: None,
+            started,
+            listened: 100.0,
+            duration: Some(241.6),
+            completed: true,
+        }
+    }
+
+    #[test]
+    fn test_setlist_formats() {
+        let events = [play("Early", None, 100), play("Voyager", Some("Daft Punk"), 2000), play("Intro", None, 3000)];
+        let plays = plays_since(&events, 2000);
+        assert_eq!(plays.len(), 2);
+        assert_eq!(
+            m3u(&plays),
+            "#EXTM3U\n#EXTINF:242,Daft Punk - Voyager\n/music/Voyager.flac\n#EXTINF:242,Intro\n/music/Intro.flac\n"
+        );
+
+        let dir = TestDir::new("setlist");
+        write(&dir.join("party.json"), &plays).unwrap();
+        let written: Vec<PlayEvent> = serde_json::from_str(&fs::read_to_string(dir.join("party.json")).unwrap()).unwrap();
+        assert_eq!(written, events[1..]);
+        write(&dir.join("party.M3U8"), &plays).unwrap();
+        assert!(fs::read_to_string(dir.join("party.M3U8")).unwrap().starts_with("#EXTM3U\n"));
+        assert!(write(&dir.join("party.txt"), &plays).is_err());
+    }
+}
@@ -0,0 +1,63 @@
+use serde::Deserialize;
+
+/// A site to look a track up on, for sharing it with someone on another service.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Service {
+    #[default]
+    MusicBrainz,
+    Songwhip,
+    YouTube,
+}
+
+impl Service {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "musicbrainz" | "mb" => Some(Service::MusicBrainz),
+            "songwhip" => Some(Service::Songwhip),
+            "youtube" | "yt" => Some(Service::YouTube),
+            _ => None,
+        }
+    }
+
+    /// A search for `query` (artist and title) on the service.
+    pub fn search_url(self, query: &str) -> String {
+        let query = encode(query);
+        match self {
+            Service::MusicBrainz => format!("https://musicbrainz.org/search?query={query}&type=recording&method=indexed"),
+            Service::Songwhip => format!("https://songwhip.com/?q={query}"),
+            Service::YouTube => format!("https://www.youtube.com/results?search_query={query}"),
+        }
+    }
+}
+
+// Percent-encoding for a query parameter: everything but the unreserved characters, spaces included
+fn encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_urls_encode_the_query() {
+        assert_eq!(
+            Service::YouTube.search_url("Sigur Rós Hoppípolla"),
+            "https://www.youtube.com/results?search_query=Sigur%20R%C3%B3s%20Hopp%C3%ADpolla"
+        );
+        assert_eq!(
+            Service::MusicBrainz.search_url("AC/DC T.N.T."),
+            "https://musicbrainz.org/search?query=AC%2FDC%20T.N.T.&type=recording&method=indexed"
+        );
+        assert_eq!(Service::parse("YT"), Some(Service::YouTube));
+        assert_eq!(Service::parse("spotify"), None);
+    }
+}
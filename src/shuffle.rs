@@ -0,0 +1,158 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::ShuffleConfig;
+
+// Unrated tracks are treated as this many stars, so rating a track can move it either way
+const NEUTRAL_RATING: f64 = 3.0;
+const SECONDS_PER_DAY: f64 = 86400.0;
+
+/// How the next song is chosen when one finishes or is skipped.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ShuffleMode {
+    #[default]
+    Off,
+    Random,
+    // Random picks favour highly rated and long-unplayed tracks
+    Weighted,
+    // Always queue the track that has gone unheard the longest
+    Rediscover,
+}
+
+impl ShuffleMode {
+    // Off -> random -> weighted -> rediscover -> off
+    pub fn next(self) -> Self {
+        match self {
+            ShuffleMode::Off => ShuffleMode::Random,
+            ShuffleMode::Random => ShuffleMode::Weighted,
+            ShuffleMode::Weighted => ShuffleMode::Rediscover,
+            ShuffleMode::Rediscover => ShuffleMode::Off,
+        }
+    }
+}
+
+/// Relative chance of a track being picked by the weighted shuffle.
+pub fn track_weight(rating: Option<u8>, last_played: Option<i64>, now: i64, config: &ShuffleConfig) -> f64 {
+    let stars = rating.map_or(NEUTRAL_RATING, f64::from);
+    let rating_factor = (stars / NEUTRAL_RATING).powf(config.rating_weight);
+
+    // Ramps from 0 right after a play up to 1 once `recency_days` have passed
+    let freshness = match last_played {
+        Some(played) if config.recency_days > 0.0 => ((now - played).max(0) as f64 / (config.recency_days * SECONDS_PER_DAY)).min(1.0),
+        _ => 1.0,
+    };
+    let recency_factor = freshness.powf(config.recency_weight);
+
+    rating_factor * recency_factor
+}
+
+/// Pick an index with probability proportional to its weight; `fraction` is a uniform number in [0, 1).
+pub fn pick_weighted(weights: &[f64], fraction: f64) -> Option<usize> {
+    let total: f64 = weights.iter().sum();
+    if total <= 0.0 || !total.is_finite() {
+        return None;
+    }
+
+    let mut target = fraction * total;
+    for (index, &weight) in weights.iter().enumerate() {
+        if target < weight {
+            return Some(index);
+        }
+        target -= weight;
+    }
+    // Rounding can leave the target just past the end
+    weights.iter().rposition(|&weight| weight > 0.0)
+}
+
+/// Index of the track played longest ago; never-played tracks come first, ties go to the earliest index.
+pub fn pick_least_recent(last_played: &[Option<i64>]) -> Option<usize> {
+    last_played
+        .iter()
+        .enumerate()
+        .min_by_key(|&(_, played)| played.unwrap_or(i64::MIN))
+        .map(|(index, _)| index)
+}
+
+/// Leave out the candidates by an artist in `recent`, the lowercased artists of the last tracks played, so one
+/// artist doesn't play back to back. Untagged tracks always stay, and when every candidate is by a recent artist
+/// they all stay, since repeating an artist beats stopping.
+pub fn spread_artists<'a>(candidates: Vec<usize>, artist: impl Fn(usize) -> Option<&'a str>, recent: &VecDeque<Option<String>>) -> Vec<usize> {
+    let is_recent = |index: usize| artist(index).is_some_and(|artist| recent.contains(&Some(artist.to_lowercase())));
+    if candidates.iter().all(|&index| is_recent(index)) {
+        return candidates;
+    }
+    candidates.into_iter().filter(|&index| !is_recent(index)).collect()
+}
+
+/// Uniform number in [0, 1) from a xorshift over the clock; good enough for picking songs.
+pub fn random_fraction() -> f64 {
+    let mut x = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64
+        | 1;
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    (x >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_track_weight() {
+        let config = ShuffleConfig::default();
+        let now = 1_000_000_000;
+        let fresh = track_weight(None, None, now, &config);
+        assert!((fresh - 1.0).abs() < 1e-9);
+
+        // Favorites come up more often, disliked tracks less
+        assert!(track_weight(Some(5), None, now, &config) > fresh);
+        assert!(track_weight(Some(1), None, now, &config) < fresh);
+
+        // Just played: (almost) never picked again; a week later fully back
+        assert!(track_weight(Some(5), Some(now - 60), now, &config) < 0.01);
+        assert!((track_weight(None, Some(now - 8 * 86400), now, &config) - 1.0).abs() < 1e-9);
+
+        let flat = ShuffleConfig {
+            rating_weight: 0.0,
+            recency_weight: 0.0,
+            ..config
+        };
+        assert_eq!(track_weight(Some(1), Some(now), now, &flat), 1.0);
+    }
+
+    #[test]
+    fn test_pick_weighted() {
+        let weights = [0.0, 1.0, 3.0];
+        assert_eq!(pick_weighted(&weights, 0.0), Some(1));
+        assert_eq!(pick_weighted(&weights, 0.24), Some(1));
+        assert_eq!(pick_weighted(&weights, 0.26), Some(2));
+        assert_eq!(pick_weighted(&weights, 0.999_999), Some(2));
+        assert_eq!(pick_weighted(&[0.0, 0.0], 0.5), None);
+        assert!((0.0..1.0).contains(&random_fraction()));
+    }
+
+    #[test]
+    fn test_pick_least_recent() {
+        assert_eq!(pick_least_recent(&[Some(300), Some(100), Some(200)]), Some(1));
+        assert_eq!(pick_least_recent(&[Some(100), None, None]), Some(1));
+        assert_eq!(pick_least_recent(&[]), None);
+        assert_eq!(ShuffleMode::Rediscover.next(), ShuffleMode::Off);
+    }
+
+    #[test]
+    fn test_spread_artists() {
+        let artists = [Some("Compilation Artist"), Some("Compilation Artist"), Some("Other"), None];
+        let artist = |index: usize| artists[index];
+        let recent = VecDeque::from([Some("compilation artist".to_string()), None]);
+        assert_eq!(spread_artists(vec![0, 1, 2, 3], artist, &recent), vec![2, 3]);
+        // Nothing else left to play
+        assert_eq!(spread_artists(vec![0, 1], artist, &recent), vec![0, 1]);
+        assert_eq!(spread_artists(vec![0, 1, 2], artist, &VecDeque::new()), vec![0, 1, 2]);
+    }
+}
@@ -0,0 +1,79 @@
+use std::{
+    ffi::OsStr,
+    io::Write,
+    process::{Command, Stdio},
+    sync::mpsc::{self, Sender},
+    thread,
+};
+
+// Speech synthesizers tried in turn; the first that runs gets the text on its stdin
+#[cfg(target_os = "macos")]
+const COMMANDS: &[&[&str]] = &[&["say"]];
+#[cfg(windows)]
+const COMMANDS: &[&[&str]] = &[&[
+    "powershell",
+    "-NoProfile",
+    "-Command",
+    "Add-Type -AssemblyName System.Speech; (New-Object System.Speech.Synthesis.SpeechSynthesizer).Speak([Console]::In.ReadToEnd())",
+]];
+#[cfg(not(any(target_os = "macos", windows)))]
+const COMMANDS: &[&[&str]] = &[&["espeak-ng", "--stdin"], &["espeak", "--stdin"]];
+
+/// Speaks announcements aloud with espeak, `say` or SAPI, or with `command` when given, for a player without a
+/// screen or for listeners who can't read it.
+pub struct Speaker {
+    lines: Sender<String>,
+}
+
+impl Speaker {
+    pub fn start(command: Vec<String>) -> Self {
+        let (lines, received) = mpsc::channel::<String>();
+        thread::spawn(move || {
+            while let Ok(mut line) = received.recv() {
+                // After skipping through several tracks only the one playing now is worth saying
+                while let Ok(newer) = received.try_recv() {
+                    line = newer;
+                }
+                if command.is_empty() {
+                    COMMANDS.iter().any(|command| speak_with(command, &line));
+                } else {
+                    speak_with(&command, &line);
+                }
+            }
+        });
+        Speaker { lines }
+    }
+
+    pub fn say(&self, line: &str) {
+        let _ = self.lines.send(line.to_string());
+    }
+}
+
+// Waits for the speech to finish so the next announcement doesn't talk over it
+fn speak_with<S: AsRef<OsStr>>(command: &[S], text: &str) -> bool {
+    let Some((program, args)) = command.split_first() else {
+        return false;
+    };
+    let Ok(mut child) = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    else {
+        return false;
+    };
+    let written = child.stdin.take().is_some_and(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+    child.wait().is_ok_and(|status| status.success()) && written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_synthesizers_are_passed_over() {
+        assert!(!speak_with(&["musix-no-such-synthesizer"], "Now playing: Voyager"));
+        assert!(!speak_with::<&str>(&[], "Now playing: Voyager"));
+    }
+}
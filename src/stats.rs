@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Datelike, Days, FixedOffset, Local, NaiveDate};
+
+use crate::history::{self, PlayEvent};
+
+const DAYS_SHOWN: u64 = 7;
+const WEEKS_SHOWN: u64 = 8;
+const TOP_COUNT: usize = 5;
+
+/// An artist, album or track with its share of the listening history.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ranked {
+    pub name: String,
+    pub plays: usize,
+    // Seconds
+    pub listened: f64,
+    pub completed: usize,
+}
+
+impl Ranked {
+    pub fn completion_rate(&self) -> f64 {
+        self.completed as f64 / self.plays.max(1) as f64
+    }
+}
+
+/// Listening statistics summarized from the history log.
+#[derive(Debug, Default, Clone)]
+pub struct Stats {
+    pub plays: usize,
+    pub completed: usize,
+    // Seconds
+    pub total_listened: f64,
+    // Minutes listened per day for the last week and per week for the last two months, oldest first
+    pub daily: Vec<(String, u64)>,
+    pub weekly: Vec<(String, u64)>,
+    pub top_artists: Vec<Ranked>,
+    pub top_albums: Vec<Ranked>,
+    pub top_tracks: Vec<Ranked>,
+}
+
+impl Stats {
+    pub fn load() -> Self {
+        Self::compute(&history::load(&history::history_path()), Local::now().fixed_offset())
+    }
+
+    pub fn compute(events: &[PlayEvent], now: DateTime<FixedOffset>) -> Self {
+        let today = now.date_naive();
+        let date_of = |event: &PlayEvent| DateTime::from_timestamp(event.started, 0).map(|time| time.with_timezone(&now.timezone()).date_naive());
+
+        let days: Vec<NaiveDate> = (0..DAYS_SHOWN).rev().filter_map(|back| today.checked_sub_days(Days::new(back))).collect();
+        let this_week = week_start(today);
+        let weeks: Vec<NaiveDate> = (0..WEEKS_SHOWN)
+            .rev()
+            .filter_map(|back| this_week.checked_sub_days(Days::new(back * 7)))
+            .collect();
+
+        let mut daily = vec![0.0; days.len()];
+        let mut weekly = vec![0.0; weeks.len()];
+        for event in events {
+            let Some(date) = date_of(event) else {
+                continue;
+            };
+            if let Some(slot) = days.iter().position(|&day| day == date) {
+                daily[slot] += event.listened;
+            }
+            if let Some(slot) = weeks.iter().position(|&week| week == week_start(date)) {
+                weekly[slot] += event.listened;
+            }
+        }
+
+        let label = |date: &NaiveDate| date.format("%m/%d").to_string();
+        let minutes = |seconds: f64| (seconds / 60.0).round() as u64;
+        Stats {
+            plays: events.len(),
+            completed: events.iter().filter(|event| event.completed).count(),
+            total_listened: events.iter().map(|event| event.listened).sum(),
+            daily: days.iter().map(label).zip(daily.into_iter().map(minutes)).collect(),
+            weekly: weeks.iter().map(label).zip(weekly.into_iter().map(minutes)).collect(),
+            top_artists: rank(events, |event| event.artist.clone()),
+            top_albums: rank(events, |event| event.album.clone()),
+            top_tracks: rank(events, |event| Some(event.title.clone())),
+        }
+    }
+
+    pub fn completion_rate(&self) -> Option<f64> {
+        (self.plays > 0).then(|| self.completed as f64 / self.plays as f64)
+    }
+}
+
+// Weeks start on Monday
+fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Days::new(u64::from(date.weekday().num_days_from_monday()))
+}
+
+// The most listened-to groups, by time heard; events without a key (e.g. untagged artists) are left out
+fn rank(events: &[PlayEvent], key: impl Fn(&PlayEvent) -> Option<String>) -> Vec<Ranked> {
+    let mut groups: HashMap<String, Ranked> = HashMap::new();
+    for event in events {
+        let Some(name) = key(event) else {
+            continue;
+        };
+        let entry = groups.entry(name.clone()).or_insert_with(|| Ranked {
+            name,
+            plays: 0,
+            listened: 0.0,
+            completed: 0,
+        });
+        entry.plays += 1;
+        entry.listened += event.listened;
+        entry.completed += usize::from(event.completed);
+    }
+
+    let mut ranked: Vec<Ranked> = groups.into_values().collect();
+    ranked.sort_by(|a, b| b.listened.total_cmp(&a.listened).then_with(|| a.name.cmp(&b.name)));
+    ranked.truncate(TOP_COUNT);
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(title: &str, artist: Option<&str>, started: i64, listened: f64, completed: bool) -> PlayEvent {
+        PlayEvent {
+            path: format!("/music/{title}.mp3"),
+            title: title.to_string(),
+            artist: artist.map(str::to_string),
+            album: None,
+            started,
+            listened,
+            duration: Some(240.0),
+            completed,
+        }
+    }
+
+    #[test]
+    fn test_compute_stats() {
+        // Wednesday 2024-05-15 12:00 UTC
+        let now = DateTime::from_timestamp(1_715_774_400, 0).unwrap().fixed_offset();
+        let day = 86400;
+        let events = vec![
+            event("a", Some("X"), 1_715_774_400 - 60, 240.0, true),
+            event("b", Some("Y"), 1_715_774_400 - day, 120.0, false),
+            event("a", Some("X"), 1_715_774_400 - 3 * day, 240.0, true),
+            event("c", None, 1_715_774_400 - 30 * day, 600.0, true),
+        ];
+        let stats = Stats::compute(&events, now);
+
+        assert_eq!(stats.plays, 4);
+        assert_eq!(stats.completion_rate(), Some(0.75));
+        assert_eq!(stats.daily.len(), 7);
+        assert_eq!(stats.daily[6], ("05/15".to_string(), 4));
+        assert_eq!(stats.daily[5].1, 2);
+        assert_eq!(stats.daily[3].1, 4);
+
+        // Monday 05/13 holds today and yesterday; the play on Sunday 05/12 falls in the week before
+        assert_eq!(stats.weekly[7], ("05/13".to_string(), 6));
+        assert_eq!(stats.weekly[6].1, 4);
+
+        assert_eq!(stats.top_artists.iter().map(|r| r.name.as_str()).collect::<Vec<_>>(), ["X", "Y"]);
+        assert_eq!(stats.top_tracks[0].name, "c");
+        assert_eq!(stats.top_tracks[1].plays, 2);
+        assert_eq!(stats.top_artists[1].completion_rate(), 0.0);
+        assert_eq!(Stats::compute(&[], now).completion_rate(), None);
+    }
+}
@@ -0,0 +1,519 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+    sync::{
+        Arc, Condvar, Mutex,
+        mpsc::{self, Receiver, TryRecvError},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use rodio::Decoder;
+
+use crate::{error::MusixError, i18n::t};
+
+// Bytes kept behind the read position so decoders can rewind while probing the format
+const REWIND_WINDOW: u64 = 256 * 1024;
+// Stop pulling from the network once this much unplayed audio is buffered
+const MAX_BUFFERED: u64 = 4 * 1024 * 1024;
+const READ_CHUNK: usize = 8 * 1024;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// A server that sends nothing for this long, headers included, counts as gone
+const READ_TIMEOUT: Duration = Duration::from_secs(15);
+// A stream counts as playing once this much is buffered; running dry after that is a rebuffer
+const PRIMED: u64 = 64 * 1024;
+// Received for this long before the bitrate is worked out from it, when the server doesn't say
+const BITRATE_SAMPLE: Duration = Duration::from_secs(2);
+
+/// Live details about an internet radio stream, updated by its network thread.
+#[derive(Default)]
+pub struct StreamInfo {
+    pub title: Option<String>,
+    pub content_type: Option<String>,
+    // In kbit/s, as the server announced it or as measured
+    pub bitrate: Option<u32>,
+    pub buffer: Option<BufferHealth>,
+    // While set, received audio bytes are also written here unchanged
+    pub recording: Option<BufWriter<File>>,
+}
+
+pub type SharedStreamInfo = Arc<Mutex<StreamInfo>>;
+
+struct BufferState {
+    // Received bytes, starting at stream offset `base`
+    data: VecDeque<u8>,
+    base: u64,
+    read_position: u64,
+    // The network side hit EOF or an error
+    finished: bool,
+    // The reader was dropped, e.g. because another song started
+    closed: bool,
+    // Reached `PRIMED` at some point
+    primed: bool,
+    // Times the reader caught up with the network after the stream was primed
+    stalls: u32,
+}
+
+struct Shared {
+    state: Mutex<BufferState>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn new() -> Arc<Self> {
+        Arc::new(Shared {
+            state: Mutex::new(BufferState {
+                data: VecDeque::new(),
+                base: 0,
+                read_position: 0,
+                finished: false,
+                closed: false,
+                primed: false,
+                stalls: 0,
+            }),
+            changed: Condvar::new(),
+        })
+    }
+
+    // Add received bytes once there is room for them; false when the reader is gone
+    fn push(&self, bytes: &[u8]) -> bool {
+        let mut state = self.state.lock().unwrap();
+        while !state.closed && state.buffered() > MAX_BUFFERED {
+            state = self.changed.wait(state).unwrap();
+        }
+        if state.closed {
+            return false;
+        }
+        state.data.extend(bytes);
+        state.primed |= state.buffered() >= PRIMED;
+        self.changed.notify_all();
+        true
+    }
+}
+
+impl BufferState {
+    // Received but not yet read
+    fn buffered(&self) -> u64 {
+        self.base + self.data.len() as u64 - self.read_position
+    }
+}
+
+/// How much of a stream is waiting to be played, and how often it ran dry, for the buffer gauge.
+#[derive(Clone)]
+pub struct BufferHealth {
+    shared: Arc<Shared>,
+}
+
+impl BufferHealth {
+    pub fn buffered(&self) -> u64 {
+        self.shared.state.lock().unwrap().buffered()
+    }
+
+    pub fn stalls(&self) -> u32 {
+        self.shared.state.lock().unwrap().stalls
+    }
+}
+
+/// Blocking `Read + Seek` view of a live stream, as required by rodio's `Decoder`.
+/// Seeking only works within the recently received window.
+pub struct StreamReader {
+    shared: Arc<Shared>,
+    position: u64,
+}
+
+impl Read for StreamReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.shared.state.lock().unwrap();
+        let mut stalled = false;
+        loop {
+            let end = state.base + state.data.len() as u64;
+            if self.position < end {
+                let offset = (self.position - state.base) as usize;
+                let count = buf.len().min(state.data.len() - offset);
+                for (dst, src) in buf.iter_mut().zip(state.data.range(offset..offset + count)) {
+                    *dst = *src;
+                }
+                self.position += count as u64;
+                state.read_position = self.position;
+
+                let keep_from = self.position.saturating_sub(REWIND_WINDOW);
+                if keep_from > state.base {
+                    let stale = (keep_from - state.base) as usize;
+                    state.data.drain(..stale);
+                    state.base = keep_from;
+                }
+
+                self.shared.changed.notify_all();
+                return Ok(count);
+            }
+
+            if state.finished {
+                return Ok(0);
+            }
+            if state.primed && !stalled {
+                state.stalls += 1;
+                stalled = true;
+            }
+            state = self.shared.changed.wait(state).unwrap();
+        }
+    }
+}
+
+impl Seek for StreamReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => offset as i128,
+            SeekFrom::Current(delta) => self.position as i128 + delta as i128,
+            SeekFrom::End(_) => return Err(io::Error::new(io::ErrorKind::Unsupported, "live streams have no end")),
+        };
+
+        let state = self.shared.state.lock().unwrap();
+        if target < state.base as i128 {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "cannot rewind a live stream this far"));
+        }
+
+        // Seeking ahead of the received data simply waits for it in `read`
+        self.position = target as u64;
+        Ok(self.position)
+    }
+}
+
+impl Drop for StreamReader {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+/// Splits ICY (Shoutcast/Icecast) metadata blocks out of the audio byte stream.
+struct IcyDemuxer<R> {
+    inner: R,
+    metaint: Option<usize>,
+    until_metadata: usize,
+    title: Option<String>,
+}
+
+impl<R: Read> IcyDemuxer<R> {
+    fn new(inner: R, metaint: Option<usize>) -> Self {
+        IcyDemuxer {
+            inner,
+            metaint,
+            until_metadata: metaint.unwrap_or(0),
+            title: None,
+        }
+    }
+
+    // Read audio bytes only; returns 0 at the end of the stream
+    fn read_audio(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(metaint) = self.metaint else {
+            return self.inner.read(buf);
+        };
+
+        if self.until_metadata == 0 {
+            let mut length = [0u8];
+            if self.inner.read(&mut length)? == 0 {
+                return Ok(0);
+            }
+
+            let mut metadata = vec![0u8; length[0] as usize * 16];
+            match self.inner.read_exact(&mut metadata) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(0),
+                Err(e) => return Err(e),
+            }
+            if let Some(title) = parse_stream_title(&metadata) {
+                self.title = Some(title);
+            }
+            self.until_metadata = metaint;
+        }
+
+        let limit = buf.len().min(self.until_metadata);
+        let count = self.inner.read(&mut buf[..limit])?;
+        self.until_metadata -= count;
+        Ok(count)
+    }
+
+    fn take_title(&mut self) -> Option<String> {
+        self.title.take()
+    }
+}
+
+// Metadata blocks look like `StreamTitle='Artist - Title';StreamUrl='';` padded with NULs
+fn parse_stream_title(metadata: &[u8]) -> Option<String> {
+    let text = decode_text(metadata);
+    let start = text.find("StreamTitle='")? + "StreamTitle='".len();
+    let rest = &text[start..];
+    let end = rest.find("';").unwrap_or(rest.len());
+    let title = rest[..end].trim();
+    if title.is_empty() { None } else { Some(title.to_string()) }
+}
+
+// Stations send UTF-8 or Latin-1 without saying which
+fn decode_text(bytes: &[u8]) -> String {
+    let bytes = bytes.split(|&b| b == 0).next().unwrap_or_default();
+    String::from_utf8(bytes.to_vec()).unwrap_or_else(|_| bytes.iter().map(|&b| b as char).collect())
+}
+
+pub type OpenedStream = (Decoder<StreamReader>, SharedStreamInfo);
+
+/// A station being connected to and its format probed, on a thread of its own, so a server that accepts the
+/// connection and then stalls doesn't hold up the UI.
+pub struct Opening {
+    receiver: Receiver<Result<OpenedStream, MusixError>>,
+}
+
+impl Opening {
+    pub fn start(url: &str) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let url = url.to_string();
+        thread::spawn(move || {
+            let _ = sender.send(open(&url).and_then(|(reader, info)| Ok((Decoder::new(reader)?, info))));
+        });
+        Opening { receiver }
+    }
+
+    /// The stream once it is ready to play, or why it isn't; None while it is still connecting.
+    pub fn poll(&self) -> Option<Result<OpenedStream, MusixError>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(MusixError::Net(t("error.stream_gone").to_string()))),
+        }
+    }
+}
+
+/// Connect to a stream URL and start buffering it on a background thread.
+pub fn open(url: &str) -> Result<(StreamReader, SharedStreamInfo), MusixError> {
+    let agent = ureq::AgentBuilder::new().timeout_connect(CONNECT_TIMEOUT).timeout_read(READ_TIMEOUT).build();
+    let response = agent.get(url).set("Icy-MetaData", "1").call()?;
+
+    let metaint = response.header("icy-metaint").and_then(|value| value.trim().parse().ok()).filter(|&n| n > 0);
+    // Sometimes a list such as `128,128`
+    let announced = response
+        .header("icy-br")
+        .and_then(|value| value.split(',').next()?.trim().parse::<u32>().ok())
+        .filter(|&kbps| kbps > 0);
+    let shared = Shared::new();
+    let info = Arc::new(Mutex::new(StreamInfo {
+        content_type: Some(response.content_type().to_string()),
+        bitrate: announced,
+        buffer: Some(BufferHealth { shared: Arc::clone(&shared) }),
+        ..StreamInfo::default()
+    }));
+
+    let mut demuxer = IcyDemuxer::new(response.into_reader(), metaint);
+    let thread_shared = Arc::clone(&shared);
+    let thread_info = Arc::clone(&info);
+    thread::spawn(move || {
+        let mut buf = [0u8; READ_CHUNK];
+        let (started, mut received) = (Instant::now(), 0u64);
+        loop {
+            let count = match demuxer.read_audio(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(count) => count,
+            };
+            {
+                let mut info = thread_info.lock().unwrap();
+                if let Some(title) = demuxer.take_title() {
+                    info.title = Some(title);
+                }
+                if let Some(recording) = &mut info.recording
+                    && recording.write_all(&buf[..count]).is_err()
+                {
+                    info.recording = None;
+                }
+                received += count as u64;
+                if announced.is_none() && started.elapsed() >= BITRATE_SAMPLE {
+                    info.bitrate = Some((received * 8 / 1000 / started.elapsed().as_secs().max(1)) as u32);
+                }
+            }
+
+            if !thread_shared.push(&buf[..count]) {
+                return;
+            }
+        }
+
+        thread_shared.state.lock().unwrap().finished = true;
+        thread_shared.changed.notify_all();
+    });
+
+    Ok((StreamReader { shared, position: 0 }, info))
+}
+
+/// Read radio stations (name, URL) from an M3U or PLS playlist; local entries are ignored.
+pub fn read_station_playlist(path: &Path) -> Vec<(String, String)> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    let is_pls = path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| ext.eq_ignore_ascii_case("pls"));
+    let stations = if is_pls { parse_pls(&content) } else { parse_m3u(&content) };
+    stations.into_iter().filter(|(_, url)| is_stream_url(url)).collect()
+}
+
+fn is_stream_url(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+fn parse_m3u(content: &str) -> Vec<(String, String)> {
+    let mut stations = Vec::new();
+    let mut pending_name = None;
+    for line in content.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            pending_name = info.split_once(',').map(|(_, name)| name.trim().to_string());
+        } else if !line.starts_with('#') {
+            let name = pending_name.take().unwrap_or_else(|| station_name_from_url(line));
+            stations.push((name, line.to_string()));
+        }
+    }
+    stations
+}
+
+fn parse_pls(content: &str) -> Vec<(String, String)> {
+    let mut files: Vec<(u32, String)> = Vec::new();
+    let mut titles: Vec<(u32, String)> = Vec::new();
+    for line in content.lines().map(str::trim) {
+        let Some((key, value)) = line.split_once('=') else { continue };
+        let key = key.to_ascii_lowercase();
+        if let Some(n) = key.strip_prefix("file").and_then(|n| n.parse().ok()) {
+            files.push((n, value.trim().to_string()));
+        } else if let Some(n) = key.strip_prefix("title").and_then(|n| n.parse().ok()) {
+            titles.push((n, value.trim().to_string()));
+        }
+    }
+
+    files.sort();
+    files
+        .into_iter()
+        .map(|(n, url)| {
+            let name = titles
+                .iter()
+                .find(|(t, _)| *t == n)
+                .map(|(_, title)| title.clone())
+                .unwrap_or_else(|| station_name_from_url(&url));
+            (name, url)
+        })
+        .collect()
+}
+
+fn station_name_from_url(url: &str) -> String {
+    url.split("://").nth(1).unwrap_or(url).trim_end_matches('/').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stream_title() {
+        let mut block = b"StreamTitle='Daft Punk - Voyager';StreamUrl='';".to_vec();
+        block.resize(64, 0);
+        assert_eq!(parse_stream_title(&block).as_deref(), Some("Daft Punk - Voyager"));
+        assert_eq!(parse_stream_title(b"StreamTitle='';"), None);
+        // Latin-1 titles are common on older servers
+        assert_eq!(parse_stream_title(b"StreamTitle='Beyonc\xe9';").as_deref(), Some("Beyoncé"));
+    }
+
+    #[test]
+    fn test_icy_demuxer_strips_metadata() {
+        let mut raw = b"abcd".to_vec();
+        let mut metadata = b"StreamTitle='Song';".to_vec();
+        metadata.resize(32, 0);
+        raw.push(2);
+        raw.extend(&metadata);
+        raw.extend(b"efgh");
+        raw.push(0);
+        raw.extend(b"ij");
+
+        let mut demuxer = IcyDemuxer::new(&raw[..], Some(4));
+        let mut audio: Vec<u8> = Vec::new();
+        let mut buf = [0u8; 3];
+        loop {
+            let count = demuxer.read_audio(&mut buf).unwrap();
+            if count == 0 {
+                break;
+            }
+            audio.extend(&buf[..count]);
+        }
+        assert_eq!(audio, b"abcdefghij");
+        assert_eq!(demuxer.take_title().as_deref(), Some("Song"));
+    }
+
+    #[test]
+    fn test_running_dry_after_priming_counts_as_a_stall() {
+        let shared = Shared::new();
+        let health = BufferHealth { shared: Arc::clone(&shared) };
+        assert!(shared.push(&[0; PRIMED as usize]));
+        assert_eq!(health.buffered(), PRIMED);
+
+        let mut reader = StreamReader {
+            shared: Arc::clone(&shared),
+            position: 0,
+        };
+        let playing = thread::spawn(move || {
+            let mut buf = vec![0; PRIMED as usize + 4];
+            let mut total = 0;
+            while total < buf.len() {
+                total += reader.read(&mut buf[total..]).unwrap();
+            }
+            total
+        });
+        // The reader catches up and waits for more
+        let waited = Instant::now();
+        while health.stalls() == 0 {
+            assert!(waited.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(shared.push(b"more"));
+        assert_eq!(playing.join().unwrap(), PRIMED as usize + 4);
+        assert_eq!((health.buffered(), health.stalls()), (0, 1));
+    }
+
+    #[test]
+    fn test_stations_connect_in_the_background() {
+        // A server that accepts the connection and never answers
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/live", listener.local_addr().unwrap());
+        let started = Instant::now();
+        let stalled = Opening::start(&url);
+        let (_connection, _) = listener.accept().unwrap();
+        assert!(stalled.poll().is_none());
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        // One that refuses it
+        drop(listener);
+        let refused = Opening::start(&url);
+        let waited = Instant::now();
+        let result = loop {
+            if let Some(result) = refused.poll() {
+                break result;
+            }
+            assert!(waited.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(1));
+        };
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_station_playlists() {
+        let m3u = "#EXTM3U\n#EXTINF:-1,Groove Salad\nhttp://ice1.somafm.com/groovesalad-128-mp3\nhttp://example.com/live/\n";
+        assert_eq!(
+            parse_m3u(m3u),
+            vec![
+                ("Groove Salad".to_string(), "http://ice1.somafm.com/groovesalad-128-mp3".to_string()),
+                ("example.com/live".to_string(), "http://example.com/live/".to_string()),
+            ]
+        );
+
+        let pls = "[playlist]\nNumberOfEntries=2\nFile2=https://b.example/stream\nFile1=http://a.example/stream\nTitle1=Station A\n";
+        assert_eq!(
+            parse_pls(pls),
+            vec![
+                ("Station A".to_string(), "http://a.example/stream".to_string()),
+                ("b.example/stream".to_string(), "https://b.example/stream".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::paths;
+
+// Positions are only restored for tracks at least this long (mixes, podcasts, audiobooks)
+const RESUME_MIN_SECONDS: f64 = 10.0 * 60.0;
+// Cleared positions are kept this long so the clear reaches every device before it is forgotten
+const TOMBSTONE_SECONDS: i64 = 30 * 86400;
+
+/// Where a track was left off; `seconds` of 0 marks a position cleared after the track was finished.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Position {
+    pub seconds: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    // Unix timestamp of the change; the newest one wins when devices disagree
+    pub updated: i64,
+}
+
+/// The track that was playing last, on whichever device.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LastPlayed {
+    pub track: String,
+    pub seconds: f64,
+    pub updated: i64,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct SyncState {
+    #[serde(default)]
+    pub positions: BTreeMap<String, Position>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_played: Option<LastPlayed>,
+}
+
+impl SyncState {
+    /// Fold in another copy of the state entry by entry, keeping whichever side changed last.
+    pub fn merge(&mut self, other: SyncState) {
+        for (track, position) in other.positions {
+            match self.positions.get(&track) {
+                Some(current) if current.updated >= position.updated => {}
+                _ => {
+                    self.positions.insert(track, position);
+                }
+            }
+        }
+        if let Some(last_played) = other.last_played
+            && self.last_played.as_ref().is_none_or(|current| current.updated < last_played.updated)
+        {
+            self.last_played = Some(last_played);
+        }
+    }
+
+    fn prune(&mut self, now: i64) {
+        self.positions
+            .retain(|_, position| position.seconds > 0.0 || now - position.updated < TOMBSTONE_SECONDS);
+    }
+}
+
+/// Track key shared between devices: the path relative to the music folder it lives in, with `/` separators,
+/// so `/home/me/Music/a.mp3` and `/Users/me/Music/a.mp3` are the same track.
+pub fn track_key(path: &Path, music_dirs: &[PathBuf]) -> String {
+    let relative = music_dirs.iter().find_map(|dir| path.strip_prefix(dir).ok()).unwrap_or(path);
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn read_state(file: &Path) -> Option<SyncState> {
+    fs::read_to_string(file).ok().and_then(|json| serde_json::from_str(&json).ok())
+}
+
+// Copies left by sync tools when two devices wrote at once, e.g. `musix.sync-conflict-20240101-120000-ABC.json`
+// (Syncthing) or `musix (laptop's conflicted copy 2024-01-01).json` (Dropbox)
+fn conflict_copies(file: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(stem)) = (file.parent(), file.file_stem().map(|stem| stem.to_string_lossy().into_owned())) else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(if dir.as_os_str().is_empty() { Path::new(".") } else { dir }) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy())
+                .is_some_and(|name| name.starts_with(stem.as_str()) && name.contains("conflict") && path.as_path() != file)
+        })
+        .collect()
+}
+
+/// Playback positions shared between devices through a file in a synced folder (Syncthing, Dropbox, ...).
+pub struct SyncFile {
+    path: PathBuf,
+    pub state: SyncState,
+}
+
+impl SyncFile {
+    pub fn open(path: PathBuf) -> Self {
+        let mut sync = SyncFile {
+            path,
+            state: SyncState::default(),
+        };
+        sync.reload();
+        sync
+    }
+
+    // Merge whatever other devices have written, including conflict copies; returns the copies merged
+    fn reload(&mut self) -> Vec<PathBuf> {
+        let conflicts = conflict_copies(&self.path);
+        for file in std::iter::once(&self.path).chain(&conflicts) {
+            if let Some(state) = read_state(file) {
+                self.state.merge(state);
+            }
+        }
+        conflicts
+    }
+
+    /// Merge with the file on disk and write the result back, so changes made elsewhere in the meantime survive.
+    pub fn save(&mut self, now: i64) -> io::Result<()> {
+        let conflicts = self.reload();
+        self.state.prune(now);
+
+        let json = serde_json::to_string_pretty(&self.state).map_err(io::Error::other)?;
+        paths::write_atomic(&self.path, json)?;
+
+        // Their contents are merged in now
+        for conflict in conflicts {
+            let _ = fs::remove_file(conflict);
+        }
+        Ok(())
+    }
+
+    /// Remember where a track was left; `None` clears it because the track was played to the end.
+    pub fn record(&mut self, track: String, position: Option<f64>, duration: Option<f64>, now: i64) {
+        let seconds = position.unwrap_or(0.0);
+        self.state.positions.insert(
+            track.clone(),
+            Position {
+                seconds,
+                duration,
+                updated: now,
+            },
+        );
+        self.state.last_played = Some(LastPlayed { track, seconds, updated: now });
+    }
+
+    /// Where to resume a long track that was left unfinished, on this or another device.
+    pub fn resume_position(&self, track: &str) -> Option<f64> {
+        self.state
+            .positions
+            .get(track)
+            .filter(|position| position.seconds > 0.0 && position.duration.is_some_and(|duration| duration >= RESUME_MIN_SECONDS))
+            .map(|position| position.seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    fn position(seconds: f64, updated: i64) -> Position {
+        Position {
+            seconds,
+            duration: Some(3600.0),
+            updated,
+        }
+    }
+
+    #[test]
+    fn test_merge_keeps_newest_entries() {
+        let mut desktop = SyncState::default();
+        desktop.positions.insert("mix.mp3".to_string(), position(600.0, 100));
+        desktop.positions.insert("talk.mp3".to_string(), position(50.0, 300));
+
+        let mut laptop = SyncState::default();
+        laptop.positions.insert("mix.mp3".to_string(), position(900.0, 200));
+        laptop.positions.insert("talk.mp3".to_string(), position(10.0, 250));
+        laptop.last_played = Some(LastPlayed {
+            track: "mix.mp3".to_string(),
+            seconds: 900.0,
+            updated: 200,
+        });
+
+        desktop.merge(laptop);
+        assert_eq!(desktop.positions["mix.mp3"].seconds, 900.0);
+        assert_eq!(desktop.positions["talk.mp3"].seconds, 50.0);
+        assert_eq!(desktop.last_played.unwrap().track, "mix.mp3");
+    }
+
+    #[test]
+    fn test_track_key_is_relative_to_music_dir() {
+        let dirs = [PathBuf::from("/home/me/Music"), PathBuf::from("./data")];
+        assert_eq!(track_key(Path::new("/home/me/Music/Live/mix.mp3"), &dirs), "Live/mix.mp3");
+        assert_eq!(track_key(Path::new("./data/a.mp3"), &dirs), "a.mp3");
+    }
+
+    #[test]
+    fn test_save_merges_file_and_conflict_copies() {
+        let dir = TestDir::new("sync");
+        let path = dir.join("musix.json");
+
+        let mut other_device = SyncState::default();
+        other_device.positions.insert("talk.mp3".to_string(), position(1200.0, 500));
+        fs::write(
+            dir.join("musix.sync-conflict-20240101-120000-ABC.json"),
+            serde_json::to_string(&other_device).unwrap(),
+        )
+        .unwrap();
+
+        let mut sync = SyncFile::open(path.clone());
+        assert_eq!(sync.resume_position("talk.mp3"), Some(1200.0));
+        sync.record("mix.mp3".to_string(), Some(700.0), Some(3600.0), 600);
+        sync.record("done.mp3".to_string(), None, Some(3600.0), 600);
+        sync.save(600).unwrap();
+
+        let reopened = SyncFile::open(path);
+        assert_eq!(reopened.resume_position("mix.mp3"), Some(700.0));
+        assert_eq!(reopened.resume_position("talk.mp3"), Some(1200.0));
+        assert_eq!(reopened.resume_position("done.mp3"), None);
+        assert_eq!(reopened.state.last_played.as_ref().unwrap().track, "done.mp3");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver},
+    },
+    thread,
+    time::Duration,
+};
+
+use lofty::prelude::*;
+
+/// What the list shows and sorts by for a local track, read from its tags.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct TrackTags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub genre: Option<String>,
+    pub track: Option<u32>,
+    pub disc: Option<u32>,
+    pub duration: Option<Duration>,
+}
+
+pub fn read(path: &Path) -> TrackTags {
+    let Ok(tagged) = lofty::read_from_path(path) else {
+        return TrackTags::default();
+    };
+    let duration = Some(tagged.properties().duration()).filter(|duration| !duration.is_zero());
+    let Some(tag) = tagged.primary_tag().or_else(|| tagged.first_tag()) else {
+        return TrackTags {
+            duration,
+            ..TrackTags::default()
+        };
+    };
+    let text = |value: Option<std::borrow::Cow<str>>| value.map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+    TrackTags {
+        title: text(tag.title()),
+        artist: text(tag.artist()),
+        album: text(tag.album()),
+        genre: text(tag.genre()),
+        track: tag.track(),
+        disc: tag.disk(),
+        duration,
+    }
+}
+
+// Files still to read: the ones in view first, then the rest in list order
+#[derive(Default)]
+struct Queue {
+    urgent: VecDeque<PathBuf>,
+    rest: VecDeque<PathBuf>,
+    done: HashSet<PathBuf>,
+}
+
+impl Queue {
+    fn next(&mut self) -> Option<PathBuf> {
+        while let Some(path) = self.urgent.pop_front().or_else(|| self.rest.pop_front()) {
+            if self.done.insert(path.clone()) {
+                return Some(path);
+            }
+        }
+        None
+    }
+
+    fn prioritize(&mut self, paths: Vec<PathBuf>) {
+        self.urgent = paths.into_iter().filter(|path| !self.done.contains(path)).collect();
+    }
+}
+
+/// Reads tags on a background thread, since opening every file would hold up startup. The tracks in view can be
+/// moved to the front as the list scrolls, so their lengths show up first.
+pub struct TagReader {
+    queue: Arc<Mutex<Queue>>,
+    pub results: Receiver<(PathBuf, TrackTags)>,
+}
+
+impl TagReader {
+    pub fn start(paths: Vec<PathBuf>) -> Self {
+        let queue = Arc::new(Mutex::new(Queue {
+            rest: paths.into(),
+            ..Queue::default()
+        }));
+        let (sender, results) = mpsc::channel();
+        let worker_queue = Arc::clone(&queue);
+        thread::spawn(move || {
+            loop {
+                // The lock is only held to take the next path, not while reading
+                let Some(path) = worker_queue.lock().unwrap().next() else {
+                    break;
+                };
+                let tags = read(&path);
+                if sender.send((path, tags)).is_err() {
+                    break;
+                }
+            }
+        });
+        TagReader { queue, results }
+    }
+
+    /// Read `paths` next, in order, ahead of everything else still waiting; replaces the previous ones.
+    pub fn prioritize(&self, paths: Vec<PathBuf>) {
+        self.queue.lock().unwrap().prioritize(paths);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prioritized_paths_are_read_first_and_once() {
+        let path = |name: &str| PathBuf::from(name);
+        let mut queue = Queue {
+            rest: ["a", "b", "c", "d"].map(path).into(),
+            ..Queue::default()
+        };
+        assert_eq!(queue.next(), Some(path("a")));
+        // Scrolled to the end of the list
+        queue.prioritize(["a", "d", "c"].map(path).to_vec());
+        assert_eq!(queue.next(), Some(path("d")));
+        // Scrolled again before the rest of the view was read
+        queue.prioritize(vec![path("b")]);
+        assert_eq!(queue.next(), Some(path("b")));
+        assert_eq!(queue.next(), Some(path("c")));
+        assert_eq!(queue.next(), None);
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    env,
+    io::{self, Write},
+};
+
+use serde::Deserialize;
+
+/// How the window title showing the current track is set.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TitleMode {
+    // `pane` inside tmux or screen, `plain` everywhere else
+    #[default]
+    Auto,
+    // The xterm title sequence, which a multiplexer swallows or shows garbled
+    Plain,
+    // The tmux pane title or the screen window title; tmux's `set-titles` can pass it on to the terminal
+    Pane,
+    // Wrapped so tmux (with `allow-passthrough on`) or screen hand it to the outer terminal untouched
+    Passthrough,
+    Off,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Multiplexer {
+    Tmux,
+    Screen,
+}
+
+fn detect(var: impl Fn(&str) -> Option<String>) -> Option<Multiplexer> {
+    if var("TMUX").is_some_and(|value| !value.is_empty()) {
+        Some(Multiplexer::Tmux)
+    } else if var("STY").is_some_and(|value| !value.is_empty()) || var("TERM").is_some_and(|term| term.starts_with("screen")) {
+        Some(Multiplexer::Screen)
+    } else {
+        None
+    }
+}
+
+/// The escape sequence that sets `title` in `mode`, or None when titles are off.
+fn sequence(mode: TitleMode, multiplexer: Option<Multiplexer>, title: &str) -> Option<String> {
+    // A stray ESC or BEL in a track name would end the sequence early and print the rest
+    let title: String = title.chars().filter(|c| !c.is_control()).collect();
+    let plain = format!("\x1b]2;{title}\x07");
+    let mode = match (mode, multiplexer) {
+        (TitleMode::Auto, Some(_)) => TitleMode::Pane,
+        (TitleMode::Auto, None) => TitleMode::Plain,
+        (mode, _) => mode,
+    };
+    match (mode, multiplexer) {
+        (TitleMode::Off, _) => None,
+        (TitleMode::Pane, Some(Multiplexer::Tmux)) => Some(format!("\x1b]2;{title}\x1b\\")),
+        (TitleMode::Pane, Some(Multiplexer::Screen)) => Some(format!("\x1bk{title}\x1b\\")),
+        // Escapes inside a tmux passthrough are doubled
+        (TitleMode::Passthrough, Some(Multiplexer::Tmux)) => Some(format!("\x1bPtmux;{}\x1b\\", plain.replace('\x1b', "\x1b\x1b"))),
+        (TitleMode::Passthrough, Some(Multiplexer::Screen)) => Some(format!("\x1bP{plain}\x1b\\")),
+        _ => Some(plain),
+    }
+}
+
+/// Set the title in `mode`; errors are ignored since a title is never worth interrupting playback for.
+pub fn set(mode: TitleMode, title: &str) {
+    if let Some(sequence) = sequence(mode, detect(|name| env::var(name).ok()), title) {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(sequence.as_bytes());
+        let _ = stdout.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multiplexer_sequences() {
+        let env =
+            |vars: &'static [(&'static str, &'static str)]| move |name: &str| vars.iter().find(|(var, _)| *var == name).map(|(_, value)| value.to_string());
+        let tmux = detect(env(&[("TMUX", "/tmp/tmux-1000/default,1,0"), ("TERM", "screen-256color")]));
+        assert_eq!(tmux, Some(Multiplexer::Tmux));
+        assert_eq!(detect(env(&[("TERM", "screen.xterm-256color")])), Some(Multiplexer::Screen));
+        assert_eq!(detect(env(&[("TERM", "xterm-256color"), ("TMUX", "")])), None);
+
+        assert_eq!(sequence(TitleMode::Auto, None, "a"), Some("\x1b]2;a\x07".to_string()));
+        assert_eq!(sequence(TitleMode::Auto, tmux, "a"), Some("\x1b]2;a\x1b\\".to_string()));
+        assert_eq!(sequence(TitleMode::Auto, Some(Multiplexer::Screen), "a"), Some("\x1bka\x1b\\".to_string()));
+        assert_eq!(
+            sequence(TitleMode::Passthrough, tmux, "a"),
+            Some("\x1bPtmux;\x1b\x1b]2;a\x07\x1b\\".to_string())
+        );
+        assert_eq!(sequence(TitleMode::Plain, tmux, "a\x1b]b\x07"), Some("\x1b]2;a]b\x07".to_string()));
+        assert_eq!(sequence(TitleMode::Off, None, "a"), None);
+    }
+}
@@ -0,0 +1,39 @@
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+/// A fresh folder under the system temp folder for one test, removed again when the test ends, failed or not.
+pub struct TestDir(PathBuf);
+
+impl TestDir {
+    /// `name` keeps it apart from the folders of the other tests, which run at the same time in this process.
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("musix-{name}-{}", std::process::id()));
+        // Left over from a run that was killed
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        TestDir(dir)
+    }
+}
+
+impl Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
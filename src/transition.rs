@@ -0,0 +1,125 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use rodio::Sink;
+
+use crate::{config::PlaybackConfig, tags::TrackTags};
+
+/// How the playing track hands over to the next one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transition {
+    // Queued behind the playing track, so it starts on the sample after the last one
+    Gapless,
+    // Started early, the two overlapping while one fades out and the other in
+    Crossfade(Duration),
+    // The next track starts once the playing one has stopped
+    Gap,
+}
+
+/// Gapless between tracks that follow each other on an album, a crossfade elsewhere when one is set.
+pub fn choose(config: &PlaybackConfig, previous: Option<&TrackTags>, next: Option<&TrackTags>) -> Transition {
+    if config.gapless_albums
+        && let (Some(previous), Some(next)) = (previous, next)
+        && continues_album(previous, next)
+    {
+        return Transition::Gapless;
+    }
+    // Negative, NaN or too long for a Duration is no crossfade
+    if let Ok(fade) = Duration::try_from_secs_f64(config.crossfade)
+        && !fade.is_zero()
+    {
+        return Transition::Crossfade(fade);
+    }
+    Transition::Gap
+}
+
+/// Whether `next` comes right after `previous` on the same album: the next track on the same disc, or the first
+/// of the next disc. A missing disc number counts as disc 1.
+pub fn continues_album(previous: &TrackTags, next: &TrackTags) -> bool {
+    let same_album = match (&previous.album, &next.album) {
+        (Some(previous), Some(next)) => previous.to_lowercase() == next.to_lowercase(),
+        _ => false,
+    };
+    let (Some(track), Some(next_track)) = (previous.track, next.track) else {
+        return false;
+    };
+    let (disc, next_disc) = (previous.disc.unwrap_or(1), next.disc.unwrap_or(1));
+    same_album && (next_disc == disc && next_track == track + 1 || next_disc == disc + 1 && next_track == 1)
+}
+
+/// The track on its way out during a crossfade, on a sink of its own beside the incoming one.
+pub struct Crossfade {
+    pub sink: Arc<Mutex<Sink>>,
+    started: Instant,
+    length: Duration,
+}
+
+impl Crossfade {
+    pub fn new(sink: Arc<Mutex<Sink>>, length: Duration) -> Self {
+        Crossfade {
+            sink,
+            started: Instant::now(),
+            length,
+        }
+    }
+
+    /// From 0.0 when the crossfade starts to 1.0 when the outgoing track is silent.
+    pub fn progress(&self) -> f32 {
+        if self.length.is_zero() {
+            return 1.0;
+        }
+        (self.started.elapsed().as_secs_f32() / self.length.as_secs_f32()).min(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(album: &str, disc: Option<u32>, track: u32) -> TrackTags {
+        TrackTags {
+            album: Some(album.to_string()),
+            disc,
+            track: Some(track),
+            ..TrackTags::default()
+        }
+    }
+
+    #[test]
+    fn test_album_neighbours_play_gapless() {
+        let config = PlaybackConfig {
+            crossfade: 4.0,
+            ..PlaybackConfig::default()
+        };
+        let first = track("Live at Leeds", Some(1), 7);
+        assert_eq!(choose(&config, Some(&first), Some(&track("live at leeds", Some(1), 8))), Transition::Gapless);
+        // The first track of the next disc
+        assert_eq!(choose(&config, Some(&first), Some(&track("Live at Leeds", Some(2), 1))), Transition::Gapless);
+
+        let crossfade = Transition::Crossfade(Duration::from_secs(4));
+        assert_eq!(choose(&config, Some(&first), Some(&track("Live at Leeds", Some(1), 9))), crossfade);
+        assert_eq!(choose(&config, Some(&first), Some(&track("Tommy", Some(1), 8))), crossfade);
+        assert_eq!(choose(&config, Some(&first), None), crossfade);
+        assert!(continues_album(&track("Mix", None, 1), &track("Mix", Some(1), 2)));
+
+        assert_eq!(choose(&PlaybackConfig::default(), Some(&first), None), Transition::Gap);
+        let no_gapless = PlaybackConfig {
+            gapless_albums: false,
+            ..config
+        };
+        assert_eq!(choose(&no_gapless, Some(&first), Some(&track("Live at Leeds", Some(1), 8))), crossfade);
+    }
+
+    #[test]
+    fn test_unusable_crossfade_is_a_gap() {
+        for crossfade in [-4.0, f64::NAN, f64::INFINITY, 1e30] {
+            let config = PlaybackConfig {
+                crossfade,
+                ..PlaybackConfig::default()
+            };
+            assert_eq!(choose(&config, None, None), Transition::Gap);
+        }
+    }
+}